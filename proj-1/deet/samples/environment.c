@@ -0,0 +1,18 @@
+#include <stdio.h>
+#include <stdlib.h>
+#include <unistd.h>
+
+int main(int argc, char *argv[]) {
+    char cwd[256];
+    char line[256];
+    for (int i = 1; i < argc; i++)
+        printf("argv[%d] = %s\n", i, argv[i]);
+    const char *greeting = getenv("GREETING");
+    printf("GREETING = %s\n", greeting ? greeting : "(unset)");
+    if (getcwd(cwd, sizeof(cwd)))
+        printf("cwd = %s\n", cwd);
+    while (fgets(line, sizeof(line), stdin))
+        printf("read: %s", line);
+    fprintf(stderr, "done\n");
+    return 0;
+}
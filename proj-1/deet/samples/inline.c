@@ -0,0 +1,18 @@
+#include <stdio.h>
+
+static inline __attribute__((always_inline)) int square(int x) {
+    int result = x * x;
+    return result;
+}
+
+int sum_of_squares(int a, int b) {
+    int total = square(a);
+    total += square(b);
+    return total;
+}
+
+int main() {
+    int total = sum_of_squares(3, 4);
+    printf("%d\n", total);
+    return 0;
+}
@@ -0,0 +1,29 @@
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+char *copy_name(const char *name) {
+    char *copy = malloc(strlen(name) + 1);
+    strcpy(copy, name);
+    return copy;
+}
+
+int *make_numbers(int count) {
+    int *numbers = calloc(count, sizeof(int));
+    numbers = realloc(numbers, 2 * count * sizeof(int));
+    return numbers;
+}
+
+int main() {
+    char *names[4];
+    for (int i = 0; i < 4; i++) {
+        names[i] = copy_name("leaky");
+    }
+    free(names[1]);
+    free(names[3]);
+    int *numbers = make_numbers(10);
+    void *scratch = malloc(200);
+    free(scratch);
+    printf("%s %d\n", names[0], numbers[0]);
+    return 0;
+}
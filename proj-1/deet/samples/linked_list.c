@@ -0,0 +1,39 @@
+#include <stdio.h>
+#include <stdlib.h>
+
+struct node {
+    int value;
+    struct node *next;
+};
+
+struct list {
+    struct node *head;
+    int size;
+};
+
+struct point {
+    int x;
+    int y;
+};
+
+void push_front(struct list *list, int value) {
+    struct node *node = malloc(sizeof(struct node));
+    node->value = value;
+    node->next = list->head;
+    list->head = node;
+    list->size++;
+}
+
+int main() {
+    struct list list = {NULL, 0};
+    struct point points[3] = {{1, 2}, {3, 4}, {5, 6}};
+    for (int i = 3; i > 0; i--) {
+        push_front(&list, i);
+    }
+    printf("%d %d\n", list.size, points[2].y);
+    return 0;
+}
+
+/* Pointers into the program's own data, kept below main so that its line numbers stay put */
+int counts[2] = {7, 8};
+int *counters[2] = {&counts[0], &counts[1]};
@@ -0,0 +1,18 @@
+#include <stdio.h>
+
+int factorial(int n) {
+    if (n <= 1)
+        return 1;
+    int rest = factorial(n - 1);
+    return n * rest;
+}
+
+int main() {
+    int total = 0;
+    for (int i = 0; i < 3; i++) {
+        total += i;
+    }
+    printf("total = %d\n", total);
+    printf("factorial = %d\n", factorial(5));
+    return 0;
+}
@@ -0,0 +1,20 @@
+#[derive(Debug)]
+enum Shape {
+    Circle(f64),
+    Rect { w: u32, h: u32 },
+    Empty,
+}
+
+fn show(name: &str, shapes: &Vec<Shape>, label: &String, maybe: Option<u32>, boxed: Box<i64>) -> usize {
+    let none: Option<&str> = None;
+    let some_ref = Some(name);
+    let c = 'x';
+    println!("{} {:?} {} {:?} {} {:?} {:?} {}", name, shapes, label, maybe, boxed, none, some_ref, c);
+    shapes.len()
+}
+
+fn main() {
+    let shapes = vec![Shape::Circle(1.5), Shape::Rect { w: 2, h: 3 }, Shape::Empty];
+    let label = String::from("hello");
+    show("shapes", &shapes, &label, Some(7), Box::new(42));
+}
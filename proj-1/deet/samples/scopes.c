@@ -0,0 +1,26 @@
+#include <stdio.h>
+
+int x = 100;
+
+int outer(int n) {
+    int x = n;
+    int inner(int m) {
+        int y = m * 2;
+        return y + 1;
+    }
+    {
+        int x = n + 1;
+        for (int i = 0; i < 2; i++) {
+            int x = i * 10;
+            printf("%d\n", x);
+        }
+        printf("%d\n", x);
+    }
+    int y = inner(x);
+    return x + y;
+}
+
+int main() {
+    printf("%d\n", outer(5));
+    return 0;
+}
@@ -0,0 +1,143 @@
+//! Tab completion at the (deet) prompt: command names, and then whatever the command takes as
+//! arguments, using the names in the debugging information.
+
+use crate::debugger_command::{self, Arguments, Command, COMMANDS};
+use crate::dwarf_data::DwarfData;
+use rustyline::completion::{Completer, Pair};
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::validate::Validator;
+use rustyline::{Context, Helper};
+
+pub struct DeetHelper {
+    functions: Vec<String>,
+    /// File names without their directories, which is how locations usually refer to them
+    files: Vec<String>,
+    /// Variables visible where the inferior is stopped, updated before every prompt
+    pub variables: Vec<String>,
+    /// Names of user-defined commands and aliases
+    pub user_commands: Vec<String>,
+}
+
+impl DeetHelper {
+    pub fn new(debug_data: &DwarfData) -> DeetHelper {
+        let mut functions: Vec<String> = debug_data.function_names().map(str::to_string).collect();
+        functions.sort();
+        functions.dedup();
+        let mut files: Vec<String> = debug_data
+            .file_names()
+            .map(|name| name.rsplit('/').next().unwrap_or(name).to_string())
+            .collect();
+        files.sort();
+        files.dedup();
+        DeetHelper {
+            functions,
+            files,
+            variables: Vec::new(),
+            user_commands: Vec::new(),
+        }
+    }
+
+    /// Completes line, which starts with the name of a command in table. offset is where line
+    /// starts in the full input. If names_only is set, only command names are completed (for
+    /// help).
+    fn complete_command(
+        &self,
+        table: &'static [Command],
+        line: &str,
+        offset: usize,
+        names_only: bool,
+    ) -> (usize, Vec<Pair>) {
+        let start = line.len() - line.trim_start().len();
+        let line = &line[start..];
+        let offset = offset + start;
+        let end = match line.find(char::is_whitespace) {
+            Some(end) => end,
+            None => {
+                // Still typing the command name
+                let mut names: Vec<&str> = table.iter().map(|c| c.name).collect();
+                if std::ptr::eq(table, COMMANDS) && !names_only {
+                    names.extend(self.user_commands.iter().map(String::as_str));
+                }
+                let names = names.into_iter().filter(|name| name.starts_with(line));
+                return (offset, names.map(|name| candidate(name, " ")).collect());
+            }
+        };
+        let command = match debugger_command::lookup(table, &line[..end], None) {
+            Ok(command) => command,
+            Err(_) => return (offset + line.len(), Vec::new()),
+        };
+        let (args, offset) = (&line[end..], offset + end);
+        match command.args {
+            Arguments::Subcommand(subcommands) => {
+                self.complete_command(subcommands, args, offset, names_only)
+            }
+            Arguments::CommandName if !names_only => {
+                self.complete_command(COMMANDS, args, offset, true)
+            }
+            _ if names_only => (offset + args.len(), Vec::new()),
+            kind => self.complete_argument(kind, args, offset),
+        }
+    }
+
+    fn complete_argument(&self, kind: Arguments, args: &str, offset: usize) -> (usize, Vec<Pair>) {
+        let is_break: fn(char) -> bool = match kind {
+            Arguments::Expression => |c| !(c.is_alphanumeric() || c == '_'),
+            _ => |c| c.is_whitespace() || c == ',',
+        };
+        let start = args.rfind(is_break).map_or(0, |i| i + 1);
+        let word = &args[start..];
+        let mut candidates: Vec<Pair> = match kind {
+            Arguments::Location => {
+                let functions = matching(&self.functions, word).map(|name| candidate(name, " "));
+                let files = matching(&self.files, word).map(|name| candidate(name, ":"));
+                functions.chain(files).collect()
+            }
+            Arguments::Function => {
+                matching(&self.functions, word).map(|name| candidate(name, " ")).collect()
+            }
+            Arguments::Expression => matching(&self.variables, word)
+                .chain(matching(&self.functions, word))
+                .map(|name| candidate(name, ""))
+                .collect(),
+            _ => Vec::new(),
+        };
+        candidates.sort_by(|a, b| a.display.cmp(&b.display));
+        candidates.dedup_by(|a, b| a.display == b.display);
+        (offset + start, candidates)
+    }
+}
+
+fn matching<'a>(names: &'a [String], prefix: &'a str) -> impl Iterator<Item = &'a str> {
+    names.iter().map(String::as_str).filter(move |name| name.starts_with(prefix))
+}
+
+fn candidate(name: &str, suffix: &str) -> Pair {
+    Pair {
+        display: name.to_string(),
+        replacement: format!("{}{}", name, suffix),
+    }
+}
+
+impl Completer for DeetHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        Ok(self.complete_command(COMMANDS, &line[..pos], 0, false))
+    }
+}
+
+impl Hinter for DeetHelper {
+    type Hint = String;
+}
+
+impl Highlighter for DeetHelper {}
+
+impl Validator for DeetHelper {}
+
+impl Helper for DeetHelper {}
@@ -0,0 +1,211 @@
+//! Implements `deet --coverage`, which measures which source lines of the target execute without
+//! it being compiled for gcov. A one-shot breakpoint goes on every address in the line tables;
+//! each is removed when first hit, so the target runs at full speed once its code has warmed up.
+//! The result is written as an lcov tracefile, which genhtml and most CI services understand.
+
+use crate::debugger::{load_debug_data, Breakpoint};
+use crate::dwarf_data::DwarfData;
+use crate::inferior::{Inferior, Status};
+use nix::sys::signal::Signal;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::io::{self, Write};
+
+pub struct CoverageCollector {
+    inferior: Inferior,
+    debug_data: DwarfData,
+    /// The breakpoints that haven't been hit yet
+    breakpoints: HashMap<usize, Option<Breakpoint>>,
+    hits: HashSet<usize>,
+}
+
+impl CoverageCollector {
+    /// Spawns target under ptrace with a breakpoint on every line. Returns None if the process
+    /// couldn't be started.
+    pub fn new(target: &str, args: &Vec<String>) -> Option<Self> {
+        let debug_data = load_debug_data(target);
+        let mut breakpoints: HashMap<usize, Option<Breakpoint>> = debug_data
+            .files()
+            .flat_map(|file| &file.lines)
+            .filter(|line| line.address != 0)
+            .map(|line| (line.address, None))
+            .collect();
+        let inferior = Inferior::new(target, args, &mut breakpoints)?;
+        Some(CoverageCollector {
+            inferior,
+            debug_data,
+            breakpoints,
+            hits: HashSet::new(),
+        })
+    }
+
+    /// Runs the inferior to completion, then writes an lcov report to output. Returns the exit
+    /// status that deet should exit with (the inferior's own exit status where possible).
+    pub fn run(&mut self, output: &str) -> i32 {
+        let mut signal: Option<Signal> = None;
+        let code = loop {
+            let status = match self.inferior.cont_with_signal(&self.breakpoints, signal.take()) {
+                Ok(status) => status,
+                Err(err) => {
+                    eprintln!("Error running inferior: {}", err);
+                    return 1;
+                }
+            };
+            match status {
+                Status::Stopped(Signal::SIGTRAP, rip) if self.inferior.past_breakpoint() => {
+                    if let Some(Some(breakpoint)) = self.breakpoints.remove(&(rip - 1)) {
+                        if let Err(err) = self.inferior.remove_breakpoint(&breakpoint) {
+                            eprintln!("Error removing breakpoint at {:#x}: {}", rip - 1, err);
+                            return 1;
+                        }
+                        self.hits.insert(breakpoint.addr);
+                    }
+                }
+                Status::Stopped(sig, _) => signal = Some(sig),
+                Status::Syscall(_) => {}
+                Status::Exited(code) => break code,
+                Status::Signaled(sig) => {
+                    eprintln!("Program terminated with signal {}", sig);
+                    break 128 + sig as i32;
+                }
+            }
+        };
+        match self.write_report(output) {
+            Ok((found, hit)) => eprintln!("{} of {} lines executed; wrote {}", hit, found, output),
+            Err(err) => {
+                eprintln!("Error writing {}: {}", output, err);
+                return 1;
+            }
+        }
+        code
+    }
+
+    /// Writes the lcov tracefile, returning how many lines there are and how many executed.
+    fn write_report(&self, output: &str) -> io::Result<(usize, usize)> {
+        let (lines, functions) = self.count_hits();
+        let mut report = io::BufWriter::new(fs::File::create(output)?);
+        let totals = write_lcov(&mut report, &lines, &functions)?;
+        report.flush()?;
+        Ok(totals)
+    }
+
+    /// Works out which lines and functions of each source file executed.
+    fn count_hits(&self) -> (LineCounts, FunctionCounts<'_>) {
+        let mut lines = LineCounts::new();
+        let mut functions = FunctionCounts::new();
+        for file in self.debug_data.files() {
+            // Line tables name files relative to where they were compiled; addr2line resolves
+            // that, so look each file up once by one of its addresses
+            let mut paths: HashMap<&str, String> = HashMap::new();
+            for line in file.lines.iter().filter(|line| line.address != 0) {
+                let path = paths.entry(&line.file).or_insert_with(|| {
+                    let resolved = self.debug_data.get_line_from_addr(line.address);
+                    resolved.map_or_else(|| line.file.clone(), |resolved| resolved.file)
+                });
+                let hit = self.hits.contains(&line.address) as usize;
+                let count = lines.entry(path.clone()).or_default().entry(line.number).or_default();
+                *count = (*count).max(hit);
+            }
+            for func in file.functions.iter().filter(|func| func.text_length > 0) {
+                let line = file.lines.iter().find(|line| line.address == func.address);
+                let source = match line.and_then(|line| paths.get(line.file.as_str())) {
+                    Some(source) => source.clone(),
+                    None => continue,
+                };
+                let end = func.address + func.text_length;
+                let hit = self.hits.iter().any(|addr| (func.address..end).contains(addr)) as usize;
+                functions.entry(source).or_default().push((func.line_number, &func.name, hit));
+            }
+        }
+        (lines, functions)
+    }
+}
+
+/// Each source file's lines, with how many times each executed
+type LineCounts = BTreeMap<String, BTreeMap<usize, usize>>;
+/// Each source file's functions: the line each starts on, its name and how many times it ran
+type FunctionCounts<'a> = BTreeMap<String, Vec<(usize, &'a str, usize)>>;
+
+/// Writes one lcov record per source file, returning how many lines there are and how many
+/// executed.
+fn write_lcov<W: Write>(
+    report: &mut W,
+    lines: &LineCounts,
+    functions: &FunctionCounts,
+) -> io::Result<(usize, usize)> {
+    let (mut total_found, mut total_hit) = (0, 0);
+    for (source, counts) in lines {
+        writeln!(report, "TN:")?;
+        writeln!(report, "SF:{}", source)?;
+        let file_functions = functions.get(source).map(Vec::as_slice).unwrap_or_default();
+        for (line, name, _) in file_functions {
+            writeln!(report, "FN:{},{}", line, name)?;
+        }
+        for (_, name, hit) in file_functions {
+            writeln!(report, "FNDA:{},{}", hit, name)?;
+        }
+        writeln!(report, "FNF:{}", file_functions.len())?;
+        writeln!(report, "FNH:{}", file_functions.iter().filter(|(.., hit)| *hit > 0).count())?;
+        for (line, hit) in counts {
+            writeln!(report, "DA:{},{}", line, hit)?;
+        }
+        let hit = counts.values().filter(|&&hit| hit > 0).count();
+        writeln!(report, "LF:{}", counts.len())?;
+        writeln!(report, "LH:{}", hit)?;
+        writeln!(report, "end_of_record")?;
+        total_found += counts.len();
+        total_hit += hit;
+    }
+    Ok((total_found, total_hit))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_a_record_per_source_file() {
+        let mut lines = LineCounts::new();
+        lines.insert("/src/a.c".to_string(), BTreeMap::from([(3, 1), (4, 0), (7, 1)]));
+        lines.insert("/src/b.c".to_string(), BTreeMap::from([(1, 0)]));
+        let mut functions = FunctionCounts::new();
+        functions.insert("/src/a.c".to_string(), vec![(3, "main", 1), (7, "unused", 0)]);
+
+        let mut report = Vec::new();
+        let totals = write_lcov(&mut report, &lines, &functions).unwrap();
+        assert_eq!(totals, (4, 2));
+        let expected = "\
+TN:
+SF:/src/a.c
+FN:3,main
+FN:7,unused
+FNDA:1,main
+FNDA:0,unused
+FNF:2
+FNH:1
+DA:3,1
+DA:4,0
+DA:7,1
+LF:3
+LH:2
+end_of_record
+TN:
+SF:/src/b.c
+FNF:0
+FNH:0
+DA:1,0
+LF:1
+LH:0
+end_of_record
+";
+        assert_eq!(String::from_utf8(report).unwrap(), expected);
+    }
+
+    #[test]
+    fn writes_nothing_without_lines() {
+        let mut report = Vec::new();
+        let totals = write_lcov(&mut report, &LineCounts::new(), &FunctionCounts::new()).unwrap();
+        assert_eq!(totals, (0, 0));
+        assert!(report.is_empty());
+    }
+}
@@ -0,0 +1,553 @@
+//! A Debug Adapter Protocol server, so that editors like VS Code can drive deet. Requests,
+//! responses and events are JSON messages with a Content-Length header, exchanged over stdin and
+//! stdout. Whatever deet or the inferior prints is sent to the client as output events instead.
+
+use crate::debugger::Debugger;
+use crate::dwarf_data::TypeKind;
+use crate::expr::Evaluator;
+use crate::inferior::{StackFrame, Status};
+use crate::value::TypedValue;
+use nix::fcntl::{self, FcntlArg, OFlag};
+use nix::poll::{self, PollFd, PollFlags};
+use nix::sys::signal::Signal;
+use nix::sys::stat::Mode;
+use nix::unistd;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// deet only debugs single-threaded programs, so this is the only thread there is.
+const THREAD_ID: i64 = 1;
+
+/// The most array elements the variables view lists.
+const MAX_ELEMENTS: usize = 1000;
+
+/// Numbers the messages sent to the client and writes them out.
+struct Connection {
+    output: File,
+    seq: i64,
+}
+
+impl Connection {
+    fn send(&mut self, mut message: Value) {
+        self.seq += 1;
+        message["seq"] = json!(self.seq);
+        let body = message.to_string();
+        let _ = write!(self.output, "Content-Length: {}\r\n\r\n{}", body.len(), body);
+        let _ = self.output.flush();
+    }
+
+    fn send_event(&mut self, event: &str, body: Value) {
+        self.send(json!({ "type": "event", "event": event, "body": body }));
+    }
+}
+
+/// Forwards what deet and the inferior write to stdout to the client as output events.
+struct Relay {
+    /// The read end of the pipe that stdout goes to. Non-blocking.
+    printed: File,
+    connection: Arc<Mutex<Connection>>,
+}
+
+impl Relay {
+    /// Forwards whatever is waiting in the pipe, without waiting for more.
+    fn forward(&mut self) {
+        let mut buf = [0; 4096];
+        while let Ok(len @ 1..) = self.printed.read(&mut buf) {
+            let output = String::from_utf8_lossy(&buf[..len]);
+            let body = json!({ "category": "stdout", "output": output });
+            self.connection.lock().unwrap().send_event("output", body);
+        }
+    }
+}
+
+/// What a variablesReference handed to the client stands for.
+#[derive(Clone)]
+enum Reference {
+    /// The variables in scope in a stack frame, by its index in the last stack trace
+    Locals(usize),
+    /// The members or elements of a struct, union or array
+    Children(TypedValue),
+}
+
+/// How a request asks the inferior to run.
+enum Motion {
+    Start,
+    Continue,
+    StepOver,
+    StepIn,
+    StepOut,
+}
+
+/// What to do once a request has been answered.
+enum Then {
+    Nothing,
+    SendInitialized,
+    Run(Motion),
+    Disconnect,
+}
+
+pub struct DapServer {
+    input: BufReader<File>,
+    connection: Arc<Mutex<Connection>>,
+    relay: Arc<Mutex<Relay>>,
+    /// Created by the launch request
+    debugger: Option<Debugger>,
+    program: String,
+    /// The numbers of the breakpoints set in each source file
+    breakpoints: HashMap<String, Vec<usize>>,
+    next_breakpoint_id: i64,
+    /// The stack as of the last stackTrace request
+    frames: Vec<StackFrame>,
+    /// variablesReference n stands for references[n - 1]. Forgotten whenever the inferior runs.
+    references: Vec<Reference>,
+}
+
+/// Duplicates fd to a descriptor that the inferior won't inherit.
+fn dup_cloexec(fd: RawFd) -> Result<RawFd, nix::Error> {
+    fcntl::fcntl(fd, FcntlArg::F_DUPFD_CLOEXEC(3))
+}
+
+impl DapServer {
+    /// Takes over stdin and stdout for the protocol. The inferior gets /dev/null as its stdin,
+    /// and anything written to stdout from now on is forwarded to the client.
+    pub fn new() -> Result<DapServer, nix::Error> {
+        let input = dup_cloexec(libc::STDIN_FILENO)?;
+        let output = dup_cloexec(libc::STDOUT_FILENO)?;
+        let null = fcntl::open("/dev/null", OFlag::O_RDWR, Mode::empty())?;
+        unistd::dup2(null, libc::STDIN_FILENO)?;
+        unistd::close(null)?;
+        let (read_end, write_end) = unistd::pipe2(OFlag::O_CLOEXEC)?;
+        unistd::dup2(write_end, libc::STDOUT_FILENO)?;
+        unistd::close(write_end)?;
+
+        let connection = Arc::new(Mutex::new(Connection {
+            output: unsafe { File::from_raw_fd(output) },
+            seq: 0,
+        }));
+        let flags = OFlag::from_bits_truncate(fcntl::fcntl(read_end, FcntlArg::F_GETFL)?);
+        fcntl::fcntl(read_end, FcntlArg::F_SETFL(flags | OFlag::O_NONBLOCK))?;
+        let relay = Arc::new(Mutex::new(Relay {
+            printed: unsafe { File::from_raw_fd(read_end) },
+            connection: connection.clone(),
+        }));
+        let forwarder = relay.clone();
+        thread::spawn(move || {
+            let fd = forwarder.lock().unwrap().printed.as_raw_fd();
+            let mut fds = [PollFd::new(fd, PollFlags::POLLIN)];
+            while let Ok(_) | Err(nix::Error::EINTR) = poll::poll(&mut fds, -1) {
+                forwarder.lock().unwrap().forward();
+            }
+        });
+        Ok(DapServer {
+            input: BufReader::new(unsafe { File::from_raw_fd(input) }),
+            connection,
+            relay,
+            debugger: None,
+            program: String::new(),
+            breakpoints: HashMap::new(),
+            next_breakpoint_id: 1,
+            frames: Vec::new(),
+            references: Vec::new(),
+        })
+    }
+
+    /// Answers requests until the client disconnects or closes the connection.
+    pub fn run(&mut self) -> i32 {
+        while let Some(request) = self.read_message() {
+            let command = request["command"].as_str().unwrap_or_default().to_string();
+            let then = match self.handle(&command, &request["arguments"]) {
+                Ok((body, then)) => {
+                    self.respond(&request, true, None, body);
+                    then
+                }
+                Err(message) => {
+                    self.respond(&request, false, Some(message), Value::Null);
+                    Then::Nothing
+                }
+            };
+            match then {
+                Then::Nothing => {}
+                Then::SendInitialized => self.send_event("initialized", Value::Null),
+                Then::Run(motion) => self.run_inferior(motion),
+                Then::Disconnect => break,
+            }
+        }
+        if let Some(debugger) = &mut self.debugger {
+            debugger.kill();
+        }
+        0
+    }
+
+    /// Reads the next message, skipping any that aren't valid JSON. Returns None once the input
+    /// ends.
+    fn read_message(&mut self) -> Option<Value> {
+        loop {
+            let mut length = None;
+            loop {
+                let mut line = String::new();
+                if self.input.read_line(&mut line).ok()? == 0 {
+                    return None;
+                }
+                let line = line.trim_end();
+                if line.is_empty() && length.is_some() {
+                    break;
+                }
+                if let Some(value) = line.strip_prefix("Content-Length:") {
+                    length = value.trim().parse::<usize>().ok();
+                }
+            }
+            let mut body = vec![0; length?];
+            self.input.read_exact(&mut body).ok()?;
+            if let Ok(message) = serde_json::from_slice(&body) {
+                return Some(message);
+            }
+        }
+    }
+
+    fn respond(&self, request: &Value, success: bool, message: Option<String>, body: Value) {
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "success": success,
+            "command": request["command"],
+            "body": body,
+        });
+        if let Some(message) = message {
+            response["message"] = json!(message);
+        }
+        self.connection.lock().unwrap().send(response);
+    }
+
+    fn send_event(&self, event: &str, body: Value) {
+        self.connection.lock().unwrap().send_event(event, body);
+    }
+
+    /// Carries out a request, returning the body of the response.
+    fn handle(&mut self, command: &str, args: &Value) -> Result<(Value, Then), String> {
+        Ok(match command {
+            "initialize" => {
+                let capabilities = json!({
+                    "supportsConfigurationDoneRequest": true,
+                    "supportsEvaluateForHovers": true,
+                });
+                (capabilities, Then::SendInitialized)
+            }
+            "launch" => {
+                let program = args["program"].as_str().ok_or("No program given to launch.")?;
+                if !Path::new(program).is_file() {
+                    return Err(format!("{}: No such file", program));
+                }
+                self.program = program.to_string();
+                let mut debugger = Debugger::new(program);
+                let launch = debugger.launch_options();
+                launch.args = args["args"]
+                    .as_array()
+                    .map(|args| args.iter().filter_map(Value::as_str).map(str::to_string).collect())
+                    .unwrap_or_default();
+                if let Some(cwd) = args["cwd"].as_str() {
+                    launch.cwd = Some(cwd.to_string());
+                }
+                // A null value removes the variable
+                if let Some(env) = args["env"].as_object() {
+                    for (name, value) in env {
+                        match value.as_str() {
+                            Some(value) => launch.environment.insert(name.clone(), value.into()),
+                            None => launch.environment.remove(name),
+                        };
+                    }
+                }
+                self.debugger = Some(debugger);
+                (Value::Null, Then::Nothing)
+            }
+            "setBreakpoints" => (self.set_breakpoints(args)?, Then::Nothing),
+            "setExceptionBreakpoints" => (json!({ "breakpoints": [] }), Then::Nothing),
+            "configurationDone" => (Value::Null, Then::Run(Motion::Start)),
+            "threads" => {
+                let name = Path::new(&self.program)
+                    .file_name()
+                    .map(|name| name.to_string_lossy().to_string())
+                    .unwrap_or_default();
+                (json!({ "threads": [{ "id": THREAD_ID, "name": name }] }), Then::Nothing)
+            }
+            "stackTrace" => (self.stack_trace(args)?, Then::Nothing),
+            "scopes" => {
+                let frame = args["frameId"].as_u64().ok_or("No frameId given.")? as usize;
+                if frame >= self.frames.len() {
+                    return Err(format!("No frame {}.", frame));
+                }
+                self.references.push(Reference::Locals(frame));
+                let scope = json!({
+                    "name": "Locals",
+                    "variablesReference": self.references.len(),
+                    "expensive": false,
+                });
+                (json!({ "scopes": [scope] }), Then::Nothing)
+            }
+            "variables" => (self.variables(args)?, Then::Nothing),
+            "evaluate" => {
+                let expression = args["expression"].as_str().ok_or("No expression given.")?;
+                let debugger = self.debugger.as_mut().ok_or("No program has been launched.")?;
+                let mut evaluator = debugger.evaluator()?;
+                let value = evaluator.evaluate(expression)?;
+                let variable = describe("", value, &evaluator, &mut self.references);
+                let body = json!({
+                    "result": variable["value"],
+                    "type": variable["type"],
+                    "variablesReference": variable["variablesReference"],
+                });
+                (body, Then::Nothing)
+            }
+            "continue" => (json!({ "allThreadsContinued": true }), Then::Run(Motion::Continue)),
+            "next" => (Value::Null, Then::Run(Motion::StepOver)),
+            "stepIn" => (Value::Null, Then::Run(Motion::StepIn)),
+            "stepOut" => (Value::Null, Then::Run(Motion::StepOut)),
+            "disconnect" => (Value::Null, Then::Disconnect),
+            _ => return Err(format!("Unsupported request \"{}\".", command)),
+        })
+    }
+
+    /// Replaces the breakpoints in a source file.
+    fn set_breakpoints(&mut self, args: &Value) -> Result<Value, String> {
+        let path = args["source"]["path"].as_str().ok_or("No source path given.")?.to_string();
+        let lines: Vec<u64> = match args["breakpoints"].as_array() {
+            Some(breakpoints) => breakpoints.iter().filter_map(|bp| bp["line"].as_u64()).collect(),
+            None => args["lines"]
+                .as_array()
+                .map(|lines| lines.iter().filter_map(Value::as_u64).collect())
+                .unwrap_or_default(),
+        };
+        let debugger = self.debugger.as_mut().ok_or("No program has been launched.")?;
+        for number in self.breakpoints.remove(&path).unwrap_or_default() {
+            debugger.delete_breakpoint(number);
+        }
+        let mut numbers = Vec::new();
+        let mut breakpoints = Vec::new();
+        for line in lines {
+            let id = self.next_breakpoint_id;
+            self.next_breakpoint_id += 1;
+            match debugger.set_breakpoint(&format!("{}:{}", path, line)) {
+                Ok((number, line_addrs)) => {
+                    let actual = line_addrs
+                        .first()
+                        .and_then(|addr| debugger.debug_data().get_line_from_addr(*addr))
+                        .map_or(line as usize, |line| line.number);
+                    numbers.push(number);
+                    breakpoints.push(json!({ "id": id, "verified": true, "line": actual }));
+                }
+                Err(err) => breakpoints.push(json!({
+                    "id": id,
+                    "verified": false,
+                    "line": line,
+                    "message": err.to_string(),
+                })),
+            }
+        }
+        self.breakpoints.insert(path, numbers);
+        Ok(json!({ "breakpoints": breakpoints }))
+    }
+
+    fn stack_trace(&mut self, args: &Value) -> Result<Value, String> {
+        let debugger = self.debugger.as_mut().ok_or("No program has been launched.")?;
+        let inferior = debugger.inferior().ok_or("The program is not being run.")?;
+        let frames = inferior.backtrace(debugger.debug_data()).map_err(|err| err.to_string())?;
+        let start = args["startFrame"].as_u64().unwrap_or(0) as usize;
+        let levels = match args["levels"].as_u64() {
+            Some(levels) if levels > 0 => levels as usize,
+            _ => frames.len(),
+        };
+        let stack_frames: Vec<Value> = frames
+            .iter()
+            .enumerate()
+            .skip(start)
+            .take(levels)
+            .map(|(id, stack_frame)| {
+                let mut json = json!({
+                    "id": id,
+                    "name": stack_frame.frame.function,
+                    "line": 0,
+                    "column": 0,
+                    "instructionPointerReference": format!("{:#x}", stack_frame.pc),
+                });
+                if let Some(line) = &stack_frame.frame.line {
+                    let name = Path::new(&line.file).file_name().map(|name| name.to_string_lossy());
+                    json["source"] = json!({ "name": name, "path": line.file });
+                    json["line"] = json!(line.number);
+                    json["column"] = json!(1);
+                }
+                json
+            })
+            .collect();
+        let total = frames.len();
+        self.frames = frames;
+        Ok(json!({ "stackFrames": stack_frames, "totalFrames": total }))
+    }
+
+    fn variables(&mut self, args: &Value) -> Result<Value, String> {
+        let reference = args["variablesReference"].as_u64().unwrap_or(0) as usize;
+        let target = reference.checked_sub(1).and_then(|index| self.references.get(index)).cloned();
+        let debugger = self.debugger.as_mut().ok_or("No program has been launched.")?;
+        let evaluator = debugger.evaluator()?;
+        let mut variables = Vec::new();
+        match target {
+            Some(Reference::Locals(frame)) => {
+                let stack_frame = &self.frames[frame];
+                let func = evaluator.debug_data().get_function_containing(stack_frame.pc);
+                let locals = func.map(|func| func.variables_at(stack_frame.pc)).unwrap_or_default();
+                let mut values = Vec::new();
+                for var in locals {
+                    values.push((
+                        var.name.clone(),
+                        evaluator.frame_variable_value(var, stack_frame.base_ptr),
+                    ));
+                }
+                for (name, value) in values {
+                    variables.push(match value {
+                        Ok(value) => describe(&name, value, &evaluator, &mut self.references),
+                        Err(err) => json!({ "name": name, "value": err, "variablesReference": 0 }),
+                    });
+                }
+            }
+            Some(Reference::Children(value)) => {
+                for (name, child) in children(&value, &evaluator) {
+                    variables.push(match child {
+                        Ok(child) => describe(&name, child, &evaluator, &mut self.references),
+                        Err(err) => json!({ "name": name, "value": err, "variablesReference": 0 }),
+                    });
+                }
+            }
+            None => return Err(format!("No variables reference {}.", reference)),
+        }
+        Ok(json!({ "variables": variables }))
+    }
+
+    /// Lets the inferior run as the client asked, then tells the client why it stopped.
+    fn run_inferior(&mut self, motion: Motion) {
+        self.frames.clear();
+        self.references.clear();
+        let connection = self.connection.clone();
+        let send_event = |event: &str, body: Value| {
+            connection.lock().unwrap().send_event(event, body);
+        };
+        // The inferior's last output may still be in the pipe, and must reach the client before
+        // it hears that the program exited
+        let relay = self.relay.clone();
+        let drain_output = || {
+            let _ = std::io::stdout().flush();
+            relay.lock().unwrap().forward();
+        };
+        let debugger = match &mut self.debugger {
+            Some(debugger) => debugger,
+            None => return,
+        };
+        let (result, reason) = match motion {
+            Motion::Start => (debugger.start(), "breakpoint"),
+            Motion::Continue => (debugger.resume(), "breakpoint"),
+            Motion::StepOver => (debugger.step_line(true), "step"),
+            Motion::StepIn => (debugger.step_line(false), "step"),
+            Motion::StepOut => (debugger.finish(), "step"),
+        };
+        let status = match result {
+            Ok(status) => status,
+            Err(err) => {
+                let body = json!({ "category": "stderr", "output": format!("{}\n", err) });
+                send_event("output", body);
+                if debugger.inferior().is_some() {
+                    let body = json!({ "reason": "pause", "threadId": THREAD_ID });
+                    send_event("stopped", body);
+                } else {
+                    drain_output();
+                    send_event("terminated", Value::Null);
+                }
+                return;
+            }
+        };
+        debugger.update_state(&status);
+        match status {
+            Status::Stopped(signal, _) => {
+                let body = if signal == Signal::SIGTRAP {
+                    json!({ "reason": reason, "threadId": THREAD_ID, "allThreadsStopped": true })
+                } else {
+                    json!({
+                        "reason": "exception",
+                        "text": signal.to_string(),
+                        "threadId": THREAD_ID,
+                        "allThreadsStopped": true,
+                    })
+                };
+                send_event("stopped", body);
+            }
+            Status::Syscall(_) => {
+                let body = json!({ "reason": "step", "threadId": THREAD_ID });
+                send_event("stopped", body);
+            }
+            Status::Exited(code) => {
+                drain_output();
+                send_event("exited", json!({ "exitCode": code }));
+                send_event("terminated", Value::Null);
+            }
+            Status::Signaled(signal) => {
+                let output = format!("Child exited due to signal {}\n", signal);
+                drain_output();
+                send_event("output", json!({ "category": "console", "output": output }));
+                send_event("exited", json!({ "exitCode": 128 + signal as i32 }));
+                send_event("terminated", Value::Null);
+            }
+        }
+    }
+}
+
+/// Describes a value for the variables view, remembering how to list its members or elements
+/// if it has any.
+fn describe(
+    name: &str,
+    value: TypedValue,
+    evaluator: &Evaluator,
+    references: &mut Vec<Reference>,
+) -> Value {
+    let formatted = evaluator.format(&value);
+    let type_name = value.value_type.name.clone();
+    let reference = match value.value_type.kind {
+        TypeKind::Struct(_) | TypeKind::Union(_) | TypeKind::Array(_, _) => {
+            references.push(Reference::Children(value));
+            references.len()
+        }
+        _ => 0,
+    };
+    json!({
+        "name": name,
+        "value": formatted,
+        "type": type_name,
+        "variablesReference": reference,
+    })
+}
+
+/// Returns the members of a struct or union, or the elements of an array.
+fn children(
+    value: &TypedValue,
+    evaluator: &Evaluator,
+) -> Vec<(String, Result<TypedValue, String>)> {
+    match &value.value_type.kind {
+        TypeKind::Struct(members) | TypeKind::Union(members) => members
+            .iter()
+            .map(|member| (member.name.clone(), evaluator.member(value.clone(), &member.name)))
+            .collect(),
+        TypeKind::Array(element, len) => (0..*len.min(&MAX_ELEMENTS))
+            .map(|i| {
+                let element = match value.address {
+                    Some(addr) => {
+                        evaluator.read_value(addr + i * element.size, (**element).clone())
+                    }
+                    None => Err("Array is not in memory".to_string()),
+                };
+                (format!("[{}]", i), element)
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
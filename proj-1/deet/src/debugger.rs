@@ -1,116 +1,1857 @@
 use std::collections::HashMap;
-use crate::debugger_command::DebuggerCommand;
-use crate::dwarf_data::{DwarfData, Error as DwarfError};
+use crate::completion::DeetHelper;
+use crate::debugger_command::{self, DebuggerCommand, COMMANDS};
+use crate::dwarf_data::{
+    self, DwarfData, Error as DwarfError, Function, Line, Location, Type, TypeKind,
+};
+use crate::expr::{self, Evaluator};
+use crate::heap;
 use crate::inferior::{Inferior, Status};
+use crate::launch::LaunchOptions;
+use crate::printers::{Printers, PRINTER_NAMES};
+use crate::procfs;
+use crate::record::Recorder;
+use crate::settings::{Settings, OPTION_NAMES};
+use crate::symbols::Symbol;
+use crate::tui::{self, Pane, Panes, Tui};
+use crate::value::{self, TypedValue, Value};
+use nix::sys::signal::Signal;
+use regex::Regex;
 use rustyline::error::ReadlineError;
 use rustyline::Editor;
 use rustyline::history::FileHistory;
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::time::SystemTime;
 
 
 #[derive(Clone)]
+#[allow(dead_code)]
 pub struct Breakpoint {
     pub addr: usize,
     pub orig_byte: u8,
 }
 
 
+/// Everything that can keep a command from being carried out. These are reported at the prompt,
+/// and the session carries on.
+#[derive(Debug)]
+pub enum DebuggerError {
+    /// A ptrace or waitpid call on the inferior failed
+    Ptrace(nix::Error),
+    /// Reading from /proc failed
+    Io(std::io::Error),
+    /// Nothing in the debugging information matches what was asked for
+    Dwarf(String),
+    /// The command line couldn't be parsed
+    Parse(String),
+    /// The command doesn't make sense right now, e.g. there's no inferior to act on
+    State(String),
+    /// An expression couldn't be evaluated
+    Expression(String),
+}
+
+impl fmt::Display for DebuggerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DebuggerError::Ptrace(err) => write!(f, "ptrace: {}", err),
+            DebuggerError::Io(err) => write!(f, "{}", err),
+            DebuggerError::Dwarf(msg)
+            | DebuggerError::Parse(msg)
+            | DebuggerError::State(msg)
+            | DebuggerError::Expression(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl From<nix::Error> for DebuggerError {
+    fn from(err: nix::Error) -> Self {
+        DebuggerError::Ptrace(err)
+    }
+}
+
+impl From<std::io::Error> for DebuggerError {
+    fn from(err: std::io::Error) -> Self {
+        DebuggerError::Io(err)
+    }
+}
+
+/// The lines of /proc/<pid>/status that "info proc" shows.
+const PROC_STATUS_FIELDS: &[&str] = &["State", "PPid", "Threads", "VmPeak", "VmSize", "VmRSS"];
+
+fn not_running() -> DebuggerError {
+    DebuggerError::State("The program is not being run.".to_string())
+}
+
+fn not_recording() -> DebuggerError {
+    DebuggerError::State("The program is not being recorded.".to_string())
+}
+
+/// Returns a C declaration of a function called name, e.g. "int add(int, int)".
+fn function_declaration(func: &Function, name: &str) -> String {
+    let mut parameters: Vec<&str> =
+        func.parameters().map(|param| param.entity_type.name.as_str()).collect();
+    if parameters.is_empty() {
+        parameters.push("void");
+    }
+    let declarator = format!("{}({})", name, parameters.join(", "));
+    match &func.return_type {
+        Some(return_type) => return_type.declaration(&declarator),
+        None => format!("void {}", declarator),
+    }
+}
+
+fn compile_regex(pattern: &str) -> Result<Regex, DebuggerError> {
+    Regex::new(pattern).map_err(|err| DebuggerError::Parse(format!("Invalid regexp: {}", err)))
+}
+
+/// Prints the (file, name, line, declaration) of functions or variables, grouped by file.
+fn print_declarations(
+    kind: &str,
+    pattern: &str,
+    mut declarations: Vec<(&str, &str, usize, String)>,
+) {
+    if pattern.is_empty() {
+        println!("All defined {}:", kind);
+    } else {
+        println!("All {} matching regular expression \"{}\":", kind, pattern);
+    }
+    declarations.sort();
+    let mut current_file = None;
+    for (file, _, line, declaration) in declarations {
+        if current_file != Some(file) {
+            println!();
+            println!("File {}:", file);
+            current_file = Some(file);
+        }
+        println!("{}:\t{};", line, declaration);
+    }
+}
+
+/// Splits a location like "file.c:12" or "file.c:main" into the file and the rest. The colons of
+/// C++ and Rust paths like "ns::helper" don't count.
+fn split_file_location(location: &str) -> Option<(&str, &str)> {
+    let mut rest = location;
+    let mut start = 0;
+    while let Some(i) = rest.find(':') {
+        if rest[i + 1..].starts_with(':') {
+            start += i + 2;
+            rest = &rest[i + 2..];
+            continue;
+        }
+        return Some((&location[..start + i], &location[start + i + 1..]));
+    }
+    None
+}
+
+/// Lists symbol table entries that have no debugging information, in order of address.
+fn print_non_debugging_symbols(symbols: Vec<&Symbol>) {
+    if symbols.is_empty() {
+        return;
+    }
+    println!();
+    println!("Non-debugging symbols:");
+    for symbol in symbols {
+        println!("{:#018x}  {}", symbol.address, symbol.name);
+    }
+}
+
+/// The longest an x86-64 instruction can be.
+const MAX_INSTRUCTION_LENGTH: usize = 15;
+
+/// The most code the TUI's disassembly pane reads at once.
+const MAX_DISASSEMBLY_BYTES: usize = 4096;
+
+/// How deeply user-defined commands and aliases may expand into each other.
+const MAX_USER_CALL_DEPTH: usize = 64;
+
+/// Replaces $argc and $arg0, $arg1, ... in a line of a user-defined command.
+fn substitute_args(line: &str, args: &[&str]) -> Result<String, DebuggerError> {
+    let mut out = String::new();
+    let mut rest = line;
+    while let Some(i) = rest.find("$arg") {
+        out.push_str(&rest[..i]);
+        rest = &rest[i + "$arg".len()..];
+        if let Some(after) = rest.strip_prefix('c') {
+            out.push_str(&args.len().to_string());
+            rest = after;
+            continue;
+        }
+        let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+        if digits == 0 {
+            out.push_str("$arg");
+            continue;
+        }
+        let missing = || {
+            let index = &rest[..digits];
+            DebuggerError::State(format!("Missing argument {} in user function.", index))
+        };
+        let index: usize = rest[..digits].parse().map_err(|_| missing())?;
+        out.push_str(args.get(index).ok_or_else(missing)?);
+        rest = &rest[digits..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// What deet does when the inferior hits a breakpoint. Several actions can share one address.
+#[derive(Clone, PartialEq)]
+pub enum BreakpointAction {
+    /// Stop and return to the prompt (a regular breakpoint)
+    Stop,
+    /// Print a formatted message and keep going
+    Dprintf { format: String, args: Vec<String> },
+    /// Print the arguments a traced function was called with and keep going
+    TraceEntry(String),
+    /// Print the return of traced functions that return to this address and keep going
+    TraceReturn,
+    /// Stop, for a breakpoint that deet sets for itself and removes once the inferior stops
+    Temporary,
+    /// Stop and delete the breakpoint with this number, for a tbreak
+    Tbreak(usize),
+}
+
+/// A breakpoint, dprintf or tracepoint that the user set, remembered by its location so that it
+/// can be found again in a rebuilt target.
+struct UserBreakpoint {
+    number: usize,
+    location: String,
+    action: BreakpointAction,
+    /// Where location is in the current build
+    addrs: Vec<usize>,
+}
+
+/// A call to a traced function that hasn't returned yet.
+struct TraceFrame {
+    function: String,
+    return_type: Option<Type>,
+    /// Canonical frame address of the call; %rsp is back to this value once it returns
+    cfa: usize,
+    return_addr: usize,
+}
+
+/// A forked copy of the inferior, kept stopped so that execution can be restarted from it later.
+struct Checkpoint {
+    number: usize,
+    process: Inferior,
+    /// Where the copy is stopped
+    addr: usize,
+    /// The breakpoints that were installed in the copy's memory when it was made
+    breakpoints: Vec<Breakpoint>,
+}
+
 // impl Breakpoint {
 //     pub fn new(addr: usize, orig_byte: u8) -> Breakpoint {
 //         Breakpoint{addr, orig_byte}
 //     }
 // }
 
-pub struct Debugger {
-    target: String,
-    history_path: String,
-    readline: Editor<(), FileHistory>,
-    inferior: Option<Inferior>,
-    breakpoints: HashMap<usize, Option<Breakpoint>>,
-    debug_data: DwarfData,
-}
+/// Describes why target's debugging symbols couldn't be loaded.
+fn describe_load_error(target: &str, err: DwarfError) -> String {
+    match err {
+        DwarfError::ErrorOpeningFile => format!("Could not open file {}", target),
+        DwarfError::DwarfFormatError(err) => {
+            format!("Could not load debugging symbols from {}: {:?}", target, err)
+        }
+    }
+}
+
+/// Returns when target was last modified.
+fn modified_time(target: &str) -> Option<SystemTime> {
+    fs::metadata(target).and_then(|metadata| metadata.modified()).ok()
+}
+
+/// Loads the debugging symbols for target, exiting the process if they can't be read.
+pub fn load_debug_data(target: &str) -> DwarfData {
+    match DwarfData::from_file(target) {
+        Ok(val) => val,
+        Err(err) => {
+            println!("{}", describe_load_error(target, err));
+            std::process::exit(1);
+        }
+    }
+}
+
+pub struct Debugger {
+    target: String,
+    history_path: String,
+    readline: Editor<DeetHelper, FileHistory>,
+    inferior: Option<Inferior>,
+    breakpoints: HashMap<usize, Option<Breakpoint>>,
+    breakpoint_actions: HashMap<usize, Vec<BreakpointAction>>,
+    user_breakpoints: Vec<UserBreakpoint>,
+    next_breakpoint_number: usize,
+    trace_frames: Vec<TraceFrame>,
+    value_history: Vec<TypedValue>,
+    /// Expressions printed every time the inferior stops, with their display numbers
+    displays: Vec<(usize, String)>,
+    next_display_number: usize,
+    /// Present while the inferior is being recorded; breakpoints aren't in its memory meanwhile
+    recorder: Option<Recorder>,
+    checkpoints: Vec<Checkpoint>,
+    next_checkpoint_number: usize,
+    /// Lines of the files being sourced, innermost last
+    scripts: Vec<VecDeque<String>>,
+    /// Commands made with define, and the lines of their bodies
+    user_commands: HashMap<String, Vec<String>>,
+    aliases: HashMap<String, String>,
+    settings: Settings,
+    /// The arguments, environment and so on that the inferior is run with
+    launch: LaunchOptions,
+    printers: Printers,
+    /// Set by quit, even if it comes from a script or user-defined command
+    exiting: bool,
+    /// Present while the panes of the text user interface are shown
+    tui: Option<Tui>,
+    debug_data: DwarfData,
+    /// When the target was last modified and its build ID, as of loading debug_data
+    target_modified: Option<SystemTime>,
+    build_id: Option<Vec<u8>>,
+}
+
+impl Debugger {
+    /// Initializes the debugger, running the commands in the init files.
+    pub fn new(target: &str) -> Debugger {
+        let mut debugger = Debugger::without_init_files(target);
+        let home = std::env::var("HOME").unwrap_or_else(|_| ".".to_string());
+        debugger.load_init_files(&home);
+        debugger
+    }
+
+    /// Initializes the debugger without reading ~/.deetinit or ./.deetinit.
+    pub fn without_init_files(target: &str) -> Debugger {
+        // (milestone 3): initialize the DwarfData
+        let debug_data = load_debug_data(target);
+
+        let home = std::env::var("HOME").unwrap_or_else(|_| ".".to_string());
+        let history_path = format!("{}/.deet_history", home);
+        let mut readline = Editor::<DeetHelper, FileHistory>::new().expect("Create Editor fail");
+        readline.set_helper(Some(DeetHelper::new(&debug_data)));
+        // Attempt to load history from ~/.deet_history if it exists
+        let _ = readline.load_history(&history_path);
+
+        let breakpoints = HashMap::new();
+
+        Debugger {
+            target: target.to_string(),
+            history_path,
+            readline,
+            inferior: None,
+            debug_data,
+            breakpoints,
+            breakpoint_actions: HashMap::new(),
+            user_breakpoints: Vec::new(),
+            next_breakpoint_number: 0,
+            trace_frames: Vec::new(),
+            value_history: Vec::new(),
+            displays: Vec::new(),
+            next_display_number: 1,
+            recorder: None,
+            checkpoints: Vec::new(),
+            next_checkpoint_number: 1,
+            scripts: Vec::new(),
+            user_commands: HashMap::new(),
+            aliases: HashMap::new(),
+            settings: Settings::default(),
+            launch: LaunchOptions::default(),
+            printers: Printers::default(),
+            exiting: false,
+            tui: None,
+            target_modified: modified_time(target),
+            build_id: dwarf_data::read_build_id(target),
+        }
+    }
+
+    /// Runs ~/.deetinit, and then ./.deetinit if that's a different file.
+    fn load_init_files(&mut self, home: &str) {
+        let global = Path::new(home).join(".deetinit");
+        let local = Path::new(".deetinit");
+        let mut paths = vec![global.as_path()];
+        if fs::canonicalize(local).ok() != fs::canonicalize(&global).ok() {
+            paths.push(local);
+        }
+        for path in paths {
+            if path.exists() {
+                if let Err(err) = self.source(&path.to_string_lossy()) {
+                    println!("{}", err);
+                }
+            }
+        }
+    }
+
+    fn parse_address(&self, address: &str) -> Option<usize> {
+        let lower = address.to_lowercase();
+        if let Some(addr) = lower.strip_prefix("0x").or_else(|| lower.strip_prefix("*0x")) {
+            // b 0x123456 or b *0x123456
+            usize::from_str_radix(addr, 16).ok()
+        } else if let Some((file, location)) = split_file_location(address) {
+            // b file.c:12 or b file.c:function_name
+            match location.parse::<usize>() {
+                Ok(line_number) => self.debug_data.get_addr_for_line(Some(file), line_number),
+                Err(_) => self.debug_data.get_addr_for_function(Some(file), location),
+            }
+        } else if let Ok(line_number) = address.parse::<usize>() {
+            // b line_number
+            self.debug_data.get_addr_for_line(None, line_number)
+        } else {
+            // b function_name
+            self.debug_data.get_addr_for_function(None, address)
+        }
+    }
+
+    /// Resolves a location for breakpoints that need to inspect the frame they stop in. Function
+    /// names resolve to the first line after the prologue, so that arguments can be read.
+    fn parse_code_location(&self, location: &str) -> Result<usize, DebuggerError> {
+        let name = split_file_location(location).map_or(location, |(_, name)| name);
+        if let Some(func) = self.debug_data.get_function(name) {
+            return Ok(self.debug_data.get_addr_after_prologue(func));
+        }
+        match self.debug_data.get_symbol(name, true) {
+            Some(symbol) if name == location => Ok(symbol.after_prologue),
+            _ => self.parse_address(location).ok_or_else(|| {
+                DebuggerError::Dwarf(format!("No line or function matches \"{}\".", location))
+            }),
+        }
+    }
+
+    /// Resolves a location like parse_code_location does, except that a function whose calls were
+    /// inlined also resolves to the start of each inlined copy of its body.
+    fn parse_code_locations(&self, location: &str) -> Result<Vec<usize>, DebuggerError> {
+        let name = split_file_location(location).map_or(location, |(_, name)| name);
+        let inlined_calls = self.debug_data.get_inlined_calls(name);
+        if inlined_calls.is_empty() {
+            return Ok(vec![self.parse_code_location(location)?]);
+        }
+        let mut addrs: Vec<usize> = self
+            .debug_data
+            .get_function(name)
+            .map(|func| self.debug_data.get_addr_after_prologue(func))
+            .into_iter()
+            .chain(inlined_calls.iter().map(|call| call.address))
+            .collect();
+        addrs.sort();
+        addrs.dedup();
+        Ok(addrs)
+    }
+
+    /// Sets a breakpoint at every address location stands for, returning its number and the
+    /// addresses.
+    pub fn set_breakpoint(&mut self, location: &str) -> Result<(usize, Vec<usize>), DebuggerError> {
+        self.add_user_breakpoint(location, BreakpointAction::Stop)
+    }
+
+    /// Sets a temporary breakpoint at every address location stands for, returning its number and
+    /// the addresses.
+    fn set_temporary_breakpoint(
+        &mut self,
+        location: &str,
+    ) -> Result<(usize, Vec<usize>), DebuggerError> {
+        self.add_user_breakpoint(location, BreakpointAction::Tbreak(self.next_breakpoint_number))
+    }
+
+    /// Gives action a breakpoint number, and adds it at every address location stands for.
+    /// Returns the number and the addresses.
+    fn add_user_breakpoint(
+        &mut self,
+        location: &str,
+        action: BreakpointAction,
+    ) -> Result<(usize, Vec<usize>), DebuggerError> {
+        let addrs = self.resolve_breakpoint(location, &action)?;
+        for &addr in &addrs {
+            self.add_breakpoint_action(addr, action.clone())?;
+        }
+        let number = self.next_breakpoint_number;
+        self.next_breakpoint_number += 1;
+        self.user_breakpoints.push(UserBreakpoint {
+            number,
+            location: location.to_string(),
+            action,
+            addrs: addrs.clone(),
+        });
+        Ok((number, addrs))
+    }
+
+    /// Finds the addresses of a user breakpoint's location. Tracepoints are always on a function,
+    /// after its prologue.
+    fn resolve_breakpoint(
+        &self,
+        location: &str,
+        action: &BreakpointAction,
+    ) -> Result<Vec<usize>, DebuggerError> {
+        match action {
+            BreakpointAction::TraceEntry(function) => {
+                let func = self.debug_data.get_function(function).ok_or_else(|| {
+                    DebuggerError::Dwarf(format!("No function named \"{}\".", function))
+                })?;
+                Ok(vec![self.debug_data.get_addr_after_prologue(func)])
+            }
+            _ => self.parse_code_locations(location),
+        }
+    }
+
+    /// Deletes the user breakpoint with this number, leaving any other actions at its addresses
+    /// in place. Returns false if there is no such breakpoint.
+    pub fn delete_breakpoint(&mut self, number: usize) -> bool {
+        let index = match self.user_breakpoints.iter().position(|b| b.number == number) {
+            Some(index) => index,
+            None => return false,
+        };
+        let UserBreakpoint { action: deleted, addrs, .. } = self.user_breakpoints.remove(index);
+        for addr in addrs {
+            // Only one of the actions, since another breakpoint may have an identical one here
+            let mut removed = false;
+            self.remove_breakpoint_actions(addr, |action| {
+                let matched = !removed && *action == deleted;
+                removed |= matched;
+                matched
+            });
+        }
+        true
+    }
+
+    /// Deletes the user breakpoints with these numbers, or every one (after asking) if no
+    /// numbers are given.
+    fn delete_breakpoints(&mut self, numbers: Vec<usize>) {
+        if numbers.is_empty() {
+            if !self.user_breakpoints.is_empty() && self.confirm("Delete all breakpoints?") {
+                let numbers: Vec<usize> = self.user_breakpoints.iter().map(|b| b.number).collect();
+                for number in numbers {
+                    self.delete_breakpoint(number);
+                }
+            }
+            return;
+        }
+        for number in numbers {
+            if !self.delete_breakpoint(number) {
+                println!("No breakpoint number {}.", number);
+            }
+        }
+    }
+
+    /// Lists the breakpoints, tbreaks, dprintfs and tracepoints the user has set. A breakpoint
+    /// at several addresses gets a row for each, numbered like 1.1, 1.2, ...
+    fn info_breakpoints(&self) {
+        if self.user_breakpoints.is_empty() {
+            println!("No breakpoints or watchpoints.");
+            return;
+        }
+        println!("Num     Type           Disp Enb Address            What");
+        let row = |number: &str, kind: &str, disposition: &str, address: &str, what: &str| {
+            println!("{:<7} {:<14} {:<4} y   {:<18} {}", number, kind, disposition, address, what)
+        };
+        for breakpoint in &self.user_breakpoints {
+            let (kind, disposition) = match &breakpoint.action {
+                BreakpointAction::Tbreak(_) => ("breakpoint", "del"),
+                BreakpointAction::Dprintf { .. } => ("dprintf", "keep"),
+                BreakpointAction::TraceEntry(_) => ("tracepoint", "keep"),
+                _ => ("breakpoint", "keep"),
+            };
+            let number = breakpoint.number.to_string();
+            match breakpoint.addrs[..] {
+                [] => row(&number, kind, disposition, "<PENDING>", &breakpoint.location),
+                [addr] => {
+                    let address = format!("{:#018x}", addr);
+                    row(&number, kind, disposition, &address, &self.describe_code(addr))
+                }
+                _ => {
+                    row(&number, kind, disposition, "<MULTIPLE>", "");
+                    for (i, &addr) in breakpoint.addrs.iter().enumerate() {
+                        let number = format!("{}.{}", number, i + 1);
+                        let address = format!("{:#018x}", addr);
+                        row(&number, "", "", &address, &self.describe_code(addr));
+                    }
+                }
+            }
+            if let BreakpointAction::Dprintf { format, args } = &breakpoint.action {
+                let args: String = args.iter().map(|arg| format!(",{}", arg)).collect();
+                println!("        printf \"{}\"{}", format.escape_default(), args);
+            }
+        }
+    }
+
+    /// Describes where an address in the target's code is, like "in main at hello.c:5".
+    fn describe_code(&self, addr: usize) -> String {
+        let function = match self.debug_data.get_function_containing(addr) {
+            Some(func) => Some(func.name.clone()),
+            None => self.debug_data.get_symbol_containing(addr).map(|symbol| symbol.name.clone()),
+        };
+        match (function, self.line_at(addr)) {
+            (Some(function), Some(line)) => format!("in {} at {}", function, line),
+            (Some(function), None) => format!("in {}", function),
+            (None, Some(line)) => format!("at {}", line),
+            (None, None) => String::new(),
+        }
+    }
+
+    /// Rereads the debugging information if the target has been rebuilt since it was loaded,
+    /// and moves the user breakpoints to wherever their locations are in the new build. The
+    /// inferior must not be running.
+    fn reload_if_rebuilt(&mut self) -> Result<(), DebuggerError> {
+        let modified = modified_time(&self.target);
+        if modified == self.target_modified {
+            return Ok(());
+        }
+        let build_id = dwarf_data::read_build_id(&self.target);
+        self.target_modified = modified;
+        if build_id.is_some() && build_id == self.build_id {
+            return Ok(());
+        }
+        println!("`{}' has changed; re-reading symbols.", self.target);
+        self.debug_data = DwarfData::from_file(&self.target)
+            .map_err(|err| DebuggerError::State(describe_load_error(&self.target, err)))?;
+        self.build_id = build_id;
+        self.readline.set_helper(Some(DeetHelper::new(&self.debug_data)));
+        self.update_command_names();
+        if !self.checkpoints.is_empty() {
+            println!("Deleting the checkpoints of the old build.");
+            for mut checkpoint in self.checkpoints.drain(..) {
+                let _ = checkpoint.process.kill();
+            }
+        }
+        self.breakpoints.clear();
+        self.breakpoint_actions.clear();
+        for mut breakpoint in std::mem::take(&mut self.user_breakpoints) {
+            breakpoint.addrs = self
+                .resolve_breakpoint(&breakpoint.location, &breakpoint.action)
+                .unwrap_or_else(|err| {
+                    println!("Breakpoint {} is not set: {}", breakpoint.number, err);
+                    Vec::new()
+                });
+            for &addr in &breakpoint.addrs {
+                self.add_breakpoint_action(addr, breakpoint.action.clone())?;
+            }
+            self.user_breakpoints.push(breakpoint);
+        }
+        Ok(())
+    }
+
+    /// Adds an action to the breakpoint at addr, installing the breakpoint if it's new.
+    fn add_breakpoint_action(
+        &mut self,
+        addr: usize,
+        action: BreakpointAction,
+    ) -> Result<(), DebuggerError> {
+        if !self.breakpoints.contains_key(&addr) {
+            if let Some(inferior) = &mut self.inferior {
+                let orig_byte = if self.recorder.is_some() {
+                    inferior.read_bytes(addr, 1)?[0]
+                } else {
+                    inferior.write_byte(addr, 0xcc)?
+                };
+                self.breakpoints.insert(addr, Some(Breakpoint { addr, orig_byte }));
+            } else {
+                self.breakpoints.insert(addr, None);
+            }
+        }
+        self.breakpoint_actions.entry(addr).or_default().push(action);
+        Ok(())
+    }
+
+    /// Removes the actions at addr that match pred, and the breakpoint itself if no actions are
+    /// left.
+    fn remove_breakpoint_actions<P>(&mut self, addr: usize, mut pred: P)
+    where
+        P: FnMut(&BreakpointAction) -> bool,
+    {
+        if let Some(actions) = self.breakpoint_actions.get_mut(&addr) {
+            actions.retain(|action| !pred(action));
+            if !actions.is_empty() {
+                return;
+            }
+        }
+        self.breakpoint_actions.remove(&addr);
+        if let Some(Some(breakpoint)) = self.breakpoints.remove(&addr) {
+            if let (Some(inferior), None) = (&mut self.inferior, &self.recorder) {
+                if let Err(err) = inferior.remove_breakpoint(&breakpoint) {
+                    println!("Error removing breakpoint at {:#x}: {}", addr, err);
+                }
+            }
+        }
+    }
+
+    /// Forgets the calls to traced functions that were in progress in the previous inferior.
+    fn reset_trace_frames(&mut self) {
+        let return_addrs: Vec<usize> = self.trace_frames.drain(..).map(|f| f.return_addr).collect();
+        for addr in return_addrs {
+            self.remove_breakpoint_actions(addr, |action| {
+                matches!(action, BreakpointAction::TraceReturn)
+            });
+        }
+    }
+
+    /// Runs the actions attached to the breakpoint at addr. Returns true if the inferior should
+    /// stop and return to the prompt.
+    fn handle_breakpoint(&mut self, addr: usize) -> bool {
+        let actions = match self.breakpoint_actions.get(&addr) {
+            Some(actions) => actions.clone(),
+            None => return true,
+        };
+        let mut stop = false;
+        for action in actions {
+            match action {
+                BreakpointAction::Stop | BreakpointAction::Temporary => stop = true,
+                BreakpointAction::Dprintf { format, args } => {
+                    match self.format_dprintf(&format, &args) {
+                        Ok(output) => print!("{}", output),
+                        Err(err) => println!("dprintf: {}", err),
+                    }
+                }
+                BreakpointAction::TraceEntry(function) => self.trace_entry(&function),
+                BreakpointAction::TraceReturn => self.trace_return(addr),
+                BreakpointAction::Tbreak(number) => {
+                    println!("Temporary breakpoint {} hit and deleted", number);
+                    self.delete_breakpoint(number);
+                    stop = true;
+                }
+            }
+        }
+        stop
+    }
+
+    pub fn debug_data(&self) -> &DwarfData {
+        &self.debug_data
+    }
+
+    /// The program being debugged, if it is running.
+    pub fn inferior(&self) -> Option<&Inferior> {
+        self.inferior.as_ref()
+    }
+
+    /// Returns an evaluator for expressions in the frame the inferior is stopped in.
+    pub fn evaluator(&mut self) -> Result<Evaluator<'_>, String> {
+        let inferior = self.inferior.as_mut().ok_or("The program is not being run.")?;
+        Ok(Evaluator::new(
+            inferior,
+            &self.debug_data,
+            &self.value_history,
+            self.settings.print,
+            &self.printers,
+        ))
+    }
+
+    /// Evaluates expr and prints the result, recording it in the value history.
+    fn print_expression(&mut self, expr: &str) -> Result<(), DebuggerError> {
+        let (value, formatted) = self
+            .evaluator()
+            .and_then(|mut evaluator| {
+                let value = evaluator.evaluate(expr)?;
+                let formatted = evaluator.format(&value);
+                Ok((value, formatted))
+            })
+            .map_err(DebuggerError::Expression)?;
+        self.value_history.push(value);
+        println!("${} = {}", self.value_history.len(), formatted);
+        Ok(())
+    }
+
+    /// Prints the value of an auto-display expression.
+    fn print_display(&mut self, number: usize, expr: &str) {
+        match self.evaluator().and_then(|mut evaluator| {
+            let value = evaluator.evaluate(expr)?;
+            Ok(evaluator.format(&value))
+        }) {
+            Ok(formatted) => println!("{}: {} = {}", number, expr, formatted),
+            Err(err) => println!("{}: {} = <error: {}>", number, expr, err),
+        }
+    }
+
+    /// Re-evaluates and prints every auto-display expression.
+    fn print_displays(&mut self) {
+        for (number, expr) in self.displays.clone() {
+            self.print_display(number, &expr);
+        }
+    }
+
+    fn format_dprintf(&mut self, format: &str, args: &[String]) -> Result<String, String> {
+        let mut evaluator = self.evaluator()?;
+        let values = args
+            .iter()
+            .map(|arg| evaluator.evaluate_scalar(arg))
+            .collect::<Result<Vec<_>, _>>()?;
+        let inferior = self.inferior.as_ref().unwrap();
+        value::format_printf(format, &values, |addr| {
+            let (bytes, _) = inferior.read_cstring(addr, 4096).ok()?;
+            Some(String::from_utf8_lossy(&bytes).into_owned())
+        })
+    }
+
+    /// Prints the call to a traced function, and arranges for its return to be reported too.
+    fn trace_entry(&mut self, function: &str) {
+        let func = match self.debug_data.get_function(function) {
+            Some(func) => func.clone(),
+            None => return,
+        };
+        let inferior = self.inferior.as_ref().unwrap();
+        let (cfa, return_addr) = match inferior.getregs().and_then(|regs| {
+            let rbp = regs.rbp as usize;
+            Ok((rbp + 16, inferior.read_word(rbp + 8)?))
+        }) {
+            Ok(frame) => frame,
+            Err(err) => {
+                println!("trace {}: {}", function, err);
+                return;
+            }
+        };
+        let mut evaluator = self.evaluator().unwrap();
+        let args: Vec<String> = func
+            .parameters()
+            .map(|param| match evaluator.evaluate(&param.name) {
+                Ok(val) => format!("{}={}", param.name, evaluator.format(&val)),
+                Err(_) => format!("{}=?", param.name),
+            })
+            .collect();
+        println!(
+            "{}-> {}({})",
+            "  ".repeat(self.trace_frames.len()),
+            function,
+            args.join(", ")
+        );
+        if !self.trace_frames.iter().any(|frame| frame.return_addr == return_addr) {
+            let action = BreakpointAction::TraceReturn;
+            if let Err(err) = self.add_breakpoint_action(return_addr, action) {
+                println!("trace {}: {}", function, err);
+                return;
+            }
+        }
+        self.trace_frames.push(TraceFrame {
+            function: func.name,
+            return_type: func.return_type,
+            cfa,
+            return_addr,
+        });
+    }
+
+    /// Prints the return of every traced call whose frame has been popped.
+    fn trace_return(&mut self, addr: usize) {
+        let regs = match self.inferior.as_ref().unwrap().getregs() {
+            Ok(regs) => regs,
+            Err(err) => {
+                println!("trace: {}", err);
+                return;
+            }
+        };
+        while let Some(frame) = self.trace_frames.last() {
+            if frame.cfa > regs.rsp as usize {
+                break;
+            }
+            let frame = self.trace_frames.pop().unwrap();
+            let indent = "  ".repeat(self.trace_frames.len());
+            match &frame.return_type {
+                Some(return_type) => {
+                    let inferior = self.inferior.as_ref().unwrap();
+                    match inferior.return_value(return_type.is_float()) {
+                        Ok(value) => {
+                            let bytes = value.to_le_bytes();
+                            let size = return_type.size.min(bytes.len());
+                            let val = Value::from_bytes(&bytes[..size], return_type);
+                            println!("{}<- {} = {}", indent, frame.function, val);
+                        }
+                        Err(err) => println!("{}<- {}: {}", indent, frame.function, err),
+                    }
+                }
+                None => println!("{}<- {}", indent, frame.function),
+            }
+        }
+        if !self.trace_frames.iter().any(|frame| frame.return_addr == addr) {
+            self.remove_breakpoint_actions(addr, |action| {
+                matches!(action, BreakpointAction::TraceReturn)
+            });
+        }
+    }
+
+    /// Adds a tracepoint to the named function, returning its number.
+    fn trace_function(&mut self, function: &str) -> Result<usize, DebuggerError> {
+        let action = BreakpointAction::TraceEntry(function.to_string());
+        Ok(self.add_user_breakpoint(function, action)?.0)
+    }
+
+    fn print_status(&mut self) -> Result<(), DebuggerError> {
+        let status = self.resume()?;
+        self.report_status(status);
+        Ok(())
+    }
+
+    /// The arguments, environment, working directory and terminal for future runs.
+    pub fn launch_options(&mut self) -> &mut LaunchOptions {
+        &mut self.launch
+    }
+
+    /// Starts the target, killing the inferior if there is one, and lets it run until it stops.
+    pub fn start(&mut self) -> Result<Status, DebuggerError> {
+        let command = self.launch.command(&self.target).map_err(DebuggerError::State)?;
+        self.kill();
+        self.reset_trace_frames();
+        self.reload_if_rebuilt()?;
+        let inferior = Inferior::launch(command, &mut self.breakpoints)
+            .ok_or_else(|| DebuggerError::State("Error starting subprocess".to_string()))?;
+        self.inferior = Some(inferior);
+        self.resume()
+    }
+
+    /// Lets the inferior run until it stops at a breakpoint, receives a signal or exits.
+    pub fn resume(&mut self) -> Result<Status, DebuggerError> {
+        Ok(if self.recorder.is_some() {
+            self.continue_recording()?
+        } else {
+            // Keep going until we reach a breakpoint that should actually stop the inferior
+            loop {
+                let inferior_mut = self.inferior.as_mut().ok_or_else(not_running)?;
+                match inferior_mut.cont(&self.breakpoints)? {
+                    Status::Stopped(Signal::SIGTRAP, stop_address)
+                        if self.breakpoints.contains_key(&(stop_address - 1))
+                            && !self.handle_breakpoint(stop_address - 1) => {}
+                    status => break status,
+                }
+            }
+        })
+    }
+
+    /// Lets the inferior run until it reaches addr, unless something else stops it first.
+    fn run_until(&mut self, addr: usize) -> Result<Status, DebuggerError> {
+        self.add_breakpoint_action(addr, BreakpointAction::Temporary)?;
+        let status = self.resume();
+        self.remove_breakpoint_actions(addr, |action| {
+            matches!(action, BreakpointAction::Temporary)
+        });
+        status
+    }
+
+    /// Returns the address of the instruction the inferior will execute next, given its %rip,
+    /// which is one past the address of a breakpoint that was just hit.
+    pub fn stopped_pc(&self, rip: usize) -> usize {
+        let past_breakpoint = self.inferior.as_ref().is_some_and(Inferior::past_breakpoint);
+        match self.breakpoints.get(&rip.wrapping_sub(1)) {
+            Some(Some(_)) if past_breakpoint && self.recorder.is_none() => rip - 1,
+            _ => rip,
+        }
+    }
+
+    /// Identifies the source line at addr, and how deep in inlined calls it is.
+    fn source_position(&self, addr: usize) -> (Option<Line>, usize) {
+        let frames = self.debug_data.get_frames(addr);
+        let line = frames.first().and_then(|frame| frame.line.clone()).map(|line| Line {
+            address: 0,
+            ..line
+        });
+        (line, frames.len())
+    }
+
+    /// Runs until the inferior reaches the start of another source line, entering functions that
+    /// have debugging information, inlined calls included, unless over is set. Calls to other
+    /// functions run to completion, and prologues are skipped so that arguments can be read.
+    /// In a function without line information, runs until it returns instead.
+    pub fn step_line(&mut self, over: bool) -> Result<Status, DebuggerError> {
+        let regs = self.inferior.as_ref().ok_or_else(not_running)?.getregs()?;
+        let pc = self.stopped_pc(regs.rip as usize);
+        if self.line_at(pc).is_none() {
+            let symbol = self.debug_data.get_symbol_containing(pc).ok_or_else(|| {
+                DebuggerError::State("Cannot find bounds of current function".to_string())
+            })?;
+            println!(
+                "Single stepping until exit from function {},\n\
+                 which has no line number information.",
+                symbol.name
+            );
+            let sp = self.return_address_slot(pc, &regs);
+            return self.run_to_return(sp);
+        }
+        let frame_sp = regs.rsp;
+        let start = self.source_position(self.stopped_pc(regs.rip as usize));
+        let (mut prev_pc, mut prev_sp) = (self.stopped_pc(regs.rip as usize), regs.rsp);
+        loop {
+            let mut pc = match self.step_instruction()? {
+                Status::Stopped(Signal::SIGTRAP, pc) => pc,
+                status => return Ok(status),
+            };
+            if self.is_stop_breakpoint(pc) {
+                return Ok(Status::Stopped(Signal::SIGTRAP, pc));
+            }
+            let inferior = self.inferior.as_ref().ok_or_else(not_running)?;
+            let rsp = inferior.getregs()?.rsp;
+            // A call pushes the address of the instruction after it
+            let is_call = rsp < prev_sp
+                && inferior.read_word(rsp as usize).is_ok_and(|word| {
+                    word > prev_pc && word <= prev_pc + MAX_INSTRUCTION_LENGTH && word != pc
+                });
+            if self.line_at(pc).is_none() && rsp >= frame_sp {
+                // Returned to a caller without debugging information, e.g. from main
+                return self.resume();
+            }
+            if self.line_at(pc).is_none() || (over && is_call) {
+                match self.run_to_return(rsp as usize)? {
+                    Status::Stopped(Signal::SIGTRAP, return_addr) => pc = return_addr,
+                    status => return Ok(status),
+                }
+                if self.is_stop_breakpoint(pc) {
+                    return Ok(Status::Stopped(Signal::SIGTRAP, pc));
+                }
+            }
+            prev_pc = pc;
+            prev_sp = self.inferior.as_ref().ok_or_else(not_running)?.getregs()?.rsp;
+            let in_prologue = self
+                .debug_data
+                .get_function_containing(pc)
+                .is_some_and(|func| pc < self.debug_data.get_addr_after_prologue(func));
+            let position = self.source_position(pc);
+            let is_new_line = self.debug_data.is_line_start(pc) && position != start;
+            // Stepping over a call that was inlined means staying out of its body
+            let in_inlined_call = over && prev_sp <= frame_sp && position.1 > start.1;
+            if is_new_line && !in_prologue && !in_inlined_call {
+                return Ok(Status::Stopped(Signal::SIGTRAP, pc));
+            }
+        }
+    }
+
+    /// Runs until the function whose return address is at sp returns, unless something else
+    /// stops the inferior first. Recursive calls returning to the same address don't count.
+    fn run_to_return(&mut self, sp: usize) -> Result<Status, DebuggerError> {
+        let inferior = self.inferior.as_ref().ok_or_else(not_running)?;
+        let return_addr = inferior.read_word(sp)?;
+        loop {
+            let status = self.run_until(return_addr)?;
+            let regs = match status {
+                Status::Stopped(Signal::SIGTRAP, _) => {
+                    self.inferior.as_ref().ok_or_else(not_running)?.getregs()?
+                }
+                status => return Ok(status),
+            };
+            if regs.rip as usize != return_addr || regs.rsp as usize > sp {
+                return Ok(Status::Stopped(Signal::SIGTRAP, regs.rip as usize));
+            }
+        }
+    }
+
+    /// Runs until the current function returns to its caller. In an inlined call, steps until
+    /// the inferior leaves the inlined code instead.
+    pub fn finish(&mut self) -> Result<Status, DebuggerError> {
+        let regs = self.inferior.as_ref().ok_or_else(not_running)?.getregs()?;
+        let pc = self.stopped_pc(regs.rip as usize);
+        let (_, depth) = self.source_position(pc);
+        let func = self.debug_data.get_function_containing(pc);
+        if depth <= 1 && func.is_some_and(|func| func.name == "main") {
+            return Err(DebuggerError::State(
+                "\"finish\" not meaningful in the outermost frame.".to_string(),
+            ));
+        }
+        if depth > 1 {
+            loop {
+                match self.step_line(true)? {
+                    Status::Stopped(Signal::SIGTRAP, pc)
+                        if !self.is_stop_breakpoint(pc) && self.source_position(pc).1 >= depth => {}
+                    status => return Ok(status),
+                }
+            }
+        }
+        let sp = self.return_address_slot(pc, &regs);
+        self.run_to_return(sp)
+    }
+
+    /// Returns where on the stack the return address of the function executing at pc is.
+    fn return_address_slot(&self, pc: usize, regs: &libc::user_regs_struct) -> usize {
+        // The return address is on top of the stack until the prologue pushes %rbp. Code that
+        // nothing describes, like a PLT stub, is taken to have no frame of its own.
+        let entry = match self.debug_data.get_function_containing(pc) {
+            Some(func) => Some(func.address),
+            None => self.debug_data.get_symbol_containing(pc).map(|symbol| symbol.address),
+        };
+        match entry {
+            Some(entry) if pc != entry => regs.rbp as usize + 8,
+            _ => regs.rsp as usize,
+        }
+    }
+
+    /// Like next, but only stops on a line after the current one, so that it runs through a loop
+    /// (whose increment is often laid out after its body) to the line after it.
+    pub fn until_next_line(&mut self) -> Result<Status, DebuggerError> {
+        let regs = self.inferior.as_ref().ok_or_else(not_running)?.getregs()?;
+        let start = self.stopped_pc(regs.rip as usize);
+        let sp = self.return_address_slot(start, &regs);
+        let line = match self.line_at(start) {
+            Some(line) => line.number,
+            None => return self.step_line(true),
+        };
+        let function = self
+            .debug_data
+            .get_function_containing(start)
+            .map(|func| func.address..func.address + func.text_length);
+        loop {
+            let status = self.step_line(true)?;
+            let pc = match status {
+                Status::Stopped(Signal::SIGTRAP, pc) if !self.is_stop_breakpoint(pc) => pc,
+                status => return Ok(status),
+            };
+            // Leaving the current call also counts as getting past the loop
+            let rsp = self.inferior.as_ref().ok_or_else(not_running)?.getregs()?.rsp as usize;
+            let left_function = function.as_ref().is_none_or(|range| !range.contains(&pc));
+            let later_line = self.line_at(pc).is_none_or(|pc_line| pc_line.number > line);
+            if rsp > sp || left_function || later_line {
+                return Ok(status);
+            }
+        }
+    }
+
+    /// Runs until the inferior reaches one of addrs, or the current function returns. With
+    /// same_frame set, addrs are only stopped at in the current call, so that recursive calls
+    /// are run through.
+    pub fn run_to_location(
+        &mut self,
+        addrs: &[usize],
+        same_frame: bool,
+    ) -> Result<Status, DebuggerError> {
+        let inferior = self.inferior.as_ref().ok_or_else(not_running)?;
+        let regs = inferior.getregs()?;
+        let sp = self.return_address_slot(self.stopped_pc(regs.rip as usize), &regs);
+        let return_addr = inferior.read_word(sp)?;
+        let mut targets = addrs.to_vec();
+        targets.push(return_addr);
+        for &addr in &targets {
+            self.add_breakpoint_action(addr, BreakpointAction::Temporary)?;
+        }
+        let status = self.resume_to_location(addrs, return_addr, sp, same_frame);
+        for &addr in &targets {
+            self.remove_breakpoint_actions(addr, |action| {
+                matches!(action, BreakpointAction::Temporary)
+            });
+        }
+        status
+    }
+
+    /// Resumes the inferior until it stops somewhere that run_to_location should return.
+    fn resume_to_location(
+        &mut self,
+        addrs: &[usize],
+        return_addr: usize,
+        sp: usize,
+        same_frame: bool,
+    ) -> Result<Status, DebuggerError> {
+        loop {
+            let status = self.resume()?;
+            let addr = match status {
+                Status::Stopped(Signal::SIGTRAP, rip) => rip - 1,
+                status => return Ok(status),
+            };
+            let user_stop = self.breakpoint_actions.get(&addr).is_some_and(|actions| {
+                actions.iter().any(|a| matches!(a, BreakpointAction::Stop))
+            });
+            if user_stop || (addr != return_addr && !addrs.contains(&addr)) {
+                return Ok(status);
+            }
+            let regs = self.inferior.as_ref().ok_or_else(not_running)?.getregs()?;
+            let returned = addr == return_addr && regs.rsp as usize > sp;
+            let arrived =
+                addrs.contains(&addr) && (!same_frame || regs.rbp as usize + 8 >= sp);
+            if returned || arrived {
+                return Ok(status);
+            }
+        }
+    }
+
+    /// Reports the value the function that finish ran to the end of returned.
+    fn print_return_value(&mut self, func: Option<Function>) -> Result<(), DebuggerError> {
+        let return_type = match func.and_then(|func| func.return_type) {
+            Some(return_type) if return_type.is_scalar() => return_type,
+            _ => return Ok(()),
+        };
+        let inferior = self.inferior.as_ref().ok_or_else(not_running)?;
+        let bytes = inferior.return_value(return_type.is_float())?.to_le_bytes();
+        let size = return_type.size.min(bytes.len());
+        println!("Value returned is {}", Value::from_bytes(&bytes[..size], &return_type));
+        Ok(())
+    }
+
+    fn report_status(&mut self, status: Status) {
+        self.update_state(&status);
+        match status {
+            Status::Stopped(signal, stop_address) => {
+                println!("Child stopped (signal {})", signal);
+                self.print_location(stop_address);
+            },
+            Status::Exited(signal_code) => {
+                println!("Child exited (status {})", signal_code);
+            },
+            Status::Signaled(signal) => {
+                println!("Child exited exited due to signal {}", signal);
+            },
+            Status::Syscall(stop_address) => {
+                println!("Child stopped at syscall ({:#x})", stop_address);
+            },
+        }
+    }
+
+    /// Forgets the inferior once status says it's gone.
+    pub fn update_state(&mut self, status: &Status) {
+        if let Status::Exited(_) | Status::Signaled(_) = status {
+            self.inferior = None;
+            self.recorder = None;
+        }
+    }
+
+    /// Reports the result of executing a single instruction, forward or backward.
+    fn report_step(&mut self, status: Status) {
+        match status {
+            Status::Stopped(Signal::SIGTRAP, stop_address) => self.print_location(stop_address),
+            status => self.report_status(status),
+        }
+    }
+
+    /// Prints where the inferior is stopped, followed by the auto-display expressions.
+    fn print_location(&mut self, rip: usize) {
+        let addr = self.stopped_pc(rip);
+        match self.line_at(addr) {
+            Some(line) => println!("Stopped at {}", line),
+            None => {
+                let place = self
+                    .inferior
+                    .as_ref()
+                    .and_then(|inferior| inferior.describe_address(&self.debug_data, addr));
+                match place {
+                    Some(place) => println!("Stopped at {:#x} in {}", addr, place),
+                    None => println!("Stopped at {:#x}", addr),
+                }
+            }
+        }
+        self.print_displays();
+    }
+
+    fn line_at(&self, addr: usize) -> Option<Line> {
+        if self.debug_data.contains_addr(addr) {
+            self.debug_data.get_line_from_addr(addr)
+        } else {
+            None
+        }
+    }
+
+    fn is_stop_breakpoint(&self, addr: usize) -> bool {
+        self.breakpoint_actions
+            .get(&addr)
+            .is_some_and(|actions| {
+                actions
+                    .iter()
+                    .any(|a| {
+                        matches!(
+                            a,
+                            BreakpointAction::Stop
+                                | BreakpointAction::Temporary
+                                | BreakpointAction::Tbreak(_)
+                        )
+                    })
+            })
+    }
+
+    /// If the inferior is sitting on a breakpoint it hasn't hit yet, pretends that it hit it, so
+    /// that resuming executes the original instruction instead of trapping right away.
+    fn skip_breakpoint_at_rip(&mut self) -> Result<(), nix::Error> {
+        match &mut self.inferior {
+            Some(inferior) => inferior.skip_breakpoint(&self.breakpoints),
+            None => Ok(()),
+        }
+    }
+
+    fn step_instruction(&mut self) -> Result<Status, DebuggerError> {
+        let inferior = self.inferior.as_mut().ok_or_else(not_running)?;
+        if let Some(recorder) = &mut self.recorder {
+            return Ok(recorder.step(inferior)?);
+        }
+        let status = inferior.step_instruction(&self.breakpoints)?;
+        if let Status::Stopped(_, _) = status {
+            self.skip_breakpoint_at_rip()?;
+        }
+        Ok(status)
+    }
+
+    /// Starts recording, taking the breakpoints out of the inferior's memory: while recording,
+    /// every instruction is single-stepped anyway, and restoring old memory contents must never
+    /// bring back a stale 0xcc.
+    fn start_recording(&mut self) -> Result<(), DebuggerError> {
+        let inferior = self.inferior.as_mut().ok_or_else(not_running)?;
+        if self.recorder.is_some() {
+            return Err(DebuggerError::State(
+                "The process is already being recorded.".to_string(),
+            ));
+        }
+        for breakpoint in self.breakpoints.values().flatten() {
+            if let Err(err) = inferior.remove_breakpoint(breakpoint) {
+                println!("Error removing breakpoint at {:#x}: {}", breakpoint.addr, err);
+            }
+        }
+        match Recorder::new(inferior) {
+            Ok(recorder) => {
+                self.recorder = Some(recorder);
+                Ok(())
+            }
+            Err(err) => {
+                self.stop_recording()?;
+                Err(err.into())
+            }
+        }
+    }
+
+    /// Throws away the recording and puts the breakpoints back into memory.
+    fn stop_recording(&mut self) -> Result<(), DebuggerError> {
+        self.recorder = None;
+        let inferior = match &mut self.inferior {
+            Some(inferior) => inferior,
+            None => return Ok(()),
+        };
+        for breakpoint in self.breakpoints.values().flatten() {
+            if let Err(err) = inferior.write_byte(breakpoint.addr, 0xcc) {
+                println!("Error installing breakpoint at {:#x}: {}", breakpoint.addr, err);
+            }
+        }
+        Ok(self.skip_breakpoint_at_rip()?)
+    }
+
+    /// Continues while recording, one instruction at a time, until a breakpoint stops the
+    /// inferior or it receives a signal.
+    fn continue_recording(&mut self) -> Result<Status, DebuggerError> {
+        loop {
+            let recorder = self.recorder.as_mut().ok_or_else(not_recording)?;
+            let status = recorder.step(self.inferior.as_mut().ok_or_else(not_running)?)?;
+            match status {
+                Status::Stopped(Signal::SIGTRAP, rip) if self.breakpoints.contains_key(&rip) => {
+                    if self.handle_breakpoint(rip) {
+                        return Ok(status);
+                    }
+                }
+                Status::Stopped(Signal::SIGTRAP, _) => {}
+                status => return Ok(status),
+            }
+        }
+    }
+
+    /// Undoes one recorded instruction, returning the restored %rip, or None if the beginning of
+    /// the recording has been reached.
+    fn reverse_step(&mut self) -> Result<Option<usize>, DebuggerError> {
+        let recorder = self.recorder.as_mut().ok_or_else(not_recording)?;
+        let inferior = self.inferior.as_mut().ok_or_else(not_running)?;
+        if !recorder.reverse_step(inferior)? {
+            println!("No more reverse-execution history.");
+            return Ok(None);
+        }
+        Ok(Some(inferior.getregs()?.rip as usize))
+    }
+
+    /// Runs backward until a breakpoint or the beginning of the recording is reached.
+    fn reverse_continue(&mut self) -> Result<(), DebuggerError> {
+        while let Some(rip) = self.reverse_step()? {
+            if self.is_stop_breakpoint(rip) {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs backward to the beginning of the previous line in the current frame, skipping over
+    /// any calls it made. Functions compiled without optimizations don't move %rsp within their
+    /// bodies, so states with a lower %rsp belong to callees.
+    fn reverse_next(&mut self) -> Result<(), DebuggerError> {
+        let regs = self.inferior.as_ref().ok_or_else(not_running)?.getregs()?;
+        let frame_sp = regs.rsp;
+        let line_key = |addr: u64| self.line_at(addr as usize).map(|line| (line.file, line.number));
+        // Find how far back the previous line started by looking through the log
+        let start_line = line_key(regs.rip);
+        let mut prev_line = None;
+        let mut steps = 0;
+        let recorder = self.recorder.as_ref().ok_or_else(not_recording)?;
+        for (i, entry) in recorder.history().enumerate() {
+            if entry.regs.rsp < frame_sp {
+                continue;
+            }
+            let line = line_key(entry.regs.rip);
+            match &prev_line {
+                None if line != start_line => prev_line = Some(line),
+                Some(prev_line) if line != *prev_line => break,
+                _ => {}
+            }
+            steps = i + 1;
+        }
+        for _ in 0..steps.max(1) {
+            match self.reverse_step()? {
+                Some(rip) if self.is_stop_breakpoint(rip) => break,
+                Some(_) => {}
+                None => break,
+            }
+        }
+        Ok(())
+    }
+
+    /// Forks the inferior, keeping the copy around as a checkpoint.
+    fn checkpoint(&mut self) -> Result<(), DebuggerError> {
+        let inferior = self.inferior.as_mut().ok_or_else(not_running)?;
+        if self.recorder.is_some() {
+            return Err(DebuggerError::State(
+                "Checkpoints can't be made while recording.".to_string(),
+            ));
+        }
+        let process = inferior.fork(self.debug_data.entry_point())?;
+        let addr = process.getregs()?.rip as usize;
+        let number = self.next_checkpoint_number;
+        self.next_checkpoint_number += 1;
+        println!("checkpoint {}: fork returned pid {}.", number, process.pid());
+        self.checkpoints.push(Checkpoint {
+            number,
+            process,
+            addr,
+            breakpoints: self.breakpoints.values().flatten().cloned().collect(),
+        });
+        Ok(())
+    }
+
+    /// Replaces the inferior with a fresh fork of the given checkpoint, so that the checkpoint
+    /// itself can be restarted again later.
+    fn restart(&mut self, number: usize) -> Result<(), DebuggerError> {
+        let checkpoint = self
+            .checkpoints
+            .iter_mut()
+            .find(|c| c.number == number)
+            .ok_or_else(|| DebuggerError::State(format!("No checkpoint number {}.", number)))?;
+        let mut process = checkpoint.process.fork(self.debug_data.entry_point())?;
+        let installed = checkpoint.breakpoints.clone();
+        self.recorder = None;
+        self.reset_trace_frames();
+        if self.inferior.is_some() {
+            self.kill();
+        }
+        // Bring the copy's breakpoints up to date with the ones set since the checkpoint
+        for breakpoint in &installed {
+            if !self.breakpoints.contains_key(&breakpoint.addr) {
+                if let Err(err) = process.remove_breakpoint(breakpoint) {
+                    println!("Error removing breakpoint at {:#x}: {}", breakpoint.addr, err);
+                }
+            }
+        }
+        for (addr, breakpoint) in self.breakpoints.iter_mut() {
+            if installed.iter().any(|b| b.addr == *addr) {
+                continue;
+            }
+            match process.write_byte(*addr, 0xcc) {
+                Ok(orig_byte) => *breakpoint = Some(Breakpoint { addr: *addr, orig_byte }),
+                Err(err) => println!("Error installing breakpoint at {:#x}: {}", addr, err),
+            }
+        }
+        println!("Switching to process {}", process.pid());
+        let rip = process.getregs()?.rip as usize;
+        self.inferior = Some(process);
+        self.print_location(rip);
+        Ok(())
+    }
+
+    /// Lists the functions whose names match pattern, or all of them if it's empty.
+    fn info_functions(&self, pattern: &str) -> Result<(), DebuggerError> {
+        let regex = compile_regex(pattern)?;
+        let declarations = self
+            .debug_data
+            .find_functions(&regex)
+            .into_iter()
+            .map(|(file, func)| {
+                let declaration = function_declaration(func, &func.name);
+                (file, func.name.as_str(), func.line_number, declaration)
+            })
+            .collect();
+        print_declarations("functions", pattern, declarations);
+        print_non_debugging_symbols(self.debug_data.find_non_debugging_symbols(&regex, true));
+        Ok(())
+    }
+
+    /// Lists the global variables whose names match pattern, or all of them if it's empty.
+    fn info_variables(&self, pattern: &str) -> Result<(), DebuggerError> {
+        let regex = compile_regex(pattern)?;
+        let declarations = self
+            .debug_data
+            .find_global_variables(&regex)
+            .into_iter()
+            .map(|(file, var)| {
+                let declaration = var.entity_type.declaration(&var.name);
+                (file, var.name.as_str(), var.line_number, declaration)
+            })
+            .collect();
+        print_declarations("variables", pattern, declarations);
+        print_non_debugging_symbols(self.debug_data.find_non_debugging_symbols(&regex, false));
+        Ok(())
+    }
+
+    /// Shows the range of addresses holding the code for the line at location.
+    fn info_line(&self, location: &str) -> Result<(), DebuggerError> {
+        let addr = self.parse_address(location).ok_or_else(|| {
+            DebuggerError::Dwarf(format!("No line or function matches \"{}\".", location))
+        })?;
+        let (line, end) = self
+            .debug_data
+            .get_line_range(addr)
+            .filter(|_| self.debug_data.contains_addr(addr))
+            .ok_or_else(|| {
+                let message =
+                    format!("No line number information available for address {:#x}", addr);
+                DebuggerError::Dwarf(message)
+            })?;
+        println!(
+            "Line {} of \"{}\" starts at address {} and ends at {}.",
+            line.number,
+            line.file,
+            self.describe_code_address(line.address),
+            self.describe_code_address(end)
+        );
+        Ok(())
+    }
+
+    /// Formats addr along with the function it's in, e.g. "0x401136 <main+16>".
+    fn describe_code_address(&self, addr: usize) -> String {
+        let func = match self.debug_data.get_function_containing(addr) {
+            Some(func) => Some((func.name.as_str(), func.address)),
+            None => self
+                .debug_data
+                .get_symbol_containing(addr)
+                .map(|symbol| (symbol.name.as_str(), symbol.address)),
+        };
+        match func {
+            Some((name, start)) if start == addr => format!("{:#x} <{}>", addr, name),
+            Some((name, start)) => format!("{:#x} <{}+{}>", addr, name, addr - start),
+            None => format!("{:#x}", addr),
+        }
+    }
+
+    /// Shows which function or global variable contains the address expr evaluates to.
+    fn info_symbol(&mut self, expr: &str) -> Result<(), DebuggerError> {
+        let literal = match expr.strip_prefix("0x").or_else(|| expr.strip_prefix("0X")) {
+            Some(hex) => usize::from_str_radix(hex, 16).ok(),
+            None => expr.parse().ok(),
+        };
+        let addr = match literal {
+            Some(addr) => addr,
+            None => match self
+                .evaluator()
+                .and_then(|mut evaluator| evaluator.evaluate_scalar(expr))
+            {
+                Ok(value) => value.as_u64() as usize,
+                // Code and data without debugging info are only named by the ELF symbol table
+                Err(err) => match self.debug_data.get_symbol(expr, true) {
+                    Some(symbol) => symbol.address,
+                    None => match self.debug_data.get_symbol(expr, false) {
+                        Some(symbol) => symbol.address,
+                        None => return Err(DebuggerError::Expression(err)),
+                    },
+                },
+            },
+        };
+        let symbol = match self.debug_data.get_function_containing(addr) {
+            Some(func) => Some((func.name.as_str(), func.address)),
+            None => self.debug_data.get_global_variable_containing(addr).and_then(|var| {
+                match var.location {
+                    Location::Address(start) => Some((var.name.as_str(), start)),
+                    Location::FramePointerOffset(_) => None,
+                }
+            }),
+        };
+        let symbol = symbol.or_else(|| {
+            let symbol = self.debug_data.get_symbol_containing(addr)?;
+            Some((symbol.name.as_str(), symbol.address))
+        });
+        let (name, start) = match symbol {
+            Some(symbol) => symbol,
+            None => {
+                let pid = self.inferior.as_ref().map(Inferior::pid);
+                match pid.and_then(|pid| procfs::annotate(pid, addr)) {
+                    Some(mapping) => println!("No symbol matches {}; it is in {}.", expr, mapping),
+                    None => println!("No symbol matches {}.", expr),
+                }
+                return Ok(());
+            }
+        };
+        let mut description = name.to_string();
+        if addr != start {
+            description.push_str(&format!(" + {}", addr - start));
+        }
+        if let Some(section) = self.debug_data.get_section_containing(addr) {
+            description.push_str(&format!(" in section {}", section.name));
+        }
+        println!("{}", description);
+        Ok(())
+    }
+
+    /// Describes the source file the inferior is stopped in.
+    fn info_source(&self) -> Result<(), DebuggerError> {
+        let inferior = self.inferior.as_ref().ok_or_else(not_running)?;
+        let line = match self.line_at(inferior.getregs()?.rip as usize) {
+            Some(line) => line,
+            None => {
+                println!("No current source file.");
+                return Ok(());
+            }
+        };
+        println!("Current source file is {}", line.file);
+        if let Ok(contents) = fs::read_to_string(&line.file) {
+            println!("Contains {} lines.", contents.lines().count());
+        }
+        Ok(())
+    }
 
-impl Debugger {
-    /// Initializes the debugger.
-    pub fn new(target: &str) -> Debugger {
-        // (milestone 3): initialize the DwarfData
-        let debug_data = match DwarfData::from_file(target) {
-            Ok(val) => val,
-            Err(DwarfError::ErrorOpeningFile) => {
-                println!("Could not open file {}", target);
-                std::process::exit(1);
+    /// Prints the values of the local variables in scope where the inferior is stopped, grouped
+    /// by the block declaring them, innermost first. Shadowed variables are printed too.
+    fn info_locals(&mut self) -> Result<(), DebuggerError> {
+        let rip = self.inferior.as_ref().ok_or_else(not_running)?.getregs()?.rip as usize;
+        let pc = self.stopped_pc(rip);
+        let debug_data = &self.debug_data;
+        let func = debug_data
+            .get_function_containing(pc)
+            .ok_or_else(|| DebuggerError::Dwarf("No symbol table info available.".to_string()))?;
+        let evaluator = Evaluator::new(
+            self.inferior.as_mut().unwrap(),
+            &self.debug_data,
+            &self.value_history,
+            self.settings.print,
+            &self.printers,
+        );
+        let mut found = false;
+        for scope in func.scopes_at(pc) {
+            let variables: Vec<_> = func
+                .variables
+                .iter()
+                .filter(|var| var.block == scope && !(var.is_parameter && scope.is_none()))
+                .collect();
+            if variables.is_empty() {
+                continue;
+            }
+            found = true;
+            let block = scope.map(|index| &func.blocks[index]);
+            // Code inlined into the block may start it, so take the line from the frame of the
+            // function the block belongs to
+            let inlined_depth = func
+                .scopes_at(pc)
+                .iter()
+                .skip_while(|other| **other != scope)
+                .flatten()
+                .filter(|index| func.blocks[**index].inlined_function.is_some())
+                .count();
+            let start = block.and_then(|block| block.ranges.first()).map(|range| range.0);
+            let line = start.and_then(|start| {
+                let frames = debug_data.get_frames(start);
+                let index = frames.len().checked_sub(inlined_depth + 1)?;
+                frames[index].line.clone()
+            });
+            let at = line.map(|line| format!(" at {}", line)).unwrap_or_default();
+            match block.map(|block| &block.inlined_function) {
+                Some(Some(name)) => println!("Inlined call to {}{}:", name, at),
+                Some(None) => println!("Block{}:", at),
+                None => println!("Function {}:", func.name),
+            }
+            for var in variables {
+                let value = evaluator
+                    .variable_value(var)
+                    .map(|value| evaluator.format(&value))
+                    .unwrap_or_else(|err| format!("<{}>", err));
+                println!("  {} = {}", var.name, value);
             }
-            Err(DwarfError::DwarfFormatError(err)) => {
-                println!("Could not load debugging symbols from {}: {:?}", target, err);
-                std::process::exit(1);
+        }
+        if !found {
+            println!("No locals.");
+        }
+        Ok(())
+    }
+
+    /// Lists the shared libraries mapped into the inferior, with the addresses of their code.
+    fn info_shared_libraries(&self) -> Result<(), DebuggerError> {
+        let inferior = match &self.inferior {
+            Some(inferior) => inferior,
+            None => {
+                println!("No shared libraries loaded at this time.");
+                return Ok(());
             }
         };
+        let target = fs::canonicalize(&self.target)?;
+        let mut libraries: Vec<procfs::MemoryMapping> = Vec::new();
+        for mapping in procfs::read_maps(inferior.pid())? {
+            if !mapping.path.starts_with('/') || Path::new(&mapping.path) == target {
+                continue;
+            }
+            match libraries.iter_mut().find(|library| library.path == mapping.path) {
+                Some(library) => library.end = library.end.max(mapping.end),
+                None => libraries.push(mapping),
+            }
+        }
+        if libraries.is_empty() {
+            println!("No shared libraries loaded at this time.");
+            return Ok(());
+        }
+        println!("{:<20}{:<20}{:<12}Shared Object Library", "From", "To", "Syms Read");
+        for library in libraries {
+            let (start, end) = (library.start, library.end);
+            println!("{:#018x}  {:#018x}  {:<12}{}", start, end, "No", library.path);
+        }
+        Ok(())
+    }
 
-        debug_data.print();
+    /// Describes the inferior process: how it was started, where, and some of its status.
+    fn info_proc(&self) -> Result<(), DebuggerError> {
+        let pid = self.inferior.as_ref().ok_or_else(not_running)?.pid();
+        println!("process {}", pid);
+        println!("cmdline = '{}'", procfs::read_cmdline(pid)?.join(" "));
+        println!("cwd = '{}'", procfs::read_cwd(pid)?.display());
+        println!("exe = '{}'", procfs::read_exe(pid)?.display());
+        for (name, value) in procfs::read_status(pid)? {
+            if PROC_STATUS_FIELDS.contains(&name.as_str()) {
+                println!("{}: {}", name, value);
+            }
+        }
+        Ok(())
+    }
 
-        let history_path = format!("{}/.deet_history", std::env::var("HOME").unwrap());
-        let mut readline = Editor::<(), FileHistory>::new().expect("Create Editor fail");
-        // Attempt to load history from ~/.deet_history if it exists
-        let _ = readline.load_history(&history_path);
+    /// Lists the memory regions mapped into the inferior.
+    fn info_proc_mappings(&self) -> Result<(), DebuggerError> {
+        let pid = self.inferior.as_ref().ok_or_else(not_running)?.pid();
+        println!("process {}", pid);
+        println!("Mapped address spaces:\n");
+        println!(
+            "{:>18} {:>18} {:>10} {:>10}  {:<6} objfile",
+            "Start Addr", "End Addr", "Size", "Offset", "Perms"
+        );
+        for mapping in procfs::read_maps(pid)? {
+            println!(
+                "{:>#18x} {:>#18x} {:>#10x} {:>#10x}  {:<6} {}",
+                mapping.start,
+                mapping.end,
+                mapping.end - mapping.start,
+                mapping.offset,
+                mapping.perms,
+                mapping.path
+            );
+        }
+        Ok(())
+    }
 
-        let breakpoints = HashMap::new();
+    /// Prints the auxiliary vector the kernel passed to the inferior.
+    fn info_auxv(&self) -> Result<(), DebuggerError> {
+        let inferior = self.inferior.as_ref().ok_or_else(not_running)?;
+        for entry in procfs::read_auxv(inferior.pid())? {
+            let (name, description, format) = entry.describe();
+            let value = match format {
+                procfs::AuxvFormat::Decimal => entry.value.to_string(),
+                procfs::AuxvFormat::Hex => format!("{:#x}", entry.value),
+                procfs::AuxvFormat::String => {
+                    match inferior.read_cstring(entry.value as usize, 4096) {
+                        Ok((bytes, _)) => {
+                            format!("{:#x} {:?}", entry.value, String::from_utf8_lossy(&bytes))
+                        }
+                        Err(_) => format!("{:#x}", entry.value),
+                    }
+                }
+            };
+            println!("{:<4} {:<20} {:<40} {}", entry.key, name, description, value);
+        }
+        Ok(())
+    }
 
-        Debugger {
-            target: target.to_string(),
-            history_path,
-            readline,
-            inferior: None,
-            debug_data,
-            breakpoints,
+    /// Lists the chunks of the heap that are in use, and sums up the rest.
+    fn info_heap(&self) -> Result<(), DebuggerError> {
+        let inferior = self.inferior.as_ref().ok_or_else(not_running)?;
+        let heap = heap::walk(inferior).map_err(DebuggerError::State)?;
+        match heap.arena {
+            Some(arena) => {
+                println!("Heap {:#x}-{:#x}, main_arena at {:#x}", heap.start, heap.end, arena)
+            }
+            None => {
+                println!("Heap {:#x}-{:#x}", heap.start, heap.end);
+                println!("Couldn't find main_arena; blocks in fastbins are listed as in use.");
+            }
         }
+        let (live, free): (Vec<&heap::Chunk>, Vec<&heap::Chunk>) =
+            heap.chunks.iter().partition(|chunk| chunk.in_use);
+        if !live.is_empty() {
+            println!("{:<20}{:>12}{:>12}", "Address", "Usable size", "Chunk size");
+        }
+        for chunk in &live {
+            println!("{:<#20x}{:>12}{:>#12x}", chunk.user_addr(), chunk.usable_size(), chunk.size);
+        }
+        let total = |chunks: &[&heap::Chunk]| -> usize {
+            chunks.iter().map(|chunk| chunk.usable_size()).sum()
+        };
+        println!("{} blocks in use, {} bytes.", live.len(), total(&live));
+        println!("{} free blocks, {} bytes.", free.len(), total(&free));
+        println!("Top chunk at {:#x}, {} bytes.", heap.top.0, heap.top.1);
+        Ok(())
     }
 
-    fn parse_address(&self, address: &str) -> Option<usize> {
-        let mut addr = "";
-        if address.to_lowercase().starts_with("0x") {
-            // b 0x123456
-            addr = &address[2..];
-        } else if address.to_lowercase().starts_with("*0x") {
-             // b *0x123456
-             addr = &address[3..];
-        } else if address.parse::<usize>().is_ok() {
-            // b line_number
-            let line_number = address.parse::<usize>().unwrap();
-            return self.debug_data.get_addr_for_line(None, line_number);
-        } else {
-            // b function_name
-            return self.debug_data.get_addr_for_function(None, &address);
+    /// Lists the registered pretty-printers, which are tried in this order.
+    fn print_pretty_printers(&self) {
+        println!("Num  Printer         Types");
+        for (i, registration) in self.printers.iter().enumerate() {
+            let number = i + 1;
+            println!("{:<4} {:<15} {}", number, registration.printer_name, registration.pattern);
         }
-        usize::from_str_radix(&addr, 16).ok()
+        println!();
+        println!("Available printers: {}", PRINTER_NAMES.join(", "));
     }
 
-    fn print_status(&mut self) {
-        let inferior_mut = self.inferior.as_mut().unwrap();
-        match inferior_mut.cont(&self.breakpoints) {
-            Ok(status) => {
-                match status {
-                    Status::Stopped(signal, stop_address) => {
-                        println!("Child stopped (signal {})", signal);
-                        println!("Stopped at {}",self.debug_data.get_line_from_addr(stop_address).unwrap())
-                    },
-                    Status::Exited(signal_code) => {
-                        println!("Child exited (status {})", signal_code);
-                        self.inferior = None;
-                    },
-                    Status::Signaled(signal) => {
-                        println!("Child exited exited due to signal {}", signal);
-                        self.inferior = None;
-                    },
+    /// Prints the definition of a type, or the type of an expression.
+    fn ptype(&mut self, input: &str) -> Result<(), DebuggerError> {
+        // Local variables shadow functions, which Rust has plenty of with short names
+        let is_local = self.inferior.as_ref().is_some_and(|inferior| {
+            inferior.getregs().is_ok_and(|regs| {
+                self.debug_data
+                    .get_function_containing(regs.rip as usize)
+                    .is_some_and(|func| func.get_variable(input, regs.rip as usize).is_some())
+            })
+        });
+        if let Some(func) = self.debug_data.get_function(input).filter(|_| !is_local) {
+            println!("type = {}", function_declaration(func, ""));
+            return Ok(());
+        }
+        let ptype = match expr::parse_type(input, &self.debug_data) {
+            Some(ptype) => ptype,
+            None => match (&self.inferior, self.debug_data.get_global_variable(input)) {
+                (None, Some(var)) => var.entity_type.clone(),
+                _ => {
+                    self.evaluator()
+                        .and_then(|mut evaluator| evaluator.evaluate(input))
+                        .map_err(DebuggerError::Expression)?
+                        .value_type
                 }
-
-            },
-            Err(e) => {
-                eprintln!("{}", e);
             },
+        };
+        println!("type = {}", self.debug_data.describe_type(&ptype));
+        Ok(())
+    }
+
+    fn print_checkpoints(&self) {
+        if self.checkpoints.is_empty() {
+            println!("No checkpoints.");
         }
+        for checkpoint in &self.checkpoints {
+            match self.line_at(checkpoint.addr) {
+                Some(line) => println!(
+                    "{} process {} at {}",
+                    checkpoint.number,
+                    checkpoint.process.pid(),
+                    line
+                ),
+                None => println!(
+                    "{} process {} at {:#x}",
+                    checkpoint.number,
+                    checkpoint.process.pid(),
+                    checkpoint.addr
+                ),
+            }
+        }
+    }
+
+    fn delete_checkpoint(&mut self, number: usize) -> Result<(), DebuggerError> {
+        let index = self
+            .checkpoints
+            .iter()
+            .position(|c| c.number == number)
+            .ok_or_else(|| DebuggerError::State(format!("No checkpoint number {}.", number)))?;
+        let mut checkpoint = self.checkpoints.remove(index);
+        checkpoint.process.kill()?;
+        Ok(())
     }
 
-    fn kill(&mut self) {
-        let inferior_mut = self.inferior.as_mut().unwrap();
+    pub fn kill(&mut self) {
+        self.recorder = None;
+        let mut inferior_mut = match self.inferior.take() {
+            Some(inferior) => inferior,
+            None => return,
+        };
         let pid = inferior_mut.pid();
         match inferior_mut.kill() {
             Ok(_) => {
@@ -123,68 +1864,697 @@ impl Debugger {
     }
 
     pub fn run(&mut self) {
+        while !self.exiting {
+            self.refresh_tui();
+            let line = self.get_next_line();
+            if let Err(err) = self.execute_line(&line, 0) {
+                println!("{}", err);
+            }
+        }
+        if let Some(tui) = self.tui.take() {
+            tui.disable();
+        }
+    }
+
+    /// Shows the panes of the text user interface, or redraws them if they're already shown.
+    pub fn enable_tui(&mut self) -> Result<(), DebuggerError> {
+        if self.tui.is_none() {
+            let tui = Tui::enable().ok_or_else(|| {
+                DebuggerError::State("Cannot enable the TUI when output is not a terminal".into())
+            })?;
+            self.tui = Some(tui);
+        }
+        self.refresh_tui();
+        Ok(())
+    }
+
+    /// Redraws the TUI's panes, if it's enabled, for wherever the inferior is stopped.
+    fn refresh_tui(&mut self) {
+        let (code_lines, side_cols) = match &self.tui {
+            Some(tui) => (tui.code_lines(), tui.side_cols()),
+            None => return,
+        };
+        let panes = self.tui_panes(code_lines, side_cols);
+        if let Some(tui) = &mut self.tui {
+            tui.draw(&panes);
+        }
+    }
+
+    /// Fills in the TUI's panes. Before the program runs, the source pane shows main.
+    fn tui_panes(&mut self, code_lines: usize, side_cols: usize) -> Panes {
+        let regs = self.inferior.as_ref().and_then(|inferior| inferior.getregs().ok());
+        let pc = regs.map(|regs| self.stopped_pc(regs.rip as usize));
+        let line = match pc {
+            Some(pc) => self.line_at(pc),
+            None => self.debug_data.get_function("main").and_then(|main| {
+                self.debug_data.get_line_from_addr(self.debug_data.get_addr_after_prologue(main))
+            }),
+        };
+        let source = match &line {
+            Some(line) => {
+                let breakpoint_lines: Vec<usize> = self
+                    .breakpoints
+                    .keys()
+                    .filter_map(|addr| self.line_at(*addr))
+                    .filter(|other| other.file == line.file)
+                    .map(|other| other.number)
+                    .collect();
+                let current = pc.map(|_| line.number);
+                tui::source_pane(&line.file, line.number, current, &breakpoint_lines, code_lines)
+            }
+            None => Pane::new("source", vec!["No source available.".to_string()]),
+        };
+        let (regs, pc) = match (regs, pc) {
+            (Some(regs), Some(pc)) => (libc::user_regs_struct { rip: pc as u64, ..regs }, pc),
+            _ => {
+                let message = || vec!["The program is not being run.".to_string()];
+                return Panes {
+                    source,
+                    disassembly: Pane::new("disassembly", message()),
+                    registers: Pane::new("registers", message()),
+                    locals: Pane::new("locals", message()),
+                };
+            }
+        };
+        Panes {
+            source,
+            disassembly: self.disassembly_pane(pc),
+            registers: tui::registers_pane(&regs, side_cols),
+            locals: self.locals_pane(pc),
+        }
+    }
+
+    /// Disassembles the function containing pc, or the code following pc if it's not known.
+    fn disassembly_pane(&self, pc: usize) -> Pane {
+        let func = self.debug_data.get_function_containing(pc);
+        let symbol = self.debug_data.get_symbol_containing(pc).filter(|symbol| symbol.size > 0);
+        let (start, len, name) = match (func, symbol) {
+            (Some(func), _) => (func.address, func.text_length, Some(func.name.as_str())),
+            (None, Some(symbol)) => (symbol.address, symbol.size, Some(symbol.name.as_str())),
+            (None, None) => (pc, MAX_DISASSEMBLY_BYTES, None),
+        };
+        let (start, len, name) = if len > MAX_DISASSEMBLY_BYTES {
+            (pc, MAX_DISASSEMBLY_BYTES, None)
+        } else {
+            (start, len, name)
+        };
+        let inferior = self.inferior.as_ref().unwrap();
+        let mut code = match inferior.read_bytes(start, len) {
+            Ok(code) => code,
+            Err(err) => return Pane::new("disassembly", vec![format!("{:#x}: {}", start, err)]),
+        };
+        // Show the instructions that breakpoints replaced
+        for breakpoint in self.breakpoints.values().flatten() {
+            if let Some(byte) = breakpoint.addr.checked_sub(start).and_then(|i| code.get_mut(i)) {
+                *byte = breakpoint.orig_byte;
+            }
+        }
+        tui::disassembly_pane(&code, start, pc, name)
+    }
+
+    /// Lists the variables in scope at pc, innermost first.
+    fn locals_pane(&mut self, pc: usize) -> Pane {
+        let func = match self.debug_data.get_function_containing(pc) {
+            Some(func) => func,
+            None => return Pane::new("locals", vec!["No symbol table info available.".into()]),
+        };
+        let evaluator = Evaluator::new(
+            self.inferior.as_mut().unwrap(),
+            &self.debug_data,
+            &self.value_history,
+            self.settings.print,
+            &self.printers,
+        );
+        let lines = func
+            .variables_at(pc)
+            .into_iter()
+            .map(|var| {
+                let value = evaluator
+                    .variable_value(var)
+                    .map(|value| evaluator.format(&value))
+                    .unwrap_or_else(|err| format!("<{}>", err));
+                format!("{} = {}", var.name, value)
+            })
+            .collect();
+        Pane::new("locals", lines)
+    }
+
+    /// Runs a line of input, expanding aliases and user-defined commands. depth counts the
+    /// expansions it came from.
+    fn execute_line(&mut self, line: &str, depth: usize) -> Result<(), DebuggerError> {
+        if depth > MAX_USER_CALL_DEPTH {
+            return Err(DebuggerError::State(
+                "Max user call depth exceeded -- command aborted.".to_string(),
+            ));
+        }
+        let (word, rest) = debugger_command::split_word(line);
+        if let Some(expansion) = self.aliases.get(word) {
+            let line = format!("{} {}", expansion, rest);
+            return self.execute_line(&line, depth + 1);
+        }
+        if let Some(body) = self.user_commands.get(word).cloned() {
+            let args: Vec<&str> = rest.split_whitespace().collect();
+            for line in body {
+                self.execute_line(&substitute_args(&line, &args)?, depth + 1)?;
+                if self.exiting {
+                    break;
+                }
+            }
+            return Ok(());
+        }
+        self.execute(DebuggerCommand::from_line(line)?)
+    }
+
+    /// Runs the commands in a file. Errors are reported without stopping the rest of the file.
+    fn source(&mut self, path: &str) -> Result<(), DebuggerError> {
+        let contents = fs::read_to_string(path)
+            .map_err(|err| DebuggerError::State(format!("{}: {}", path, err)))?;
+        self.scripts.push(contents.lines().map(str::to_string).collect());
+        while let Some(line) = self.scripts.last_mut().and_then(|script| script.pop_front()) {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Err(err) = self.execute_line(line, 0) {
+                println!("{}: {}", path, err);
+            }
+            if self.exiting {
+                break;
+            }
+        }
+        self.scripts.pop();
+        Ok(())
+    }
+
+    /// Reads the body of a user-defined command, from the file being sourced or the terminal.
+    fn define(&mut self, name: &str) -> Result<(), DebuggerError> {
+        if debugger_command::is_builtin(name) {
+            return Err(DebuggerError::State(format!(
+                "Cannot redefine built-in command \"{}\".",
+                name
+            )));
+        }
+        if self.scripts.is_empty() {
+            println!("Type commands for definition of \"{}\".", name);
+            println!("End with a line saying just \"end\".");
+        }
+        let mut body = Vec::new();
         loop {
-            match self.get_next_command() {
-                DebuggerCommand::Run(args) => {
-                    // check if any existing inferiors before run new one
-                    if self.inferior.is_some() {
-                        self.kill();
-                    }
-                    if let Some(inferior) = Inferior::new(&self.target, &args, &mut self.breakpoints) {
-                        // Create the inferior
-                        self.inferior = Some(inferior);
-                        self.print_status();
-                       
-                    } else {
-                        println!("Error starting subprocess");
-                    }
-                },
-                DebuggerCommand::Continue => {
-                    // check if there have inferior to debug
-                    if self.inferior.is_none() {
-                        println!("Nothing is being debugged!");
-                        continue;
-                    }
-                    self.print_status();
-                },
-                DebuggerCommand::Backtrace => {
-                    let inferior = self.inferior.as_ref().unwrap();
-                    inferior.print_backtrace(&self.debug_data).unwrap();
+            let line = match self.scripts.last_mut() {
+                Some(script) => script.pop_front(),
+                None => self.readline.readline(">").ok(),
+            };
+            let line = line.ok_or_else(|| {
+                DebuggerError::Parse(format!("Definition of \"{}\" is missing \"end\".", name))
+            })?;
+            match line.trim() {
+                "end" => break,
+                "" => {}
+                line => body.push(line.to_string()),
+            }
+        }
+        self.aliases.remove(name);
+        self.user_commands.insert(name.to_string(), body);
+        self.update_command_names();
+        Ok(())
+    }
+
+    fn alias(&mut self, name: String, command: String) -> Result<(), DebuggerError> {
+        if debugger_command::is_builtin(&name) {
+            return Err(DebuggerError::State(format!(
+                "Alias \"{}\" would replace a built-in command.",
+                name
+            )));
+        }
+        let (word, _) = debugger_command::split_word(&command);
+        if !self.user_commands.contains_key(word) && !self.aliases.contains_key(word) {
+            debugger_command::lookup(COMMANDS, word, None)?;
+        }
+        self.user_commands.remove(&name);
+        self.aliases.insert(name, command);
+        self.update_command_names();
+        Ok(())
+    }
+
+    /// Tells the completer about the user-defined commands and aliases.
+    fn update_command_names(&mut self) {
+        let mut names: Vec<String> =
+            self.user_commands.keys().chain(self.aliases.keys()).cloned().collect();
+        names.sort();
+        if let Some(helper) = self.readline.helper_mut() {
+            helper.user_commands = names;
+        }
+    }
+
+    /// Asks whether to kill the inferior to start it again, if it's running.
+    fn confirm_restart(&mut self) -> bool {
+        let question = "The program being debugged has been started already. \
+            Start it from the beginning?";
+        if self.inferior.is_some() && !self.confirm(question) {
+            println!("Program not restarted.");
+            return false;
+        }
+        true
+    }
+
+    /// Asks a yes-or-no question, unless confirmation is turned off or the answer can't come
+    /// from a person.
+    fn confirm(&mut self, question: &str) -> bool {
+        if !self.settings.confirm || !self.scripts.is_empty() {
+            return true;
+        }
+        if !nix::unistd::isatty(0).unwrap_or(false) {
+            println!("{} (y or n) [answered Y; input not from terminal]", question);
+            return true;
+        }
+        loop {
+            match self.readline.readline(&format!("{} (y or n) ", question)) {
+                Ok(answer) => match answer.trim() {
+                    "y" | "Y" | "yes" => return true,
+                    "n" | "N" | "no" => return false,
+                    _ => println!("Please answer y or n."),
                 },
-                DebuggerCommand::Break(address) => {
-                    let addr = self.parse_address(&address).unwrap();
-                    println!("Set breakpoint {} at {:x}", self.breakpoints.len(), &addr);
-
-                    if let Some(inferior) = &mut self.inferior {
-                        match inferior.write_byte(addr, 0xcc) {
-                            Ok(orig_byte) => {
-                                self.breakpoints
-                                    .insert(addr, Some(Breakpoint { addr, orig_byte }));
-                            }
-                            Err(err) => {
-                                println!("Debugger::new breakpoint write_byte: {}", err)
-                            }
-                        }
-                    } else {
-                        self.breakpoints.insert(addr, None);
+                Err(ReadlineError::Eof) => {
+                    println!("EOF [answered Y; input not from terminal]");
+                    return true;
+                }
+                Err(_) => return false,
+            }
+        }
+    }
+
+    fn print_help(&self, topic: &str) -> Result<(), DebuggerError> {
+        let (word, _) = debugger_command::split_word(topic);
+        if let Some(body) = self.user_commands.get(word) {
+            println!("User-defined command \"{}\":", word);
+            for line in body {
+                println!("  {}", line);
+            }
+            return Ok(());
+        }
+        if let Some(expansion) = self.aliases.get(word) {
+            println!("\"{}\" is an alias for \"{}\".", word, expansion);
+            return Ok(());
+        }
+        println!("{}", debugger_command::help(topic)?);
+        if topic.is_empty() && !(self.user_commands.is_empty() && self.aliases.is_empty()) {
+            let mut names: Vec<&str> =
+                self.user_commands.keys().chain(self.aliases.keys()).map(String::as_str).collect();
+            names.sort();
+            println!();
+            println!("User-defined commands and aliases: {}", names.join(", "));
+        }
+        Ok(())
+    }
+
+    /// Carries out a single command.
+    fn execute(&mut self, command: DebuggerCommand) -> Result<(), DebuggerError> {
+        match command {
+            DebuggerCommand::Run(args) => {
+                // check if any existing inferiors before run new one
+                if !self.confirm_restart() {
+                    return Ok(());
+                }
+                if !args.is_empty() {
+                    self.launch.args = args;
+                }
+                let status = self.start()?;
+                self.report_status(status);
+                Ok(())
+            },
+            DebuggerCommand::Start(args) => {
+                if !self.confirm_restart() {
+                    return Ok(());
+                }
+                if !args.is_empty() {
+                    self.launch.args = args;
+                }
+                let (number, addrs) = self.set_temporary_breakpoint("main")?;
+                let addrs: Vec<String> = addrs.iter().map(|addr| format!("{:x}", addr)).collect();
+                println!("Temporary breakpoint {} at {}", number, addrs.join(", "));
+                let status = self.start()?;
+                self.report_status(status);
+                Ok(())
+            },
+            DebuggerCommand::Continue => {
+                // check if there have inferior to debug
+                if self.inferior.is_none() {
+                    return Err(DebuggerError::State("Nothing is being debugged!".to_string()));
+                }
+                self.print_status()
+            },
+            DebuggerCommand::Backtrace => {
+                let inferior = self.inferior.as_ref().ok_or_else(|| {
+                    DebuggerError::State("No stack.".to_string())
+                })?;
+                Ok(inferior.print_backtrace(&self.debug_data)?)
+            },
+            DebuggerCommand::Step | DebuggerCommand::Next => {
+                let status = self.step_line(matches!(command, DebuggerCommand::Next))?;
+                self.report_step(status);
+                Ok(())
+            }
+            DebuggerCommand::Finish => {
+                let regs = self.inferior.as_ref().ok_or_else(not_running)?.getregs()?;
+                let pc = self.stopped_pc(regs.rip as usize);
+                let func = self.debug_data.get_function_containing(pc).cloned();
+                let frames = self.debug_data.get_frames(pc);
+                let inlined = frames.len() > 1;
+                let function = frames.first().map_or("??", |frame| frame.function.as_str());
+                println!("Run till exit from {}", function);
+                let status = self.finish()?;
+                let returned = matches!(status, Status::Stopped(Signal::SIGTRAP, pc)
+                    if !self.is_stop_breakpoint(pc));
+                self.report_step(status);
+                if returned && !inlined {
+                    self.print_return_value(func)?;
+                }
+                Ok(())
+            }
+            DebuggerCommand::Until(None) => {
+                let status = self.until_next_line()?;
+                self.report_step(status);
+                Ok(())
+            }
+            DebuggerCommand::Until(Some(ref location)) | DebuggerCommand::Advance(ref location) => {
+                let addrs = self.parse_code_locations(location)?;
+                let same_frame = matches!(command, DebuggerCommand::Until(_));
+                let status = self.run_to_location(&addrs, same_frame)?;
+                self.report_step(status);
+                Ok(())
+            }
+            DebuggerCommand::StepInstruction => {
+                let status = self.step_instruction()?;
+                self.report_step(status);
+                Ok(())
+            },
+            DebuggerCommand::Record => self.start_recording(),
+            DebuggerCommand::RecordStop => {
+                if self.recorder.is_none() {
+                    return Err(DebuggerError::State(
+                        "No recording is currently active.".to_string(),
+                    ));
+                }
+                self.stop_recording()?;
+                println!("Process record is stopped and all execution logs are deleted.");
+                Ok(())
+            },
+            DebuggerCommand::InfoRecord => {
+                match &self.recorder {
+                    Some(recorder) => println!("Recorded {} instructions", recorder.len()),
+                    None => println!("No recording is currently active."),
+                }
+                Ok(())
+            },
+            DebuggerCommand::ReverseStepInstruction
+            | DebuggerCommand::ReverseContinue
+            | DebuggerCommand::ReverseNext => {
+                if self.recorder.is_none() {
+                    return Err(not_recording());
+                }
+                match command {
+                    DebuggerCommand::ReverseStepInstruction => self.reverse_step().map(|_| ())?,
+                    DebuggerCommand::ReverseContinue => self.reverse_continue()?,
+                    _ => self.reverse_next()?,
+                }
+                let regs = self.inferior.as_ref().ok_or_else(not_running)?.getregs()?;
+                self.print_location(regs.rip as usize);
+                Ok(())
+            },
+            DebuggerCommand::Checkpoint => self.checkpoint(),
+            DebuggerCommand::Restart(number) => self.restart(number),
+            DebuggerCommand::InfoCheckpoints => {
+                self.print_checkpoints();
+                Ok(())
+            },
+            DebuggerCommand::DeleteCheckpoint(number) => self.delete_checkpoint(number),
+            DebuggerCommand::DeleteBreakpoints(numbers) => {
+                self.delete_breakpoints(numbers);
+                Ok(())
+            }
+            DebuggerCommand::InfoBreakpoints => {
+                self.info_breakpoints();
+                Ok(())
+            }
+            DebuggerCommand::Print(expr) => self.print_expression(&expr),
+            DebuggerCommand::Display(expr) => {
+                if expr.is_empty() {
+                    self.print_displays();
+                    return Ok(());
+                }
+                let number = self.next_display_number;
+                self.next_display_number += 1;
+                self.displays.push((number, expr.clone()));
+                if self.inferior.is_some() {
+                    self.print_display(number, &expr);
+                }
+                Ok(())
+            },
+            DebuggerCommand::Undisplay(numbers) => {
+                if numbers.is_empty() {
+                    self.displays.clear();
+                }
+                for number in numbers {
+                    let before = self.displays.len();
+                    self.displays.retain(|(n, _)| *n != number);
+                    if self.displays.len() == before {
+                        println!("No display number {}.", number);
                     }
-                },
-                DebuggerCommand::Quit => {
-                    if self.inferior.is_some() {
-                        self.kill();
+                }
+                Ok(())
+            },
+            DebuggerCommand::InfoFunctions(pattern) => self.info_functions(&pattern),
+            DebuggerCommand::InfoVariables(pattern) => self.info_variables(&pattern),
+            DebuggerCommand::InfoLine(location) => self.info_line(&location),
+            DebuggerCommand::InfoSymbol(expr) => self.info_symbol(&expr),
+            DebuggerCommand::InfoSource => self.info_source(),
+            DebuggerCommand::InfoLocals => self.info_locals(),
+            DebuggerCommand::InfoSharedLibrary => self.info_shared_libraries(),
+            DebuggerCommand::InfoProc => self.info_proc(),
+            DebuggerCommand::InfoProcMappings => self.info_proc_mappings(),
+            DebuggerCommand::InfoAuxv => self.info_auxv(),
+            DebuggerCommand::InfoHeap => self.info_heap(),
+            DebuggerCommand::Ptype(input) => self.ptype(&input),
+            DebuggerCommand::PrettyPrinter(name, pattern) => {
+                let pattern = compile_regex(&pattern)?;
+                self.printers.register(&name, pattern)
+            }
+            DebuggerCommand::InfoPrettyPrinters => {
+                self.print_pretty_printers();
+                Ok(())
+            }
+            DebuggerCommand::DeletePrettyPrinter(number) => self.printers.remove(number),
+            DebuggerCommand::InfoDisplay => {
+                if self.displays.is_empty() {
+                    println!("There are no auto-display expressions now.");
+                } else {
+                    println!("Auto-display expressions now in effect:");
+                    println!("Num Expression");
+                    for (number, expr) in &self.displays {
+                        println!("{:<3} {}", number, expr);
                     }
-                    return;
                 }
+                Ok(())
+            },
+            DebuggerCommand::SetVar(expr) => {
+                self.evaluator()
+                    .and_then(|mut evaluator| evaluator.evaluate(&expr))
+                    .map_err(DebuggerError::Expression)?;
+                Ok(())
+            },
+            DebuggerCommand::Call(expr) => {
+                let result = self
+                    .evaluator()
+                    .and_then(|mut evaluator| {
+                        let value = evaluator.evaluate(&expr)?;
+                        Ok(if value.value_type.kind == TypeKind::Void {
+                            None
+                        } else {
+                            Some((evaluator.format(&value), value))
+                        })
+                    })
+                    .map_err(DebuggerError::Expression)?;
+                if let Some((formatted, value)) = result {
+                    self.value_history.push(value);
+                    println!("${} = {}", self.value_history.len(), formatted);
+                }
+                Ok(())
+            },
+            DebuggerCommand::Tbreak(location) => {
+                let (number, addrs) = self.set_temporary_breakpoint(&location)?;
+                let addrs: Vec<String> = addrs.iter().map(|addr| format!("{:x}", addr)).collect();
+                println!("Temporary breakpoint {} at {}", number, addrs.join(", "));
+                Ok(())
+            },
+            DebuggerCommand::Break(address) => {
+                let (number, addrs) = self.set_breakpoint(&address)?;
+                let addrs: Vec<String> = addrs.iter().map(|addr| format!("{:x}", addr)).collect();
+                println!("Set breakpoint {} at {}", number, addrs.join(", "));
+                Ok(())
+            },
+            DebuggerCommand::Dprintf(location, format, args) => {
+                let action = BreakpointAction::Dprintf { format, args };
+                let (number, addrs) = self.add_user_breakpoint(&location, action)?;
+                let addrs: Vec<String> = addrs.iter().map(|addr| format!("{:#x}", addr)).collect();
+                println!("Dprintf {} at {}", number, addrs.join(", "));
+                Ok(())
+            },
+            DebuggerCommand::Trace(function) => {
+                let number = self.trace_function(&function)?;
+                println!("Tracepoint {} on {}", number, function);
+                Ok(())
+            },
+            DebuggerCommand::TraceAllFunctions => {
+                let functions: Vec<String> =
+                    self.debug_data.functions().map(|func| func.name.clone()).collect();
+                for function in &functions {
+                    self.trace_function(function)?;
+                }
+                println!("Tracing {} functions", functions.len());
+                Ok(())
+            },
+            DebuggerCommand::Help(topic) => self.print_help(&topic),
+            DebuggerCommand::Alias(None) => {
+                let mut aliases: Vec<_> = self.aliases.iter().collect();
+                aliases.sort();
+                for (name, command) in aliases {
+                    println!("{} = {}", name, command);
+                }
+                Ok(())
+            },
+            DebuggerCommand::Alias(Some((name, command))) => self.alias(name, command),
+            DebuggerCommand::Define(name) => self.define(&name),
+            DebuggerCommand::Source(path) => self.source(&path),
+            DebuggerCommand::SetOption(name, value) => self.settings.set(&name, &value),
+            DebuggerCommand::ShowOption(name) => {
+                let prefix = format!("{} ", name);
+                let names: Vec<&str> = OPTION_NAMES
+                    .iter()
+                    .copied()
+                    .filter(|option| {
+                        name.is_empty() || *option == name || option.starts_with(&prefix)
+                    })
+                    .collect();
+                if names.is_empty() {
+                    return Err(DebuggerError::Parse(format!("No option named \"{}\".", name)));
+                }
+                for option in names {
+                    println!("{}", self.settings.show(option)?);
+                }
+                Ok(())
+            },
+            DebuggerCommand::SetArgs(args) => {
+                self.launch.args = args;
+                Ok(())
+            },
+            DebuggerCommand::ShowArgs => {
+                println!(
+                    "Argument list to give program being debugged when it is started is \"{}\".",
+                    self.launch.args.join(" ")
+                );
+                Ok(())
+            },
+            DebuggerCommand::SetEnvironment(name, value) => {
+                self.launch.environment.insert(name, value);
+                Ok(())
+            },
+            DebuggerCommand::UnsetEnvironment(Some(name)) => {
+                self.launch.environment.remove(&name);
+                Ok(())
+            },
+            DebuggerCommand::UnsetEnvironment(None) => {
+                if self.confirm("Delete all environment variables?") {
+                    self.launch.environment.clear();
+                }
+                Ok(())
+            },
+            DebuggerCommand::ShowEnvironment(Some(name)) => {
+                match self.launch.environment.get(&name) {
+                    Some(value) => println!("{} = {}", name, value),
+                    None => println!("Environment variable \"{}\" not defined.", name),
+                }
+                Ok(())
+            },
+            DebuggerCommand::ShowEnvironment(None) => {
+                for (name, value) in &self.launch.environment {
+                    println!("{}={}", name, value);
+                }
+                Ok(())
+            },
+            DebuggerCommand::SetCwd(cwd) => {
+                self.launch.cwd = cwd;
+                Ok(())
+            },
+            DebuggerCommand::ShowCwd => {
+                match &self.launch.cwd {
+                    Some(cwd) => println!(
+                        "Current working directory that will be used when starting the \
+                         inferior is \"{}\".",
+                        cwd
+                    ),
+                    None => println!(
+                        "You have not set the inferior's current working directory.\n\
+                         The inferior will inherit deet's cwd."
+                    ),
+                }
+                Ok(())
+            },
+            DebuggerCommand::Tty(tty) => {
+                self.launch.tty = tty;
+                Ok(())
+            },
+            DebuggerCommand::ShowTty => {
+                println!(
+                    "Terminal for future runs of program being debugged is \"{}\".",
+                    self.launch.tty.as_deref().unwrap_or_default()
+                );
+                Ok(())
+            },
+            DebuggerCommand::Layout(true) => self.enable_tui(),
+            DebuggerCommand::Layout(false) => {
+                if let Some(tui) = self.tui.take() {
+                    tui.disable();
+                }
+                Ok(())
+            }
+            DebuggerCommand::Quit => {
+                let question = "A debugging session is active. Quit anyway?";
+                if self.inferior.is_some() && !self.confirm(question) {
+                    return Ok(());
+                }
+                self.exiting = true;
+                self.kill();
+                for mut checkpoint in self.checkpoints.drain(..) {
+                    let _ = checkpoint.process.kill();
+                }
+                Ok(())
             }
         }
     }
 
 
+    /// Returns the names of the variables visible where the inferior is stopped.
+    fn variables_in_scope(&self) -> Vec<String> {
+        let mut names: Vec<String> =
+            self.debug_data.global_variable_names().map(str::to_string).collect();
+        let regs = self.inferior.as_ref().and_then(|inferior| inferior.getregs().ok());
+        if let Some(rip) = regs.map(|regs| regs.rip as usize) {
+            if let Some(func) = self.debug_data.get_function_containing(rip) {
+                names.extend(func.variables_at(rip).iter().map(|var| var.name.clone()));
+            }
+        }
+        names.sort();
+        names.dedup();
+        names
+    }
+
     /// This function prompts the user to enter a command, and continues re-prompting until the user
-    /// enters a valid command. It uses DebuggerCommand::from_tokens to do the command parsing.
-    ///
-    /// You don't need to read, understand, or modify this function.
-    fn get_next_command(&mut self) -> DebuggerCommand {
+    /// enters a non-empty line.
+    fn get_next_line(&mut self) -> String {
+        let variables = self.variables_in_scope();
+        if let Some(helper) = self.readline.helper_mut() {
+            helper.variables = variables;
+        }
         loop {
             // Print prompt and get next line of user input
             match self.readline.readline("(deet) ") {
@@ -194,13 +2564,13 @@ impl Debugger {
                 }
                 Err(ReadlineError::Eof) => {
                     // User pressed ctrl+d, which is the equivalent of "quit" for our purposes
-                    return DebuggerCommand::Quit;
+                    return "quit".to_string();
                 }
                 Err(err) => {
                     panic!("Unexpected I/O error: {:?}", err);
                 }
                 Ok(line) => {
-                    if line.trim().len() == 0 {
+                    if line.trim().is_empty() {
                         continue;
                     }
                     let _ = self.readline.add_history_entry(line.as_str());
@@ -210,14 +2580,87 @@ impl Debugger {
                             self.history_path, err
                         );
                     }
-                    let tokens: Vec<&str> = line.split_whitespace().collect();
-                    if let Some(cmd) = DebuggerCommand::from_tokens(&tokens) {
-                        return cmd;
-                    } else {
-                        println!("Unrecognized command.");
-                    }
+                    return line;
                 }
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::sample;
+
+    fn line_of(debugger: &Debugger, addr: usize) -> usize {
+        debugger.debug_data.get_line_from_addr(addr).unwrap().number
+    }
+
+    #[test]
+    fn functions_resolve_past_the_prologue() {
+        let debugger = Debugger::without_init_files(&sample("function_calls"));
+        let func = debugger.debug_data.get_function("func2").unwrap();
+        let addr = debugger.parse_code_location("func2").unwrap();
+        assert!(addr > func.address);
+        assert_eq!(addr, debugger.debug_data.get_addr_after_prologue(func));
+        assert_eq!(line_of(&debugger, addr), 10);
+        assert_eq!(debugger.parse_code_location("function_calls.c:func2").unwrap(), addr);
+    }
+
+    #[test]
+    fn lines_resolve_to_their_first_address() {
+        let debugger = Debugger::without_init_files(&sample("function_calls"));
+        let addr = debugger.parse_code_location("11").unwrap();
+        assert_eq!(line_of(&debugger, addr), 11);
+        assert_eq!(debugger.parse_code_location("function_calls.c:11").unwrap(), addr);
+    }
+
+    #[test]
+    fn addresses_resolve_to_themselves() {
+        let debugger = Debugger::without_init_files(&sample("function_calls"));
+        assert_eq!(debugger.parse_code_location("*0x401136").unwrap(), 0x401136);
+        assert_eq!(debugger.parse_code_location("0x401136").unwrap(), 0x401136);
+    }
+
+    #[test]
+    fn symbols_without_debugging_information_resolve_past_the_prologue() {
+        let debugger = Debugger::without_init_files(&sample("function_calls"));
+        let symbol = debugger.debug_data.get_symbol("_start", true).unwrap();
+        assert!(debugger.debug_data.get_function("_start").is_none());
+        assert_eq!(debugger.parse_code_location("_start").unwrap(), symbol.after_prologue);
+    }
+
+    #[test]
+    fn unknown_locations_are_errors() {
+        let debugger = Debugger::without_init_files(&sample("function_calls"));
+        for location in ["no_such_function", "function_calls.c:no_such_function", "500"] {
+            match debugger.parse_code_location(location) {
+                Err(DebuggerError::Dwarf(message)) => assert_eq!(
+                    message,
+                    format!("No line or function matches \"{}\".", location)
+                ),
+                _ => panic!("{} resolved", location),
+            }
+        }
+    }
+
+    #[test]
+    fn user_command_arguments_are_substituted() {
+        let args = ["x", "y + 1", "2", "3", "4", "5", "6", "7", "8", "9", "ten"];
+        assert_eq!(substitute_args("print $arg0", &args).unwrap(), "print x");
+        assert_eq!(substitute_args("print $arg1*$arg0", &args).unwrap(), "print y + 1*x");
+        assert_eq!(substitute_args("echo $arg10 of $argc", &args).unwrap(), "echo ten of 11");
+        assert_eq!(substitute_args("echo $argv $", &args).unwrap(), "echo $argv $");
+        assert_eq!(substitute_args("echo $argc", &[]).unwrap(), "echo 0");
+    }
+
+    #[test]
+    fn missing_user_command_arguments_are_errors() {
+        match substitute_args("print $arg0 + $arg2", &["x", "y"]) {
+            Err(DebuggerError::State(message)) => {
+                assert_eq!(message, "Missing argument 2 in user function.")
+            }
+            _ => panic!("$arg2 was substituted"),
+        }
+    }
+}
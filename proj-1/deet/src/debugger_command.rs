@@ -1,29 +1,1122 @@
+use crate::debugger::DebuggerError;
+
 pub enum DebuggerCommand {
     Quit,
     Run(Vec<String>),
+    Start(Vec<String>),
+    SetArgs(Vec<String>),
+    ShowArgs,
+    /// A variable's name and value
+    SetEnvironment(String, String),
+    /// A variable's name, or None to remove them all
+    UnsetEnvironment(Option<String>),
+    ShowEnvironment(Option<String>),
+    /// A directory, or None to start in deet's own
+    SetCwd(Option<String>),
+    ShowCwd,
+    /// A terminal device, or None to share deet's
+    Tty(Option<String>),
+    ShowTty,
     Continue,
     Backtrace,
     Break(String),
+    Tbreak(String),
+    /// Breakpoint numbers, or none to delete them all
+    DeleteBreakpoints(Vec<usize>),
+    InfoBreakpoints,
+    Dprintf(String, String, Vec<String>),
+    Print(String),
+    SetVar(String),
+    Call(String),
+    Display(String),
+    Undisplay(Vec<usize>),
+    InfoDisplay,
+    Trace(String),
+    TraceAllFunctions,
+    Step,
+    Next,
+    Finish,
+    Until(Option<String>),
+    Advance(String),
+    StepInstruction,
+    Record,
+    RecordStop,
+    InfoRecord,
+    ReverseStepInstruction,
+    ReverseContinue,
+    ReverseNext,
+    Checkpoint,
+    Restart(usize),
+    InfoCheckpoints,
+    DeleteCheckpoint(usize),
+    Help(String),
+    /// A new name and what it stands for, or None to list the aliases
+    Alias(Option<(String, String)>),
+    Define(String),
+    Source(String),
+    SetOption(String, String),
+    ShowOption(String),
+    /// A regular expression, or "" for all of them
+    InfoFunctions(String),
+    InfoVariables(String),
+    InfoLine(String),
+    InfoSymbol(String),
+    InfoSource,
+    InfoLocals,
+    InfoSharedLibrary,
+    InfoProc,
+    InfoProcMappings,
+    InfoAuxv,
+    InfoHeap,
+    Ptype(String),
+    /// The name of a built-in printer and a regular expression matching type names
+    PrettyPrinter(String, String),
+    InfoPrettyPrinters,
+    DeletePrettyPrinter(usize),
+    /// Whether to turn the TUI on (or redraw it) or off
+    Layout(bool),
 }
 
-impl DebuggerCommand {
-    pub fn from_tokens(tokens: &Vec<&str>) -> Option<DebuggerCommand> {
-        match tokens[0] {
-            "q" | "quit" => Some(DebuggerCommand::Quit),
-            "r" | "run" => {
-                let args = tokens[1..].to_vec();
-                Some(DebuggerCommand::Run(
-                    args.iter().map(|s| s.to_string()).collect(),
-                ))
+/// What a command expects after its name, for tab completion.
+#[derive(Clone, Copy)]
+pub enum Arguments {
+    None,
+    /// Arbitrary words, like the arguments to run
+    Words,
+    /// A function name, line number, file:line or address
+    Location,
+    Function,
+    /// A C expression, which may contain spaces
+    Expression,
+    Numbers,
+    /// The name of a command, possibly followed by subcommand names
+    CommandName,
+    /// The name of one of a nested table of commands, followed by its arguments
+    Subcommand(&'static [Command]),
+}
+
+/// An entry in the command registry.
+pub struct Command {
+    pub name: &'static str,
+    /// Short names that work even though they aren't unique prefixes, like "b" for break
+    pub aliases: &'static [&'static str],
+    pub args: Arguments,
+    pub usage: &'static str,
+    /// The first line doubles as the summary in command lists
+    pub help: &'static str,
+    /// Parses everything after the command's name
+    parse: fn(&str) -> Result<DebuggerCommand, DebuggerError>,
+}
+
+/// Registers a pretty-printer, both as a command of its own and as "set pretty-printer".
+const PRETTY_PRINTER_COMMAND: Command = Command {
+    name: "pretty-printer",
+    aliases: &[],
+    args: Arguments::Words,
+    usage: "pretty-printer <printer> <regex>",
+    help: "Format values whose type names match regex with one of the built-in printers.\n\
+           Printers registered later take precedence. \"info pretty-printer\" lists the\n\
+           printers; for example, \"pretty-printer linked-list ^struct list$\" prints a\n\
+           struct list holding a head pointer to struct nodes as [1, 2, 3].",
+    parse: |rest| {
+        let usage = || parse_error("Usage: pretty-printer <printer> <regex>");
+        let (name, pattern) = split_word(rest);
+        if name.is_empty() || pattern.is_empty() {
+            return Err(usage());
+        }
+        Ok(DebuggerCommand::PrettyPrinter(name.to_string(), pattern.to_string()))
+    },
+};
+
+/// Every top-level command, in the order help lists them.
+pub const COMMANDS: &[Command] = &[
+    Command {
+        name: "advance",
+        aliases: &[],
+        args: Arguments::Location,
+        usage: "advance <location>",
+        help: "Run until a location is reached, or the current function returns.",
+        parse: |rest| Ok(DebuggerCommand::Advance(required(rest, "location")?.to_string())),
+    },
+    Command {
+        name: "alias",
+        aliases: &[],
+        args: Arguments::Words,
+        usage: "alias [<name> = <command> [arg...]]",
+        help: "Define a new name for a command, optionally with some of its arguments.\n\
+               With no arguments, list the aliases.",
+        parse: |rest| {
+            if rest.is_empty() {
+                return Ok(DebuggerCommand::Alias(None));
+            }
+            let usage = || parse_error("Usage: alias <name> = <command> [arg...]");
+            let (name, command) = rest.split_once('=').ok_or_else(usage)?;
+            let (name, command) = (name.trim(), command.trim());
+            if name.is_empty() || name.contains(char::is_whitespace) || command.is_empty() {
+                return Err(usage());
+            }
+            Ok(DebuggerCommand::Alias(Some((name.to_string(), command.to_string()))))
+        },
+    },
+    Command {
+        name: "backtrace",
+        aliases: &["bt", "back"],
+        args: Arguments::None,
+        usage: "backtrace",
+        help: "Print the stack of function calls that led to the current location.",
+        parse: |_| Ok(DebuggerCommand::Backtrace),
+    },
+    Command {
+        name: "break",
+        aliases: &["b"],
+        args: Arguments::Location,
+        usage: "break <location>",
+        help: "Set a breakpoint.\n\
+               The location can be a function, a line number (optionally file:line), or an\n\
+               address like 0x401136.",
+        parse: |rest| Ok(DebuggerCommand::Break(required(rest, "location")?.to_string())),
+    },
+    Command {
+        name: "call",
+        aliases: &[],
+        args: Arguments::Expression,
+        usage: "call <expression>",
+        help: "Evaluate an expression, usually a function call, printing it unless it's void.",
+        parse: |rest| Ok(DebuggerCommand::Call(required(rest, "expression")?.to_string())),
+    },
+    Command {
+        name: "checkpoint",
+        aliases: &[],
+        args: Arguments::None,
+        usage: "checkpoint",
+        help: "Fork the inferior, saving its current state so that \"restart\" can return to it.",
+        parse: |_| Ok(DebuggerCommand::Checkpoint),
+    },
+    Command {
+        name: "continue",
+        aliases: &["c", "cont"],
+        args: Arguments::None,
+        usage: "continue",
+        help: "Continue running the inferior until a breakpoint or signal stops it.",
+        parse: |_| Ok(DebuggerCommand::Continue),
+    },
+    Command {
+        name: "delete",
+        aliases: &[],
+        args: Arguments::Subcommand(DELETE_COMMANDS),
+        usage: "delete [<number>...] | delete <subcommand>",
+        help: "Delete breakpoints, checkpoints or pretty-printers.\n\
+               With breakpoint numbers, delete those breakpoints; with no arguments, delete\n\
+               all of them.",
+        parse: |rest| match rest.chars().next() {
+            None | Some('0'..='9') => parse_breakpoint_numbers(rest),
+            _ => parse_subcommand(DELETE_COMMANDS, "delete", rest),
+        },
+    },
+    Command {
+        name: "define",
+        aliases: &[],
+        args: Arguments::Words,
+        usage: "define <name>",
+        help: "Define a new command from the lines that follow, up to one saying just \"end\".\n\
+               In the definition, $arg0, $arg1, ... stand for the command's arguments, and\n\
+               $argc for how many there are.",
+        parse: |rest| {
+            let name = required(rest, "name of command to define")?;
+            if name.contains(char::is_whitespace) {
+                return Err(parse_error("Usage: define <name>"));
             }
-            "c" | "cont" | "continue" => Some(DebuggerCommand::Continue),
-            "bt" | "back" | "backtrace" => Some(DebuggerCommand::Backtrace),
-            "break" | "b" => {
-                let address = tokens[1].to_string();
-                Some(DebuggerCommand::Break(address))
+            Ok(DebuggerCommand::Define(name.to_string()))
+        },
+    },
+    Command {
+        name: "display",
+        aliases: &[],
+        args: Arguments::Expression,
+        usage: "display [expression]",
+        help: "Print an expression every time the inferior stops.\n\
+               With no expression, print all of the auto-display expressions now.",
+        parse: |rest| Ok(DebuggerCommand::Display(rest.to_string())),
+    },
+    Command {
+        name: "dprintf",
+        aliases: &[],
+        args: Arguments::Location,
+        usage: "dprintf <location>,\"<format>\",<arg>...",
+        help: "Print a formatted message whenever a location is reached, without stopping.",
+        parse: parse_dprintf,
+    },
+    Command {
+        name: "finish",
+        aliases: &["fin"],
+        args: Arguments::None,
+        usage: "finish",
+        help: "Run until the current function returns, and print the value it returns.",
+        parse: |_| Ok(DebuggerCommand::Finish),
+    },
+    Command {
+        name: "help",
+        aliases: &["h"],
+        args: Arguments::CommandName,
+        usage: "help [command]",
+        help: "Describe a command, or list all of them.",
+        parse: |rest| Ok(DebuggerCommand::Help(rest.to_string())),
+    },
+    Command {
+        name: "info",
+        aliases: &[],
+        args: Arguments::Subcommand(INFO_COMMANDS),
+        usage: "info <subcommand>",
+        help: "Show information about the inferior or the debugger.",
+        parse: |rest| parse_subcommand(INFO_COMMANDS, "info", rest),
+    },
+    Command {
+        name: "layout",
+        aliases: &[],
+        args: Arguments::Words,
+        usage: "layout [off]",
+        help: "Show the source, disassembly, registers and locals in panes above the prompt.\n\
+               \"layout off\" goes back to the plain command line.",
+        parse: |rest| match rest {
+            "" => Ok(DebuggerCommand::Layout(true)),
+            "off" => Ok(DebuggerCommand::Layout(false)),
+            _ => Err(parse_error("Usage: layout [off]")),
+        },
+    },
+    Command {
+        name: "next",
+        aliases: &["n"],
+        args: Arguments::None,
+        usage: "next",
+        help: "Run to the next source line, stepping over function calls.",
+        parse: |_| Ok(DebuggerCommand::Next),
+    },
+    PRETTY_PRINTER_COMMAND,
+    Command {
+        name: "print",
+        aliases: &["p"],
+        args: Arguments::Expression,
+        usage: "print <expression>",
+        help: "Evaluate a C expression and print its value.\n\
+               The value is saved in the value history, where $N refers to it later.",
+        parse: |rest| Ok(DebuggerCommand::Print(required(rest, "expression")?.to_string())),
+    },
+    Command {
+        name: "ptype",
+        aliases: &[],
+        args: Arguments::Expression,
+        usage: "ptype <expression>|<type>",
+        help: "Print the definition of a type, or the type of an expression.",
+        parse: |rest| Ok(DebuggerCommand::Ptype(required(rest, "expression or type")?.to_string())),
+    },
+    Command {
+        name: "quit",
+        aliases: &["q"],
+        args: Arguments::None,
+        usage: "quit",
+        help: "Kill the inferior and exit.",
+        parse: |_| Ok(DebuggerCommand::Quit),
+    },
+    Command {
+        name: "record",
+        aliases: &["rec"],
+        args: Arguments::Words,
+        usage: "record [stop]",
+        help: "Start recording the inferior's execution, so that it can be run in reverse.\n\
+               \"record stop\" throws the recording away.",
+        parse: |rest| match rest {
+            "" => Ok(DebuggerCommand::Record),
+            "stop" => Ok(DebuggerCommand::RecordStop),
+            other => Err(parse_error(&format!("Undefined record command: \"{}\".", other))),
+        },
+    },
+    Command {
+        name: "restart",
+        aliases: &[],
+        args: Arguments::Numbers,
+        usage: "restart <checkpoint>",
+        help: "Go back to the state saved by a checkpoint.",
+        parse: |rest| Ok(DebuggerCommand::Restart(parse_number(rest, "checkpoint number")?)),
+    },
+    Command {
+        name: "reverse-continue",
+        aliases: &["rc"],
+        args: Arguments::None,
+        usage: "reverse-continue",
+        help: "Run backward until a breakpoint or the start of the recording.",
+        parse: |_| Ok(DebuggerCommand::ReverseContinue),
+    },
+    Command {
+        name: "reverse-next",
+        aliases: &["rn"],
+        args: Arguments::None,
+        usage: "reverse-next",
+        help: "Run backward to the start of the previous line, stepping over calls.",
+        parse: |_| Ok(DebuggerCommand::ReverseNext),
+    },
+    Command {
+        name: "reverse-stepi",
+        aliases: &["rsi"],
+        args: Arguments::None,
+        usage: "reverse-stepi",
+        help: "Run backward one instruction.",
+        parse: |_| Ok(DebuggerCommand::ReverseStepInstruction),
+    },
+    Command {
+        name: "run",
+        aliases: &["r"],
+        args: Arguments::Words,
+        usage: "run [arg...]",
+        help: "Start the program with the given arguments, killing it first if it's running.\n\
+               The arguments are remembered for later runs; with none, the last ones are used.\n\
+               They can redirect the standard streams like a shell would: < in.txt, > out.txt,\n\
+               >> out.txt, 2> err.txt and 2>> err.txt.",
+        parse: |rest| {
+            Ok(DebuggerCommand::Run(rest.split_whitespace().map(str::to_string).collect()))
+        },
+    },
+    Command {
+        name: "set",
+        aliases: &[],
+        args: Arguments::Subcommand(SET_COMMANDS),
+        usage: "set <subcommand>",
+        help: "Change a variable in the inferior, or one of deet's options.",
+        parse: |rest| parse_subcommand(SET_COMMANDS, "set", rest),
+    },
+    Command {
+        name: "show",
+        aliases: &[],
+        args: Arguments::Subcommand(SHOW_COMMANDS),
+        usage: "show [option]",
+        help: "Show the value of one of deet's options, or all of them.",
+        parse: |rest| match rest {
+            "" => Ok(DebuggerCommand::ShowOption(String::new())),
+            rest => parse_subcommand(SHOW_COMMANDS, "show", rest),
+        },
+    },
+    Command {
+        name: "source",
+        aliases: &[],
+        args: Arguments::Words,
+        usage: "source <file>",
+        help: "Run the commands in a file, such as a .deetinit.",
+        parse: |rest| Ok(DebuggerCommand::Source(required(rest, "file name")?.to_string())),
+    },
+    Command {
+        name: "start",
+        aliases: &[],
+        args: Arguments::Words,
+        usage: "start [arg...]",
+        help: "Set a temporary breakpoint on main and run the program with the given arguments.",
+        parse: |rest| {
+            Ok(DebuggerCommand::Start(rest.split_whitespace().map(str::to_string).collect()))
+        },
+    },
+    Command {
+        name: "step",
+        aliases: &["s"],
+        args: Arguments::None,
+        usage: "step",
+        help: "Run to the next source line, entering called functions and inlined calls.\n\
+               Functions without debugging information are run to completion.",
+        parse: |_| Ok(DebuggerCommand::Step),
+    },
+    Command {
+        name: "stepi",
+        aliases: &["si"],
+        args: Arguments::None,
+        usage: "stepi",
+        help: "Execute one instruction.",
+        parse: |_| Ok(DebuggerCommand::StepInstruction),
+    },
+    Command {
+        name: "tbreak",
+        aliases: &[],
+        args: Arguments::Location,
+        usage: "tbreak <location>",
+        help: "Set a temporary breakpoint, which is deleted the first time it's hit.",
+        parse: |rest| Ok(DebuggerCommand::Tbreak(required(rest, "location")?.to_string())),
+    },
+    Command {
+        name: "trace",
+        aliases: &[],
+        args: Arguments::Function,
+        usage: "trace <function>",
+        help: "Print every call to a function, with its arguments and return value.",
+        parse: |rest| Ok(DebuggerCommand::Trace(required(rest, "function name")?.to_string())),
+    },
+    Command {
+        name: "trace-all-functions",
+        aliases: &[],
+        args: Arguments::None,
+        usage: "trace-all-functions",
+        help: "Trace every function in the program.",
+        parse: |_| Ok(DebuggerCommand::TraceAllFunctions),
+    },
+    Command {
+        name: "tty",
+        aliases: &[],
+        args: Arguments::Words,
+        usage: "tty [device]",
+        help: "Give the program a terminal of its own, like /dev/pts/3, for future runs.\n\
+               With no device, it shares deet's terminal again.",
+        parse: |rest| Ok(DebuggerCommand::Tty(optional(rest))),
+    },
+    Command {
+        name: "undisplay",
+        aliases: &[],
+        args: Arguments::Numbers,
+        usage: "undisplay [number...]",
+        help: "Stop displaying the given auto-display expressions, or all of them.",
+        parse: |rest| {
+            let numbers = rest
+                .split_whitespace()
+                .map(|n| parse_number(n, "display number"))
+                .collect::<Result<Vec<usize>, _>>()?;
+            Ok(DebuggerCommand::Undisplay(numbers))
+        },
+    },
+    Command {
+        name: "unset",
+        aliases: &[],
+        args: Arguments::Subcommand(UNSET_COMMANDS),
+        usage: "unset <subcommand>",
+        help: "Remove environment variables from future runs of the program.",
+        parse: |rest| parse_subcommand(UNSET_COMMANDS, "unset", rest),
+    },
+    Command {
+        name: "until",
+        aliases: &["u"],
+        args: Arguments::Location,
+        usage: "until [location]",
+        help: "Run to a source line past the current one, running through loops to their end.\n\
+               With a location, run until it's reached in the current call, or the current\n\
+               function returns.",
+        parse: |rest| match rest {
+            "" => Ok(DebuggerCommand::Until(None)),
+            location => Ok(DebuggerCommand::Until(Some(location.to_string()))),
+        },
+    },
+];
+
+const DELETE_COMMANDS: &[Command] = &[
+    Command {
+        name: "breakpoints",
+        aliases: &[],
+        args: Arguments::Numbers,
+        usage: "delete breakpoints [<number>...]",
+        help: "Delete breakpoints, or all of them if no numbers are given.",
+        parse: parse_breakpoint_numbers,
+    },
+    Command {
+        name: "checkpoint",
+        aliases: &[],
+        args: Arguments::Numbers,
+        usage: "delete checkpoint <number>",
+        help: "Delete a checkpoint, killing its process.",
+        parse: |rest| {
+            Ok(DebuggerCommand::DeleteCheckpoint(parse_number(rest, "checkpoint number")?))
+        },
+    },
+    Command {
+        name: "pretty-printer",
+        aliases: &[],
+        args: Arguments::Numbers,
+        usage: "delete pretty-printer <number>",
+        help: "Unregister a pretty-printer, as numbered by \"info pretty-printer\".",
+        parse: |rest| {
+            let number = parse_number(rest, "pretty-printer number")?;
+            Ok(DebuggerCommand::DeletePrettyPrinter(number))
+        },
+    },
+];
+
+const INFO_COMMANDS: &[Command] = &[
+    Command {
+        name: "auxv",
+        aliases: &[],
+        args: Arguments::None,
+        usage: "info auxv",
+        help: "Show the auxiliary vector the kernel passed to the inferior.",
+        parse: |_| Ok(DebuggerCommand::InfoAuxv),
+    },
+    Command {
+        name: "breakpoints",
+        aliases: &[],
+        args: Arguments::None,
+        usage: "info breakpoints",
+        help: "List the breakpoints, dprintfs and tracepoints, with where they are set.",
+        parse: |_| Ok(DebuggerCommand::InfoBreakpoints),
+    },
+    Command {
+        name: "checkpoints",
+        aliases: &[],
+        args: Arguments::None,
+        usage: "info checkpoints",
+        help: "List the checkpoints.",
+        parse: |_| Ok(DebuggerCommand::InfoCheckpoints),
+    },
+    Command {
+        name: "display",
+        aliases: &[],
+        args: Arguments::None,
+        usage: "info display",
+        help: "List the auto-display expressions.",
+        parse: |_| Ok(DebuggerCommand::InfoDisplay),
+    },
+    Command {
+        name: "functions",
+        aliases: &[],
+        args: Arguments::Words,
+        usage: "info functions [regex]",
+        help: "List the functions with debugging information, or those whose names match regex.",
+        parse: |rest| Ok(DebuggerCommand::InfoFunctions(rest.to_string())),
+    },
+    Command {
+        name: "heap",
+        aliases: &[],
+        args: Arguments::None,
+        usage: "info heap",
+        help: "List the malloc'd blocks that are still in use, and the free space.\n\
+               Walks the chunks of glibc's main arena, so only the main thread's heap is shown.",
+        parse: |_| Ok(DebuggerCommand::InfoHeap),
+    },
+    Command {
+        name: "line",
+        aliases: &[],
+        args: Arguments::Location,
+        usage: "info line <location>",
+        help: "Show which addresses the code for a source line occupies.",
+        parse: |rest| Ok(DebuggerCommand::InfoLine(required(rest, "location")?.to_string())),
+    },
+    Command {
+        name: "locals",
+        aliases: &[],
+        args: Arguments::None,
+        usage: "info locals",
+        help: "Print the local variables in scope, grouped by the block declaring them.",
+        parse: |_| Ok(DebuggerCommand::InfoLocals),
+    },
+    Command {
+        name: "pretty-printer",
+        aliases: &[],
+        args: Arguments::None,
+        usage: "info pretty-printer",
+        help: "List the registered pretty-printers and the types they apply to.",
+        parse: |_| Ok(DebuggerCommand::InfoPrettyPrinters),
+    },
+    Command {
+        name: "proc",
+        aliases: &[],
+        args: Arguments::Words,
+        usage: "info proc [mappings]",
+        help: "Show the inferior's command line, working directory, executable and status.\n\
+               \"info proc mappings\" lists the memory regions mapped into it instead.",
+        parse: |rest| match rest {
+            "" => Ok(DebuggerCommand::InfoProc),
+            _ if "mappings".starts_with(rest) => Ok(DebuggerCommand::InfoProcMappings),
+            _ => Err(parse_error("Usage: info proc [mappings]")),
+        },
+    },
+    Command {
+        name: "record",
+        aliases: &[],
+        args: Arguments::None,
+        usage: "info record",
+        help: "Show how many instructions have been recorded.",
+        parse: |_| Ok(DebuggerCommand::InfoRecord),
+    },
+    Command {
+        name: "sharedlibrary",
+        aliases: &["dll"],
+        args: Arguments::None,
+        usage: "info sharedlibrary",
+        help: "List the shared libraries loaded into the inferior.",
+        parse: |_| Ok(DebuggerCommand::InfoSharedLibrary),
+    },
+    Command {
+        name: "source",
+        aliases: &[],
+        args: Arguments::None,
+        usage: "info source",
+        help: "Describe the source file the inferior is stopped in.",
+        parse: |_| Ok(DebuggerCommand::InfoSource),
+    },
+    Command {
+        name: "symbol",
+        aliases: &[],
+        args: Arguments::Expression,
+        usage: "info symbol <address>",
+        help: "Show which function or variable an address is in.",
+        parse: |rest| Ok(DebuggerCommand::InfoSymbol(required(rest, "address")?.to_string())),
+    },
+    Command {
+        name: "variables",
+        aliases: &[],
+        args: Arguments::Words,
+        usage: "info variables [regex]",
+        help: "List the global variables, or those whose names match regex.",
+        parse: |rest| Ok(DebuggerCommand::InfoVariables(rest.to_string())),
+    },
+];
+
+const SET_COMMANDS: &[Command] = &[
+    Command {
+        name: "args",
+        aliases: &[],
+        args: Arguments::Words,
+        usage: "set args [arg...]",
+        help: "Set the arguments for future runs of the program.\n\
+               \"run\" with no arguments uses them. Like the arguments to \"run\", they can\n\
+               redirect the standard streams.",
+        parse: |rest| {
+            Ok(DebuggerCommand::SetArgs(rest.split_whitespace().map(str::to_string).collect()))
+        },
+    },
+    Command {
+        name: "confirm",
+        aliases: &[],
+        args: Arguments::Words,
+        usage: "set confirm on|off",
+        help: "Set whether to ask before doing things like killing a running program.",
+        parse: |rest| set_option("confirm", rest),
+    },
+    Command {
+        name: "cwd",
+        aliases: &[],
+        args: Arguments::Words,
+        usage: "set cwd [directory]",
+        help: "Set the directory future runs of the program start in.\n\
+               With no directory, they start in deet's working directory.",
+        parse: |rest| Ok(DebuggerCommand::SetCwd(optional(rest))),
+    },
+    Command {
+        name: "environment",
+        aliases: &["env"],
+        args: Arguments::Words,
+        usage: "set environment <name> [=] <value>",
+        help: "Set an environment variable for future runs of the program.",
+        parse: |rest| {
+            let (name, value) = match rest.split_once('=') {
+                Some((name, value)) => (name.trim(), value.trim()),
+                None => split_word(rest),
+            };
+            if name.is_empty() || name.contains(char::is_whitespace) {
+                return Err(parse_error("Usage: set environment <name> [=] <value>"));
+            }
+            Ok(DebuggerCommand::SetEnvironment(name.to_string(), value.to_string()))
+        },
+    },
+    Command {
+        name: "inferior-tty",
+        aliases: &[],
+        args: Arguments::Words,
+        usage: "set inferior-tty [device]",
+        help: "Give the program a terminal of its own for future runs, like \"tty\".",
+        parse: |rest| Ok(DebuggerCommand::Tty(optional(rest))),
+    },
+    Command {
+        name: "output-radix",
+        aliases: &[],
+        args: Arguments::Words,
+        usage: "set output-radix 10|16",
+        help: "Set whether integers are printed in decimal or hexadecimal.",
+        parse: |rest| set_option("output-radix", rest),
+    },
+    PRETTY_PRINTER_COMMAND,
+    Command {
+        name: "print",
+        aliases: &["p"],
+        args: Arguments::Subcommand(SET_PRINT_COMMANDS),
+        usage: "set print <option> <value>",
+        help: "Set how values are printed.",
+        parse: |rest| parse_subcommand(SET_PRINT_COMMANDS, "set print", rest),
+    },
+    Command {
+        name: "variable",
+        aliases: &["var"],
+        args: Arguments::Expression,
+        usage: "set variable <variable> = <expression>",
+        help: "Assign to a variable without printing the result.",
+        parse: |rest| Ok(DebuggerCommand::SetVar(required(rest, "expression")?.to_string())),
+    },
+];
+
+const SET_PRINT_COMMANDS: &[Command] = &[
+    Command {
+        name: "elements",
+        aliases: &[],
+        args: Arguments::Words,
+        usage: "set print elements <number>|unlimited",
+        help: "Set how many string characters or array elements are printed.",
+        parse: |rest| set_option("print elements", rest),
+    },
+    Command {
+        name: "pretty",
+        aliases: &[],
+        args: Arguments::Words,
+        usage: "set print pretty on|off",
+        help: "Set whether structs are printed with one member per line.",
+        parse: |rest| set_option("print pretty", rest),
+    },
+];
+
+const SHOW_COMMANDS: &[Command] = &[
+    Command {
+        name: "args",
+        aliases: &[],
+        args: Arguments::None,
+        usage: "show args",
+        help: "Show the arguments for future runs of the program.",
+        parse: |_| Ok(DebuggerCommand::ShowArgs),
+    },
+    Command {
+        name: "confirm",
+        aliases: &[],
+        args: Arguments::None,
+        usage: "show confirm",
+        help: "Show whether deet asks before doing things like killing a running program.",
+        parse: |_| Ok(DebuggerCommand::ShowOption("confirm".to_string())),
+    },
+    Command {
+        name: "cwd",
+        aliases: &[],
+        args: Arguments::None,
+        usage: "show cwd",
+        help: "Show the directory future runs of the program start in.",
+        parse: |_| Ok(DebuggerCommand::ShowCwd),
+    },
+    Command {
+        name: "environment",
+        aliases: &["env"],
+        args: Arguments::Words,
+        usage: "show environment [name]",
+        help: "Show the environment future runs of the program get, or one variable in it.",
+        parse: |rest| Ok(DebuggerCommand::ShowEnvironment(optional(rest))),
+    },
+    Command {
+        name: "inferior-tty",
+        aliases: &[],
+        args: Arguments::None,
+        usage: "show inferior-tty",
+        help: "Show the terminal future runs of the program use.",
+        parse: |_| Ok(DebuggerCommand::ShowTty),
+    },
+    Command {
+        name: "output-radix",
+        aliases: &[],
+        args: Arguments::None,
+        usage: "show output-radix",
+        help: "Show whether integers are printed in decimal or hexadecimal.",
+        parse: |_| Ok(DebuggerCommand::ShowOption("output-radix".to_string())),
+    },
+    Command {
+        name: "pretty-printer",
+        aliases: &[],
+        args: Arguments::None,
+        usage: "show pretty-printer",
+        help: "List the registered pretty-printers and the types they apply to.",
+        parse: |_| Ok(DebuggerCommand::InfoPrettyPrinters),
+    },
+    Command {
+        name: "print",
+        aliases: &["p"],
+        args: Arguments::Subcommand(SHOW_PRINT_COMMANDS),
+        usage: "show print [option]",
+        help: "Show how values are printed.",
+        parse: |rest| match rest {
+            "" => Ok(DebuggerCommand::ShowOption("print".to_string())),
+            rest => parse_subcommand(SHOW_PRINT_COMMANDS, "show print", rest),
+        },
+    },
+];
+
+const SHOW_PRINT_COMMANDS: &[Command] = &[
+    Command {
+        name: "elements",
+        aliases: &[],
+        args: Arguments::None,
+        usage: "show print elements",
+        help: "Show how many string characters or array elements are printed.",
+        parse: |_| Ok(DebuggerCommand::ShowOption("print elements".to_string())),
+    },
+    Command {
+        name: "pretty",
+        aliases: &[],
+        args: Arguments::None,
+        usage: "show print pretty",
+        help: "Show whether structs are printed with one member per line.",
+        parse: |_| Ok(DebuggerCommand::ShowOption("print pretty".to_string())),
+    },
+];
+
+const UNSET_COMMANDS: &[Command] = &[
+    Command {
+        name: "environment",
+        aliases: &["env"],
+        args: Arguments::Words,
+        usage: "unset environment [name]",
+        help: "Remove an environment variable from future runs of the program, or all of them.",
+        parse: |rest| Ok(DebuggerCommand::UnsetEnvironment(optional(rest))),
+    },
+];
+
+impl DebuggerCommand {
+    /// Parses a full line of input. Commands may be abbreviated to any unique prefix.
+    pub fn from_line(line: &str) -> Result<DebuggerCommand, DebuggerError> {
+        let (word, rest) = split_word(line);
+        if word.is_empty() {
+            return Err(parse_error("No command given."));
+        }
+        (lookup(COMMANDS, word, None)?.parse)(rest)
+    }
+}
+
+/// Splits off the first word of line, returning it and the rest of the line, both trimmed.
+pub fn split_word(line: &str) -> (&str, &str) {
+    let line = line.trim_start();
+    let end = line.find(char::is_whitespace).unwrap_or(line.len());
+    (&line[..end], line[end..].trim())
+}
+
+/// Returns whether name is the exact name or alias of a top-level command.
+pub fn is_builtin(name: &str) -> bool {
+    COMMANDS.iter().any(|c| c.name == name || c.aliases.contains(&name))
+}
+
+/// Finds a command in table by name, alias, or unique prefix of its name. parent is the name of
+/// the command that table belongs to, if any, for error messages.
+pub fn lookup(
+    table: &'static [Command],
+    word: &str,
+    parent: Option<&str>,
+) -> Result<&'static Command, DebuggerError> {
+    let kind = parent.map(|p| format!("{} ", p)).unwrap_or_default();
+    if let Some(command) = table
+        .iter()
+        .find(|c| c.name == word || c.aliases.contains(&word))
+    {
+        return Ok(command);
+    }
+    let matches: Vec<&Command> = table.iter().filter(|c| c.name.starts_with(word)).collect();
+    match matches.as_slice() {
+        [command] => Ok(command),
+        [] => Err(parse_error(&format!(
+            "Undefined {}command: \"{}\".  Try \"help{}\".",
+            kind,
+            word,
+            parent.map(|p| format!(" {}", p)).unwrap_or_default()
+        ))),
+        _ => {
+            let names: Vec<&str> = matches.iter().map(|c| c.name).collect();
+            Err(parse_error(&format!(
+                "Ambiguous {}command \"{}\": {}.",
+                kind,
+                word,
+                names.join(", ")
+            )))
+        }
+    }
+}
+
+fn parse_subcommand(
+    table: &'static [Command],
+    parent: &str,
+    rest: &str,
+) -> Result<DebuggerCommand, DebuggerError> {
+    let (word, rest) = split_word(rest);
+    if word.is_empty() {
+        return Err(parse_error(&format!(
+            "\"{}\" must be followed by the name of a subcommand.  Try \"help {}\".",
+            parent, parent
+        )));
+    }
+    (lookup(table, word, Some(parent))?.parse)(rest)
+}
+
+/// Returns help for topic, which is a command name possibly followed by subcommand names, or a
+/// list of all commands if topic is empty.
+pub fn help(topic: &str) -> Result<String, DebuggerError> {
+    let mut table = COMMANDS;
+    let mut path: Vec<&str> = Vec::new();
+    let mut command: Option<&Command> = None;
+    for word in topic.split_whitespace() {
+        let parent = if path.is_empty() { None } else { Some(path.join(" ")) };
+        let found = lookup(table, word, parent.as_deref())?;
+        path.push(found.name);
+        command = Some(found);
+        match found.args {
+            Arguments::Subcommand(subcommands) => table = subcommands,
+            _ => break,
+        }
+    }
+    let command = match command {
+        Some(command) => command,
+        None => {
+            return Ok(format!(
+                "List of commands:\n\n{}\n\
+                 Type \"help\" followed by a command name for more information.",
+                list_commands(COMMANDS)
+            ))
+        }
+    };
+    let mut text = format!("Usage: {}\n\n{}", command.usage, command.help);
+    if !command.aliases.is_empty() {
+        text += &format!("\n\nAliases: {}", command.aliases.join(", "));
+    }
+    if let Arguments::Subcommand(subcommands) = command.args {
+        text += &format!(
+            "\n\nList of {} subcommands:\n\n{}",
+            path.join(" "),
+            list_commands(subcommands).trim_end()
+        );
+    }
+    Ok(text)
+}
+
+fn list_commands(table: &[Command]) -> String {
+    table
+        .iter()
+        .map(|c| format!("{:<20} -- {}\n", c.name, c.help.lines().next().unwrap_or_default()))
+        .collect()
+}
+
+fn set_option(name: &str, value: &str) -> Result<DebuggerCommand, DebuggerError> {
+    Ok(DebuggerCommand::SetOption(name.to_string(), value.to_string()))
+}
+
+fn parse_error(msg: &str) -> DebuggerError {
+    DebuggerError::Parse(msg.to_string())
+}
+
+/// Returns the argument text, or an error naming what was expected if it's empty.
+fn required<'a>(rest: &'a str, what: &str) -> Result<&'a str, DebuggerError> {
+    if rest.is_empty() {
+        return Err(parse_error(&format!("Argument required ({}).", what)));
+    }
+    Ok(rest)
+}
+
+/// Returns the argument text, or None if there isn't any.
+fn optional(rest: &str) -> Option<String> {
+    Some(rest.to_string()).filter(|rest| !rest.is_empty())
+}
+
+/// Parses a numeric argument, describing what was expected if it's missing or malformed.
+fn parse_number(token: &str, what: &str) -> Result<usize, DebuggerError> {
+    required(token, what)?
+        .parse()
+        .map_err(|_| parse_error(&format!("Invalid {}: \"{}\"", what, token)))
+}
+
+/// Parses the breakpoint numbers given to delete.
+fn parse_breakpoint_numbers(rest: &str) -> Result<DebuggerCommand, DebuggerError> {
+    let numbers = rest
+        .split_whitespace()
+        .map(|n| parse_number(n, "breakpoint number"))
+        .collect::<Result<Vec<usize>, _>>()?;
+    Ok(DebuggerCommand::DeleteBreakpoints(numbers))
+}
+
+/// Parses the arguments to dprintf, which look like `location,"format",arg1,arg2`.
+fn parse_dprintf(rest: &str) -> Result<DebuggerCommand, DebuggerError> {
+    let usage = || parse_error("Usage: dprintf location,\"format\",arg1,arg2,...");
+    let (location, rest) = rest.split_once(',').ok_or_else(usage)?;
+    let rest = rest.trim_start().strip_prefix('"').ok_or_else(usage)?;
+    let mut format = String::new();
+    let mut chars = rest.char_indices();
+    let end = loop {
+        match chars.next().ok_or_else(usage)? {
+            (i, '"') => break i,
+            (_, '\\') => match chars.next().ok_or_else(usage)?.1 {
+                'n' => format.push('\n'),
+                't' => format.push('\t'),
+                '0' => format.push('\0'),
+                other => format.push(other),
             },
-            // Default case:
+            (_, c) => format.push(c),
+        }
+    };
+    let rest = rest[end + 1..].trim();
+    let args = if rest.is_empty() {
+        Vec::new()
+    } else {
+        let args = split_arguments(rest.strip_prefix(',').ok_or_else(usage)?);
+        if args.iter().any(|arg| arg.is_empty()) {
+            return Err(usage());
+        }
+        args
+    };
+    Ok(DebuggerCommand::Dprintf(location.trim().to_string(), format, args))
+}
+
+/// Splits a list of expressions at the commas that aren't inside parentheses, brackets or
+/// quotes, so that `f(a, b), s[1]` is two arguments.
+fn split_arguments(list: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut start = 0;
+    let mut depth = 0usize;
+    let mut quote = None;
+    let mut chars = list.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match (quote, c) {
+            (Some(_), '\\') => {
+                chars.next();
+            }
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '(' | '[' | '{') => depth += 1,
+            (None, ')' | ']' | '}') => depth = depth.saturating_sub(1),
+            (None, ',') if depth == 0 => {
+                args.push(list[start..i].trim().to_string());
+                start = i + 1;
+            }
+            (None, _) => {}
+        }
+    }
+    args.push(list[start..].trim().to_string());
+    args
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dprintf(line: &str) -> Option<(String, String, Vec<String>)> {
+        match DebuggerCommand::from_line(line) {
+            Ok(DebuggerCommand::Dprintf(location, format, args)) => Some((location, format, args)),
             _ => None,
         }
     }
+
+    #[test]
+    fn dprintf_splits_arguments_only_at_top_level_commas() {
+        let (location, format, args) =
+            dprintf(r#"dprintf 12, "%d %s\n", f(a, b), s[g(1, 2)], "x,y", ',' "#).unwrap();
+        assert_eq!(location, "12");
+        assert_eq!(format, "%d %s\n");
+        assert_eq!(args, ["f(a, b)", "s[g(1, 2)]", "\"x,y\"", "','"]);
+    }
+
+    #[test]
+    fn dprintf_takes_no_arguments() {
+        let (location, format, args) = dprintf(r#"dprintf main,"hi \"there\"\n""#).unwrap();
+        assert_eq!((location.as_str(), format.as_str()), ("main", "hi \"there\"\n"));
+        assert!(args.is_empty());
+    }
+
+    #[test]
+    fn dprintf_rejects_malformed_arguments() {
+        for line in [
+            r#"dprintf main,"%d" x"#,
+            r#"dprintf main,"%d"x, y"#,
+            r#"dprintf main,"%d",x,,y"#,
+            r#"dprintf main,"%d","#,
+            r#"dprintf main,"%d"#,
+            r#"dprintf main"#,
+        ] {
+            assert!(dprintf(line).is_none(), "{} was accepted", line);
+        }
+    }
 }
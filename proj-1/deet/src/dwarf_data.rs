@@ -1,7 +1,13 @@
-use crate::gimli_wrapper;
+use crate::dwarf_index::{self, IndexSection, NameIndex, NameKind};
+use crate::gimli_wrapper::{self, DwarfReader};
+use crate::symbols::{self, Symbol, SymbolTable};
 use addr2line::Context;
-use object::Object;
+use object::{Object, ObjectSection};
+use regex::Regex;
+use std::cell::OnceCell;
+use std::collections::HashMap;
 use std::convert::TryInto;
+use std::path::{Path, PathBuf};
 use std::{fmt, fs};
 
 #[derive(Debug)]
@@ -10,14 +16,105 @@ pub enum Error {
     DwarfFormatError(gimli_wrapper::Error),
 }
 
+/// A compilation unit. Only its name and address ranges are read up front; its DIEs and line
+/// table are parsed the first time something in it is looked up.
+struct Unit {
+    header: gimli::UnitHeader<DwarfReader>,
+    /// Where the DIEs are, if this is the skeleton of a split unit
+    split: Option<gimli_wrapper::SplitUnit>,
+    name: String,
+    loaded: OnceCell<LoadedUnit>,
+}
+
+/// The types of split units are keyed by their offset in the .dwo plus a multiple of this, which
+/// keeps them apart from each other and from the offsets of other units' types.
+const SPLIT_UNIT_SPACING: usize = 1 << 32;
+
+fn split_offset_base(unit: usize) -> usize {
+    (unit + 1) * SPLIT_UNIT_SPACING
+}
+
+/// Where separate debug info files are installed, as with gdb's debug-file-directory.
+const DEBUG_FILE_DIRECTORY: &str = "/usr/lib/debug";
+
+#[derive(Default)]
+struct LoadedUnit {
+    file: File,
+    types: gimli_wrapper::TypeTable,
+    /// The DIE offset of the first type with each name
+    types_by_name: HashMap<String, usize>,
+    /// Indices into file.functions of the functions with code, sorted by address
+    functions_by_addr: Vec<usize>,
+}
+
+impl LoadedUnit {
+    fn new(file: File, types: gimli_wrapper::TypeTable) -> LoadedUnit {
+        let mut offsets: Vec<&usize> = types.keys().collect();
+        offsets.sort();
+        let mut types_by_name = HashMap::new();
+        for offset in offsets {
+            types_by_name.entry(types[offset].name.clone()).or_insert(*offset);
+        }
+        // Types in namespaces can also be named without them, if that's unambiguous enough
+        for (offset, t) in &types {
+            let name = unqualified(&t.name);
+            if name.len() < t.name.len() {
+                types_by_name.entry(name.to_string()).or_insert(*offset);
+            }
+        }
+        let mut functions_by_addr: Vec<usize> = (0..file.functions.len())
+            .filter(|&i| file.functions[i].text_length > 0)
+            .collect();
+        functions_by_addr.sort_by_key(|&i| file.functions[i].address);
+        LoadedUnit {
+            file,
+            types,
+            types_by_name,
+            functions_by_addr,
+        }
+    }
+
+    /// Returns the functions with code, in order of address.
+    fn functions(&self) -> impl Iterator<Item = &Function> {
+        self.functions_by_addr.iter().map(move |&i| &self.file.functions[i])
+    }
+
+    fn get_function_containing(&self, addr: usize) -> Option<&Function> {
+        let end = self
+            .functions_by_addr
+            .partition_point(|&i| self.file.functions[i].address <= addr);
+        let func = &self.file.functions[*self.functions_by_addr.get(end.checked_sub(1)?)?];
+        (addr < func.address + func.text_length).then_some(func)
+    }
+}
+
 pub struct DwarfData {
-    files: Vec<File>,
+    dwarf: gimli::Dwarf<DwarfReader>,
+    /// The compilation units, in .debug_info order
+    units: Vec<Unit>,
+    /// (start, end, unit) for each range of code, sorted by start address
+    unit_ranges: Vec<(usize, usize, usize)>,
+    names: NameIndex,
+    entry_point: usize,
     addr2line: Context<addr2line::gimli::EndianRcSlice<addr2line::gimli::RunTimeEndian>>,
+    /// The target's allocated sections, sorted by address
+    sections: Vec<Section>,
+    /// For code and data that DWARF doesn't describe
+    symbols: SymbolTable,
+}
+
+/// A section of the target that is loaded into memory, e.g. .text or .data.
+#[derive(Debug, Clone)]
+pub struct Section {
+    pub name: String,
+    pub address: usize,
+    pub size: usize,
 }
 
 impl fmt::Debug for DwarfData {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "DwarfData {{files: {:?}}}", self.files)
+        let names: Vec<&str> = self.units.iter().map(|unit| unit.name.as_str()).collect();
+        write!(f, "DwarfData {{units: {:?}}}", names)
     }
 }
 
@@ -29,39 +126,178 @@ impl From<gimli_wrapper::Error> for Error {
 
 impl DwarfData {
     pub fn from_file(path: &str) -> Result<DwarfData, Error> {
-        let file = fs::File::open(path).or(Err(Error::ErrorOpeningFile))?;
-        let mmap = unsafe { memmap2::Mmap::map(&file).or(Err(Error::ErrorOpeningFile))? };
-        let object = object::File::parse(&*mmap)
-            .or_else(|e| Err(gimli_wrapper::Error::ObjectError(e.to_string())))?;
-        let endian = if object.is_little_endian() {
-            gimli::RunTimeEndian::Little
-        } else {
-            gimli::RunTimeEndian::Big
+        let mmap = map_file(Path::new(path)).ok_or(Error::ErrorOpeningFile)?;
+        let object = parse_object(&mmap)?;
+        let endian = endianness(&object);
+        // A stripped binary's debug info may be in a separate file
+        let debug_mmap = match object.section_by_name(".debug_info") {
+            Some(_) => None,
+            None => find_debug_file(path, &object).and_then(|debug_path| map_file(&debug_path)),
+        };
+        let debug_object = match &debug_mmap {
+            Some(debug_mmap) => Some(parse_object(debug_mmap)?),
+            None => None,
         };
+        let dwarf_object = debug_object.as_ref().unwrap_or(&object);
+        let dwarf = gimli_wrapper::load_dwarf(dwarf_object, endian)?;
+        // Split units are looked up in a package next to the binary before their own .dwo files
+        let package_path = format!("{}.dwp", path);
+        let package = map_file(Path::new(&package_path)).and_then(|package_mmap| {
+            let package = parse_object(&package_mmap)
+                .and_then(|object| Ok(gimli_wrapper::load_package(&object, endian)?));
+            package
+                .map_err(|err| println!("Could not read {}: {:?}", package_path, err))
+                .ok()
+        });
+        let mut sections: Vec<Section> = object
+            .sections()
+            .filter(|section| section.address() != 0 && section.size() != 0)
+            .filter_map(|section| {
+                Some(Section {
+                    name: section.name().ok()?.to_string(),
+                    address: section.address() as usize,
+                    size: section.size() as usize,
+                })
+            })
+            .collect();
+        sections.sort_by_key(|section| section.address);
+
+        let headers = gimli_wrapper::unit_headers(&dwarf)?;
+        let unit_numbers: HashMap<usize, usize> = headers
+            .iter()
+            .enumerate()
+            .map(|(i, header)| (gimli_wrapper::unit_offset(header), i))
+            .collect();
+        let index_section = read_index_section(dwarf_object, &dwarf, endian);
+        let mut names = NameIndex::default();
+        for (kind, name, offset) in index_section.names {
+            if let Some(&unit) = unit_numbers.get(&offset) {
+                names.insert(kind, name, unit);
+            }
+        }
+        let mut unit_ranges = Vec::new();
+        for (offset, start, end) in gimli_wrapper::arange_ranges(&dwarf)? {
+            if let Some(&unit) = unit_numbers.get(&offset) {
+                unit_ranges.push((start, end, unit));
+            }
+        }
+        let has_aranges: Vec<bool> = (0..headers.len())
+            .map(|unit| unit_ranges.iter().any(|range| range.2 == unit))
+            .collect();
+
+        let mut units = Vec::with_capacity(headers.len());
+        for (i, header) in headers.into_iter().enumerate() {
+            let unit = dwarf.unit(header.clone()).map_err(gimli_wrapper::Error::from)?;
+            let split = unit
+                .dwo_id
+                .and_then(|dwo_id| load_split_unit(path, &dwarf, &unit, dwo_id, package.as_ref()));
+            let (name, ranges) = gimli_wrapper::unit_summary(&dwarf, &unit, split.as_ref())?;
+            if !has_aranges[i] {
+                unit_ranges.extend(ranges.into_iter().map(|(start, end)| (start, end, i)));
+            }
+            if !index_section.units.contains(&gimli_wrapper::unit_offset(&header)) {
+                let unit_names = match &split {
+                    Some(split) => {
+                        gimli_wrapper::top_level_names(&split.dwarf, &split.unit(&unit)?)?
+                    }
+                    None => gimli_wrapper::top_level_names(&dwarf, &unit)?,
+                };
+                for (kind, name) in unit_names {
+                    names.insert(kind, name, i);
+                }
+            }
+            units.push(Unit {
+                header,
+                split,
+                name,
+                loaded: OnceCell::new(),
+            });
+        }
+        names.finish();
+        unit_ranges.sort();
+
+        // Stripped binaries may have left their symbol table in the debug file too
+        let mut symbols = SymbolTable::from_object(&object);
+        if symbols.iter().next().is_none() {
+            symbols = SymbolTable::from_object(dwarf_object);
+        }
+
         Ok(DwarfData {
-            files: gimli_wrapper::load_file(&object, endian)?,
-            addr2line: Context::new(&object).or_else(|e| Err(gimli_wrapper::Error::from(e)))?,
+            dwarf,
+            units,
+            unit_ranges,
+            names,
+            entry_point: object.entry() as usize,
+            addr2line: Context::new(dwarf_object).map_err(gimli_wrapper::Error::from)?,
+            sections,
+            symbols,
         })
     }
 
+    /// Returns a compilation unit, parsing it if this is the first time it's needed.
+    fn unit(&self, index: usize) -> &LoadedUnit {
+        let unit = &self.units[index];
+        unit.loaded.get_or_init(|| {
+            let offset_base = if unit.split.is_some() { split_offset_base(index) } else { 0 };
+            let header = unit.header.clone();
+            match gimli_wrapper::load_unit(&self.dwarf, header, unit.split.as_ref(), offset_base) {
+                Ok((file, types)) => LoadedUnit::new(file, types),
+                Err(err) => {
+                    println!("Could not load debugging symbols for {}: {:?}", unit.name, err);
+                    LoadedUnit::default()
+                }
+            }
+        })
+    }
+
+    /// Returns every compilation unit, parsing all of them. Avoid this where an index will do.
+    fn all_units(&self) -> impl Iterator<Item = &LoadedUnit> {
+        (0..self.units.len()).map(move |i| self.unit(i))
+    }
+
+    /// Returns the units that define a name, according to the name index.
+    fn units_defining(&self, kind: NameKind, name: &str) -> impl Iterator<Item = &LoadedUnit> {
+        self.names.get(kind, name).iter().map(move |&i| self.unit(i))
+    }
+
+    /// Returns the unit whose code contains addr.
+    fn unit_containing(&self, addr: usize) -> Option<&LoadedUnit> {
+        let end = self.unit_ranges.partition_point(|&(start, _, _)| start <= addr);
+        // Ranges of different units don't overlap, but one unit's ranges might
+        self.unit_ranges[..end]
+            .iter()
+            .rev()
+            .find(|&&(_, range_end, _)| addr < range_end)
+            .map(|&(_, _, unit)| self.unit(unit))
+    }
+
     #[allow(dead_code)]
     fn get_target_file(&self, file: &str) -> Option<&File> {
-        self.files.iter().find(|f| {
-            f.name == file || (!file.contains("/") && f.name.ends_with(&format!("/{}", file)))
-        })
+        // Editors give absolute paths, while units are often named relative to where they were
+        // compiled
+        let index = self.units.iter().position(|unit| {
+            unit.name == file
+                || (!file.contains('/') && unit.name.ends_with(&format!("/{}", file)))
+                || (file.starts_with('/') && file.ends_with(&format!("/{}", unit.name)))
+        })?;
+        Some(&self.unit(index).file)
     }
 
     #[allow(dead_code)]
     pub fn get_addr_for_line(&self, file: Option<&str>, line_number: usize) -> Option<usize> {
         let target_file = match file {
             Some(filename) => self.get_target_file(filename)?,
-            None => self.files.get(0)?,
+            None if self.units.is_empty() => return None,
+            None => &self.unit(0).file,
         };
+        // Functions aren't necessarily laid out in source order (rustc puts main first), so find
+        // the nearest line rather than the first one in address order
         Some(
             target_file
                 .lines
                 .iter()
-                .find(|line| line.number >= line_number)?
+                .filter(|line| line.number >= line_number)
+                .min_by_key(|line| (line.number, line.address))?
                 .address,
         )
     }
@@ -76,17 +312,199 @@ impl DwarfData {
                     .find(|func| func.name == func_name)?
                     .address,
             ),
-            None => {
-                for file in &self.files {
-                    if let Some(func) = file.functions.iter().find(|func| func.name == func_name) {
-                        return Some(func.address);
-                    }
-                }
-                None
-            }
+            None => match self.get_function(func_name) {
+                Some(func) => Some(func.address),
+                None => Some(self.symbols.get(func_name, true)?.address),
+            },
         }
     }
 
+    /// Returns the address of the target's entry point (usually _start).
+    pub fn entry_point(&self) -> usize {
+        self.entry_point
+    }
+
+    /// Returns the type whose DIE is at the given offset.
+    pub fn get_type(&self, offset: usize) -> Option<&Type> {
+        let index = if offset >= SPLIT_UNIT_SPACING {
+            offset / SPLIT_UNIT_SPACING - 1
+        } else {
+            // The unit containing a DIE is the last one that starts before it
+            let end = self
+                .units
+                .partition_point(|unit| gimli_wrapper::unit_offset(&unit.header) <= offset);
+            end.checked_sub(1)?
+        };
+        if index >= self.units.len() {
+            return None;
+        }
+        self.unit(index).types.get(&offset)
+    }
+
+    /// Returns a type by its C name, e.g. "int", "struct Node" or a typedef name.
+    pub fn get_type_by_name(&self, name: &str) -> Option<&Type> {
+        // The index has the names of the DIEs, which don't include the keyword
+        let die_name = ["struct ", "union ", "enum "]
+            .iter()
+            .find_map(|keyword| name.strip_prefix(keyword))
+            .unwrap_or(name);
+        self.units_defining(NameKind::Type, unqualified(die_name))
+            .find_map(|unit| unit.types.get(unit.types_by_name.get(name)?))
+    }
+
+    /// Returns every function that has code in the target. This parses every compilation unit.
+    pub fn functions(&self) -> impl Iterator<Item = &Function> {
+        self.all_units().flat_map(LoadedUnit::functions)
+    }
+
+    /// Returns the line tables and functions of every compilation unit. This parses every
+    /// compilation unit.
+    pub fn files(&self) -> impl Iterator<Item = &File> {
+        self.all_units().map(|unit| &unit.file)
+    }
+
+    /// Returns the names of all functions, without parsing any compilation units.
+    pub fn function_names(&self) -> impl Iterator<Item = &str> {
+        self.names.names(NameKind::Function)
+    }
+
+    /// Returns the functions with code whose names match regex, along with the file each is in.
+    pub fn find_functions(&self, regex: &Regex) -> Vec<(&str, &Function)> {
+        self.units_with_names(NameKind::Function, regex)
+            .flat_map(|unit| unit.functions().map(move |func| (unit.file.name.as_str(), func)))
+            .filter(|(_, func)| regex.is_match(&func.name))
+            .collect()
+    }
+
+    /// Returns the units defining a name of the given kind that matches regex.
+    fn units_with_names(&self, kind: NameKind, regex: &Regex) -> impl Iterator<Item = &LoadedUnit> {
+        let mut units: Vec<usize> = self
+            .names
+            .names(kind)
+            .filter(|name| regex.is_match(name))
+            .flat_map(|name| self.names.get(kind, name).iter().copied())
+            .collect();
+        units.sort_unstable();
+        units.dedup();
+        units.into_iter().map(move |i| self.unit(i))
+    }
+
+    /// Returns the function with the given name.
+    pub fn get_function(&self, name: &str) -> Option<&Function> {
+        self.units_defining(NameKind::Function, name)
+            .find_map(|unit| unit.functions().find(|func| func.name == name))
+    }
+
+    /// Returns the function whose code contains addr.
+    pub fn get_function_containing(&self, addr: usize) -> Option<&Function> {
+        self.unit_containing(addr)?.get_function_containing(addr)
+    }
+
+    /// Returns the function that a nested function (a GNU C extension) is defined in.
+    pub fn get_enclosing_function(&self, func: &Function) -> Option<&Function> {
+        let index = func.enclosing.as_ref()?.index;
+        self.unit_containing(func.address)?.file.functions.get(index)
+    }
+
+    /// Returns the names of all global variables, without parsing any compilation units.
+    pub fn global_variable_names(&self) -> impl Iterator<Item = &str> {
+        self.names.names(NameKind::Variable)
+    }
+
+    /// Returns the global variables whose names match regex, along with the file each is in.
+    pub fn find_global_variables(&self, regex: &Regex) -> Vec<(&str, &Variable)> {
+        self.units_with_names(NameKind::Variable, regex)
+            .flat_map(|unit| {
+                let file = unit.file.name.as_str();
+                unit.file.global_variables.iter().map(move |var| (file, var))
+            })
+            .filter(|(_, var)| regex.is_match(&var.name))
+            .collect()
+    }
+
+    /// Returns the global variable with the given name.
+    pub fn get_global_variable(&self, name: &str) -> Option<&Variable> {
+        self.units_defining(NameKind::Variable, name)
+            .find_map(|unit| unit.file.global_variables.iter().find(|var| var.name == name))
+    }
+
+    /// Returns the global variable whose storage contains addr.
+    pub fn get_global_variable_containing(&self, addr: usize) -> Option<&Variable> {
+        self.names
+            .units(NameKind::Variable)
+            .into_iter()
+            .flat_map(|i| self.unit(i).file.global_variables.iter())
+            .find(|var| match var.location {
+                Location::Address(start) => {
+                    start <= addr && addr < start + var.entity_type.size.max(1)
+                }
+                Location::FramePointerOffset(_) => false,
+            })
+    }
+
+    /// Returns the section containing addr.
+    pub fn get_section_containing(&self, addr: usize) -> Option<&Section> {
+        let end = self.sections.partition_point(|section| section.address <= addr);
+        let section = self.sections.get(end.checked_sub(1)?)?;
+        (addr < section.address + section.size).then_some(section)
+    }
+
+    /// Returns the line table row covering addr, and the address where the code for the next
+    /// line starts.
+    pub fn get_line_range(&self, addr: usize) -> Option<(&Line, usize)> {
+        let lines = &self.unit_containing(addr)?.file.lines;
+        let end = lines.partition_point(|line| line.address <= addr);
+        let line = lines.get(end.checked_sub(1)?)?;
+        let next = lines[end..]
+            .iter()
+            .find(|next| next.number != line.number || next.file != line.file)
+            .map_or(line.address, |next| next.address);
+        Some((line, next))
+    }
+
+    /// Returns the paths of the source files the target was compiled from.
+    pub fn file_names(&self) -> impl Iterator<Item = &str> {
+        self.units.iter().map(|unit| unit.name.as_str())
+    }
+
+    /// Returns the address of the first line of func's body, just past the prologue that sets up
+    /// its stack frame and spills its arguments. Falls back to the function's entry address if
+    /// the line table has no later row for it.
+    pub fn get_addr_after_prologue(&self, func: &Function) -> usize {
+        let lines = match self.unit_containing(func.address) {
+            Some(unit) => &unit.file.lines,
+            None => return func.address,
+        };
+        let start = lines.partition_point(|line| line.address <= func.address);
+        lines
+            .get(start)
+            .map(|line| line.address)
+            .filter(|&addr| addr < func.address + func.text_length)
+            .unwrap_or(func.address)
+    }
+
+    /// Returns the addresses where the named function's body was inlined into its callers.
+    pub fn get_inlined_calls(&self, name: &str) -> Vec<&InlinedCall> {
+        self.units_defining(NameKind::Function, name)
+            .flat_map(|unit| unit.file.inlined_calls.iter())
+            .filter(|call| call.name == name)
+            .collect()
+    }
+
+    /// Returns true if addr is where the code for a line begins, rather than in the middle of it.
+    pub fn is_line_start(&self, addr: usize) -> bool {
+        self.unit_containing(addr).is_some_and(|unit| {
+            let lines = &unit.file.lines;
+            let i = lines.partition_point(|line| line.address < addr);
+            lines.get(i).is_some_and(|line| line.address == addr)
+        })
+    }
+
+    /// Returns true if addr lies within one of the functions described by the debug info.
+    pub fn contains_addr(&self, addr: usize) -> bool {
+        self.get_function_containing(addr).is_some()
+    }
+
     #[allow(dead_code)]
     pub fn get_line_from_addr(&self, curr_addr: usize) -> Option<Line> {
         let location = self
@@ -100,67 +518,417 @@ impl DwarfData {
         })
     }
 
-    #[allow(dead_code)]
+    /// Returns the functions that are executing at addr, innermost first: the calls that were
+    /// inlined there, and then the function whose machine code contains addr.
+    pub fn get_frames(&self, addr: usize) -> Vec<Frame> {
+        let mut frames = Vec::new();
+        if let Ok(mut iter) = self.addr2line.find_frames(addr as u64) {
+            while let Ok(Some(frame)) = iter.next() {
+                let function = match frame.function.as_ref().and_then(|f| f.raw_name().ok()) {
+                    Some(name) => symbols::demangle(&name),
+                    None => continue,
+                };
+                let line = frame.location.and_then(|location| {
+                    Some(Line {
+                        file: location.file?.to_string(),
+                        number: location.line? as usize,
+                        address: addr,
+                    })
+                });
+                frames.push(Frame {
+                    function,
+                    line,
+                    inlined: true,
+                });
+            }
+        }
+        match frames.last_mut() {
+            Some(outermost) => outermost.inlined = false,
+            None => frames.push(Frame {
+                function: self.get_function_from_addr(addr).unwrap_or_else(|| "??".to_string()),
+                line: self.get_line_from_addr(addr),
+                inlined: false,
+            }),
+        }
+        frames
+    }
+
+    /// Returns the name of the function containing curr_addr, from the symbol table if DWARF
+    /// doesn't describe it.
     pub fn get_function_from_addr(&self, curr_addr: usize) -> Option<String> {
         let frame = self
             .addr2line
             .find_frames(curr_addr.try_into().unwrap())
-            .ok()?
-            .next()
-            .ok()??;
-        Some(frame.function?.raw_name().ok()?.to_string())
+            .ok()
+            .and_then(|mut frames| frames.next().ok().flatten());
+        if let Some(function) = frame.and_then(|frame| frame.function) {
+            return Some(symbols::demangle(&function.raw_name().ok()?));
+        }
+        // addr2line doesn't read split units
+        match self.get_function_containing(curr_addr) {
+            Some(func) => Some(func.name.clone()),
+            None => Some(self.get_symbol_containing(curr_addr)?.name.clone()),
+        }
     }
 
-    #[allow(dead_code)]
-    pub fn print(&self) {
-        for file in &self.files {
-            println!("------");
-            println!("{}", file.name);
-            println!("------");
-
-            println!("Global variables:");
-            for var in &file.global_variables {
-                println!(
-                    "  * {} ({}, located at {}, declared at line {})",
-                    var.name, var.entity_type.name, var.location, var.line_number
-                );
-            }
+    /// Returns the symbol table entry for a function or global variable.
+    pub fn get_symbol(&self, name: &str, is_function: bool) -> Option<&Symbol> {
+        self.symbols.get(name, is_function)
+    }
+
+    /// Returns the symbol table entry whose code or data contains addr.
+    pub fn get_symbol_containing(&self, addr: usize) -> Option<&Symbol> {
+        self.symbols.get_containing(addr)
+    }
+
+    /// Returns the function or data symbols whose names match regex and that DWARF doesn't
+    /// describe.
+    pub fn find_non_debugging_symbols(&self, regex: &Regex, functions: bool) -> Vec<&Symbol> {
+        self.symbols
+            .iter()
+            .filter(|symbol| symbol.is_function == functions && regex.is_match(&symbol.name))
+            .filter(|symbol| {
+                if functions {
+                    !self.contains_addr(symbol.address)
+                } else {
+                    self.get_global_variable(&symbol.name).is_none()
+                }
+            })
+            .collect()
+    }
 
-            println!("Functions:");
-            for func in &file.functions {
-                println!(
-                    "  * {} (declared on line {}, located at {:#x}, {} bytes long)",
-                    func.name, func.line_number, func.address, func.text_length
-                );
-                for var in &func.variables {
-                    println!(
-                        "    * Variable: {} ({}, located at {}, declared at line {})",
-                        var.name, var.entity_type.name, var.location, var.line_number
-                    );
+    /// Describes a type the way C would declare it, with the members of structs and unions and
+    /// the enumerators of enums spelled out. Pointers and arrays show the type they're built on.
+    pub fn describe_type(&self, t: &Type) -> String {
+        let mut base = t;
+        while let TypeKind::Pointer(inner) | TypeKind::Array(inner, _) = &base.kind {
+            base = inner;
+        }
+        let suffix = t.name.strip_prefix(base.name.as_str()).unwrap_or("");
+        let body = match &base.kind {
+            TypeKind::Struct(members) | TypeKind::Union(members) => {
+                let keyword = if let TypeKind::Union(_) = base.kind { "union" } else { "struct" };
+                // A typedef names the struct itself in the common `typedef struct Node {...} Node`
+                let tag = format!("{} {}", keyword, base.name);
+                let mut body = if base.name.starts_with(keyword) {
+                    format!("{} {{\n", base.name)
+                } else if self.get_type_by_name(&tag).is_some_and(|t| t.kind == base.kind)
+                    // Rust types are named without the keyword
+                    || base.name.contains("::")
+                    || base.name.starts_with('&')
+                {
+                    format!("{} {{\n", tag)
+                } else {
+                    format!("{} {{\n", keyword)
+                };
+                for member in members {
+                    let declaration = match self.get_type(member.type_offset) {
+                        Some(member_type) => member_type.declaration(&member.name),
+                        None => format!("<unknown type> {}", member.name),
+                    };
+                    body.push_str(&format!("    {};\n", declaration));
                 }
+                body + "}"
             }
+            TypeKind::Variants(_, variants) => {
+                let mut body = format!("enum {} {{\n", base.name);
+                for (_, variant) in variants {
+                    body.push_str(&format!("    {},\n", self.describe_variant(variant)));
+                }
+                body + "}"
+            }
+            TypeKind::Enum(enumerators) => {
+                let mut next = 0;
+                let enumerators: Vec<String> = enumerators
+                    .iter()
+                    .map(|(name, value)| {
+                        let item = if *value == next {
+                            name.clone()
+                        } else {
+                            format!("{} = {}", name, value)
+                        };
+                        next = value + 1;
+                        item
+                    })
+                    .collect();
+                let name = if base.name.starts_with("enum") { base.name.as_str() } else { "enum" };
+                format!("{} {{{}}}", name, enumerators.join(", "))
+            }
+            _ => base.name.clone(),
+        };
+        format!("{}{}", body, suffix)
+    }
+
+    /// Describes a variant of a Rust enum, e.g. "Circle(f64)" or "Rect{w: u32, h: u32}".
+    fn describe_variant(&self, variant: &Member) -> String {
+        let members = match self.get_type(variant.type_offset).map(|t| &t.kind) {
+            Some(TypeKind::Struct(members)) if !members.is_empty() => members,
+            _ => return variant.name.clone(),
+        };
+        let is_tuple = members.iter().all(|member| member.name.starts_with("__"));
+        let fields: Vec<String> = members
+            .iter()
+            .map(|member| {
+                let type_name = self
+                    .get_type(member.type_offset)
+                    .map_or("<unknown type>", |t| t.name.as_str());
+                if is_tuple {
+                    type_name.to_string()
+                } else {
+                    format!("{}: {}", member.name, type_name)
+                }
+            })
+            .collect();
+        if is_tuple {
+            format!("{}({})", variant.name, fields.join(", "))
+        } else {
+            format!("{}{{{}}}", variant.name, fields.join(", "))
+        }
+    }
+}
+
+
+/// Reads the binary's name index section, if it has one that can be parsed.
+fn read_index_section(
+    object: &object::File,
+    dwarf: &gimli::Dwarf<DwarfReader>,
+    endian: gimli::RunTimeEndian,
+) -> IndexSection {
+    let section_data = |name| object.section_by_name(name)?.uncompressed_data().ok();
+    let result = if let Some(data) = section_data(".debug_names") {
+        let section = gimli::EndianSlice::new(&data, endian);
+        let debug_str = dwarf.debug_str.borrow(|section| gimli::EndianSlice::new(section, endian));
+        dwarf_index::read_debug_names(section, &debug_str)
+    } else if let Some(data) = section_data(".gdb_index") {
+        dwarf_index::read_gdb_index(&data)
+    } else {
+        return IndexSection::default();
+    };
+    result.unwrap_or_else(|err| {
+        println!("Ignoring the name index, which could not be read: {}", err);
+        IndexSection::default()
+    })
+}
+
+/// Reads the build ID that the linker put in the binary at path, if it has one.
+pub fn read_build_id(path: &str) -> Option<Vec<u8>> {
+    let mmap = map_file(Path::new(path))?;
+    let object = parse_object(&mmap).ok()?;
+    let build_id = object.build_id().ok()??;
+    Some(build_id.to_vec())
+}
+
+/// Reads the ELF symbol table of the binary or shared library at path.
+pub fn read_symbol_table(path: &str) -> Option<SymbolTable> {
+    let mmap = map_file(Path::new(path))?;
+    let object = parse_object(&mmap).ok()?;
+    Some(SymbolTable::from_object(&object))
+}
+
+fn map_file(path: &Path) -> Option<memmap2::Mmap> {
+    let file = fs::File::open(path).ok()?;
+    unsafe { memmap2::Mmap::map(&file).ok() }
+}
 
-            println!("Line numbers:");
-            for line in &file.lines {
-                println!("  * {} (at {:#x})", line.number, line.address);
+fn parse_object(data: &[u8]) -> Result<object::File<'_>, Error> {
+    Ok(object::File::parse(data).map_err(|e| gimli_wrapper::Error::ObjectError(e.to_string()))?)
+}
+
+fn endianness(object: &object::File) -> gimli::RunTimeEndian {
+    if object.is_little_endian() {
+        gimli::RunTimeEndian::Little
+    } else {
+        gimli::RunTimeEndian::Big
+    }
+}
+
+/// Finds the separate file holding a stripped binary's debug info, looking where gdb does: under
+/// .build-id in the debug file directory, then for the file named by .gnu_debuglink next to the
+/// binary, in its .debug directory and under the debug file directory.
+fn find_debug_file(path: &str, object: &object::File) -> Option<PathBuf> {
+    if let Ok(Some(build_id)) = object.build_id() {
+        let hex: String = build_id.iter().map(|byte| format!("{:02x}", byte)).collect();
+        if hex.len() > 2 {
+            let candidate = Path::new(DEBUG_FILE_DIRECTORY)
+                .join(".build-id")
+                .join(&hex[..2])
+                .join(format!("{}.debug", &hex[2..]));
+            if candidate.is_file() {
+                return Some(candidate);
             }
         }
     }
+    let (name, crc) = object.gnu_debuglink().ok()??;
+    let name = String::from_utf8_lossy(name).into_owned();
+    let binary = fs::canonicalize(path).ok()?;
+    let dir = binary.parent()?;
+    let candidates = vec![
+        dir.join(&name),
+        dir.join(".debug").join(&name),
+        Path::new(DEBUG_FILE_DIRECTORY)
+            .join(dir.strip_prefix("/").unwrap_or(dir))
+            .join(&name),
+    ];
+    candidates.into_iter().find(|candidate| {
+        // The link may name the binary itself, which has no debug info
+        *candidate != binary
+            && fs::read(candidate).is_ok_and(|data| crc32fast::hash(&data) == crc)
+    })
+}
+
+/// Finds the DIEs of a split unit, in the binary's .dwp package or the unit's .dwo file. Prints
+/// a warning and returns None if they aren't there.
+fn load_split_unit(
+    binary: &str,
+    dwarf: &gimli::Dwarf<DwarfReader>,
+    skeleton: &gimli::Unit<DwarfReader>,
+    dwo_id: gimli::DwoId,
+    package: Option<&gimli::DwarfPackage<DwarfReader>>,
+) -> Option<gimli_wrapper::SplitUnit> {
+    if let Some(package) = package {
+        match gimli_wrapper::find_in_package(package, dwarf, dwo_id) {
+            Ok(Some(split)) => return Some(split),
+            Ok(None) => {}
+            Err(err) => println!("Could not read {}.dwp: {:?}", binary, err),
+        }
+    }
+    let dwo_path = gimli_wrapper::dwo_path(dwarf, skeleton).ok()??;
+    // The objects may have been compiled somewhere else and moved next to the binary
+    let moved_path = Path::new(binary).with_file_name(dwo_path.file_name()?);
+    let mmap = match map_file(&dwo_path).or_else(|| map_file(&moved_path)) {
+        Some(mmap) => mmap,
+        None => {
+            println!("Could not find split debug info {}", dwo_path.display());
+            return None;
+        }
+    };
+    let split = parse_object(&mmap).and_then(|object| {
+        Ok(gimli_wrapper::load_dwo(&object, endianness(&object), dwarf, dwo_id)?)
+    });
+    match split {
+        Ok(Some(split)) => Some(split),
+        Ok(None) => {
+            println!("{} does not match {}", dwo_path.display(), binary);
+            None
+        }
+        Err(err) => {
+            println!("Could not read {}: {:?}", dwo_path.display(), err);
+            None
+        }
+    }
+}
+
+/// How the bits of a base type are interpreted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Signed,
+    Unsigned,
+    Float,
+    Bool,
+    SignedChar,
+    UnsignedChar,
+    /// A Unicode code point, like Rust's char
+    Utf,
 }
 
-#[derive(Debug, Clone, Default)]
+/// A field of a struct or union. Its type is stored as the offset of the type's DIE, since
+/// structs may refer to themselves (e.g. linked list nodes).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Member {
+    pub name: String,
+    pub offset: usize,
+    pub type_offset: usize,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum TypeKind {
+    #[default]
+    Void,
+    Base(Encoding),
+    Pointer(Box<Type>),
+    Struct(Vec<Member>),
+    Union(Vec<Member>),
+    Array(Box<Type>, usize),
+    Enum(Vec<(String, i64)>),
+    /// A Rust enum: a struct with a DW_TAG_variant_part, holding the discriminant (if there's
+    /// more than one variant) and each variant's discriminant value (None for the variant used
+    /// when no other value matches) along with the member holding its fields
+    Variants(Option<Member>, Vec<(Option<u64>, Member)>),
+    Function,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
 pub struct Type {
     pub name: String,
     pub size: usize,
+    pub kind: TypeKind,
+    /// The names and type offsets of a generic type's parameters, e.g. T for Rust's Vec<T>
+    pub template_params: Vec<(String, usize)>,
 }
 
 impl Type {
-    pub fn new(name: String, size: usize) -> Self {
+    pub fn base(name: &str, size: usize, encoding: Encoding) -> Self {
+        Type {
+            name: name.to_string(),
+            size,
+            kind: TypeKind::Base(encoding),
+            ..Default::default()
+        }
+    }
+
+    pub fn void() -> Self {
+        Type {
+            name: "void".to_string(),
+            size: 1,
+            kind: TypeKind::Void,
+            ..Default::default()
+        }
+    }
+
+    /// Returns the offset of the type of a generic type's parameter.
+    pub fn template_param(&self, name: &str) -> Option<usize> {
+        self.template_params
+            .iter()
+            .find(|(param, _)| param == name)
+            .map(|(_, offset)| *offset)
+    }
+
+    pub fn pointer_to(pointee: Type) -> Self {
+        let name = if pointee.name.ends_with('*') {
+            format!("{}*", pointee.name)
+        } else {
+            format!("{} *", pointee.name)
+        };
         Type {
-            name: name,
-            size: size,
+            name,
+            size: size_of::<usize>(),
+            kind: TypeKind::Pointer(Box::new(pointee)),
+            ..Default::default()
         }
     }
+
+    /// Returns a C declaration of name with this type, e.g. "int arr[4]" or "struct Node *next".
+    pub fn declaration(&self, name: &str) -> String {
+        match &self.kind {
+            TypeKind::Array(element, count) => element.declaration(&format!("{}[{}]", name, count)),
+            _ if self.name.ends_with('*') => format!("{}{}", self.name, name),
+            _ => format!("{} {}", self.name, name),
+        }
+    }
+
+    /// Returns true for integers, floats, pointers and enums: the types that arithmetic and
+    /// comparisons work on.
+    pub fn is_scalar(&self) -> bool {
+        matches!(
+            self.kind,
+            TypeKind::Base(_) | TypeKind::Pointer(_) | TypeKind::Enum(_)
+        )
+    }
+
+    /// Returns true for float and double, which are passed and returned in xmm registers.
+    pub fn is_float(&self) -> bool {
+        matches!(self.kind, TypeKind::Base(Encoding::Float))
+    }
 }
 
 #[derive(Clone)]
@@ -191,6 +959,29 @@ pub struct Variable {
     pub entity_type: Type,
     pub location: Location,
     pub line_number: usize, // Line number in source file
+    pub is_parameter: bool,
+    /// The index of the innermost block declaring the variable in Function.blocks, or None if
+    /// it's declared in the function's outermost scope
+    pub block: Option<usize>,
+}
+
+/// A `{}` block, or the body of an inlined call, whose variables are only in scope while the
+/// program counter is within its address ranges.
+#[derive(Debug, Default, Clone)]
+pub struct Block {
+    /// Half-open ranges of addresses. Blocks that the compiler generated no code for have none,
+    /// and are never in scope.
+    pub ranges: Vec<(usize, usize)>,
+    /// The index of the enclosing block, or None if it's the function's outermost scope
+    pub parent: Option<usize>,
+    /// The name of the function, if the block is the body of an inlined call
+    pub inlined_function: Option<String>,
+}
+
+impl Block {
+    pub fn contains(&self, addr: usize) -> bool {
+        self.ranges.iter().any(|&(begin, end)| begin <= addr && addr < end)
+    }
 }
 
 #[derive(Debug, Default, Clone)]
@@ -199,7 +990,97 @@ pub struct Function {
     pub address: usize,
     pub text_length: usize,
     pub line_number: usize, // Line number in source file
+    pub return_type: Option<Type>,
     pub variables: Vec<Variable>,
+    pub blocks: Vec<Block>,
+    /// For a nested function (a GNU C extension), the function it's defined in
+    pub enclosing: Option<EnclosingFunction>,
+}
+
+/// The function a nested function is defined in, whose variables the nested function can use.
+#[derive(Debug, Clone)]
+pub struct EnclosingFunction {
+    /// Its index in File.functions
+    pub index: usize,
+    /// The innermost of its blocks that the nested function is defined in
+    pub block: Option<usize>,
+    /// How to find its frame from the nested function's, unless DW_AT_static_link is missing or
+    /// too complicated
+    pub static_link: Option<StaticLink>,
+}
+
+/// A DW_AT_static_link of the form gcc emits: following derefs pointers from a slot in the nested
+/// function's frame leads to the enclosing function's frame base.
+#[derive(Debug, Clone)]
+pub struct StaticLink {
+    /// The slot's offset from the nested function's canonical frame address
+    pub offset: isize,
+    pub derefs: usize,
+    /// The enclosing function's frame base's offset from its canonical frame address
+    pub frame_base_adjustment: isize,
+}
+
+impl Function {
+    /// Returns the function's formal parameters, in declaration order.
+    pub fn parameters(&self) -> impl Iterator<Item = &Variable> {
+        self.variables.iter().filter(|var| var.is_parameter && var.block.is_none())
+    }
+
+    /// Returns the blocks in scope at addr, innermost first. The last is always None, the
+    /// function's outermost scope.
+    pub fn scopes_at(&self, addr: usize) -> Vec<Option<usize>> {
+        // Blocks are pushed before the blocks they contain, so the innermost comes last
+        let innermost = (0..self.blocks.len()).rev().find(|&index| {
+            let mut block = Some(index);
+            while let Some(index) = block {
+                if !self.blocks[index].contains(addr) {
+                    return false;
+                }
+                block = self.blocks[index].parent;
+            }
+            true
+        });
+        self.scopes_from(innermost)
+    }
+
+    /// Returns block and the blocks enclosing it, innermost first, ending with None.
+    fn scopes_from(&self, block: Option<usize>) -> Vec<Option<usize>> {
+        let mut scopes = vec![block];
+        while let Some(index) = scopes.last().copied().flatten() {
+            scopes.push(self.blocks[index].parent);
+        }
+        scopes
+    }
+
+    /// Returns the variables in scope at addr, innermost first, leaving out those shadowed by
+    /// declarations of the same name in inner blocks.
+    pub fn variables_at(&self, addr: usize) -> Vec<&Variable> {
+        self.variables_in(self.scopes_at(addr))
+    }
+
+    fn variables_in(&self, scopes: Vec<Option<usize>>) -> Vec<&Variable> {
+        let mut variables: Vec<&Variable> = Vec::new();
+        for scope in scopes {
+            for var in self.variables.iter().filter(|var| var.block == scope) {
+                if !variables.iter().any(|visible| visible.name == var.name) {
+                    variables.push(var);
+                }
+            }
+        }
+        variables
+    }
+
+    /// Returns the innermost declaration of name that is in scope at addr.
+    pub fn get_variable(&self, name: &str, addr: usize) -> Option<&Variable> {
+        self.variables_at(addr).into_iter().find(|var| var.name == name)
+    }
+
+    /// Returns the innermost declaration of name that is in scope in block, e.g. where a nested
+    /// function is defined.
+    pub fn get_variable_in_block(&self, name: &str, block: Option<usize>) -> Option<&Variable> {
+        let variables = self.variables_in(self.scopes_from(block));
+        variables.into_iter().find(|var| var.name == name)
+    }
 }
 
 #[derive(Debug, Default, Clone)]
@@ -207,9 +1088,28 @@ pub struct File {
     pub name: String,
     pub global_variables: Vec<Variable>,
     pub functions: Vec<Function>,
+    pub inlined_calls: Vec<InlinedCall>,
     pub lines: Vec<Line>,
 }
 
+/// A function being executed at some address. A call that was inlined gets a frame of its own,
+/// even though it shares its caller's stack frame.
+#[derive(Debug, Clone)]
+pub struct Frame {
+    pub function: String,
+    pub line: Option<Line>,
+    pub inlined: bool,
+}
+
+/// A call that the compiler replaced with a copy of the called function's body.
+#[derive(Debug, Default, Clone)]
+pub struct InlinedCall {
+    /// The name of the inlined function
+    pub name: String,
+    /// Where the inlined body starts
+    pub address: usize,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Line {
     pub file: String,
@@ -221,4 +1121,20 @@ impl fmt::Display for Line {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}:{}", self.file, self.number)
     }
-}
\ No newline at end of file
+}
+
+/// Strips the namespaces from a name, e.g. "alloc::vec::Vec<u8, alloc::alloc::Global>" becomes
+/// "Vec<u8, alloc::alloc::Global>".
+fn unqualified(name: &str) -> &str {
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in name.char_indices() {
+        match c {
+            '<' | '(' | '[' => depth += 1,
+            '>' | ')' | ']' => depth -= 1,
+            ':' if depth == 0 && name[i..].starts_with("::") => start = i + 2,
+            _ => {}
+        }
+    }
+    &name[start..]
+}
@@ -0,0 +1,449 @@
+//! Name indices, which tell DwarfData which compilation units define a name so that only those
+//! units have to be parsed. They come from the DWARF 5 .debug_names section or gdb's .gdb_index
+//! section when the binary has one; units that neither covers are indexed by scanning their
+//! top-level DIEs (see gimli_wrapper::top_level_names).
+
+use gimli::Reader;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NameKind {
+    Function,
+    Variable,
+    Type,
+}
+
+/// Returns the kind of name a DIE with the given tag defines, if it's one deet looks up by name.
+pub fn tag_kind(tag: gimli::DwTag) -> Option<NameKind> {
+    match tag {
+        gimli::DW_TAG_subprogram => Some(NameKind::Function),
+        gimli::DW_TAG_variable => Some(NameKind::Variable),
+        gimli::DW_TAG_base_type
+        | gimli::DW_TAG_structure_type
+        | gimli::DW_TAG_union_type
+        | gimli::DW_TAG_enumeration_type
+        | gimli::DW_TAG_typedef
+        | gimli::DW_TAG_class_type => Some(NameKind::Type),
+        _ => None,
+    }
+}
+
+/// Names found in an index section, with the .debug_info offsets of the units defining them.
+#[derive(Default)]
+pub struct IndexSection {
+    /// Every unit the section covers, including those that define no names
+    pub units: Vec<usize>,
+    pub names: Vec<(NameKind, String, usize)>,
+}
+
+/// Maps names to the compilation units (as indices into DwarfData's unit list) that define them.
+#[derive(Default)]
+pub struct NameIndex {
+    functions: HashMap<String, Vec<usize>>,
+    variables: HashMap<String, Vec<usize>>,
+    types: HashMap<String, Vec<usize>>,
+}
+
+impl NameIndex {
+    pub fn insert(&mut self, kind: NameKind, name: String, unit: usize) {
+        let units = self.map_mut(kind).entry(name).or_default();
+        if !units.contains(&unit) {
+            units.push(unit);
+        }
+    }
+
+    /// Returns the units that define name, in .debug_info order.
+    pub fn get(&self, kind: NameKind, name: &str) -> &[usize] {
+        self.map(kind).get(name).map_or(&[], Vec::as_slice)
+    }
+
+    pub fn names(&self, kind: NameKind) -> impl Iterator<Item = &str> {
+        self.map(kind).keys().map(String::as_str)
+    }
+
+    /// Returns every unit that defines a name of the given kind.
+    pub fn units(&self, kind: NameKind) -> Vec<usize> {
+        let mut units: Vec<usize> = self.map(kind).values().flatten().copied().collect();
+        units.sort_unstable();
+        units.dedup();
+        units
+    }
+
+    /// Puts each list of units in order, once everything has been inserted.
+    pub fn finish(&mut self) {
+        for map in [&mut self.functions, &mut self.variables, &mut self.types] {
+            for units in map.values_mut() {
+                units.sort_unstable();
+            }
+        }
+    }
+
+    fn map(&self, kind: NameKind) -> &HashMap<String, Vec<usize>> {
+        match kind {
+            NameKind::Function => &self.functions,
+            NameKind::Variable => &self.variables,
+            NameKind::Type => &self.types,
+        }
+    }
+
+    fn map_mut(&mut self, kind: NameKind) -> &mut HashMap<String, Vec<usize>> {
+        match kind {
+            NameKind::Function => &mut self.functions,
+            NameKind::Variable => &mut self.variables,
+            NameKind::Type => &mut self.types,
+        }
+    }
+}
+
+/// Reads a DWARF 5 .debug_names section (DWARF 5 section 6.1.1). Name strings are offsets into
+/// .debug_str.
+pub fn read_debug_names<R: Reader<Offset = usize>>(
+    mut section: R,
+    debug_str: &gimli::DebugStr<R>,
+) -> gimli::Result<IndexSection> {
+    let mut index = IndexSection::default();
+    // Linkers may concatenate one name table per unit
+    while !section.is_empty() {
+        let (length, format) = section.read_initial_length()?;
+        let mut table = section.split(length)?;
+        let version = table.read_u16()?;
+        if version != 5 {
+            return Err(gimli::Error::UnknownVersion(version.into()));
+        }
+        table.skip(2)?; // padding
+        let unit_count = table.read_u32()? as usize;
+        let local_type_unit_count = table.read_u32()? as usize;
+        let foreign_type_unit_count = table.read_u32()? as usize;
+        let bucket_count = table.read_u32()? as usize;
+        let name_count = table.read_u32()? as usize;
+        let abbrev_table_size = table.read_u32()? as usize;
+        let augmentation_size = table.read_u32()? as usize;
+        table.skip(augmentation_size)?;
+
+        let mut units = Vec::with_capacity(unit_count);
+        for _ in 0..unit_count {
+            units.push(table.read_offset(format)?);
+        }
+        table.skip(local_type_unit_count * format.word_size() as usize)?;
+        table.skip(foreign_type_unit_count * 8)?;
+        table.skip(bucket_count * 4)?;
+        if bucket_count > 0 {
+            // The hashes are only needed to look names up without reading them all
+            table.skip(name_count * 4)?;
+        }
+        let mut string_offsets = Vec::with_capacity(name_count);
+        for _ in 0..name_count {
+            string_offsets.push(table.read_offset(format)?);
+        }
+        let mut entry_offsets = Vec::with_capacity(name_count);
+        for _ in 0..name_count {
+            entry_offsets.push(table.read_offset(format)?);
+        }
+
+        // Abbreviation code -> (tag, [(DW_IDX_*, DW_FORM_*)])
+        let mut abbrevs = HashMap::new();
+        let mut abbrev_table = table.split(abbrev_table_size)?;
+        loop {
+            let code = abbrev_table.read_uleb128()?;
+            if code == 0 {
+                break;
+            }
+            let tag = gimli::DwTag(abbrev_table.read_uleb128_u16()?);
+            let mut attributes = Vec::new();
+            loop {
+                let idx = abbrev_table.read_uleb128_u16()?;
+                let form = gimli::DwForm(abbrev_table.read_uleb128_u16()?);
+                if idx == 0 && form.0 == 0 {
+                    break;
+                }
+                attributes.push((gimli::DwIdx(idx), form));
+            }
+            abbrevs.insert(code, (tag, attributes));
+        }
+
+        let entry_pool = table;
+        for (string_offset, entry_offset) in string_offsets.into_iter().zip(entry_offsets) {
+            let name = debug_str.get_str(gimli::DebugStrOffset(string_offset))?;
+            let name = name.to_string_lossy()?.into_owned();
+            let mut entries = entry_pool.clone();
+            entries.skip(entry_offset)?;
+            loop {
+                let code = entries.read_uleb128()?;
+                if code == 0 {
+                    break;
+                }
+                let (tag, attributes) =
+                    abbrevs.get(&code).ok_or(gimli::Error::UnknownAbbreviation)?;
+                // A table covering a single unit may leave DW_IDX_compile_unit out
+                let mut unit = if unit_count == 1 { Some(0) } else { None };
+                for (idx, form) in attributes {
+                    let value = read_index_value(&mut entries, *form, format)?;
+                    if *idx == gimli::DW_IDX_compile_unit {
+                        unit = Some(value as usize);
+                    }
+                }
+                let unit_offset = unit.and_then(|unit| units.get(unit));
+                if let (Some(kind), Some(unit_offset)) = (tag_kind(*tag), unit_offset) {
+                    index.names.push((kind, name.clone(), *unit_offset));
+                }
+            }
+        }
+        index.units.extend(units);
+    }
+    Ok(index)
+}
+
+/// Reads the value of one attribute of a .debug_names entry.
+fn read_index_value<R: Reader<Offset = usize>>(
+    input: &mut R,
+    form: gimli::DwForm,
+    format: gimli::Format,
+) -> gimli::Result<u64> {
+    Ok(match form {
+        gimli::DW_FORM_flag_present => 1,
+        gimli::DW_FORM_data1 | gimli::DW_FORM_ref1 | gimli::DW_FORM_flag => {
+            input.read_u8()?.into()
+        }
+        gimli::DW_FORM_data2 | gimli::DW_FORM_ref2 => input.read_u16()?.into(),
+        gimli::DW_FORM_data4 | gimli::DW_FORM_ref4 => input.read_u32()?.into(),
+        gimli::DW_FORM_data8 | gimli::DW_FORM_ref8 | gimli::DW_FORM_ref_sig8 => input.read_u64()?,
+        gimli::DW_FORM_udata | gimli::DW_FORM_ref_udata => input.read_uleb128()?,
+        gimli::DW_FORM_sdata => input.read_sleb128()? as u64,
+        gimli::DW_FORM_sec_offset => input.read_offset(format)? as u64,
+        _ => return Err(gimli::Error::UnknownForm),
+    })
+}
+
+/// Reads a .gdb_index section (version 7 or later, which records what kind of symbol each name
+/// is). The format is described in the "Index Section Format" appendix of the gdb manual.
+pub fn read_gdb_index(data: &[u8]) -> gimli::Result<IndexSection> {
+    let section = gimli::EndianSlice::new(data, gimli::LittleEndian);
+    let mut header = section;
+    let version = header.read_u32()?;
+    if version < 7 {
+        return Err(gimli::Error::UnknownVersion(version.into()));
+    }
+    let unit_list_offset = header.read_u32()? as usize;
+    let type_unit_list_offset = header.read_u32()? as usize;
+    header.skip(4)?; // address area, which .debug_aranges already provides
+    let symbol_table_offset = header.read_u32()? as usize;
+    let constant_pool_offset = header.read_u32()? as usize;
+    let subsection = |start: usize, end: usize| {
+        let mut input = section;
+        input.skip(start)?;
+        input.truncate(end.checked_sub(start).ok_or(gimli::Error::BadLength)?)?;
+        gimli::Result::Ok(input)
+    };
+
+    let mut index = IndexSection::default();
+    let mut unit_list = subsection(unit_list_offset, type_unit_list_offset)?;
+    while !unit_list.is_empty() {
+        index.units.push(unit_list.read_u64()? as usize);
+        unit_list.skip(8)?; // length
+    }
+
+    let mut symbol_table = subsection(symbol_table_offset, constant_pool_offset)?;
+    let constant_pool = subsection(constant_pool_offset, data.len())?;
+    while !symbol_table.is_empty() {
+        let name_offset = symbol_table.read_u32()? as usize;
+        let vector_offset = symbol_table.read_u32()? as usize;
+        if name_offset == 0 && vector_offset == 0 {
+            continue;
+        }
+        let mut name = constant_pool;
+        name.skip(name_offset)?;
+        let name = name.read_null_terminated_slice()?.to_string_lossy().into_owned();
+        let mut vector = constant_pool;
+        vector.skip(vector_offset)?;
+        for _ in 0..vector.read_u32()? {
+            let value = vector.read_u32()?;
+            // Bits 0-23 are the unit's index (type units come after the compilation units),
+            // bits 28-30 the kind of symbol
+            let unit = (value & 0xff_ffff) as usize;
+            let kind = match (value >> 28) & 7 {
+                1 => NameKind::Type,
+                2 => NameKind::Variable,
+                3 => NameKind::Function,
+                _ => continue,
+            };
+            if let Some(unit_offset) = index.units.get(unit) {
+                index.names.push((kind, name.clone(), *unit_offset));
+            }
+        }
+    }
+    Ok(index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn u32s(values: &[u32]) -> Vec<u8> {
+        values.iter().flat_map(|value| value.to_le_bytes()).collect()
+    }
+
+    /// Builds a 32-bit .debug_names name table without a hash table. Each name is a .debug_str
+    /// offset, a tag and the index of the unit defining it; with one unit, entries leave
+    /// DW_IDX_compile_unit out.
+    fn name_table(units: &[u32], names: &[(u32, gimli::DwTag, u8)]) -> Vec<u8> {
+        let with_unit = units.len() > 1;
+        // One abbreviation per tag: code, tag, [DW_IDX_compile_unit data1,] DW_IDX_die_offset ref4
+        let mut tags: Vec<gimli::DwTag> = names.iter().map(|&(_, tag, _)| tag).collect();
+        tags.dedup();
+        let mut abbrevs = Vec::new();
+        for (code, tag) in tags.iter().enumerate() {
+            abbrevs.extend([code as u8 + 1, tag.0 as u8]);
+            if with_unit {
+                abbrevs.extend([gimli::DW_IDX_compile_unit.0 as u8, gimli::DW_FORM_data1.0 as u8]);
+            }
+            abbrevs.extend([gimli::DW_IDX_die_offset.0 as u8, gimli::DW_FORM_ref4.0 as u8, 0, 0]);
+        }
+        abbrevs.push(0);
+        let mut pool = Vec::new();
+        let mut entry_offsets = Vec::new();
+        for &(_, tag, unit) in names {
+            entry_offsets.push(pool.len() as u32);
+            let code = tags.iter().position(|&t| t == tag).unwrap() as u8 + 1;
+            pool.push(code);
+            if with_unit {
+                pool.push(unit);
+            }
+            pool.extend(0x2a_u32.to_le_bytes());
+            pool.push(0);
+        }
+
+        let mut table = vec![5, 0, 0, 0];
+        let counts = [units.len() as u32, 0, 0, 0, names.len() as u32, abbrevs.len() as u32, 0];
+        table.extend(u32s(&counts));
+        table.extend(u32s(units));
+        table.extend(u32s(&names.iter().map(|&(offset, ..)| offset).collect::<Vec<_>>()));
+        table.extend(u32s(&entry_offsets));
+        table.extend(abbrevs);
+        table.extend(pool);
+        let mut section = u32s(&[table.len() as u32]);
+        section.extend(table);
+        section
+    }
+
+    #[test]
+    fn reads_debug_names() {
+        let debug_str = b"\0main\0counter\0node\0std\0";
+        let names = [
+            (1, gimli::DW_TAG_subprogram, 0),
+            (6, gimli::DW_TAG_variable, 1),
+            (14, gimli::DW_TAG_structure_type, 1),
+            (19, gimli::DW_TAG_namespace, 0),
+        ];
+        // A table per unit set, as a linker concatenating them would leave them
+        let mut section = name_table(&[0x0, 0x40], &names);
+        section.extend(name_table(&[0x80], &[(1, gimli::DW_TAG_subprogram, 0)]));
+
+        let endian = gimli::LittleEndian;
+        let debug_str = gimli::DebugStr::new(debug_str, endian);
+        let section = gimli::EndianSlice::new(&section, endian);
+        let index = read_debug_names(section, &debug_str).unwrap();
+        assert_eq!(index.units, vec![0x0, 0x40, 0x80]);
+        assert_eq!(
+            index.names,
+            vec![
+                (NameKind::Function, "main".to_string(), 0x0),
+                (NameKind::Variable, "counter".to_string(), 0x40),
+                (NameKind::Type, "node".to_string(), 0x40),
+                (NameKind::Function, "main".to_string(), 0x80),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_other_debug_names_versions() {
+        let mut section = name_table(&[0], &[]);
+        section[4] = 4;
+        let endian = gimli::LittleEndian;
+        let debug_str = gimli::DebugStr::new(&[], endian);
+        let result = read_debug_names(gimli::EndianSlice::new(&section, endian), &debug_str);
+        assert!(result.is_err());
+    }
+
+    /// Builds a version 8 .gdb_index covering units at the given offsets. Each symbol is a name
+    /// and its (unit index, kind) pairs; an empty name leaves an empty hash table slot.
+    fn gdb_index(units: &[u64], symbols: &[(&str, &[(u32, u32)])]) -> Vec<u8> {
+        let mut unit_list = Vec::new();
+        for &unit in units {
+            unit_list.extend(unit.to_le_bytes());
+            unit_list.extend(0x40_u64.to_le_bytes());
+        }
+        let mut symbol_table = Vec::new();
+        let mut constant_pool = Vec::new();
+        for &(name, entries) in symbols {
+            if name.is_empty() {
+                symbol_table.extend(u32s(&[0, 0]));
+                continue;
+            }
+            let vector_offset = constant_pool.len() as u32;
+            constant_pool.extend(u32s(&[entries.len() as u32]));
+            for &(unit, kind) in entries {
+                constant_pool.extend(u32s(&[unit | kind << 28]));
+            }
+            symbol_table.extend(u32s(&[constant_pool.len() as u32, vector_offset]));
+            constant_pool.extend(name.as_bytes());
+            constant_pool.push(0);
+        }
+        let unit_list_offset = 24;
+        let type_unit_list_offset = unit_list_offset + unit_list.len() as u32;
+        let symbol_table_offset = type_unit_list_offset;
+        let constant_pool_offset = symbol_table_offset + symbol_table.len() as u32;
+        let mut section = u32s(&[
+            8,
+            unit_list_offset,
+            type_unit_list_offset,
+            type_unit_list_offset,
+            symbol_table_offset,
+            constant_pool_offset,
+        ]);
+        section.extend(unit_list);
+        section.extend(symbol_table);
+        section.extend(constant_pool);
+        section
+    }
+
+    #[test]
+    fn reads_gdb_index() {
+        let symbols: [(&str, &[(u32, u32)]); 4] = [
+            ("main", &[(0, 3)]),
+            ("", &[]),
+            ("list", &[(0, 1), (1, 1)]),
+            ("counter", &[(1, 2), (0, 0), (7, 2)]),
+        ];
+        let index = read_gdb_index(&gdb_index(&[0x0, 0x100], &symbols)).unwrap();
+        assert_eq!(index.units, vec![0x0, 0x100]);
+        assert_eq!(
+            index.names,
+            vec![
+                (NameKind::Function, "main".to_string(), 0x0),
+                (NameKind::Type, "list".to_string(), 0x0),
+                (NameKind::Type, "list".to_string(), 0x100),
+                (NameKind::Variable, "counter".to_string(), 0x100),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_gdb_index_versions_without_symbol_kinds() {
+        let mut section = gdb_index(&[0], &[]);
+        section[0] = 6;
+        assert!(read_gdb_index(&section).is_err());
+    }
+
+    #[test]
+    fn name_index_keeps_units_in_order() {
+        let mut names = NameIndex::default();
+        names.insert(NameKind::Function, "main".to_string(), 3);
+        names.insert(NameKind::Function, "main".to_string(), 1);
+        names.insert(NameKind::Function, "main".to_string(), 3);
+        names.insert(NameKind::Variable, "main".to_string(), 2);
+        names.finish();
+        assert_eq!(names.get(NameKind::Function, "main"), &[1, 3]);
+        assert_eq!(names.get(NameKind::Type, "main"), &[] as &[usize]);
+        assert_eq!(names.units(NameKind::Variable), vec![2]);
+    }
+}
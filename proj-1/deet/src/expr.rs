@@ -0,0 +1,1369 @@
+//! A small evaluator for C expressions, used by print, set var, call and dprintf. Supports
+//! arithmetic, comparisons, logical operators, casts, member access, array indexing, dereference,
+//! address-of, assignment and calls to functions in the inferior.
+
+use crate::dwarf_data::{DwarfData, Encoding, Member, Type, TypeKind, Variable};
+use crate::inferior::{Inferior, Status};
+use crate::printers::Printers;
+use crate::procfs::{self, MemoryMapping};
+use crate::value::{TypedValue, Value};
+use nix::sys::signal::Signal;
+use std::cell::OnceCell;
+
+/// How values get formatted, as changed by `set print ...` and `set output-radix`.
+#[derive(Clone, Copy)]
+pub struct PrintOptions {
+    /// Maximum number of array elements and string characters that get printed
+    pub elements: usize,
+    /// Print struct members one per line, indented
+    pub pretty: bool,
+    /// Print integers in hexadecimal
+    pub hex: bool,
+}
+
+impl Default for PrintOptions {
+    fn default() -> Self {
+        PrintOptions {
+            elements: 200,
+            pretty: false,
+            hex: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Int(u64),
+    Float(f64),
+    Char(u8),
+    Ident(String),
+    Punct(&'static str),
+}
+
+/// Punctuators, longest first so that e.g. "<=" isn't lexed as "<" followed by "=".
+const PUNCTUATORS: [&str; 28] = [
+    "->", "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "+", "-", "*", "/", "%", "<", ">", "=",
+    "!", "~", "&", "|", "^", "(", ")", "[", "]", ".", ",",
+];
+
+fn tokenize(input: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let bytes = input.as_bytes();
+    let mut i = 0;
+    'outer: while i < bytes.len() {
+        let c = bytes[i];
+        if c.is_ascii_whitespace() {
+            i += 1;
+        } else if c.is_ascii_digit() {
+            let start = i;
+            while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'.') {
+                i += 1;
+            }
+            let literal = &input[start..i];
+            let digits = literal.trim_end_matches(['u', 'U', 'l', 'L']);
+            let token = if let Some(hex) = digits
+                .strip_prefix("0x")
+                .or_else(|| digits.strip_prefix("0X"))
+            {
+                u64::from_str_radix(hex, 16).ok().map(Token::Int)
+            } else if digits.contains(['.', 'e', 'E']) {
+                literal
+                    .trim_end_matches(['f', 'F'])
+                    .parse()
+                    .ok()
+                    .map(Token::Float)
+            } else if digits.len() > 1 && digits.starts_with('0') {
+                u64::from_str_radix(&digits[1..], 8).ok().map(Token::Int)
+            } else {
+                digits.parse().ok().map(Token::Int)
+            };
+            tokens.push(token.ok_or_else(|| format!("Invalid number \"{}\".", literal))?);
+        } else if c.is_ascii_alphabetic() || c == b'_' || c == b'$' {
+            let start = i;
+            let is_ident_char = |c: u8| c.is_ascii_alphanumeric() || c == b'_' || c == b'$';
+            loop {
+                while i < bytes.len() && is_ident_char(bytes[i]) {
+                    i += 1;
+                }
+                // A qualified name, e.g. std::string or alloc::string::String
+                match bytes.get(i..i + 3) {
+                    Some([b':', b':', c]) if is_ident_char(*c) => i += 2,
+                    _ => break,
+                }
+            }
+            tokens.push(Token::Ident(input[start..i].to_string()));
+        } else if c == b'\'' {
+            let (value, len) = match bytes.get(i + 1..i + 4) {
+                Some([b'\\', escaped, b'\'']) => (unescape(*escaped), 4),
+                _ => match bytes.get(i + 1..i + 3) {
+                    Some([value, b'\'']) => (*value, 3),
+                    _ => return Err("Unmatched single quote.".to_string()),
+                },
+            };
+            tokens.push(Token::Char(value));
+            i += len;
+        } else {
+            for punct in PUNCTUATORS {
+                if input[i..].starts_with(punct) {
+                    tokens.push(Token::Punct(punct));
+                    i += punct.len();
+                    continue 'outer;
+                }
+            }
+            return Err(format!("Invalid character '{}' in expression.", c as char));
+        }
+    }
+    Ok(tokens)
+}
+
+fn unescape(c: u8) -> u8 {
+    match c {
+        b'n' => b'\n',
+        b't' => b'\t',
+        b'r' => b'\r',
+        b'0' => 0,
+        other => other,
+    }
+}
+
+/// A type name as written in a cast, e.g. `unsigned long` or `struct Node *`.
+#[derive(Debug, Clone, PartialEq)]
+struct TypeName {
+    base: String,
+    pointers: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Int(u64),
+    Float(f64),
+    Char(u8),
+    Var(String),
+    Unary(&'static str, Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+    Assign(Box<Expr>, Box<Expr>),
+    Cast(TypeName, Box<Expr>),
+    Member(Box<Expr>, String),
+    Index(Box<Expr>, Box<Expr>),
+    Call(String, Vec<Expr>),
+}
+
+/// Binary operators from lowest to highest precedence.
+const BINARY_PRECEDENCE: [&[&str]; 10] = [
+    &["||"],
+    &["&&"],
+    &["|"],
+    &["^"],
+    &["&"],
+    &["==", "!="],
+    &["<", "<=", ">", ">="],
+    &["<<", ">>"],
+    &["+", "-"],
+    &["*", "/", "%"],
+];
+
+const TYPE_KEYWORDS: [&str; 14] = [
+    "void", "char", "short", "int", "long", "float", "double", "signed", "unsigned", "struct",
+    "union", "enum", "const", "_Bool",
+];
+
+/// The type of a global variable that only the symbol table knows about, as gdb names it
+const NO_DEBUG_INFO_TYPE: &str = "<data variable, no debug info>";
+
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    pos: usize,
+    debug_data: &'a DwarfData,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn peek_punct(&self, punct: &str) -> bool {
+        matches!(self.peek(), Some(Token::Punct(p)) if *p == punct)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn expect(&mut self, punct: &str) -> Result<(), String> {
+        if self.peek_punct(punct) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(format!(
+                "A syntax error in expression, expected '{}'.",
+                punct
+            ))
+        }
+    }
+
+    fn parse_expression(&mut self) -> Result<Expr, String> {
+        let lhs = self.parse_binary(0)?;
+        if self.peek_punct("=") {
+            self.pos += 1;
+            // Assignment is right-associative
+            let rhs = self.parse_expression()?;
+            return Ok(Expr::Assign(Box::new(lhs), Box::new(rhs)));
+        }
+        Ok(lhs)
+    }
+
+    fn parse_binary(&mut self, level: usize) -> Result<Expr, String> {
+        if level == BINARY_PRECEDENCE.len() {
+            return self.parse_unary();
+        }
+        let mut lhs = self.parse_binary(level + 1)?;
+        while let Some(Token::Punct(op)) = self.peek() {
+            let op = *op;
+            if !BINARY_PRECEDENCE[level].contains(&op) {
+                break;
+            }
+            self.pos += 1;
+            let rhs = self.parse_binary(level + 1)?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, String> {
+        match self.peek() {
+            Some(Token::Punct(op @ ("-" | "+" | "!" | "~" | "*" | "&"))) => {
+                let op = *op;
+                self.pos += 1;
+                let operand = self.parse_unary()?;
+                Ok(Expr::Unary(op, Box::new(operand)))
+            }
+            Some(Token::Punct("(")) if self.is_type_name_at(self.pos + 1) => {
+                self.pos += 1;
+                let type_name = self.parse_type_name()?;
+                self.expect(")")?;
+                let operand = self.parse_unary()?;
+                Ok(Expr::Cast(type_name, Box::new(operand)))
+            }
+            _ => self.parse_postfix(),
+        }
+    }
+
+    fn is_type_name_at(&self, pos: usize) -> bool {
+        match self.tokens.get(pos) {
+            Some(Token::Ident(name)) => {
+                TYPE_KEYWORDS.contains(&name.as_str())
+                    || (self.debug_data.get_type_by_name(name).is_some()
+                        && self.debug_data.get_global_variable(name).is_none())
+            }
+            _ => false,
+        }
+    }
+
+    fn parse_type_name(&mut self) -> Result<TypeName, String> {
+        let mut words = Vec::new();
+        while let Some(Token::Ident(word)) = self.peek() {
+            if word != "const" {
+                words.push(word.clone());
+            }
+            self.pos += 1;
+        }
+        let mut pointers = 0;
+        while self.peek_punct("*") {
+            pointers += 1;
+            self.pos += 1;
+        }
+        Ok(TypeName {
+            base: words.join(" "),
+            pointers,
+        })
+    }
+
+    fn parse_postfix(&mut self) -> Result<Expr, String> {
+        let mut expr = self.parse_primary()?;
+        loop {
+            if self.peek_punct(".") || self.peek_punct("->") {
+                let arrow = self.peek_punct("->");
+                self.pos += 1;
+                let member = match self.next() {
+                    Some(Token::Ident(member)) => member,
+                    _ => {
+                        return Err(
+                            "A syntax error in expression, expected a member name.".to_string()
+                        )
+                    }
+                };
+                if arrow {
+                    expr = Expr::Unary("*", Box::new(expr));
+                }
+                expr = Expr::Member(Box::new(expr), member);
+            } else if self.peek_punct("[") {
+                self.pos += 1;
+                let index = self.parse_expression()?;
+                self.expect("]")?;
+                expr = Expr::Index(Box::new(expr), Box::new(index));
+            } else if self.peek_punct("(") {
+                let name = match expr {
+                    Expr::Var(name) => name,
+                    _ => return Err("Only named functions can be called.".to_string()),
+                };
+                self.pos += 1;
+                let mut args = Vec::new();
+                if !self.peek_punct(")") {
+                    loop {
+                        // Parse below the comma operator level so that commas separate args
+                        args.push(self.parse_expression()?);
+                        if !self.peek_punct(",") {
+                            break;
+                        }
+                        self.pos += 1;
+                    }
+                }
+                self.expect(")")?;
+                expr = Expr::Call(name, args);
+            } else {
+                return Ok(expr);
+            }
+        }
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, String> {
+        match self.next() {
+            Some(Token::Int(value)) => Ok(Expr::Int(value)),
+            Some(Token::Float(value)) => Ok(Expr::Float(value)),
+            Some(Token::Char(value)) => Ok(Expr::Char(value)),
+            Some(Token::Ident(name)) => Ok(Expr::Var(name)),
+            Some(Token::Punct("(")) => {
+                let expr = self.parse_expression()?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            Some(Token::Punct(punct)) => {
+                Err(format!("A syntax error in expression, near `{}'.", punct))
+            }
+            None => Err("A syntax error in expression, near `'.".to_string()),
+        }
+    }
+}
+
+/// Synthesizes the C base types, in case the target's debug info doesn't happen to define them.
+fn resolve_type_name(debug_data: &DwarfData, type_name: &TypeName) -> Result<Type, String> {
+    let mut resolved = builtin_type(&type_name.base)
+        .or_else(|| debug_data.get_type_by_name(&type_name.base).cloned())
+        .ok_or_else(|| format!("No symbol \"{}\" in current context.", type_name.base))?;
+    for _ in 0..type_name.pointers {
+        resolved = Type::pointer_to(resolved);
+    }
+    Ok(resolved)
+}
+
+/// Parses input as a type name, e.g. `struct Node *`. Returns None if it isn't one.
+pub fn parse_type(input: &str, debug_data: &DwarfData) -> Option<Type> {
+    let mut parser = Parser {
+        tokens: tokenize(input).ok()?,
+        pos: 0,
+        debug_data,
+    };
+    if !parser.is_type_name_at(0) {
+        return None;
+    }
+    let type_name = parser.parse_type_name().ok()?;
+    if parser.peek().is_some() {
+        return None;
+    }
+    resolve_type_name(debug_data, &type_name).ok()
+}
+
+fn builtin_type(name: &str) -> Option<Type> {
+    let (dwarf_name, size, encoding) = match name {
+        "char" => ("char", 1, Encoding::SignedChar),
+        "signed char" => ("signed char", 1, Encoding::SignedChar),
+        "unsigned char" => ("unsigned char", 1, Encoding::UnsignedChar),
+        "short" | "short int" | "signed short" => ("short int", 2, Encoding::Signed),
+        "unsigned short" | "unsigned short int" | "short unsigned int" => {
+            ("short unsigned int", 2, Encoding::Unsigned)
+        }
+        "int" | "signed" | "signed int" => ("int", 4, Encoding::Signed),
+        "unsigned" | "unsigned int" => ("unsigned int", 4, Encoding::Unsigned),
+        "long" | "long int" | "signed long" | "long long" | "long long int" => {
+            ("long int", 8, Encoding::Signed)
+        }
+        "unsigned long"
+        | "unsigned long int"
+        | "long unsigned int"
+        | "unsigned long long"
+        | "long long unsigned int" => ("long unsigned int", 8, Encoding::Unsigned),
+        "float" => ("float", 4, Encoding::Float),
+        "double" => ("double", 8, Encoding::Float),
+        "_Bool" => ("_Bool", 1, Encoding::Bool),
+        "void" => return Some(Type::void()),
+        _ => return None,
+    };
+    Some(Type::base(dwarf_name, size, encoding))
+}
+
+fn int_type() -> Type {
+    Type::base("int", 4, Encoding::Signed)
+}
+
+fn long_type() -> Type {
+    Type::base("long int", 8, Encoding::Signed)
+}
+
+fn double_type() -> Type {
+    Type::base("double", 8, Encoding::Float)
+}
+
+pub fn is_char_type(value_type: &Type) -> bool {
+    matches!(
+        value_type.kind,
+        TypeKind::Base(Encoding::SignedChar | Encoding::UnsignedChar)
+    )
+}
+
+pub struct Evaluator<'a> {
+    inferior: &'a mut Inferior,
+    debug_data: &'a DwarfData,
+    /// Values printed earlier in the session, referred to as $1, $2, ... ($ is the last one)
+    history: &'a [TypedValue],
+    options: PrintOptions,
+    printers: &'a Printers,
+    /// The inferior's memory mappings, read when a pointer first needs describing
+    maps: OnceCell<Vec<MemoryMapping>>,
+}
+
+impl<'a> Evaluator<'a> {
+    pub fn new(
+        inferior: &'a mut Inferior,
+        debug_data: &'a DwarfData,
+        history: &'a [TypedValue],
+        options: PrintOptions,
+        printers: &'a Printers,
+    ) -> Self {
+        Evaluator {
+            inferior,
+            debug_data,
+            history,
+            options,
+            printers,
+            maps: OnceCell::new(),
+        }
+    }
+
+    /// Parses and evaluates a C expression in the context of the frame the inferior is stopped
+    /// in.
+    pub fn evaluate(&mut self, input: &str) -> Result<TypedValue, String> {
+        let mut parser = Parser {
+            tokens: tokenize(input)?,
+            pos: 0,
+            debug_data: self.debug_data,
+        };
+        let expr = parser.parse_expression()?;
+        if let Some(token) = parser.peek() {
+            return Err(format!("A syntax error in expression, near `{:?}'.", token));
+        }
+        let value = self.eval(&expr)?;
+        if value.value_type.name == NO_DEBUG_INFO_TYPE {
+            return Err(format!(
+                "'{}' has unknown type; cast it to its declared type",
+                input.trim()
+            ));
+        }
+        Ok(value)
+    }
+
+    /// Evaluates an expression that must produce a scalar (arrays decay to pointers), e.g. for
+    /// use as a printf argument.
+    pub fn evaluate_scalar(&mut self, input: &str) -> Result<Value, String> {
+        let value = self.evaluate(input)?;
+        let value = self.decay(value);
+        if !value.value_type.is_scalar() {
+            return Err(format!(
+                "Value of type {} is not a scalar.",
+                value.value_type.name
+            ));
+        }
+        Ok(value.scalar())
+    }
+
+    fn eval(&mut self, expr: &Expr) -> Result<TypedValue, String> {
+        match expr {
+            Expr::Int(value) => {
+                let value_type = if *value <= i32::MAX as u64 {
+                    int_type()
+                } else {
+                    long_type()
+                };
+                Ok(TypedValue::from_scalar(Value::Uint(*value), value_type))
+            }
+            Expr::Float(value) => Ok(TypedValue::from_scalar(Value::Float(*value), double_type())),
+            Expr::Char(value) => Ok(TypedValue::from_scalar(
+                Value::Char(*value),
+                builtin_type("char").unwrap(),
+            )),
+            Expr::Var(name) => self.eval_variable(name),
+            Expr::Unary(op, operand) => {
+                let operand = self.eval(operand)?;
+                self.eval_unary(op, operand)
+            }
+            Expr::Binary(op @ ("&&" | "||"), lhs, rhs) => {
+                // Short-circuit, so that e.g. `p && p->next` is safe
+                let lhs = self.eval_scalar(lhs)?;
+                let result = match (*op, is_true(lhs)) {
+                    ("&&", false) => false,
+                    ("||", true) => true,
+                    _ => is_true(self.eval_scalar(rhs)?),
+                };
+                Ok(TypedValue::from_scalar(
+                    Value::Int(result as i64),
+                    int_type(),
+                ))
+            }
+            Expr::Binary(op, lhs, rhs) => {
+                let lhs = self.eval(lhs)?;
+                let rhs = self.eval(rhs)?;
+                self.eval_binary(op, lhs, rhs)
+            }
+            Expr::Assign(lhs, rhs) => {
+                let lhs = self.eval(lhs)?;
+                let rhs = self.eval(rhs)?;
+                self.assign(lhs, rhs)
+            }
+            Expr::Cast(type_name, operand) => {
+                let target = self.resolve_type_name(type_name)?;
+                let operand = self.eval(operand)?;
+                self.cast(operand, target)
+            }
+            Expr::Member(operand, member) => {
+                let operand = self.eval(operand)?;
+                self.member(operand, member)
+            }
+            Expr::Index(array, index) => {
+                let array = self.eval(array)?;
+                let index = self.eval(index)?;
+                let element = self.eval_binary("+", array, index)?;
+                self.eval_unary("*", element)
+            }
+            Expr::Call(name, args) => {
+                let args = args
+                    .iter()
+                    .map(|arg| self.eval(arg))
+                    .collect::<Result<Vec<_>, _>>()?;
+                self.call(name, &args)
+            }
+        }
+    }
+
+    fn eval_scalar(&mut self, expr: &Expr) -> Result<Value, String> {
+        let value = self.eval(expr)?;
+        let value = self.decay(value);
+        if !value.value_type.is_scalar() {
+            return Err(format!(
+                "Value of type {} is not a scalar.",
+                value.value_type.name
+            ));
+        }
+        Ok(value.scalar())
+    }
+
+    /// Looks up the innermost variable in scope where the inferior is stopped, then the globals.
+    fn eval_variable(&mut self, name: &str) -> Result<TypedValue, String> {
+        if let Some(index) = name.strip_prefix('$') {
+            let value = if index.is_empty() {
+                self.history.last()
+            } else {
+                index
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|i| self.history.get(i.checked_sub(1)?))
+            };
+            return value
+                .cloned()
+                .ok_or_else(|| format!("History has not yet reached {}.", name));
+        }
+        let regs = self.inferior.getregs().map_err(|err| err.to_string())?;
+        let local = self.find_local(name, regs.rip as usize, regs.rbp as usize)?;
+        if let Some((var, base_ptr)) = local {
+            return self.frame_variable_value(var, base_ptr);
+        }
+        match self.debug_data.get_global_variable(name) {
+            Some(var) => self.variable_value(var),
+            None => self.eval_symbol(name),
+        }
+    }
+
+    /// Looks up a local variable in scope at rip, in the frame whose %rbp is base_ptr. In a nested
+    /// function (a GNU C extension), the functions it's defined in are searched next, following
+    /// static links to their frames. Returns the variable with the %rbp of its frame.
+    fn find_local(
+        &self,
+        name: &str,
+        rip: usize,
+        base_ptr: usize,
+    ) -> Result<Option<(&'a Variable, usize)>, String> {
+        let mut func = match self.debug_data.get_function_containing(rip) {
+            Some(func) => func,
+            None => return Ok(None),
+        };
+        if let Some(var) = func.get_variable(name, rip) {
+            return Ok(Some((var, base_ptr)));
+        }
+        // Only fail to follow a static link if a variable turns up in that frame
+        let mut base_ptr = Ok(base_ptr);
+        while let Some(enclosing) = &func.enclosing {
+            let nested = &func.name;
+            base_ptr = match (&enclosing.static_link, base_ptr) {
+                (Some(link), Ok(base_ptr)) => self
+                    .inferior
+                    .enclosing_frame(link, base_ptr)
+                    .map_err(|err| format!("Cannot follow the static link of {}: {}", nested, err)),
+                (None, Ok(_)) => Err(format!("{} has no usable static link", nested)),
+                (_, Err(err)) => Err(err),
+            };
+            func = match self.debug_data.get_enclosing_function(func) {
+                Some(func) => func,
+                None => break,
+            };
+            if let Some(var) = func.get_variable_in_block(name, enclosing.block) {
+                return match base_ptr {
+                    Ok(base_ptr) => Ok(Some((var, base_ptr))),
+                    Err(err) => Err(format!("Value of \"{}\" is unavailable: {}.", name, err)),
+                };
+            }
+        }
+        Ok(None)
+    }
+
+    /// Reads the value of a local variable of the current function, or of a global.
+    pub fn variable_value(&self, var: &Variable) -> Result<TypedValue, String> {
+        let addr = self
+            .inferior
+            .variable_address(var)
+            .map_err(|err| err.to_string())?;
+        self.read_value(addr, var.entity_type.clone())
+    }
+
+    /// Reads the value of a variable of the frame whose %rbp is base_ptr.
+    pub fn frame_variable_value(
+        &self,
+        var: &Variable,
+        base_ptr: usize,
+    ) -> Result<TypedValue, String> {
+        let addr = self.inferior.frame_variable_address(var, base_ptr);
+        self.read_value(addr, var.entity_type.clone())
+    }
+
+    /// Looks up a global variable that only the symbol table knows about. Its address can be
+    /// taken, but it has to be cast to a type before its value can be used.
+    fn eval_symbol(&self, name: &str) -> Result<TypedValue, String> {
+        let symbol = self
+            .debug_data
+            .get_symbol(name, false)
+            .ok_or_else(|| format!("No symbol \"{}\" in current context.", name))?;
+        Ok(TypedValue {
+            value_type: Type {
+                name: NO_DEBUG_INFO_TYPE.to_string(),
+                size: 0,
+                kind: TypeKind::Void,
+                ..Default::default()
+            },
+            bytes: Vec::new(),
+            address: Some(symbol.address),
+        })
+    }
+
+    pub fn read_value(&self, addr: usize, value_type: Type) -> Result<TypedValue, String> {
+        let bytes = self
+            .inferior
+            .read_bytes(addr, value_type.size)
+            .map_err(|_| format!("Cannot access memory at address {:#x}", addr))?;
+        Ok(TypedValue {
+            value_type,
+            bytes,
+            address: Some(addr),
+        })
+    }
+
+    /// Converts arrays to pointers to their first element, as C does in most expressions.
+    fn decay(&self, value: TypedValue) -> TypedValue {
+        match (&value.value_type.kind, value.address) {
+            (TypeKind::Array(element, _), Some(addr)) => TypedValue::from_scalar(
+                Value::Uint(addr as u64),
+                Type::pointer_to((**element).clone()),
+            ),
+            _ => value,
+        }
+    }
+
+    fn eval_unary(&mut self, op: &str, operand: TypedValue) -> Result<TypedValue, String> {
+        match op {
+            "&" => {
+                let addr = operand
+                    .address
+                    .ok_or("Attempt to take address of value not located in memory.")?;
+                Ok(TypedValue::from_scalar(
+                    Value::Uint(addr as u64),
+                    Type::pointer_to(operand.value_type),
+                ))
+            }
+            "*" => {
+                let operand = self.decay(operand);
+                match &operand.value_type.kind {
+                    TypeKind::Pointer(pointee)
+                        if !matches!(pointee.kind, TypeKind::Void | TypeKind::Function) =>
+                    {
+                        let addr = operand.scalar().as_u64() as usize;
+                        self.read_value(addr, (**pointee).clone())
+                    }
+                    _ => Err("Attempt to take contents of a non-pointer value.".to_string()),
+                }
+            }
+            _ => {
+                if !operand.value_type.is_scalar() {
+                    return Err(
+                        "Argument to arithmetic operation not a number or boolean.".to_string()
+                    );
+                }
+                let value = operand.scalar();
+                let result_type = promote(&operand.value_type, &operand.value_type);
+                let result = match (op, value) {
+                    ("!", value) => Value::Int(!is_true(value) as i64),
+                    ("+", value) => value,
+                    ("-", Value::Float(value)) => Value::Float(-value),
+                    ("-", value) => Value::Int((value.as_u64() as i64).wrapping_neg()),
+                    ("~", Value::Float(_)) => {
+                        return Err("Argument to complement operation not an integer.".to_string())
+                    }
+                    (_, value) => Value::Int(!(value.as_u64() as i64)),
+                };
+                let result_type = if op == "!" { int_type() } else { result_type };
+                Ok(TypedValue::from_scalar(result, result_type))
+            }
+        }
+    }
+
+    fn eval_binary(
+        &mut self,
+        op: &str,
+        lhs: TypedValue,
+        rhs: TypedValue,
+    ) -> Result<TypedValue, String> {
+        let lhs = self.decay(lhs);
+        let rhs = self.decay(rhs);
+        if !lhs.value_type.is_scalar() || !rhs.value_type.is_scalar() {
+            return Err("Argument to arithmetic operation not a number or boolean.".to_string());
+        }
+        let (l, r) = (lhs.scalar(), rhs.scalar());
+
+        // Pointer arithmetic is scaled by the size of the pointee
+        let pointee_size = |value_type: &Type| match &value_type.kind {
+            TypeKind::Pointer(pointee) => Some(pointee.size.max(1)),
+            _ => None,
+        };
+        match (
+            op,
+            pointee_size(&lhs.value_type),
+            pointee_size(&rhs.value_type),
+        ) {
+            ("+" | "-", Some(size), None) => {
+                let offset = (r.as_u64() as i64).wrapping_mul(size as i64);
+                let addr = if op == "+" {
+                    (l.as_u64() as i64).wrapping_add(offset)
+                } else {
+                    (l.as_u64() as i64).wrapping_sub(offset)
+                };
+                return Ok(TypedValue::from_scalar(Value::Int(addr), lhs.value_type));
+            }
+            ("+", None, Some(size)) => {
+                let addr = (r.as_u64() as i64).wrapping_add((l.as_u64() as i64) * size as i64);
+                return Ok(TypedValue::from_scalar(Value::Int(addr), rhs.value_type));
+            }
+            ("-", Some(size), Some(_)) => {
+                let diff = (l.as_u64() as i64).wrapping_sub(r.as_u64() as i64) / size as i64;
+                return Ok(TypedValue::from_scalar(Value::Int(diff), long_type()));
+            }
+            _ => {}
+        }
+
+        let result_type = promote(&lhs.value_type, &rhs.value_type);
+        let is_float = matches!(result_type.kind, TypeKind::Base(Encoding::Float));
+        let is_unsigned = matches!(result_type.kind, TypeKind::Base(Encoding::Unsigned));
+        let compare = |ordering: Option<std::cmp::Ordering>| -> Result<TypedValue, String> {
+            let ordering = ordering.ok_or("Comparison with NaN.")?;
+            let result = match op {
+                "==" => ordering.is_eq(),
+                "!=" => ordering.is_ne(),
+                "<" => ordering.is_lt(),
+                "<=" => ordering.is_le(),
+                ">" => ordering.is_gt(),
+                _ => ordering.is_ge(),
+            };
+            Ok(TypedValue::from_scalar(
+                Value::Int(result as i64),
+                int_type(),
+            ))
+        };
+        if matches!(op, "==" | "!=" | "<" | "<=" | ">" | ">=") {
+            return if is_float {
+                compare(l.as_f64().partial_cmp(&r.as_f64()))
+            } else if is_unsigned {
+                compare(Some(l.as_u64().cmp(&r.as_u64())))
+            } else {
+                compare(Some((l.as_u64() as i64).cmp(&(r.as_u64() as i64))))
+            };
+        }
+
+        let result = if is_float {
+            let (l, r) = (l.as_f64(), r.as_f64());
+            Value::Float(match op {
+                "+" => l + r,
+                "-" => l - r,
+                "*" => l * r,
+                "/" => l / r,
+                _ => return Err("Integer only operation on floating point number.".to_string()),
+            })
+        } else {
+            let (lu, ru) = (l.as_u64(), r.as_u64());
+            let (li, ri) = (lu as i64, ru as i64);
+            if matches!(op, "/" | "%") && ru == 0 {
+                return Err("Division by zero".to_string());
+            }
+            Value::Int(match op {
+                "+" => li.wrapping_add(ri),
+                "-" => li.wrapping_sub(ri),
+                "*" => li.wrapping_mul(ri),
+                "/" if is_unsigned => (lu / ru) as i64,
+                "/" => li.wrapping_div(ri),
+                "%" if is_unsigned => (lu % ru) as i64,
+                "%" => li.wrapping_rem(ri),
+                "&" => li & ri,
+                "|" => li | ri,
+                "^" => li ^ ri,
+                "<<" => li.wrapping_shl(ru as u32),
+                ">>" if is_unsigned => (lu.wrapping_shr(ru as u32)) as i64,
+                ">>" => li.wrapping_shr(ru as u32),
+                _ => return Err(format!("Unsupported operator {}", op)),
+            })
+        };
+        Ok(TypedValue::from_scalar(result, result_type))
+    }
+
+    pub fn member(&self, operand: TypedValue, name: &str) -> Result<TypedValue, String> {
+        let members = match &operand.value_type.kind {
+            TypeKind::Struct(members) | TypeKind::Union(members) => members,
+            _ => {
+                return Err(
+                    "Attempt to extract a component of a value that is not a structure."
+                        .to_string(),
+                )
+            }
+        };
+        let member = members
+            .iter()
+            .find(|member| member.name == name)
+            .ok_or_else(|| format!("There is no member named {}.", name))?;
+        self.member_value(&operand, member)
+    }
+
+    /// Extracts a member from a struct's value.
+    pub fn member_value(
+        &self,
+        operand: &TypedValue,
+        member: &Member,
+    ) -> Result<TypedValue, String> {
+        let member_type = self
+            .debug_data
+            .get_type(member.type_offset)
+            .cloned()
+            .ok_or_else(|| format!("Unknown type for member {}.", member.name))?;
+        let end = member.offset + member_type.size;
+        Ok(TypedValue {
+            bytes: operand
+                .bytes
+                .get(member.offset..end)
+                .ok_or("Member lies outside of its struct.")?
+                .to_vec(),
+            address: operand.address.map(|addr| addr + member.offset),
+            value_type: member_type,
+        })
+    }
+
+    fn resolve_type_name(&self, type_name: &TypeName) -> Result<Type, String> {
+        resolve_type_name(self.debug_data, type_name)
+    }
+
+    fn cast(&self, operand: TypedValue, target: Type) -> Result<TypedValue, String> {
+        if operand.value_type.name == NO_DEBUG_INFO_TYPE {
+            if let Some(addr) = operand.address {
+                return self.read_value(addr, target);
+            }
+        }
+        let operand = self.decay(operand);
+        if target.kind == TypeKind::Void {
+            return Ok(TypedValue {
+                value_type: target,
+                bytes: Vec::new(),
+                address: None,
+            });
+        }
+        if target.is_scalar() && operand.value_type.is_scalar() {
+            return Ok(TypedValue::from_scalar(
+                convert(operand.scalar(), &target),
+                target,
+            ));
+        }
+        if target.size == operand.value_type.size {
+            // Reinterpret aggregates of the same size, e.g. between struct typedefs
+            return Ok(TypedValue {
+                value_type: target,
+                ..operand
+            });
+        }
+        Err(format!(
+            "Invalid cast from {} to {}.",
+            operand.value_type.name, target.name
+        ))
+    }
+
+    /// Writes rhs into the memory that lhs refers to, converting it to lhs's type.
+    fn assign(&mut self, lhs: TypedValue, rhs: TypedValue) -> Result<TypedValue, String> {
+        let addr = lhs
+            .address
+            .ok_or("Left operand of assignment is not an lvalue.")?;
+        let new_value = self.cast(rhs, lhs.value_type.clone())?;
+        self.inferior
+            .write_bytes(addr, &new_value.bytes)
+            .map_err(|_| format!("Cannot access memory at address {:#x}", addr))?;
+        Ok(TypedValue {
+            address: Some(addr),
+            ..new_value
+        })
+    }
+
+    /// Calls a function in the inferior and returns its return value.
+    fn call(&mut self, name: &str, args: &[TypedValue]) -> Result<TypedValue, String> {
+        let func = self
+            .debug_data
+            .get_function(name)
+            .ok_or_else(|| format!("No symbol \"{}\" in current context.", name))?;
+        if args.len() > 6 {
+            return Err("Calls with more than 6 arguments are not supported.".to_string());
+        }
+        let mut arg_words = Vec::new();
+        for arg in args {
+            let arg = self.decay(arg.clone());
+            if !arg.value_type.is_scalar()
+                || matches!(arg.value_type.kind, TypeKind::Base(Encoding::Float))
+            {
+                return Err(format!(
+                    "Passing arguments of type {} is not supported.",
+                    arg.value_type.name
+                ));
+            }
+            arg_words.push(arg.scalar().as_u64());
+        }
+        let return_type = func.return_type.clone().filter(|return_type| return_type.is_scalar());
+        let float_return = return_type.as_ref().is_some_and(Type::is_float);
+        let entry_point = self.debug_data.entry_point();
+        let value = match self
+            .inferior
+            .call_function(func.address, &arg_words, entry_point, float_return)
+            .map_err(|err| err.to_string())?
+        {
+            Ok(value) => value,
+            Err(Status::Stopped(Signal::SIGTRAP, _)) => {
+                return Err(format!(
+                    "The program being debugged stopped at a breakpoint while in a function \
+                     called from deet. The call to {} was abandoned.",
+                    name
+                ))
+            }
+            Err(Status::Stopped(signal, _)) => {
+                return Err(format!(
+                    "The program being debugged was signaled ({}) while in a function called \
+                     from deet. The call was abandoned.",
+                    signal
+                ))
+            }
+            Err(_) => {
+                return Err(
+                    "The program being debugged exited while in a function called from deet."
+                        .to_string(),
+                )
+            }
+        };
+        Ok(match return_type {
+            Some(return_type) => {
+                let size = return_type.size.min(8);
+                TypedValue {
+                    bytes: value.to_le_bytes()[..size].to_vec(),
+                    value_type: return_type,
+                    address: None,
+                }
+            }
+            None => TypedValue {
+                value_type: Type::void(),
+                bytes: Vec::new(),
+                address: None,
+            },
+        })
+    }
+
+    pub fn debug_data(&self) -> &DwarfData {
+        self.debug_data
+    }
+
+    pub fn options(&self) -> PrintOptions {
+        self.options
+    }
+
+    /// Formats a value for display, the way gdb's print command does.
+    pub fn format(&self, value: &TypedValue) -> String {
+        self.format_value(value, 0)
+    }
+
+    /// Describes which of the inferior's memory mappings addr points into, if any.
+    fn describe_mapping(&self, addr: usize) -> Option<String> {
+        if addr == 0 {
+            return None;
+        }
+        let maps = self
+            .maps
+            .get_or_init(|| procfs::read_maps(self.inferior.pid()).unwrap_or_default());
+        maps.iter().find(|mapping| mapping.contains(addr))?.describe(addr)
+    }
+
+    /// Formats a value nested depth levels deep in structs and arrays.
+    pub fn format_value(&self, value: &TypedValue, depth: usize) -> String {
+        if let Some(formatted) = self.printers.format(value, self, depth) {
+            return formatted;
+        }
+        match &value.value_type.kind {
+            TypeKind::Void => "void".to_string(),
+            TypeKind::Function => format!(
+                "{{{}}} {:#x}",
+                value.value_type.name,
+                value.address.unwrap_or(0)
+            ),
+            TypeKind::Base(Encoding::Bool) => (value.scalar().as_u64() != 0).to_string(),
+            TypeKind::Base(Encoding::Signed | Encoding::Unsigned) if self.options.hex => {
+                let bits = value.value_type.size.min(8) * 8;
+                let mask = if bits == 64 { u64::MAX } else { (1 << bits) - 1 };
+                format!("{:#x}", value.scalar().as_u64() & mask)
+            }
+            TypeKind::Base(Encoding::Utf) => {
+                let raw = value.scalar().as_u64() as u32;
+                match char::from_u32(raw) {
+                    Some(c) => format!("{:?}", c),
+                    None => format!("{:#x}", raw),
+                }
+            }
+            TypeKind::Base(_) => value.scalar().to_string(),
+            TypeKind::Enum(enumerators) => {
+                let raw = value.scalar().as_u64() as i64;
+                match enumerators.iter().find(|(_, v)| *v == raw) {
+                    Some((name, _)) => name.clone(),
+                    None => raw.to_string(),
+                }
+            }
+            TypeKind::Pointer(_) => {
+                let addr = value.scalar().as_u64() as usize;
+                // Only the outermost value is labelled with its type, as gdb does
+                let formatted = if depth == 0 && addr != 0 {
+                    format!("({}) {:#x}", value.value_type.name, addr)
+                } else {
+                    format!("{:#x}", addr)
+                };
+                match self.describe_mapping(addr) {
+                    Some(mapping) => format!("{} in {}", formatted, mapping),
+                    None => formatted,
+                }
+            }
+            TypeKind::Struct(members) | TypeKind::Union(members) => {
+                let fields: Vec<String> = members
+                    .iter()
+                    .map(|member| match self.member(value.clone(), &member.name) {
+                        Ok(field) => {
+                            format!("{} = {}", member.name, self.format_value(&field, depth + 1))
+                        }
+                        Err(_) => format!("{} = <unknown>", member.name),
+                    })
+                    .collect();
+                if self.options.pretty {
+                    let indent = "  ".repeat(depth + 1);
+                    let fields: Vec<String> =
+                        fields.iter().map(|field| format!("{}{}", indent, field)).collect();
+                    format!("{{\n{}\n{}}}", fields.join(",\n"), "  ".repeat(depth))
+                } else {
+                    format!("{{{}}}", fields.join(", "))
+                }
+            }
+            TypeKind::Variants(discriminant, variants) => {
+                let discriminant = discriminant
+                    .as_ref()
+                    .and_then(|member| self.member_value(value, member).ok())
+                    .map(|discriminant| discriminant.scalar().as_u64());
+                let variant = variants
+                    .iter()
+                    .find(|(v, _)| v.is_some() && *v == discriminant)
+                    .or_else(|| variants.iter().find(|(v, _)| v.is_none()));
+                match variant {
+                    Some((_, member)) => self.format_variant(value, member, depth),
+                    None => format!("<invalid discriminant {}>", discriminant.unwrap_or(0)),
+                }
+            }
+            TypeKind::Array(element, count) => {
+                let mut elements = Vec::new();
+                for i in 0..(*count).min(self.options.elements) {
+                    let start = i * element.size;
+                    let element_value = TypedValue {
+                        value_type: (**element).clone(),
+                        bytes: value.bytes[start..start + element.size].to_vec(),
+                        address: value.address.map(|addr| addr + start),
+                    };
+                    elements.push(self.format_value(&element_value, depth + 1));
+                }
+                if *count > self.options.elements {
+                    elements.push("...".to_string());
+                }
+                format!("{{{}}}", elements.join(", "))
+            }
+        }
+    }
+
+    /// Formats the active variant of a Rust enum, e.g. `Some(5)` or `Rect{w: 2, h: 3}`.
+    fn format_variant(&self, value: &TypedValue, variant: &Member, depth: usize) -> String {
+        let fields = match self.member_value(value, variant) {
+            Ok(fields) => fields,
+            Err(_) => return format!("{}(<unknown>)", variant.name),
+        };
+        let members = match &fields.value_type.kind {
+            TypeKind::Struct(members) if !members.is_empty() => members,
+            _ => return variant.name.clone(),
+        };
+        let is_tuple = members.iter().all(|member| member.name.starts_with("__"));
+        let formatted: Vec<String> = members
+            .iter()
+            .map(|member| {
+                let field = match self.member_value(&fields, member) {
+                    Ok(field) => self.format_value(&field, depth + 1),
+                    Err(_) => "<unknown>".to_string(),
+                };
+                if is_tuple {
+                    field
+                } else {
+                    format!("{}: {}", member.name, field)
+                }
+            })
+            .collect();
+        if is_tuple {
+            format!("{}({})", variant.name, formatted.join(", "))
+        } else {
+            format!("{}{{{}}}", variant.name, formatted.join(", "))
+        }
+    }
+
+    /// Formats the first few of len elements stored contiguously at addr.
+    pub fn format_elements(&self, addr: usize, element: &Type, len: usize, depth: usize) -> String {
+        let mut elements = Vec::new();
+        for i in 0..len.min(self.options.elements) {
+            let element_addr = addr + i * element.size;
+            elements.push(match self.read_value(element_addr, element.clone()) {
+                Ok(element_value) => self.format_value(&element_value, depth + 1),
+                Err(err) => format!("<error: {}>", err),
+            });
+        }
+        if len > self.options.elements {
+            elements.push("...".to_string());
+        }
+        elements.join(", ")
+    }
+
+    /// Formats a string that's stored as UTF-8 bytes and a length rather than NUL-terminated.
+    pub fn format_str(&self, addr: usize, len: usize) -> String {
+        match self.inferior.read_bytes(addr, len.min(self.options.elements)) {
+            Ok(bytes) => format!("{:?}", String::from_utf8_lossy(&bytes))
+                + if len > self.options.elements { "..." } else { "" },
+            Err(_) => format!("<error: Cannot access memory at address {:#x}>", addr),
+        }
+    }
+
+    pub fn format_string(&self, addr: usize) -> String {
+        match self.inferior.read_cstring(addr, self.options.elements) {
+            Ok((bytes, truncated)) => quote(&bytes, truncated),
+            Err(_) => format!("<error: Cannot access memory at address {:#x}>", addr),
+        }
+    }
+}
+
+fn is_true(value: Value) -> bool {
+    match value {
+        Value::Float(value) => value != 0.0,
+        value => value.as_u64() != 0,
+    }
+}
+
+/// Picks the type of a binary arithmetic result, following (a simplified version of) C's usual
+/// arithmetic conversions.
+fn promote(lhs: &Type, rhs: &Type) -> Type {
+    let is = |value_type: &Type, encoding: Encoding| value_type.kind == TypeKind::Base(encoding);
+    if is(lhs, Encoding::Float) || is(rhs, Encoding::Float) {
+        return double_type();
+    }
+    let size = lhs.size.max(rhs.size).max(4);
+    let unsigned = [lhs, rhs].iter().any(|value_type| {
+        value_type.size == size
+            && matches!(
+                value_type.kind,
+                TypeKind::Base(Encoding::Unsigned) | TypeKind::Pointer(_)
+            )
+    });
+    match (size, unsigned) {
+        (8, true) => builtin_type("unsigned long").unwrap(),
+        (8, false) => long_type(),
+        (_, true) => builtin_type("unsigned int").unwrap(),
+        (_, false) => int_type(),
+    }
+}
+
+/// Converts a scalar value to the representation used by another scalar type.
+fn convert(value: Value, target: &Type) -> Value {
+    match target.kind {
+        TypeKind::Base(Encoding::Float) => Value::Float(value.as_f64()),
+        TypeKind::Base(Encoding::Bool) => Value::Uint(is_true(value) as u64),
+        _ => match value {
+            Value::Float(value) => Value::Int(value as i64),
+            value => value,
+        },
+    }
+}
+
+pub fn quote(bytes: &[u8], truncated: bool) -> String {
+    let mut quoted = String::from("\"");
+    for &byte in bytes {
+        quoted.extend(std::ascii::escape_default(byte).map(|c| c as char));
+    }
+    quoted.push('"');
+    if truncated {
+        quoted.push_str("...");
+    }
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::sample;
+    use crate::debugger::Debugger;
+
+    /// Evaluates each expression in linked_list.c just before its printf, once the list has
+    /// been built, and returns what print would show for it (or the error).
+    fn print_all(exprs: &[&str]) -> Vec<Result<String, String>> {
+        let mut debugger = Debugger::without_init_files(&sample("linked_list"));
+        debugger.set_breakpoint("33").unwrap();
+        debugger.start().unwrap();
+        let mut evaluator = debugger.evaluator().unwrap();
+        let results = exprs
+            .iter()
+            .map(|expr| {
+                let value = evaluator.evaluate(expr)?;
+                Ok(evaluator.format(&value))
+            })
+            .collect();
+        debugger.kill();
+        results
+    }
+
+    fn printed(exprs: &[&str]) -> Vec<String> {
+        print_all(exprs).into_iter().map(|result| result.unwrap()).collect()
+    }
+
+    #[test]
+    fn binary_operators_follow_c_precedence() {
+        let exprs = [
+            "1 + 2 * 3",
+            "(1 + 2) * 3",
+            "10 - 4 - 3",
+            "1 << 2 + 1",
+            "-2 * 3 + !0",
+            "1 < 2 == 1",
+            "0 || 2 && 3",
+            "7 / 2",
+            "7 / 2.0",
+            "7 % 3",
+        ];
+        assert_eq!(printed(&exprs), ["7", "9", "3", "8", "-5", "1", "1", "3", "3.5", "1"]);
+    }
+
+    #[test]
+    fn casts_convert_between_scalar_types() {
+        let exprs = [
+            "(char) 65",
+            "(unsigned char) 300",
+            "(int) 3.9",
+            "(long) -1",
+            "(unsigned int) -1",
+            "(double) 1 / 4",
+        ];
+        assert_eq!(printed(&exprs), ["65 'A'", "44 ','", "3", "-1", "4294967295", "0.25"]);
+    }
+
+    #[test]
+    fn pointer_arithmetic_scales_by_the_element_size() {
+        let exprs = [
+            "*(points + 1)",
+            "(points + 2)->y",
+            "&points[2] - &points[0]",
+            "*&points[1].y",
+            "&list.size == &list.size",
+        ];
+        assert_eq!(printed(&exprs), ["{x = 3, y = 4}", "6", "2", "4", "1"]);
+    }
+
+    #[test]
+    fn members_and_elements_can_be_read_and_assigned() {
+        let exprs = [
+            "list.head->value",
+            "list.head->next->value",
+            "list.head->next->next->next",
+            "points[1].x",
+            "list.head->value + points[2].y * 2",
+            "list.size = 7",
+            "list.size",
+            "points[0] = points[2]",
+        ];
+        let results = printed(&exprs);
+        assert_eq!(results[..7], ["1", "2", "0x0", "3", "13", "7", "7"]);
+        assert_eq!(results[7], "{x = 5, y = 6}");
+    }
+
+    #[test]
+    fn errors_are_reported_like_gdb() {
+        let exprs = [
+            "nosuch",
+            "1 +",
+            "list.nosuch",
+            "*1",
+            "points.x",
+            "1 / 0",
+            "(struct point) 1",
+            "(struct nothere *) 0",
+            "3 = 4",
+        ];
+        let errors: Vec<String> = print_all(&exprs).into_iter().map(Result::unwrap_err).collect();
+        assert_eq!(
+            errors,
+            [
+                "No symbol \"nosuch\" in current context.",
+                "A syntax error in expression, near `'.",
+                "There is no member named nosuch.",
+                "Attempt to take contents of a non-pointer value.",
+                "Attempt to extract a component of a value that is not a structure.",
+                "Division by zero",
+                "Invalid cast from int to struct point.",
+                "No symbol \"struct nothere\" in current context.",
+                "Left operand of assignment is not an lvalue.",
+            ]
+        );
+    }
+}
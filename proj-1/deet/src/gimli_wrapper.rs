@@ -5,252 +5,1033 @@
 //!
 //! This code is a huge mess. Please don't read it unless you're trying to do an extension :)
 
-use gimli;
+use gimli::Reader as _;
 use gimli::{UnitOffset, UnitSectionOffset};
 use object::{Object, ObjectSection};
 use std::borrow;
 //use std::io::{BufWriter, Write};
-use crate::dwarf_data::{File, Function, Line, Location, Type, Variable};
+use crate::dwarf_data::{
+    Block, EnclosingFunction, Encoding, File, Function, InlinedCall, Line, Location, Member,
+    StaticLink, Type, TypeKind, Variable,
+};
+use crate::dwarf_index::{self, NameKind};
 use std::collections::HashMap;
 use std::convert::TryInto;
 use std::fmt::Write;
+use std::sync::Arc;
 use std::{io, path};
 
-pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<Vec<File>, Error> {
-    // Load a section and return as `Cow<[u8]>`.
-    let load_section = |id: gimli::SectionId| -> Result<borrow::Cow<[u8]>, gimli::Error> {
-        match object.section_by_name(id.name()) {
-            Some(ref section) => Ok(section
-                .uncompressed_data()
-                .unwrap_or(borrow::Cow::Borrowed(&[][..]))),
-            None => Ok(borrow::Cow::Borrowed(&[][..])),
+/// Types are keyed by the section offset of their DIE.
+pub type TypeTable = HashMap<usize, Type>;
+
+/// The DWARF sections are kept in memory in this form, so that units can be parsed whenever they
+/// are first needed.
+pub type DwarfReader = gimli::EndianArcSlice<gimli::RunTimeEndian>;
+
+/// Loads the DWARF sections of object without parsing any of them.
+pub fn load_dwarf(
+    object: &object::File,
+    endian: gimli::RunTimeEndian,
+) -> Result<gimli::Dwarf<DwarfReader>, Error> {
+    gimli::Dwarf::load(|id| load_section(object, id.name(), endian))
+}
+
+/// Loads one section of object, decompressing it if it's compressed (SHF_COMPRESSED or the
+/// older .zdebug_* sections). A missing section is empty.
+fn load_section(
+    object: &object::File,
+    name: &str,
+    endian: gimli::RunTimeEndian,
+) -> Result<DwarfReader, Error> {
+    let data = match object.section_by_name(name) {
+        Some(ref section) => section
+            .uncompressed_data()
+            .map_err(|err| Error::ObjectError(format!("{}: {}", name, err)))?,
+        None => borrow::Cow::Borrowed(&[][..]),
+    };
+    Ok(gimli::EndianArcSlice::new(Arc::from(&*data), endian))
+}
+
+/// The full DIEs of a split compilation unit, which live in a .dwo file or a .dwp package
+/// rather than in the executable.
+pub struct SplitUnit {
+    pub dwarf: gimli::Dwarf<DwarfReader>,
+    pub header: gimli::UnitHeader<DwarfReader>,
+}
+
+/// Returns where the .dwo file of a skeleton unit should be, or None if the unit isn't split.
+pub fn dwo_path(
+    dwarf: &gimli::Dwarf<DwarfReader>,
+    skeleton: &gimli::Unit<DwarfReader>,
+) -> Result<Option<path::PathBuf>, Error> {
+    let name = match skeleton.dwo_name()? {
+        Some(name) => dwarf.attr_string(skeleton, name)?,
+        None => return Ok(None),
+    };
+    let mut path = path::PathBuf::new();
+    if let Some(comp_dir) = &skeleton.comp_dir {
+        path.push(comp_dir.to_string_lossy()?.as_ref());
+    }
+    path.push(name.to_string_lossy()?.as_ref());
+    Ok(Some(path))
+}
+
+/// Finds the split unit with the given id in a .dwo file.
+pub fn load_dwo(
+    object: &object::File,
+    endian: gimli::RunTimeEndian,
+    parent: &gimli::Dwarf<DwarfReader>,
+    dwo_id: gimli::DwoId,
+) -> Result<Option<SplitUnit>, Error> {
+    let mut dwarf =
+        gimli::Dwarf::load(|id| load_section(object, id.dwo_name().unwrap_or(""), endian))?;
+    dwarf.make_dwo(parent);
+    find_split_unit(dwarf, dwo_id)
+}
+
+/// Loads the index of a .dwp package, which holds the split units of a whole program.
+pub fn load_package(
+    object: &object::File,
+    endian: gimli::RunTimeEndian,
+) -> Result<gimli::DwarfPackage<DwarfReader>, Error> {
+    let empty = gimli::EndianArcSlice::new(Arc::from(&[][..]), endian);
+    gimli::DwarfPackage::load(
+        |id| {
+            let section = load_section(object, id.dwo_name().unwrap_or(""), endian)?;
+            // binutils' dwp writes an index with no slots when there are no type units, which
+            // gimli rejects
+            let mut header = section.clone();
+            let unit_count = header.skip(8).and_then(|_| header.read_u32());
+            if matches!(id, gimli::SectionId::DebugCuIndex | gimli::SectionId::DebugTuIndex)
+                && unit_count == Ok(0)
+            {
+                return Ok(empty.clone());
+            }
+            Ok(section)
+        },
+        empty.clone(),
+    )
+}
+
+/// Finds the split unit with the given id in a .dwp package.
+pub fn find_in_package(
+    package: &gimli::DwarfPackage<DwarfReader>,
+    parent: &gimli::Dwarf<DwarfReader>,
+    dwo_id: gimli::DwoId,
+) -> Result<Option<SplitUnit>, Error> {
+    match package.find_cu(dwo_id, parent)? {
+        Some(dwarf) => find_split_unit(dwarf, dwo_id),
+        None => Ok(None),
+    }
+}
+
+fn find_split_unit(
+    dwarf: gimli::Dwarf<DwarfReader>,
+    dwo_id: gimli::DwoId,
+) -> Result<Option<SplitUnit>, Error> {
+    for header in unit_headers(&dwarf)? {
+        if dwarf.unit(header.clone())?.dwo_id == Some(dwo_id) {
+            return Ok(Some(SplitUnit { dwarf, header }));
         }
+    }
+    Ok(None)
+}
+
+impl SplitUnit {
+    /// Parses the split unit's header, copying over the attributes that only the skeleton unit
+    /// in the executable has.
+    pub fn unit(
+        &self,
+        skeleton: &gimli::Unit<DwarfReader>,
+    ) -> Result<gimli::Unit<DwarfReader>, Error> {
+        let mut unit = self.dwarf.unit(self.header.clone())?;
+        unit.copy_relocated_attributes(skeleton);
+        Ok(unit)
+    }
+}
+
+/// Returns the headers of every compilation unit, in .debug_info order.
+pub fn unit_headers(
+    dwarf: &gimli::Dwarf<DwarfReader>,
+) -> Result<Vec<gimli::UnitHeader<DwarfReader>>, Error> {
+    let mut headers = Vec::new();
+    let mut iter = dwarf.units();
+    while let Some(header) = iter.next()? {
+        headers.push(header);
+    }
+    Ok(headers)
+}
+
+/// Returns the .debug_info offset of a unit's header.
+pub fn unit_offset(header: &gimli::UnitHeader<DwarfReader>) -> usize {
+    match header.offset() {
+        UnitSectionOffset::DebugInfoOffset(offset) => offset.0,
+        UnitSectionOffset::DebugTypesOffset(offset) => offset.0,
+    }
+}
+
+/// Returns the (unit offset, start, end) address ranges listed in .debug_aranges.
+pub fn arange_ranges(
+    dwarf: &gimli::Dwarf<DwarfReader>,
+) -> Result<Vec<(usize, usize, usize)>, Error> {
+    let mut ranges = Vec::new();
+    let mut headers = dwarf.debug_aranges.headers();
+    while let Some(header) = headers.next()? {
+        let mut entries = header.entries();
+        while let Some(entry) = entries.next()? {
+            let range = entry.range();
+            if range.begin < range.end {
+                let unit = header.debug_info_offset().0;
+                ranges.push((unit, range.begin as usize, range.end as usize));
+            }
+        }
+    }
+    Ok(ranges)
+}
+
+/// Returns the name of a unit (the path of its main source file) and the address ranges of its
+/// code. Only the unit's root DIE is read; for a split unit, that's the root of the split unit,
+/// since the skeleton doesn't have a name.
+pub fn unit_summary(
+    dwarf: &gimli::Dwarf<DwarfReader>,
+    unit: &gimli::Unit<DwarfReader>,
+    split: Option<&SplitUnit>,
+) -> Result<(String, Vec<(usize, usize)>), Error> {
+    let name = match split {
+        Some(split) => root_name(&split.dwarf, &split.unit(unit)?)?,
+        None => root_name(dwarf, unit)?,
     };
-    // Load a supplementary section. We don't have a supplementary object file,
-    // so always return an empty slice.
-    // let load_section_sup = |_| Ok(borrow::Cow::Borrowed(&[][..]));
+    let mut ranges = Vec::new();
+    let mut iter = dwarf.unit_ranges(unit)?;
+    while let Some(range) = iter.next()? {
+        if range.begin < range.end {
+            ranges.push((range.begin as usize, range.end as usize));
+        }
+    }
+    Ok((name.unwrap_or_else(|| "<unknown>".to_string()), ranges))
+}
+
+fn root_name(
+    dwarf: &gimli::Dwarf<DwarfReader>,
+    unit: &gimli::Unit<DwarfReader>,
+) -> Result<Option<String>, Error> {
+    let mut entries = unit.entries();
+    Ok(match entries.next_dfs()? {
+        Some((_, root)) => die_name(root, unit, dwarf).map(unit_name),
+        None => None,
+    })
+}
+
+/// Returns the path of a unit's main source file given the unit's DW_AT_name. rustc appends the
+/// codegen unit, e.g. "src/main.rs/@/main.1a2b3c-cgu.0".
+fn unit_name(name: String) -> String {
+    match name.find("/@/") {
+        Some(end) => name[..end].to_string(),
+        None => name,
+    }
+}
 
-    // Load all of the sections.
-    let dwarf_cow = gimli::Dwarf::load(&load_section)?;
+/// Returns the names of the functions, variables and types declared at the top level of a unit
+/// or in its namespaces, for binaries without a name index section. Other nested DIEs are
+/// skipped without being decoded.
+pub fn top_level_names(
+    dwarf: &gimli::Dwarf<DwarfReader>,
+    unit: &gimli::Unit<DwarfReader>,
+) -> Result<Vec<(NameKind, String)>, Error> {
+    let mut names = Vec::new();
+    let mut tree = unit.entries_tree(None)?;
+    collect_names(tree.root()?, unit, dwarf, &mut names)?;
+    Ok(names)
+}
 
-    // Borrow a `Cow<[u8]>` to create an `EndianSlice`.
-    let borrow_section: &dyn for<'a> Fn(
-        &'a borrow::Cow<[u8]>,
-    ) -> gimli::EndianSlice<'a, gimli::RunTimeEndian> =
-        &|section| gimli::EndianSlice::new(&*section, endian);
+fn collect_names(
+    node: gimli::EntriesTreeNode<DwarfReader>,
+    unit: &gimli::Unit<DwarfReader>,
+    dwarf: &gimli::Dwarf<DwarfReader>,
+    names: &mut Vec<(NameKind, String)>,
+) -> Result<(), Error> {
+    let mut children = node.children();
+    while let Some(child) = children.next()? {
+        let entry = child.entry();
+        if entry.tag() == gimli::DW_TAG_namespace {
+            collect_names(child, unit, dwarf, names)?;
+            continue;
+        }
+        if let Some(kind) = dwarf_index::tag_kind(entry.tag()) {
+            if let Some(name) = die_name(entry, unit, dwarf) {
+                names.push((kind, name));
+            }
+        }
+    }
+    Ok(())
+}
 
-    // Create `EndianSlice`s for all of the sections.
-    let dwarf = dwarf_cow.borrow(&borrow_section);
+/// Parses every DIE of a compilation unit, along with its line number rows. The DIEs of a split
+/// unit come from its .dwo, but its line table is still in the executable. offset_base is added
+/// to every type offset, so that split units' types (whose offsets are into their own
+/// .debug_info.dwo) don't collide with other units' types.
+pub fn load_unit(
+    dwarf: &gimli::Dwarf<DwarfReader>,
+    header: gimli::UnitHeader<DwarfReader>,
+    split: Option<&SplitUnit>,
+    offset_base: usize,
+) -> Result<(File, TypeTable), Error> {
+    let skeleton = dwarf.unit(header)?;
+    let (mut file, types) = match split {
+        Some(split) => load_entries(&split.dwarf, &split.unit(&skeleton)?)?,
+        None => load_entries(dwarf, &skeleton)?,
+    };
+    load_lines(dwarf, &skeleton, &mut file)?;
+    // Stable, so rows at the same address stay in line table order
+    file.lines.sort_by_key(|line| line.address);
+    if offset_base == 0 {
+        return Ok((file, types));
+    }
+    for func in &mut file.functions {
+        func.return_type.iter_mut().for_each(|t| relocate_type(t, offset_base));
+        for var in &mut func.variables {
+            relocate_type(&mut var.entity_type, offset_base);
+        }
+    }
+    for var in &mut file.global_variables {
+        relocate_type(&mut var.entity_type, offset_base);
+    }
+    let types = types
+        .into_iter()
+        .map(|(offset, mut t)| {
+            relocate_type(&mut t, offset_base);
+            (offset + offset_base, t)
+        })
+        .collect();
+    Ok((file, types))
+}
 
+/// Adds offset_base to the offsets of the types of t's members and parameters.
+fn relocate_type(t: &mut Type, offset_base: usize) {
+    for (_, type_offset) in &mut t.template_params {
+        *type_offset += offset_base;
+    }
+    match &mut t.kind {
+        TypeKind::Pointer(target) | TypeKind::Array(target, _) => {
+            relocate_type(target, offset_base)
+        }
+        TypeKind::Struct(members) | TypeKind::Union(members) => {
+            for member in members {
+                member.type_offset += offset_base;
+            }
+        }
+        TypeKind::Variants(discriminant, variants) => {
+            let variant_members = variants.iter_mut().map(|(_, member)| member);
+            for member in discriminant.iter_mut().chain(variant_members) {
+                member.type_offset += offset_base;
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Parses the functions, variables and types of a unit.
+fn load_entries(
+    dwarf: &gimli::Dwarf<DwarfReader>,
+    unit: &gimli::Unit<DwarfReader>,
+) -> Result<(File, TypeTable), Error> {
     // Define a mapping from type offsets to type structs
-    let mut offset_to_type: HashMap<usize, Type> = HashMap::new();
+    let mut offset_to_type: TypeTable = HashMap::new();
 
     let mut compilation_units: Vec<File> = Vec::new();
 
-    // Iterate over the compilation units.
-    let mut iter = dwarf.units();
-    while let Some(header) = iter.next()? {
-        let unit = dwarf.unit(header)?;
-
-        // Iterate over the Debugging Information Entries (DIEs) in the unit.
-        let mut depth = 0;
-        let mut entries = unit.entries();
-        while let Some((delta_depth, entry)) = entries.next_dfs()? {
-            depth += delta_depth;
-            // Update the offset_to_type mapping for types
-            // Update the variable list for formal params/variables
-            match entry.tag() {
-                gimli::DW_TAG_compile_unit => {
-                    let name = if let Ok(Some(attr)) = entry.attr(gimli::DW_AT_name) {
-                        if let Ok(DebugValue::Str(name)) = get_attr_value(&attr, &unit, &dwarf) {
-                            name
-                        } else {
-                            "<unknown>".to_string()
-                        }
+    // Types may be referenced before they are declared, so collect them in a first pass
+    let raw_types = load_types(unit, dwarf)?;
+    for offset in raw_types.keys() {
+        resolve_type(*offset, &raw_types, &mut offset_to_type, 0);
+    }
+
+    // Iterate over the Debugging Information Entries (DIEs) in the unit.
+    let mut depth = 0;
+    // The functions and blocks enclosing the current DIE, with their depths
+    let mut scopes: Vec<(isize, CodeScope)> = Vec::new();
+    let mut entries = unit.entries();
+    while let Some((delta_depth, entry)) = entries.next_dfs()? {
+        depth += delta_depth;
+        while scopes.last().is_some_and(|(scope_depth, _)| *scope_depth >= depth) {
+            scopes.pop();
+        }
+        // The innermost function, and its innermost block
+        let mut function = None;
+        let mut block = None;
+        for (_, scope) in scopes.iter().rev() {
+            match *scope {
+                CodeScope::Block(index) if block.is_none() => block = Some(index),
+                CodeScope::Block(_) => {}
+                CodeScope::Function(index, frame_base_adjustment) => {
+                    function = Some((index, frame_base_adjustment));
+                    break;
+                }
+            }
+        }
+        // Update the offset_to_type mapping for types
+        // Update the variable list for formal params/variables
+        match entry.tag() {
+            gimli::DW_TAG_compile_unit | gimli::DW_TAG_skeleton_unit => {
+                let name = if let Ok(Some(attr)) = entry.attr(gimli::DW_AT_name) {
+                    if let Ok(DebugValue::Str(name)) = get_attr_value(&attr, unit, dwarf) {
+                        unit_name(name)
                     } else {
                         "<unknown>".to_string()
-                    };
-                    compilation_units.push(File {
-                        name,
-                        global_variables: Vec::new(),
-                        functions: Vec::new(),
-                        lines: Vec::new(),
-                    });
+                    }
+                } else {
+                    "<unknown>".to_string()
+                };
+                compilation_units.push(File {
+                    name,
+                    ..Default::default()
+                });
+            }
+            gimli::DW_TAG_subprogram => {
+                // Methods are declared inside their types, and defined elsewhere
+                if entry.attr_value(gimli::DW_AT_declaration)?.is_some() {
+                    scopes.push((depth, CodeScope::Function(None, 0)));
+                    continue;
                 }
-                gimli::DW_TAG_base_type => {
-                    let name = if let Ok(Some(attr)) = entry.attr(gimli::DW_AT_name) {
-                        if let Ok(DebugValue::Str(name)) = get_attr_value(&attr, &unit, &dwarf) {
-                            name
-                        } else {
-                            "<unknown>".to_string()
+                // What to add to the function's DW_OP_fbreg offsets to make them relative to the
+                // canonical frame address, which is where Location::FramePointerOffset offsets
+                // start
+                let frame_base_adjustment = match entry.attr(gimli::DW_AT_frame_base)? {
+                    Some(attr) => frame_base_adjustment_of(&attr, unit),
+                    None => 0,
+                };
+                let mut func: Function = Default::default();
+                let mut attrs = entry.attrs();
+                while let Some(attr) = attrs.next()? {
+                    let val = get_attr_value(&attr, unit, dwarf);
+                    //println!("   {}: {:?}", attr.name(), val);
+                    match attr.name() {
+                        gimli::DW_AT_name => {
+                            if let Ok(DebugValue::Str(name)) = val {
+                                func.name = name;
+                            }
                         }
-                    } else {
-                        "<unknown>".to_string()
-                    };
-                    let byte_size = if let Ok(Some(attr)) = entry.attr(gimli::DW_AT_byte_size) {
-                        if let Ok(DebugValue::Uint(byte_size)) =
-                            get_attr_value(&attr, &unit, &dwarf)
-                        {
-                            byte_size
-                        } else {
-                            // TODO: report error?
-                            0
+                        gimli::DW_AT_high_pc => {
+                            if let Ok(DebugValue::Uint(high_pc)) = val {
+                                func.text_length = high_pc.try_into().unwrap();
+                            }
                         }
-                    } else {
-                        // TODO: report error?
-                        0
+                        gimli::DW_AT_low_pc => {
+                            //println!("low pc {:?}", attr.value());
+                            if let Ok(DebugValue::Uint(low_pc)) = val {
+                                func.address = low_pc.try_into().unwrap();
+                            }
+                        }
+                        gimli::DW_AT_decl_line => {
+                            if let Ok(DebugValue::Uint(line_number)) = val {
+                                func.line_number = line_number.try_into().unwrap();
+                            }
+                        }
+                        gimli::DW_AT_type => {
+                            if let Ok(DebugValue::Size(offset)) = val {
+                                func.return_type = offset_to_type.get(&offset).cloned();
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                if let Some(origin) = die_origin(entry, unit) {
+                    if func.name.is_empty() {
+                        func.name = die_name(&origin, unit, dwarf).unwrap_or_default();
+                    }
+                    if func.return_type.is_none() {
+                        let return_type = die_type(&origin, unit, dwarf);
+                        func.return_type =
+                            return_type.and_then(|t| offset_to_type.get(&t)).cloned();
+                    }
+                }
+                if let Some((Some(index), enclosing_adjustment)) = function {
+                    let static_link = match entry.attr(gimli::DW_AT_static_link)? {
+                        Some(attr) => static_link_of(&attr, unit, frame_base_adjustment)
+                            .map(|(offset, derefs)| StaticLink {
+                                offset,
+                                derefs,
+                                frame_base_adjustment: enclosing_adjustment,
+                            }),
+                        None => None,
                     };
-                    let type_offset = entry.offset().0;
-                    offset_to_type
-                        .insert(type_offset, Type::new(name, byte_size.try_into().unwrap()));
+                    func.enclosing = Some(EnclosingFunction { index, block, static_link });
                 }
-                gimli::DW_TAG_subprogram => {
-                    let mut func: Function = Default::default();
-                    let mut attrs = entry.attrs();
-                    while let Some(attr) = attrs.next()? {
-                        let val = get_attr_value(&attr, &unit, &dwarf);
-                        //println!("   {}: {:?}", attr.name(), val);
-                        match attr.name() {
-                            gimli::DW_AT_name => {
-                                if let Ok(DebugValue::Str(name)) = val {
-                                    func.name = name;
-                                }
-                            }
-                            gimli::DW_AT_high_pc => {
-                                if let Ok(DebugValue::Uint(high_pc)) = val {
-                                    func.text_length = high_pc.try_into().unwrap();
-                                }
+                let functions = &mut compilation_units.last_mut().unwrap().functions;
+                let index = functions.len();
+                functions.push(func);
+                scopes.push((depth, CodeScope::Function(Some(index), frame_base_adjustment)));
+            }
+            gimli::DW_TAG_lexical_block | gimli::DW_TAG_inlined_subroutine => {
+                let mut ranges = Vec::new();
+                let mut iter = dwarf.die_ranges(unit, entry)?;
+                while let Some(range) = iter.next()? {
+                    if range.begin < range.end {
+                        ranges.push((range.begin as usize, range.end as usize));
+                    }
+                }
+                let is_inlined = entry.tag() == gimli::DW_TAG_inlined_subroutine;
+                let origin = die_origin(entry, unit);
+                let name = origin.as_ref().and_then(|origin| die_name(origin, unit, dwarf));
+                let file = compilation_units.last_mut().unwrap();
+                if let Some((Some(index), _)) = function {
+                    let blocks = &mut file.functions[index].blocks;
+                    scopes.push((depth, CodeScope::Block(blocks.len())));
+                    blocks.push(Block {
+                        ranges: ranges.clone(),
+                        parent: block,
+                        inlined_function: name.clone().filter(|_| is_inlined),
+                    });
+                }
+                let name = match name {
+                    Some(name) if is_inlined => name,
+                    _ => continue,
+                };
+                let entry_pc = match entry.attr_value(gimli::DW_AT_entry_pc)? {
+                    Some(value) => dwarf.attr_address(unit, value)?.map(|addr| addr as usize),
+                    None => None,
+                };
+                let address = match entry_pc.or_else(|| ranges.iter().map(|r| r.0).min()) {
+                    Some(address) => address,
+                    None => continue,
+                };
+                let call = InlinedCall { name, address };
+                compilation_units.last_mut().unwrap().inlined_calls.push(call);
+            }
+            gimli::DW_TAG_formal_parameter | gimli::DW_TAG_variable => {
+                let mut name = String::new();
+                let mut entity_type: Option<Type> = None;
+                let mut location: Option<Location> = None;
+                let mut line_number = 0;
+                let mut attrs = entry.attrs();
+                while let Some(attr) = attrs.next()? {
+                    let val = get_attr_value(&attr, unit, dwarf);
+                    //println!("   {}: {:?}", attr.name(), val);
+                    match attr.name() {
+                        gimli::DW_AT_name => {
+                            if let Ok(DebugValue::Str(attr_name)) = val {
+                                name = attr_name;
                             }
-                            gimli::DW_AT_low_pc => {
-                                //println!("low pc {:?}", attr.value());
-                                if let Ok(DebugValue::Uint(low_pc)) = val {
-                                    func.address = low_pc.try_into().unwrap();
+                        }
+                        gimli::DW_AT_type => {
+                            if let Ok(DebugValue::Size(offset)) = val {
+                                if let Some(dtype) = offset_to_type.get(&offset) {
+                                    entity_type = Some(dtype.clone());
                                 }
                             }
-                            gimli::DW_AT_decl_line => {
-                                if let Ok(DebugValue::Uint(line_number)) = val {
-                                    func.line_number = line_number.try_into().unwrap();
+                        }
+                        gimli::DW_AT_location => {
+                            location = match get_location(&attr, unit, dwarf) {
+                                Some(Location::FramePointerOffset(offset)) => {
+                                    let adjustment = function.map_or(0, |function| function.1);
+                                    Some(Location::FramePointerOffset(offset + adjustment))
                                 }
+                                loc => loc,
+                            };
+                        }
+                        gimli::DW_AT_decl_line => {
+                            if let Ok(DebugValue::Uint(num)) = val {
+                                line_number = num;
                             }
-                            _ => {}
                         }
+                        _ => {}
                     }
-                    compilation_units.last_mut().unwrap().functions.push(func);
                 }
-                gimli::DW_TAG_formal_parameter | gimli::DW_TAG_variable => {
-                    let mut name = String::new();
-                    let mut entity_type: Option<Type> = None;
-                    let mut location: Option<Location> = None;
-                    let mut line_number = 0;
-                    let mut attrs = entry.attrs();
-                    while let Some(attr) = attrs.next()? {
-                        let val = get_attr_value(&attr, &unit, &dwarf);
-                        //println!("   {}: {:?}", attr.name(), val);
-                        match attr.name() {
-                            gimli::DW_AT_name => {
-                                if let Ok(DebugValue::Str(attr_name)) = val {
-                                    name = attr_name;
-                                }
+                // The parameters and variables of inlined calls are described by the function's
+                // abstract instance
+                if let Some(origin) = die_origin(entry, unit) {
+                    if name.is_empty() {
+                        name = die_name(&origin, unit, dwarf).unwrap_or_default();
+                    }
+                    if entity_type.is_none() {
+                        let origin_type = die_type(&origin, unit, dwarf);
+                        entity_type = origin_type.and_then(|t| offset_to_type.get(&t)).cloned();
+                    }
+                    if line_number == 0 {
+                        let decl_line = die_udata(&origin, gimli::DW_AT_decl_line);
+                        line_number = decl_line.unwrap_or(0) as u64;
+                    }
+                }
+                if let (Some(entity_type), Some(location)) = (entity_type, location) {
+                    let var = Variable {
+                        name,
+                        entity_type,
+                        location,
+                        line_number: line_number.try_into().unwrap(),
+                        is_parameter: entry.tag() == gimli::DW_TAG_formal_parameter,
+                        block,
+                    };
+                    let file = compilation_units.last_mut().unwrap();
+                    match function {
+                        Some((Some(index), _)) => file.functions[index].variables.push(var),
+                        Some((None, _)) => {}
+                        None => file.global_variables.push(var),
+                    }
+                }
+            }
+            // NOTE: :You may consider supporting other types by extending this
+            // match statement
+            _ => {}
+        }
+    }
+
+    Ok((compilation_units.pop().unwrap_or_default(), offset_to_type))
+}
+
+/// Adds the rows of a unit's line table that belong to its main source file to file.lines.
+fn load_lines(
+    dwarf: &gimli::Dwarf<DwarfReader>,
+    unit: &gimli::Unit<DwarfReader>,
+    file: &mut File,
+) -> Result<(), Error> {
+    // Line tables may give the path relative to the compilation directory, or joined with it
+    let mut full_name = path::PathBuf::new();
+    if let Some(comp_dir) = &unit.comp_dir {
+        full_name.push(comp_dir.to_string_lossy()?.as_ref());
+    }
+    full_name.push(&file.name);
+    if let Some(program) = unit.line_program.clone() {
+        // Iterate over the line program rows.
+        let mut rows = program.rows();
+        while let Some((header, row)) = rows.next_row()? {
+            if !row.end_sequence() {
+                // Determine the path. Real applications should cache this for performance.
+                let mut path = path::PathBuf::new();
+                if let Some(file) = row.file(header) {
+                    if let Some(dir) = file.directory(header) {
+                        path.push(dwarf.attr_string(unit, dir)?.to_string_lossy()?.as_ref());
+                    }
+                    path.push(
+                        dwarf
+                            .attr_string(unit, file.path_name())?
+                            .to_string_lossy()?
+                            .as_ref(),
+                    );
+                }
+
+                // Determine line/column. DWARF line/column is never 0, so we use that
+                // but other applications may want to display this differently.
+                let line = if let Some(line) = row.line() {
+                    line.get().try_into().unwrap()
+                } else {
+                    0
+                };
+
+                if file.name == path.as_os_str().to_str().unwrap() || full_name == path {
+                    file.lines.push(Line {
+                        file: file.name.clone(),
+                        number: line.try_into().unwrap(),
+                        address: row.address().try_into().unwrap(),
+                    });
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// A type DIE whose references to other types haven't been resolved yet.
+enum RawType {
+    Base(String, usize, Encoding),
+    /// Rust names its pointer types, e.g. "&str" or "alloc::boxed::Box<i32, ...>"
+    Pointer(Option<usize>, Option<String>),
+    Struct(RawStruct),
+    Enum(String, usize, Vec<(String, i64)>),
+    Array(Option<usize>, usize),
+    Typedef(String, Option<usize>),
+    Qualified(&'static str, Option<usize>),
+    Subroutine,
+}
+
+/// A struct, union or Rust enum.
+struct RawStruct {
+    name: String,
+    size: usize,
+    is_union: bool,
+    members: Vec<Member>,
+    /// The variants, if the struct has a DW_TAG_variant_part, and the member they depend on
+    variants: Option<Vec<(Option<u64>, Member)>>,
+    discriminant: Option<Member>,
+    template_params: Vec<(String, usize)>,
+}
+
+/// A DIE whose variables are local to it.
+enum CodeScope {
+    /// A function, by its index in File.functions (None for declarations), with the adjustment
+    /// to make to its DW_OP_fbreg offsets
+    Function(Option<usize>, isize),
+    /// A block, by its index in Function.blocks
+    Block(usize),
+}
+
+/// A DIE that the DIEs nested in it belong to.
+enum Scope {
+    /// A namespace, by its fully qualified name
+    Namespace(String),
+    /// A struct, enum or array, by its offset
+    Type(usize),
+    /// The DW_TAG_variant_part of a struct, with the offset of its discriminant's DIE
+    VariantPart(usize, Option<usize>),
+    /// One DW_TAG_variant of a struct, with its discriminant value
+    Variant(usize, Option<u64>),
+    Other,
+}
+
+fn die_offset<R: Reader>(entry: &gimli::DebuggingInformationEntry<R>, unit: &gimli::Unit<R>) -> usize {
+    match entry.offset().to_unit_section_offset(unit) {
+        UnitSectionOffset::DebugInfoOffset(goff) => goff.0,
+        UnitSectionOffset::DebugTypesOffset(goff) => goff.0,
+    }
+}
+
+fn die_offset_of<R: Reader>(offset: UnitOffset, unit: &gimli::Unit<R>) -> usize {
+    match offset.to_unit_section_offset(unit) {
+        UnitSectionOffset::DebugInfoOffset(goff) => goff.0,
+        UnitSectionOffset::DebugTypesOffset(goff) => goff.0,
+    }
+}
+
+fn die_name<R: Reader>(
+    entry: &gimli::DebuggingInformationEntry<R>,
+    unit: &gimli::Unit<R>,
+    dwarf: &gimli::Dwarf<R>,
+) -> Option<String> {
+    match get_attr_value(&entry.attr(gimli::DW_AT_name).ok()??, unit, dwarf) {
+        Ok(DebugValue::Str(name)) => Some(name),
+        _ => None,
+    }
+}
+
+/// Returns the DIE that an abstract or out-of-line instance of a function or variable refers to
+/// for its name and type.
+fn die_origin<'unit, R: Reader>(
+    entry: &gimli::DebuggingInformationEntry<R>,
+    unit: &'unit gimli::Unit<R>,
+) -> Option<gimli::DebuggingInformationEntry<'unit, 'unit, R>> {
+    let origin = entry
+        .attr_value(gimli::DW_AT_abstract_origin)
+        .ok()?
+        .or_else(|| entry.attr_value(gimli::DW_AT_specification).ok()?)?;
+    match origin {
+        gimli::AttributeValue::UnitRef(offset) => unit.entry(offset).ok(),
+        _ => None,
+    }
+}
+
+fn die_type<R: Reader>(
+    entry: &gimli::DebuggingInformationEntry<R>,
+    unit: &gimli::Unit<R>,
+    dwarf: &gimli::Dwarf<R>,
+) -> Option<usize> {
+    match get_attr_value(&entry.attr(gimli::DW_AT_type).ok()??, unit, dwarf) {
+        Ok(DebugValue::Size(offset)) => Some(offset),
+        _ => None,
+    }
+}
+
+fn die_udata<R: Reader>(
+    entry: &gimli::DebuggingInformationEntry<R>,
+    name: gimli::DwAt,
+) -> Option<usize> {
+    entry.attr(name).ok()??.udata_value()?.try_into().ok()
+}
+
+/// Collects every type DIE in the unit, along with the members of structs and enums, the
+/// variants of Rust enums and the bounds of arrays. Types in namespaces (and, in Rust, types
+/// nested in other types) get qualified names, e.g. "alloc::string::String".
+fn load_types<R: Reader>(
+    unit: &gimli::Unit<R>,
+    dwarf: &gimli::Dwarf<R>,
+) -> Result<HashMap<usize, RawType>, Error> {
+    let mut types = HashMap::new();
+    let mut is_rust = false;
+    // The DIEs enclosing the current one, with their depths
+    let mut scopes: Vec<(isize, Scope)> = Vec::new();
+    let mut depth = 0;
+    let mut entries = unit.entries();
+    while let Some((delta_depth, entry)) = entries.next_dfs()? {
+        depth += delta_depth;
+        while scopes.last().is_some_and(|(scope_depth, _)| *scope_depth >= depth) {
+            scopes.pop();
+        }
+        let parent = match scopes.last() {
+            Some((parent_depth, scope)) if *parent_depth == depth - 1 => scope,
+            _ => &Scope::Other,
+        };
+        let offset = die_offset(entry, unit);
+        let name = die_name(entry, unit, dwarf);
+        let target = die_type(entry, unit, dwarf);
+        let size = die_udata(entry, gimli::DW_AT_byte_size).unwrap_or(0);
+        // The name that a type declared here gets
+        let qualify = |name: String| {
+            let prefix = scopes.iter().rev().find_map(|(_, scope)| match scope {
+                Scope::Namespace(namespace) => Some(namespace.as_str()),
+                Scope::Type(offset) if is_rust => match types.get(offset) {
+                    Some(RawType::Struct(parent)) => Some(parent.name.as_str()),
+                    Some(RawType::Enum(parent, _, _)) => Some(parent.as_str()),
+                    _ => None,
+                },
+                _ => None,
+            });
+            match prefix {
+                Some(prefix) => format!("{}::{}", prefix, name),
+                None => name,
+            }
+        };
+        let raw = match entry.tag() {
+            gimli::DW_TAG_compile_unit | gimli::DW_TAG_skeleton_unit => {
+                is_rust = matches!(
+                    entry.attr_value(gimli::DW_AT_language)?,
+                    Some(gimli::AttributeValue::Language(gimli::DW_LANG_Rust))
+                );
+                continue;
+            }
+            gimli::DW_TAG_namespace => {
+                let name = name.unwrap_or_else(|| "(anonymous namespace)".to_string());
+                let namespace = qualify(name);
+                scopes.push((depth, Scope::Namespace(namespace)));
+                continue;
+            }
+            gimli::DW_TAG_base_type => {
+                let encoding = match entry.attr_value(gimli::DW_AT_encoding)? {
+                    Some(gimli::AttributeValue::Encoding(gimli::DW_ATE_float)) => Encoding::Float,
+                    Some(gimli::AttributeValue::Encoding(gimli::DW_ATE_boolean)) => Encoding::Bool,
+                    Some(gimli::AttributeValue::Encoding(gimli::DW_ATE_signed_char)) => {
+                        Encoding::SignedChar
+                    }
+                    Some(gimli::AttributeValue::Encoding(gimli::DW_ATE_unsigned_char)) => {
+                        Encoding::UnsignedChar
+                    }
+                    Some(gimli::AttributeValue::Encoding(gimli::DW_ATE_unsigned)) => {
+                        Encoding::Unsigned
+                    }
+                    Some(gimli::AttributeValue::Encoding(gimli::DW_ATE_UTF)) => Encoding::Utf,
+                    _ => Encoding::Signed,
+                };
+                RawType::Base(name.unwrap_or_else(|| "<unknown>".to_string()), size, encoding)
+            }
+            gimli::DW_TAG_pointer_type => RawType::Pointer(target, name),
+            gimli::DW_TAG_structure_type | gimli::DW_TAG_union_type => {
+                let is_union = entry.tag() == gimli::DW_TAG_union_type;
+                let keyword = if is_union { "union" } else { "struct" };
+                let name = match name {
+                    Some(name) if is_rust => qualify(name),
+                    Some(name) => format!("{} {}", keyword, qualify(name)),
+                    None => format!("{} {{...}}", keyword),
+                };
+                scopes.push((depth, Scope::Type(offset)));
+                RawType::Struct(RawStruct {
+                    name,
+                    size,
+                    is_union,
+                    members: Vec::new(),
+                    variants: None,
+                    discriminant: None,
+                    template_params: Vec::new(),
+                })
+            }
+            gimli::DW_TAG_enumeration_type => {
+                let name = match name {
+                    Some(name) if is_rust => qualify(name),
+                    Some(name) => format!("enum {}", qualify(name)),
+                    None => "enum {...}".to_string(),
+                };
+                scopes.push((depth, Scope::Type(offset)));
+                RawType::Enum(name, size, Vec::new())
+            }
+            gimli::DW_TAG_array_type => {
+                scopes.push((depth, Scope::Type(offset)));
+                RawType::Array(target, 0)
+            }
+            gimli::DW_TAG_typedef => {
+                RawType::Typedef(qualify(name.unwrap_or_else(|| "<unknown>".to_string())), target)
+            }
+            gimli::DW_TAG_const_type => RawType::Qualified("const", target),
+            gimli::DW_TAG_volatile_type => RawType::Qualified("volatile", target),
+            gimli::DW_TAG_subroutine_type => RawType::Subroutine,
+            gimli::DW_TAG_variant_part => {
+                if let Scope::Type(struct_offset) = *parent {
+                    if let Some(RawType::Struct(parent_struct)) = types.get_mut(&struct_offset) {
+                        parent_struct.variants = Some(Vec::new());
+                        let discriminant = match entry.attr_value(gimli::DW_AT_discr)? {
+                            Some(gimli::AttributeValue::UnitRef(discriminant)) => {
+                                Some(die_offset_of(discriminant, unit))
                             }
-                            gimli::DW_AT_type => {
-                                if let Ok(DebugValue::Size(offset)) = val {
-                                    if let Some(dtype) = offset_to_type.get(&offset).clone() {
-                                        entity_type = Some(dtype.clone());
+                            _ => None,
+                        };
+                        scopes.push((depth, Scope::VariantPart(struct_offset, discriminant)));
+                    }
+                }
+                continue;
+            }
+            gimli::DW_TAG_variant => {
+                if let Scope::VariantPart(struct_offset, _) = *parent {
+                    let value = entry.attr(gimli::DW_AT_discr_value)?.and_then(|attr| {
+                        attr.udata_value().or_else(|| attr.sdata_value().map(|v| v as u64))
+                    });
+                    scopes.push((depth, Scope::Variant(struct_offset, value)));
+                }
+                continue;
+            }
+            gimli::DW_TAG_member
+            | gimli::DW_TAG_enumerator
+            | gimli::DW_TAG_subrange_type
+            | gimli::DW_TAG_template_type_parameter => {
+                let parent_offset = match *parent {
+                    Scope::Type(offset)
+                    | Scope::VariantPart(offset, _)
+                    | Scope::Variant(offset, _) => offset,
+                    _ => continue,
+                };
+                let member = |name: String, type_offset: usize| Member {
+                    name,
+                    offset: die_udata(entry, gimli::DW_AT_data_member_location).unwrap_or(0),
+                    type_offset,
+                };
+                match (types.get_mut(&parent_offset), entry.tag()) {
+                    (Some(RawType::Struct(parent_struct)), gimli::DW_TAG_member) => {
+                        match (parent, &mut parent_struct.variants) {
+                            (Scope::VariantPart(_, discriminant), Some(_)) => {
+                                if *discriminant == Some(offset) {
+                                    if let Some(type_offset) = target {
+                                        parent_struct.discriminant =
+                                            Some(member(String::new(), type_offset));
                                     }
                                 }
                             }
-                            gimli::DW_AT_location => {
-                                if let Some(loc) = get_location(&attr, &unit) {
-                                    location = Some(loc);
+                            (Scope::Variant(_, value), Some(variants)) => {
+                                if let (Some(name), Some(type_offset)) = (name, target) {
+                                    variants.push((*value, member(name, type_offset)));
                                 }
                             }
-                            gimli::DW_AT_decl_line => {
-                                if let Ok(DebugValue::Uint(num)) = val {
-                                    line_number = num;
+                            _ => {
+                                if let (Some(name), Some(type_offset)) = (name, target) {
+                                    parent_struct.members.push(member(name, type_offset));
                                 }
                             }
-                            _ => {}
                         }
                     }
-                    if entity_type.is_some() && location.is_some() {
-                        let var = Variable {
-                            name,
-                            entity_type: entity_type.unwrap(),
-                            location: location.unwrap(),
-                            line_number: line_number.try_into().unwrap(),
-                        };
-                        if depth == 1 {
-                            compilation_units
-                                .last_mut()
-                                .unwrap()
-                                .global_variables
-                                .push(var);
-                        } else if depth > 1 {
-                            compilation_units
-                                .last_mut()
-                                .unwrap()
-                                .functions
-                                .last_mut()
-                                .unwrap()
-                                .variables
-                                .push(var);
+                    (
+                        Some(RawType::Struct(parent_struct)),
+                        gimli::DW_TAG_template_type_parameter,
+                    ) => {
+                        if let (Some(name), Some(type_offset)) = (name, target) {
+                            parent_struct.template_params.push((name, type_offset));
                         }
                     }
-                }
-                // NOTE: :You may consider supporting other types by extending this
-                // match statement
-                _ => {}
-            }
-        }
-
-        // Get line numbers
-        if let Some(program) = unit.line_program.clone() {
-            // Iterate over the line program rows.
-            let mut rows = program.rows();
-            while let Some((header, row)) = rows.next_row()? {
-                if !row.end_sequence() {
-                    // Determine the path. Real applications should cache this for performance.
-                    let mut path = path::PathBuf::new();
-                    if let Some(file) = row.file(header) {
-                        if let Some(dir) = file.directory(header) {
-                            path.push(dwarf.attr_string(&unit, dir)?.to_string_lossy().as_ref());
+                    (Some(RawType::Enum(_, _, enumerators)), gimli::DW_TAG_enumerator) => {
+                        if let (Some(name), Some(attr)) =
+                            (name, entry.attr(gimli::DW_AT_const_value)?)
+                        {
+                            let value = attr
+                                .sdata_value()
+                                .or_else(|| attr.udata_value().map(|v| v as i64))
+                                .unwrap_or(0);
+                            enumerators.push((name, value));
                         }
-                        path.push(
-                            dwarf
-                                .attr_string(&unit, file.path_name())?
-                                .to_string_lossy()
-                                .as_ref(),
-                        );
                     }
-
-                    // Get the File
-                    let file = compilation_units
-                        .iter_mut()
-                        .find(|f| f.name == path.as_os_str().to_str().unwrap());
-
-                    // Determine line/column. DWARF line/column is never 0, so we use that
-                    // but other applications may want to display this differently.
-                    let line = if let Some(line) = row.line() {
-                        line.get().try_into().unwrap()
-                    } else {
-                        0
-                    };
-
-                    if let Some(file) = file {
-                        file.lines.push(Line {
-                            file: file.name.clone(),
-                            number: line.try_into().unwrap(),
-                            address: row.address().try_into().unwrap(),
-                        });
+                    (Some(RawType::Array(_, count)), gimli::DW_TAG_subrange_type) => {
+                        if let Some(n) = die_udata(entry, gimli::DW_AT_count) {
+                            *count = n;
+                        } else if let Some(upper_bound) = die_udata(entry, gimli::DW_AT_upper_bound)
+                        {
+                            *count = upper_bound + 1;
+                        }
                     }
+                    _ => {}
                 }
+                continue;
             }
-        }
+            _ => {
+                scopes.push((depth, Scope::Other));
+                continue;
+            }
+        };
+        types.insert(offset, raw);
+    }
+    Ok(types)
+}
+
+/// Resolves the type at offset (and everything it refers to, except for struct members, which
+/// are looked up lazily so that self-referential structs don't recurse forever).
+fn resolve_type(
+    offset: usize,
+    raw_types: &HashMap<usize, RawType>,
+    resolved: &mut TypeTable,
+    depth: usize,
+) -> Option<Type> {
+    if let Some(resolved_type) = resolved.get(&offset) {
+        return Some(resolved_type.clone());
     }
-    Ok(compilation_units)
+    if depth > 64 {
+        return None;
+    }
+    let resolve_target = |target: &Option<usize>, resolved: &mut TypeTable| match target {
+        Some(target) => resolve_type(*target, raw_types, resolved, depth + 1),
+        None => Some(Type::void()),
+    };
+    let resolved_type = match raw_types.get(&offset)? {
+        RawType::Base(name, size, encoding) => Type::base(name, *size, *encoding),
+        RawType::Pointer(target, name) => {
+            let pointer = Type::pointer_to(resolve_target(target, resolved)?);
+            match name {
+                Some(name) => Type {
+                    name: name.clone(),
+                    ..pointer
+                },
+                None => pointer,
+            }
+        }
+        RawType::Struct(raw) => Type {
+            name: raw.name.clone(),
+            size: raw.size,
+            kind: match &raw.variants {
+                Some(variants) => TypeKind::Variants(raw.discriminant.clone(), variants.clone()),
+                None if raw.is_union => TypeKind::Union(raw.members.clone()),
+                None => TypeKind::Struct(raw.members.clone()),
+            },
+            template_params: raw.template_params.clone(),
+        },
+        RawType::Enum(name, size, enumerators) => Type {
+            name: name.clone(),
+            size: *size,
+            kind: TypeKind::Enum(enumerators.clone()),
+            ..Default::default()
+        },
+        RawType::Array(target, count) => {
+            let element = resolve_target(target, resolved)?;
+            Type {
+                name: format!("{} [{}]", element.name, count),
+                size: element.size * count,
+                kind: TypeKind::Array(Box::new(element), *count),
+                ..Default::default()
+            }
+        }
+        RawType::Typedef(name, target) => Type {
+            name: name.clone(),
+            ..resolve_target(target, resolved)?
+        },
+        RawType::Qualified(qualifier, target) => {
+            let target = resolve_target(target, resolved)?;
+            Type {
+                name: format!("{} {}", qualifier, target.name),
+                ..target
+            }
+        }
+        RawType::Subroutine => Type {
+            name: "function".to_string(),
+            size: 1,
+            kind: TypeKind::Function,
+            ..Default::default()
+        },
+    };
+    resolved.insert(offset, resolved_type.clone());
+    Some(resolved_type)
 }
 
 #[derive(Debug, Clone)]
+#[allow(dead_code)]
 pub enum DebugValue {
     Str(String),
     Uint(u64),
@@ -260,6 +1041,7 @@ pub enum DebugValue {
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[allow(clippy::enum_variant_names)]
 pub enum Error {
     GimliError(gimli::Error),
     // Addr2lineError(addr2line::gimli::Error),
@@ -296,9 +1078,64 @@ impl<'input, Endian> Reader for gimli::EndianSlice<'input, Endian> where
 {
 }
 
+impl<Endian> Reader for gimli::EndianArcSlice<Endian> where
+    Endian: gimli::Endianity + Send + Sync
+{
+}
+
 trait Reader: gimli::Reader<Offset = usize> + Send + Sync {}
 
-fn get_location<R: Reader>(attr: &gimli::Attribute<R>, unit: &gimli::Unit<R>) -> Option<Location> {
+/// Returns the offset of a function's DW_AT_frame_base from its canonical frame address. gcc uses
+/// the CFA itself, while LLVM (e.g. rustc) uses %rbp, which sits 16 bytes below it.
+fn frame_base_adjustment_of<R: Reader>(attr: &gimli::Attribute<R>, unit: &gimli::Unit<R>) -> isize {
+    if let gimli::AttributeValue::Exprloc(ref data) = attr.value() {
+        let mut pc = data.0.clone();
+        match gimli::Operation::parse(&mut pc, unit.encoding()) {
+            Ok(gimli::Operation::Register { register }) if register == gimli::X86_64::RBP => {
+                return -16;
+            }
+            Ok(gimli::Operation::RegisterOffset {
+                register, offset, ..
+            }) if register == gimli::X86_64::RBP => {
+                return offset as isize - 16;
+            }
+            _ => {}
+        }
+    }
+    0
+}
+
+/// Parses a nested function's DW_AT_static_link if it's a DW_OP_fbreg followed by DW_OP_derefs,
+/// returning the offset from the canonical frame address and the number of derefs.
+fn static_link_of<R: Reader>(
+    attr: &gimli::Attribute<R>,
+    unit: &gimli::Unit<R>,
+    frame_base_adjustment: isize,
+) -> Option<(isize, usize)> {
+    let data = match attr.value() {
+        gimli::AttributeValue::Exprloc(data) => data,
+        _ => return None,
+    };
+    let mut pc = data.0;
+    let offset = match gimli::Operation::parse(&mut pc, unit.encoding()).ok()? {
+        gimli::Operation::FrameOffset { offset } => offset as isize + frame_base_adjustment,
+        _ => return None,
+    };
+    let mut derefs = 0;
+    while !pc.is_empty() {
+        match gimli::Operation::parse(&mut pc, unit.encoding()).ok()? {
+            gimli::Operation::Deref { .. } => derefs += 1,
+            _ => return None,
+        }
+    }
+    Some((offset, derefs))
+}
+
+fn get_location<R: Reader>(
+    attr: &gimli::Attribute<R>,
+    unit: &gimli::Unit<R>,
+    dwarf: &gimli::Dwarf<R>,
+) -> Option<Location> {
     if let gimli::AttributeValue::Exprloc(ref data) = attr.value() {
         let encoding = unit.encoding();
         let mut pc = data.0.clone();
@@ -311,6 +1148,11 @@ fn get_location<R: Reader>(attr: &gimli::Attribute<R>, unit: &gimli::Unit<R>) ->
                     gimli::Operation::Address { address } => {
                         return Some(Location::Address(address.try_into().unwrap()));
                     }
+                    // Split units keep addresses in the executable's .debug_addr
+                    gimli::Operation::AddressIndex { index } => {
+                        let address = dwarf.address(unit, index).ok()?;
+                        return Some(Location::Address(address.try_into().unwrap()));
+                    }
                     _ => {}
                 }
             }
@@ -345,6 +1187,13 @@ fn get_attr_value<R: Reader>(
                 Ok(DebugValue::Str(format!("<.debug_str+0x{:08x}>", offset.0)))
             }
         }
+        gimli::AttributeValue::DebugStrOffsetsIndex(_) => {
+            let s = dwarf.attr_string(unit, attr.value())?;
+            Ok(DebugValue::Str(format!("{}", s.to_string_lossy()?)))
+        }
+        gimli::AttributeValue::DebugAddrIndex(index) => {
+            Ok(DebugValue::Uint(dwarf.address(unit, index)?))
+        }
         gimli::AttributeValue::Sdata(data) => Ok(DebugValue::Int(data)),
         gimli::AttributeValue::Addr(data) => Ok(DebugValue::Uint(data)),
         gimli::AttributeValue::Udata(data) => Ok(DebugValue::Uint(data)),
@@ -0,0 +1,258 @@
+//! Walks glibc's malloc heap in the inferior. Every chunk starts with a header holding the size
+//! of the previous chunk (if it's free) and its own size, whose low bits are flags; the chunks of
+//! the main arena are laid out back to back in the [heap] mapping, ending with the top chunk.
+//! A chunk is free if the next one's PREV_INUSE flag is clear, or if it's sitting in one of the
+//! tcache or fastbin lists, which leave that flag set.
+
+use crate::inferior::Inferior;
+use crate::procfs::{self, MemoryMapping};
+use std::collections::HashSet;
+use std::convert::TryInto;
+use std::io;
+use std::os::unix::fs::FileExt;
+use std::path::Path;
+
+/// Set in a chunk's size if the chunk before it is in use
+const PREV_INUSE: usize = 0x1;
+/// The flag bits in a chunk's size
+const SIZE_BITS: usize = 0x7;
+/// The header before the memory malloc returns: prev_size and size
+const HEADER_SIZE: usize = 16;
+const MIN_CHUNK_SIZE: usize = 32;
+
+/// Where the fastbin lists and the top chunk pointer are in struct malloc_state, after the
+/// mutex, flags and have_fastchunks
+const FASTBINS_OFFSET: usize = 0x10;
+const NFASTBINS: usize = 10;
+const TOP_OFFSET: usize = 0x60;
+/// The unsorted bin's fd pointer, which points back into the arena when the bin is empty
+const UNSORTED_OFFSET: usize = 0x70;
+
+/// The tcache is the first chunk of the heap: 64 counts (16 bits each since glibc 2.30, 8 bits
+/// before), then 64 list heads.
+const TCACHE_BINS: usize = 64;
+const TCACHE_CHUNK_SIZES: [(usize, usize); 2] = [(0x290, 2), (0x250, 1)];
+
+/// Free lists longer than this are taken to be corrupt.
+const MAX_LIST_LENGTH: usize = 100_000;
+
+pub struct Chunk {
+    /// The address of the chunk's header
+    pub addr: usize,
+    /// Including the header
+    pub size: usize,
+    pub in_use: bool,
+}
+
+impl Chunk {
+    /// The address malloc returned for this chunk.
+    pub fn user_addr(&self) -> usize {
+        self.addr + HEADER_SIZE
+    }
+
+    /// How many bytes the program can use; the next chunk's prev_size is part of them.
+    pub fn usable_size(&self) -> usize {
+        self.size - HEADER_SIZE + 8
+    }
+}
+
+pub struct Heap {
+    pub start: usize,
+    pub end: usize,
+    /// glibc's main_arena, if it could be found
+    pub arena: Option<usize>,
+    /// Every chunk but the top chunk and the tcache, in address order
+    pub chunks: Vec<Chunk>,
+    /// The top chunk's address and size
+    pub top: (usize, usize),
+}
+
+/// Walks the main arena's heap, working out which chunks are in use.
+pub fn walk(inferior: &Inferior) -> Result<Heap, String> {
+    let pid = inferior.pid();
+    let maps = procfs::read_maps(pid).map_err(|err| err.to_string())?;
+    let mapping = maps
+        .iter()
+        .find(|mapping| mapping.path == "[heap]")
+        .ok_or("The program has no heap yet.")?;
+    let (start, end) = (mapping.start, mapping.end);
+    let heap = read_memory(inferior, mapping).map_err(|err| err.to_string())?;
+    let word = |addr: usize| -> Option<usize> {
+        let offset = addr.checked_sub(start)?;
+        let bytes = heap.get(offset..offset + 8)?;
+        Some(usize::from_ne_bytes(bytes.try_into().unwrap()))
+    };
+
+    let mut headers = Vec::new();
+    let mut addr = start;
+    let top = loop {
+        let size = word(addr + 8).ok_or("The heap is corrupt.")? & !SIZE_BITS;
+        if size < MIN_CHUNK_SIZE || addr + size > end {
+            return Err(format!("The heap is corrupt: bad chunk size at {:#x}.", addr));
+        }
+        if addr + size + MIN_CHUNK_SIZE > end {
+            break (addr, size);
+        }
+        headers.push((addr, size));
+        addr += size;
+    };
+
+    let arena = find_arena(inferior, &maps, top.0, start..end);
+    let mut free = HashSet::new();
+    let in_heap = |addr: usize| start <= addr && addr < end && addr.is_multiple_of(16);
+    if let Some(arena) = arena {
+        for i in 0..NFASTBINS {
+            let mut chunk = inferior.read_word(arena + FASTBINS_OFFSET + 8 * i).unwrap_or(0);
+            while in_heap(chunk) && free.len() < MAX_LIST_LENGTH && free.insert(chunk) {
+                let fd = chunk + HEADER_SIZE;
+                chunk = word(fd).map_or(0, |stored| reveal(fd, stored, &in_heap));
+            }
+        }
+    }
+    let tcache = headers.first().and_then(|&(addr, size)| {
+        let sizes = TCACHE_CHUNK_SIZES.iter();
+        let (_, count_size) = sizes.clone().find(|(chunk_size, _)| *chunk_size == size)?;
+        Some((addr, addr + HEADER_SIZE + TCACHE_BINS * count_size))
+    });
+    if let Some((_, entries)) = tcache {
+        for i in 0..TCACHE_BINS {
+            let mut entry = word(entries + 8 * i).unwrap_or(0);
+            while in_heap(entry) && free.len() < MAX_LIST_LENGTH && free.insert(entry - HEADER_SIZE)
+            {
+                entry = word(entry).map_or(0, |stored| reveal(entry, stored, &in_heap));
+            }
+        }
+    }
+
+    let mut chunks = Vec::new();
+    for (i, &(addr, size)) in headers.iter().enumerate() {
+        if tcache.is_some() && i == 0 {
+            continue;
+        }
+        let next_size = word(addr + size + 8).unwrap_or(0);
+        let in_use = next_size & PREV_INUSE != 0 && !free.contains(&addr);
+        chunks.push(Chunk { addr, size, in_use });
+    }
+    Ok(Heap { start, end, arena, chunks, top })
+}
+
+/// Reads a whole mapping of the inferior's memory.
+fn read_memory(inferior: &Inferior, mapping: &MemoryMapping) -> io::Result<Vec<u8>> {
+    let mem = procfs::open_mem(inferior.pid())?;
+    let mut bytes = vec![0; mapping.end - mapping.start];
+    mem.read_exact_at(&mut bytes, mapping.start as u64)?;
+    Ok(bytes)
+}
+
+/// Finds main_arena, which libc doesn't export, by looking through libc's data for a pointer to
+/// the top chunk that sits where an arena keeps it.
+fn find_arena(
+    inferior: &Inferior,
+    maps: &[MemoryMapping],
+    top: usize,
+    heap: std::ops::Range<usize>,
+) -> Option<usize> {
+    let libc_data = maps.iter().filter(|mapping| is_libc(mapping) && mapping.is_writable());
+    for mapping in libc_data {
+        let data = match read_memory(inferior, mapping) {
+            Ok(data) => data,
+            Err(_) => continue,
+        };
+        let word = |offset: usize| -> Option<usize> {
+            let bytes = data.get(offset..offset + 8)?;
+            Some(usize::from_ne_bytes(bytes.try_into().unwrap()))
+        };
+        for offset in (TOP_OFFSET..data.len()).step_by(8) {
+            if word(offset) != Some(top) {
+                continue;
+            }
+            let arena = mapping.start + offset - TOP_OFFSET;
+            let unsorted = word(offset - TOP_OFFSET + UNSORTED_OFFSET);
+            if unsorted == Some(arena + TOP_OFFSET) || unsorted.is_some_and(|fd| heap.contains(&fd))
+            {
+                return Some(arena);
+            }
+        }
+    }
+    None
+}
+
+/// Whether a mapping is part of glibc's libc.so.
+pub fn is_libc(mapping: &MemoryMapping) -> bool {
+    Path::new(&mapping.path)
+        .file_name()
+        .is_some_and(|name| name.to_string_lossy().starts_with("libc.so"))
+}
+
+/// Decodes a tcache or fastbin pointer. Since glibc 2.32 they are stored xored with the address
+/// they're stored at, shifted right 12 bits; before that they're plain pointers.
+fn reveal(addr: usize, stored: usize, in_heap: &dyn Fn(usize) -> bool) -> usize {
+    let revealed = (addr >> 12) ^ stored;
+    if revealed == 0 || in_heap(revealed) || !in_heap(stored) {
+        revealed
+    } else {
+        stored
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::sample;
+    use crate::debugger::Debugger;
+
+    fn mapping(path: &str) -> MemoryMapping {
+        MemoryMapping {
+            start: 0x7f0000000000,
+            end: 0x7f0000001000,
+            perms: "r-xp".to_string(),
+            offset: 0,
+            path: path.to_string(),
+        }
+    }
+
+    #[test]
+    fn only_glibc_is_libc() {
+        assert!(is_libc(&mapping("/usr/lib/x86_64-linux-gnu/libc.so.6")));
+        assert!(!is_libc(&mapping("/usr/lib/x86_64-linux-gnu/libcrypt.so.1")));
+        assert!(!is_libc(&mapping("/home/user/libc.so.d/program")));
+        assert!(!is_libc(&mapping("[heap]")));
+    }
+
+    #[test]
+    fn free_list_pointers_are_revealed_whether_or_not_they_are_protected() {
+        let in_heap = |addr: usize| (0x1000..0x3000).contains(&addr) && addr.is_multiple_of(16);
+        let (addr, next) = (0x2010, 0x2040);
+        assert_eq!(reveal(addr, (addr >> 12) ^ next, &in_heap), next);
+        assert_eq!(reveal(addr, addr >> 12, &in_heap), 0);
+        assert_eq!(reveal(addr, next, &in_heap), next);
+    }
+
+    #[test]
+    fn walking_the_heap_tells_freed_chunks_from_live_ones() {
+        // Stopped at the printf in leak.c, after names[1], names[3] and scratch were freed
+        let mut debugger = Debugger::without_init_files(&sample("leak"));
+        debugger.set_breakpoint("27").unwrap();
+        debugger.start().unwrap();
+        let mut evaluator = debugger.evaluator().unwrap();
+        let exprs = ["names[0]", "names[1]", "names[2]", "names[3]", "numbers", "scratch"];
+        let addrs: Vec<usize> = exprs
+            .iter()
+            .map(|expr| {
+                let value = evaluator.evaluate(&format!("(long){}", expr)).unwrap();
+                evaluator.format(&value).parse().unwrap()
+            })
+            .collect();
+        let heap = walk(debugger.inferior().unwrap()).unwrap();
+        debugger.kill();
+
+        let chunks: Vec<(usize, bool)> =
+            heap.chunks.iter().map(|chunk| (chunk.user_addr(), chunk.in_use)).collect();
+        let in_use = [true, false, true, false, true, false];
+        assert_eq!(chunks, addrs.iter().copied().zip(in_use).collect::<Vec<_>>());
+        // The block realloc returned for 20 ints
+        assert!(heap.chunks[4].usable_size() >= 80);
+        assert!(heap.start <= heap.top.0 && heap.top.0 + heap.top.1 == heap.end);
+        assert!(heap.arena.is_some());
+    }
+}
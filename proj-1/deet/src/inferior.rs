@@ -4,12 +4,20 @@ use nix::sys::signal::Signal;
 use nix::sys::wait::{waitpid, WaitPidFlag, WaitStatus};
 use nix::unistd::Pid;
 use std::collections::HashMap;
-use std::convert::TryInto;
 use std::os::unix::process::CommandExt;
-use std::process::Child;
 use std::process::Command;
 use crate::debugger::Breakpoint;
-use crate::dwarf_data::DwarfData;
+use crate::dwarf_data::{DwarfData, Frame, Location, StaticLink, Variable};
+use crate::procfs;
+
+/// A function on the inferior's stack, found by following the chain of saved frame pointers.
+/// Inlined calls get their own StackFrames, sharing their caller's pc and base_ptr.
+pub struct StackFrame {
+    pub frame: Frame,
+    pub pc: usize,
+    /// The frame's %rbp, which its local variables are located relative to
+    pub base_ptr: usize,
+}
 
 pub enum Status {
     /// Indicates inferior stopped. Contains the signal that stopped the process, as well as the
@@ -22,15 +30,16 @@ pub enum Status {
     /// Indicates the inferior exited due to a signal. Contains the signal that killed the
     /// process.
     Signaled(signal::Signal),
+
+    /// Indicates inferior stopped on entry to or exit from a syscall. Only reported after
+    /// trace_syscalls has been called. Contains the current instruction pointer.
+    Syscall(usize),
 }
 
 /// This function calls ptrace with PTRACE_TRACEME to enable debugging on a process. You should use
 /// pre_exec with Command to call this in the child process.
 fn child_traceme() -> Result<(), std::io::Error> {
-    ptrace::traceme().or(Err(std::io::Error::new(
-        std::io::ErrorKind::Other,
-        "ptrace TRACEME failed",
-    )))
+    ptrace::traceme().or(Err(std::io::Error::other("ptrace TRACEME failed")))
 }
 
 fn align_addr_to_word(addr: usize) -> usize {
@@ -39,22 +48,39 @@ fn align_addr_to_word(addr: usize) -> usize {
 
 
 pub struct Inferior {
-    child: Child,
+    /// Not a std::process::Child, since forked copies of the inferior are Inferiors too
+    pid: Pid,
+    /// Set when the inferior stopped by hitting a breakpoint, which leaves %rip one past it. A
+    /// single step can also stop there without the breakpoint having been hit.
+    past_breakpoint: bool,
 }
 
 impl Inferior {
     /// Attempts to start a new inferior process. Returns Some(Inferior) if successful, or None if
     /// an error is encountered.
     pub fn new(target: &str, args: &Vec<String>, breakpoints: &mut HashMap<usize, Option<Breakpoint>>) -> Option<Inferior> {
-        let mut binding = Command::new(target);
-        let process = binding.args(args);
+        let mut command = Command::new(target);
+        command.args(args);
+        Inferior::launch(command, breakpoints)
+    }
+
+    /// Like new, but starts the inferior with a command that has been set up already, e.g. with
+    /// its environment and standard streams.
+    pub fn launch(
+        mut command: Command,
+        breakpoints: &mut HashMap<usize, Option<Breakpoint>>,
+    ) -> Option<Inferior> {
+        let process = &mut command;
         unsafe {
             process.pre_exec(|| {
                 child_traceme()
             });
         }
         let child_process = process.spawn().ok()?;
-        let mut inferior = Inferior{child: child_process};
+        let mut inferior = Inferior{
+            pid: Pid::from_raw(child_process.id() as i32),
+            past_breakpoint: false,
+        };
         match inferior.wait(None) {
             Ok(status) => {
                 match status {
@@ -83,19 +109,36 @@ impl Inferior {
     fn install(&mut self, breakpoints: &mut HashMap<usize, Option<Breakpoint>>) {
         let interrupt_instruction: u8 = 0xcc;
         for (addr, _) in breakpoints.clone() {
-            let orig_byte = self.write_byte(addr.clone(), interrupt_instruction).unwrap();
-            
-            breakpoints.insert(addr, Some(Breakpoint{addr, orig_byte}));
+            match self.write_byte(addr, interrupt_instruction) {
+                Ok(orig_byte) => {
+                    breakpoints.insert(addr, Some(Breakpoint{addr, orig_byte}));
+                }
+                // Leave it uninstalled; the address may be valid in a later run
+                Err(err) => println!("Cannot insert breakpoint at {:#x}: {}", addr, err),
+            }
         }
     }
 
+    /// Removes a breakpoint by restoring the original byte. If the inferior is stopped just past
+    /// this breakpoint, %rip is rewound so that the original instruction gets executed.
+    pub fn remove_breakpoint(&mut self, breakpoint: &Breakpoint) -> Result<(), nix::Error> {
+        self.write_byte(breakpoint.addr, breakpoint.orig_byte)?;
+        let mut regs = ptrace::getregs(self.pid())?;
+        if self.past_breakpoint && regs.rip as usize == breakpoint.addr + 1 {
+            regs.rip = breakpoint.addr as u64;
+            ptrace::setregs(self.pid(), regs)?;
+            self.past_breakpoint = false;
+        }
+        Ok(())
+    }
+
     pub fn write_byte(&mut self, addr: usize, val: u8) -> Result<u8, nix::Error> {
         let aligned_addr = align_addr_to_word(addr);
         let byte_offset = addr - aligned_addr;
         let word = ptrace::read(self.pid(), aligned_addr as ptrace::AddressType)? as u64;
-        let orig_byte = (word >> 8 * byte_offset) & 0xff;
-        let masked_word = word & !(0xff << 8 * byte_offset);
-        let updated_word = masked_word | ((val as u64) << 8 * byte_offset);
+        let orig_byte = (word >> (8 * byte_offset)) & 0xff;
+        let masked_word = word & !(0xff << (8 * byte_offset));
+        let updated_word = masked_word | ((val as u64) << (8 * byte_offset));
         unsafe {
             ptrace::write(
                 self.pid(),
@@ -106,78 +149,467 @@ impl Inferior {
         Ok(orig_byte as u8)
     }
 
+    /// Reads one word of the inferior's memory at addr (which does not need to be aligned).
+    pub fn read_word(&self, addr: usize) -> Result<usize, nix::Error> {
+        Ok(ptrace::read(self.pid(), addr as ptrace::AddressType)? as usize)
+    }
+
+    /// Reads len bytes of the inferior's memory starting at addr.
+    pub fn read_bytes(&self, addr: usize, len: usize) -> Result<Vec<u8>, nix::Error> {
+        let mut bytes = Vec::with_capacity(len);
+        let mut offset = 0;
+        while offset < len {
+            let word = self.read_word(addr + offset)?;
+            let take = (len - offset).min(size_of::<usize>());
+            bytes.extend_from_slice(&word.to_le_bytes()[..take]);
+            offset += take;
+        }
+        Ok(bytes)
+    }
+
+    /// Reads a NUL-terminated string from the inferior's memory, stopping after max_len bytes.
+    /// Returns the bytes read (without the terminator) and whether the string was truncated.
+    pub fn read_cstring(&self, addr: usize, max_len: usize) -> Result<(Vec<u8>, bool), nix::Error> {
+        let mut bytes = Vec::new();
+        while bytes.len() < max_len {
+            let word = self.read_word(addr + bytes.len())?;
+            for byte in word.to_le_bytes() {
+                if byte == 0 {
+                    return Ok((bytes, false));
+                }
+                bytes.push(byte);
+            }
+        }
+        bytes.truncate(max_len);
+        Ok((bytes, true))
+    }
+
+    /// Returns the address of var. Local variables are located relative to the current frame, so
+    /// this is only meaningful once the frame's prologue has run.
+    pub fn variable_address(&self, var: &Variable) -> Result<usize, nix::Error> {
+        Ok(match var.location {
+            Location::Address(addr) => addr,
+            Location::FramePointerOffset(_) => {
+                self.frame_variable_address(var, self.getregs()?.rbp as usize)
+            }
+        })
+    }
+
+    /// Returns the address of var in the frame whose %rbp is base_ptr.
+    pub fn frame_variable_address(&self, var: &Variable, base_ptr: usize) -> usize {
+        match var.location {
+            Location::Address(addr) => addr,
+            // The frame base is the canonical frame address, which sits just above the saved %rbp
+            // and the return address
+            Location::FramePointerOffset(offset) => (base_ptr as isize + 16 + offset) as usize,
+        }
+    }
+
+    /// Follows a nested function's static link from its frame, whose %rbp is base_ptr, to the
+    /// frame of the function it's defined in, returning that frame's %rbp.
+    pub fn enclosing_frame(&self, link: &StaticLink, base_ptr: usize) -> Result<usize, nix::Error> {
+        let mut addr = (base_ptr as isize + 16 + link.offset) as usize;
+        for _ in 0..link.derefs {
+            addr = self.read_word(addr)?;
+        }
+        Ok((addr as isize - link.frame_base_adjustment - 16) as usize)
+    }
+
+    /// Writes bytes into the inferior's memory starting at addr.
+    pub fn write_bytes(&mut self, addr: usize, bytes: &[u8]) -> Result<(), nix::Error> {
+        for (i, byte) in bytes.iter().enumerate() {
+            self.write_byte(addr + i, *byte)?;
+        }
+        Ok(())
+    }
+
+    /// Calls the function at func_addr with the given integer/pointer arguments, following the
+    /// System V calling convention. A temporary breakpoint at return_addr (which must be an
+    /// address the function won't otherwise execute, such as _start) catches the return. The
+    /// inferior's registers are restored afterward, whether or not the call completed.
+    ///
+    /// Returns Ok(value) if the function returned, where value is xmm0 if float_return is set
+    /// and rax otherwise, or Err(status) if the inferior stopped for some other reason (e.g. a
+    /// breakpoint or a signal) before it could return.
+    pub fn call_function(
+        &mut self,
+        func_addr: usize,
+        args: &[u64],
+        return_addr: usize,
+        float_return: bool,
+    ) -> Result<Result<u64, Status>, nix::Error> {
+        const ARG_REGISTERS: usize = 6;
+        let saved_regs = self.getregs()?;
+        let mut regs = saved_regs;
+        // Skip the red zone below the current frame, and align the stack so that it is 16-byte
+        // aligned at the call instruction (i.e. just before the return address is pushed)
+        let mut rsp = (saved_regs.rsp as usize - 128) & !0xf;
+        rsp -= size_of::<usize>();
+        self.write_bytes(rsp, &return_addr.to_le_bytes())?;
+        for (i, arg) in args.iter().take(ARG_REGISTERS).enumerate() {
+            match i {
+                0 => regs.rdi = *arg,
+                1 => regs.rsi = *arg,
+                2 => regs.rdx = *arg,
+                3 => regs.rcx = *arg,
+                4 => regs.r8 = *arg,
+                _ => regs.r9 = *arg,
+            }
+        }
+        regs.rsp = rsp as u64;
+        regs.rip = func_addr as u64;
+        // Number of vector registers used by a varargs call, and no syscall to restart
+        regs.rax = 0;
+        regs.orig_rax = u64::MAX;
+        ptrace::setregs(self.pid(), regs)?;
+
+        let result = self.run_to_return(return_addr, float_return);
+        if let Ok(Err(Status::Exited(_) | Status::Signaled(_))) = result {
+            return result;
+        }
+        // Put the registers back even if the call failed partway, so the inferior can go on
+        let restored = ptrace::setregs(self.pid(), saved_regs);
+        let result = result?;
+        restored?;
+        Ok(result)
+    }
+
+    /// Runs a function that call_function has set up until it returns to return_addr, and
+    /// returns its return value. Removes the temporary breakpoint at return_addr on every path
+    /// where the inferior is still alive.
+    fn run_to_return(
+        &mut self,
+        return_addr: usize,
+        float_return: bool,
+    ) -> Result<Result<u64, Status>, nix::Error> {
+        let orig_byte = self.write_byte(return_addr, 0xcc)?;
+        let result = ptrace::cont(self.pid(), None).and_then(|_| self.wait(None));
+        let result = match result {
+            Ok(Status::Stopped(Signal::SIGTRAP, rip)) if rip == return_addr + 1 => {
+                self.return_value(float_return).map(Ok)
+            }
+            Ok(status @ (Status::Exited(_) | Status::Signaled(_))) => return Ok(Err(status)),
+            Ok(status) => Ok(Err(status)),
+            Err(err) => Err(err),
+        };
+        let restored = self.write_byte(return_addr, orig_byte);
+        let result = result?;
+        restored?;
+        Ok(result)
+    }
+
+    /// Returns the register that a function just returned a scalar in: xmm0 for floating-point
+    /// values if float is set, and rax for everything else.
+    pub fn return_value(&self, float: bool) -> Result<u64, nix::Error> {
+        if !float {
+            return Ok(self.getregs()?.rax);
+        }
+        let fpregs = self.getfpregs()?;
+        let xmm0 = &fpregs.xmm_space;
+        Ok(xmm0[0] as u64 | (xmm0[1] as u64) << 32)
+    }
+
+    /// Forks the inferior by having it execute a fork syscall at scratch_addr, which (like
+    /// call_function's return_addr) must be an address it won't otherwise execute. The copy is
+    /// traced as well, and is left stopped in the same state as the inferior.
+    pub fn fork(&mut self, scratch_addr: usize) -> Result<Inferior, nix::Error> {
+        const SYSCALL_INSTRUCTION: [u8; 2] = [0x0f, 0x05];
+        let saved_regs = self.getregs()?;
+        let orig_bytes = self.read_bytes(scratch_addr, SYSCALL_INSTRUCTION.len())?;
+        self.write_bytes(scratch_addr, &SYSCALL_INSTRUCTION)?;
+        let mut regs = saved_regs;
+        regs.rip = scratch_addr as u64;
+        regs.rax = libc::SYS_fork as u64;
+        regs.orig_rax = u64::MAX;
+        ptrace::setregs(self.pid(), regs)?;
+        ptrace::setoptions(
+            self.pid(),
+            ptrace::Options::PTRACE_O_EXITKILL | ptrace::Options::PTRACE_O_TRACEFORK,
+        )?;
+        let result = self.step_over_fork();
+        ptrace::setoptions(self.pid(), ptrace::Options::PTRACE_O_EXITKILL)?;
+        self.write_bytes(scratch_addr, &orig_bytes)?;
+        ptrace::setregs(self.pid(), saved_regs)?;
+
+        let mut copy = Inferior {
+            pid: result?,
+            past_breakpoint: self.past_breakpoint,
+        };
+        ptrace::setoptions(copy.pid(), ptrace::Options::PTRACE_O_EXITKILL)?;
+        copy.write_bytes(scratch_addr, &orig_bytes)?;
+        ptrace::setregs(copy.pid(), saved_regs)?;
+        Ok(copy)
+    }
+
+    /// Single-steps over a fork syscall, returning the pid of the new process once it has
+    /// stopped.
+    fn step_over_fork(&mut self) -> Result<Pid, nix::Error> {
+        ptrace::step(self.pid(), None)?;
+        let copy_pid = match waitpid(self.pid(), None)? {
+            WaitStatus::PtraceEvent(_, _, libc::PTRACE_EVENT_FORK) => {
+                Pid::from_raw(ptrace::getevent(self.pid())? as i32)
+            }
+            WaitStatus::Stopped(_, Signal::SIGTRAP) => {
+                // The fork failed, so there's no event; %rax holds -errno
+                let errno = -(self.getregs()?.rax as i64) as i32;
+                return Err(nix::Error::from_i32(errno));
+            }
+            _ => return Err(nix::Error::ESRCH),
+        };
+        // Finish the step, and wait for the new process to stop with the SIGSTOP it starts with
+        ptrace::step(self.pid(), None)?;
+        waitpid(self.pid(), None)?;
+        match waitpid(copy_pid, None)? {
+            WaitStatus::Stopped(_, Signal::SIGSTOP) => Ok(copy_pid),
+            _ => Err(nix::Error::ESRCH),
+        }
+    }
+
+    /// Returns the inferior's current register state.
+    pub fn getregs(&self) -> Result<libc::user_regs_struct, nix::Error> {
+        ptrace::getregs(self.pid())
+    }
+
+    /// Returns the inferior's floating-point and vector register state.
+    pub fn getfpregs(&self) -> Result<libc::user_fpregs_struct, nix::Error> {
+        let mut fpregs = std::mem::MaybeUninit::<libc::user_fpregs_struct>::uninit();
+        let res = unsafe {
+            libc::ptrace(
+                libc::PTRACE_GETFPREGS,
+                libc::pid_t::from(self.pid()),
+                std::ptr::null_mut::<libc::c_void>(),
+                fpregs.as_mut_ptr(),
+            )
+        };
+        nix::errno::Errno::result(res)?;
+        Ok(unsafe { fpregs.assume_init() })
+    }
+
+    /// Makes the inferior report syscall entries and exits (as Status::Syscall) when it is
+    /// resumed with Inferior::syscall.
+    pub fn trace_syscalls(&mut self) -> Result<(), nix::Error> {
+        ptrace::setoptions(
+            self.pid(),
+            ptrace::Options::PTRACE_O_TRACESYSGOOD | ptrace::Options::PTRACE_O_EXITKILL,
+        )
+    }
+
+    /// Resumes the inferior until the next syscall entry or exit, delivering signal (if any).
+    pub fn syscall(&mut self, signal: Option<Signal>) -> Result<Status, nix::Error> {
+        ptrace::syscall(self.pid(), signal)?;
+        self.wait(None)
+    }
+
     /// Returns the pid of this inferior.
     pub fn pid(&self) -> Pid {
-        nix::unistd::Pid::from_raw(self.child.id() as i32)
+        self.pid
     }
 
     // wake up the inferior and run it until it stops or terminates
     pub fn cont(&mut self, breakpoints: &HashMap<usize, Option<Breakpoint>>) -> Result<Status, nix::Error> {
-        let mut regs = ptrace::getregs(self.pid()).unwrap();
-        let rip: usize = regs.rip.try_into().unwrap(); 
-        if breakpoints.contains_key(&(rip-1)) {
-            // restore the first byte of the instruction we replaced
-            let orig_byte = breakpoints[&(rip-1)].clone().unwrap().orig_byte;
-            self.write_byte(rip-1, orig_byte).unwrap();
-            // set %rip = %rip - 1 to rewind the instruction pointer
-            regs.rip = (rip - 1) as u64;
-            ptrace::setregs(self.pid(), regs)?;
-            // ptrace::step to go to next instruction
-            ptrace::step(self.pid(), None)?;
-            match self.wait(None).unwrap() {
-                Status::Stopped(_, _) => {
-                    // restore 0xcc in the breakpoint location
-                    self.write_byte(rip-1, 0xcc)?;
-                },
+        self.cont_with_signal(breakpoints, None)
+    }
+
+    /// Like cont, but delivers a signal to the inferior as it resumes, e.g. one it stopped with.
+    pub fn cont_with_signal(
+        &mut self,
+        breakpoints: &HashMap<usize, Option<Breakpoint>>,
+        signal: Option<Signal>,
+    ) -> Result<Status, nix::Error> {
+        let regs = ptrace::getregs(self.pid())?;
+        let rip = regs.rip as usize;
+        if self.past_breakpoint && breakpoints.contains_key(&(rip-1)) {
+            // step past the breakpoint we're stopped at before letting the inferior run
+            match self.step_instruction(breakpoints)? {
+                Status::Stopped(_, _) | Status::Syscall(_) => {},
                 Status::Exited(exit_code) => return Ok(Status::Exited(exit_code)),
                 Status::Signaled(signal) => return Ok(Status::Signaled(signal)),
             }
     
         }
         // contiune execute child process
-        ptrace::cont(self.pid(), None)?;
+        ptrace::cont(self.pid(), signal)?;
         // wait the statue of child process
+        let status = self.wait(None)?;
+        self.past_breakpoint = matches!(status, Status::Stopped(Signal::SIGTRAP, rip)
+            if matches!(breakpoints.get(&(rip - 1)), Some(Some(_))));
+        Ok(status)
+    }
+
+    /// Executes a single instruction. If the inferior is stopped just past a breakpoint, the
+    /// instruction that the breakpoint replaced is executed, and the breakpoint is put back.
+    pub fn step_instruction(
+        &mut self,
+        breakpoints: &HashMap<usize, Option<Breakpoint>>,
+    ) -> Result<Status, nix::Error> {
+        let mut regs = ptrace::getregs(self.pid())?;
+        let rip = regs.rip as usize;
+        match breakpoints.get(&(rip - 1)) {
+            Some(Some(breakpoint)) if self.past_breakpoint => {
+                // restore the first byte of the instruction we replaced
+                self.write_byte(rip - 1, breakpoint.orig_byte)?;
+                // set %rip = %rip - 1 to rewind the instruction pointer
+                regs.rip = (rip - 1) as u64;
+                ptrace::setregs(self.pid(), regs)?;
+                let status = self.single_step()?;
+                if let Status::Stopped(_, _) | Status::Syscall(_) = status {
+                    // restore 0xcc in the breakpoint location
+                    self.write_byte(rip - 1, 0xcc)?;
+                }
+                Ok(status)
+            }
+            _ => {
+                let status = self.single_step()?;
+                // Stepping from a breakpoint that hasn't been hit yet executes its 0xcc
+                self.past_breakpoint = matches!(breakpoints.get(&rip), Some(Some(_)))
+                    && matches!(status, Status::Stopped(Signal::SIGTRAP, pc) if pc == rip + 1);
+                Ok(status)
+            }
+        }
+    }
+
+    /// Executes a single instruction, without regard for breakpoints.
+    pub fn single_step(&mut self) -> Result<Status, nix::Error> {
+        ptrace::step(self.pid(), None)?;
+        self.past_breakpoint = false;
         self.wait(None)
+    }
 
+    /// If the inferior is sitting on a breakpoint it hasn't hit yet, moves it just past the
+    /// breakpoint as if it had been hit.
+    pub fn skip_breakpoint(
+        &mut self,
+        breakpoints: &HashMap<usize, Option<Breakpoint>>,
+    ) -> Result<(), nix::Error> {
+        let mut regs = ptrace::getregs(self.pid())?;
+        if let Some(Some(_)) = breakpoints.get(&(regs.rip as usize)) {
+            regs.rip += 1;
+            ptrace::setregs(self.pid(), regs)?;
+            self.past_breakpoint = true;
+        }
+        Ok(())
+    }
 
+    /// Whether the inferior is stopped just past a breakpoint that it hit.
+    pub fn past_breakpoint(&self) -> bool {
+        self.past_breakpoint
+    }
+
+    /// Overwrites the inferior's registers.
+    pub fn setregs(&mut self, regs: libc::user_regs_struct) -> Result<(), nix::Error> {
+        ptrace::setregs(self.pid(), regs)
     }
 
     pub fn print_backtrace(&self, debug_data: &DwarfData) -> Result<(), nix::Error> {
-        let regs = ptrace::getregs(self.pid()).unwrap();
-        let mut instruction_ptr = regs.rip as usize;
-        let mut base_ptr = regs.rbp as usize;
+        for stack_frame in self.backtrace(debug_data)? {
+            let frame = &stack_frame.frame;
+            let inlined = if frame.inlined { " [inlined]" } else { "" };
+            match &frame.line {
+                Some(line) => println!("{} ({}){}", frame.function, line, inlined),
+                None => match self.describe_address(debug_data, stack_frame.pc) {
+                    Some(place) => println!(
+                        "{} ({:#x} in {}){}",
+                        frame.function, stack_frame.pc, place, inlined
+                    ),
+                    None => println!("{} ({:#x}){}", frame.function, stack_frame.pc, inlined),
+                },
+            }
+        }
+        Ok(())
+    }
+
+    /// Describes where an address without a line number is: in an ELF symbol, as
+    /// `func2+4`, or else in one of the inferior's mappings, as `libc.so.6+0x1234`.
+    pub fn describe_address(&self, debug_data: &DwarfData, addr: usize) -> Option<String> {
+        match debug_data.get_symbol_containing(addr) {
+            Some(symbol) if symbol.address == addr => Some(symbol.name.clone()),
+            Some(symbol) => Some(format!("{}+{}", symbol.name, addr - symbol.address)),
+            None => procfs::annotate(self.pid(), addr),
+        }
+    }
+
+    /// Returns the functions on the stack, innermost first, up to main. If the inferior has
+    /// just hit a breakpoint, the innermost frame is at the breakpoint rather than past it.
+    pub fn backtrace(&self, debug_data: &DwarfData) -> Result<Vec<StackFrame>, nix::Error> {
+        let regs = ptrace::getregs(self.pid())?;
+        let rip = regs.rip as usize - self.past_breakpoint as usize;
+        self.walk_stack(debug_data, rip, regs.rbp as usize, false)
+    }
+
+    /// Returns the functions on the stack starting from a frame other than the current one,
+    /// e.g. a function's caller while its prologue hasn't run yet. instruction_ptr is the return
+    /// address into that frame.
+    pub fn backtrace_from(
+        &self,
+        debug_data: &DwarfData,
+        instruction_ptr: usize,
+        base_ptr: usize,
+    ) -> Result<Vec<StackFrame>, nix::Error> {
+        self.walk_stack(debug_data, instruction_ptr, base_ptr, true)
+    }
+
+    /// Follows the chain of saved frame pointers. Callers are looked up by the byte before their
+    /// return address, which is still part of the call instruction; the return address itself
+    /// may be the start of the next line, or of another function's code.
+    fn walk_stack(
+        &self,
+        debug_data: &DwarfData,
+        mut instruction_ptr: usize,
+        mut base_ptr: usize,
+        mut is_return_address: bool,
+    ) -> Result<Vec<StackFrame>, nix::Error> {
+        let mut stack_frames = Vec::new();
         loop {
-            let func_name = debug_data.get_function_from_addr(instruction_ptr).unwrap();
-            let line = debug_data.get_line_from_addr(instruction_ptr).unwrap();
-            println!("{func_name} ({line})");
-            if func_name == "main" {
+            let lookup = if is_return_address { instruction_ptr - 1 } else { instruction_ptr };
+            let frames = debug_data.get_frames(lookup);
+            let is_main = frames.last().is_some_and(|frame| frame.function == "main");
+            // Somewhere without debugging info or symbols (e.g. libc) has an unknown frame layout
+            let is_unknown = frames.last().is_some_and(|frame| frame.function == "??");
+            stack_frames.extend(frames.into_iter().map(|frame| StackFrame {
+                frame,
+                pc: instruction_ptr,
+                base_ptr,
+            }));
+            if is_main || is_unknown || base_ptr == 0 {
                 break;
             }
             instruction_ptr = ptrace::read(self.pid(), (base_ptr + 8) as ptrace::AddressType)? as usize;
+            is_return_address = true;
             base_ptr = ptrace::read(self.pid(), base_ptr as ptrace::AddressType)? as usize;
         }
-        Ok(())
+        Ok(stack_frames)
     }
 
 
     pub fn kill(&mut self) -> Result<Status, nix::Error> {
-        let _ = Child::kill(&mut self.child);
+        let _ = signal::kill(self.pid(), Signal::SIGKILL);
         
         // Note: wait the statue of child process, make sure the child process quit successful
         self.wait(None)
     }
 
     /// Calls waitpid on this inferior and returns a Status to indicate the state of the process
-    /// after the waitpid call.
+    /// after the waitpid call. With WNOHANG, returns EAGAIN if the state hasn't changed.
     pub fn wait(&self, options: Option<WaitPidFlag>) -> Result<Status, nix::Error> {
-        Ok(match waitpid(self.pid(), options)? {
-            WaitStatus::Exited(_pid, exit_code) => Status::Exited(exit_code),
-            WaitStatus::Signaled(_pid, signal, _core_dumped) => Status::Signaled(signal),
-            WaitStatus::Stopped(_pid, signal) => {
-                let regs = ptrace::getregs(self.pid())?;
-                Status::Stopped(signal, regs.rip as usize)
+        loop {
+            match waitpid(self.pid(), options)? {
+                WaitStatus::Exited(_pid, exit_code) => return Ok(Status::Exited(exit_code)),
+                WaitStatus::Signaled(_pid, signal, _core_dumped) => {
+                    return Ok(Status::Signaled(signal))
+                }
+                // A ptrace event (e.g. a fork with PTRACE_O_TRACEFORK) stops it with SIGTRAP
+                WaitStatus::Stopped(_pid, signal) | WaitStatus::PtraceEvent(_pid, signal, _) => {
+                    let regs = ptrace::getregs(self.pid())?;
+                    return Ok(Status::Stopped(signal, regs.rip as usize));
+                }
+                WaitStatus::PtraceSyscall(_pid) => {
+                    let regs = ptrace::getregs(self.pid())?;
+                    return Ok(Status::Syscall(regs.rip as usize));
+                }
+                // Being resumed by SIGCONT (with WCONTINUED) isn't a stop, so keep waiting
+                WaitStatus::Continued(_pid) => continue,
+                WaitStatus::StillAlive => return Err(nix::Error::EAGAIN),
             }
-            other => panic!("waitpid returned unexpected status: {:?}", other),
-        })
+        }
     }
 }
@@ -0,0 +1,219 @@
+//! How the inferior is started: its arguments, environment, working directory and terminal,
+//! remembered from one run to the next. Arguments can redirect the standard streams like a
+//! shell would, e.g. `run < in.txt > out.txt`.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::os::unix::io::AsRawFd;
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::process::Command;
+
+/// The redirection operators, longest first so that ">>" isn't taken for ">".
+const REDIRECTIONS: [&str; 5] = ["2>>", "2>", ">>", ">", "<"];
+
+pub struct LaunchOptions {
+    /// Arguments, possibly including redirections
+    pub args: Vec<String>,
+    /// Starts out as deet's own environment
+    pub environment: BTreeMap<String, String>,
+    /// The directory to start in, if not deet's
+    pub cwd: Option<String>,
+    /// A terminal for the inferior's standard streams instead of deet's
+    pub tty: Option<String>,
+}
+
+/// A standard stream sent to or read from a file instead.
+struct Redirection {
+    fd: i32,
+    path: String,
+    append: bool,
+}
+
+impl Default for LaunchOptions {
+    fn default() -> Self {
+        LaunchOptions {
+            args: Vec::new(),
+            environment: env::vars_os()
+                .map(|(name, value)| {
+                    (name.to_string_lossy().to_string(), value.to_string_lossy().to_string())
+                })
+                .collect(),
+            cwd: None,
+            tty: None,
+        }
+    }
+}
+
+impl LaunchOptions {
+    /// Builds the command that starts target, opening any files it's redirected to or from.
+    pub fn command(&self, target: &str) -> Result<Command, String> {
+        let (args, redirections) = split_redirections(&self.args)?;
+        // Relative to deet's directory, even if the inferior starts somewhere else
+        let program = fs::canonicalize(target).unwrap_or_else(|_| target.into());
+        let mut command = Command::new(program);
+        command.args(args).env_clear().envs(&self.environment);
+        if let Some(cwd) = &self.cwd {
+            if !Path::new(cwd).is_dir() {
+                return Err(format!("{}: No such directory.", cwd));
+            }
+            command.current_dir(cwd);
+        }
+        if let Some(tty) = &self.tty {
+            let open_error = |err: std::io::Error| format!("{}: {}", tty, err);
+            let terminal =
+                OpenOptions::new().read(true).write(true).open(tty).map_err(open_error)?;
+            let fd = terminal.as_raw_fd();
+            command.stdin(terminal.try_clone().map_err(open_error)?);
+            command.stdout(terminal.try_clone().map_err(open_error)?);
+            command.stderr(terminal);
+            // Make it the controlling terminal, so that ctrl+c typed there reaches the inferior
+            unsafe {
+                command.pre_exec(move || {
+                    libc::setsid();
+                    libc::ioctl(fd, libc::TIOCSCTTY, 0);
+                    Ok(())
+                });
+            }
+        }
+        for redirection in redirections {
+            let file = if redirection.fd == 0 {
+                File::open(&redirection.path)
+            } else {
+                OpenOptions::new()
+                    .write(true)
+                    .create(true)
+                    .append(redirection.append)
+                    .truncate(!redirection.append)
+                    .open(&redirection.path)
+            }
+            .map_err(|err| format!("{}: {}", redirection.path, err))?;
+            match redirection.fd {
+                0 => command.stdin(file),
+                1 => command.stdout(file),
+                _ => command.stderr(file),
+            };
+        }
+        Ok(command)
+    }
+}
+
+/// Separates the redirections from the real arguments. The file name can follow the operator
+/// directly or as the next word.
+fn split_redirections(args: &[String]) -> Result<(Vec<&String>, Vec<Redirection>), String> {
+    let mut words = Vec::new();
+    let mut redirections = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let op = match REDIRECTIONS.iter().find(|op| arg.starts_with(*op)) {
+            Some(op) => *op,
+            None => {
+                words.push(arg);
+                continue;
+            }
+        };
+        let path = match &arg[op.len()..] {
+            "" => args
+                .next()
+                .ok_or_else(|| format!("Missing file name after \"{}\".", op))?
+                .clone(),
+            path => path.to_string(),
+        };
+        let (fd, append) = match op {
+            "<" => (0, false),
+            ">" => (1, false),
+            ">>" => (1, true),
+            "2>" => (2, false),
+            _ => (2, true),
+        };
+        redirections.push(Redirection { fd, path, append });
+    }
+    Ok((words, redirections))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::sample;
+
+    fn strings(words: &[&str]) -> Vec<String> {
+        words.iter().map(|word| word.to_string()).collect()
+    }
+
+    #[test]
+    fn redirections_are_split_from_the_arguments() {
+        let args = strings(&["a", "<in", ">", "out", "b", "2>>log", ">>", "all", "2>", "err"]);
+        let (words, redirections) = split_redirections(&args).unwrap();
+        assert_eq!(words, ["a", "b"]);
+        let redirections: Vec<_> = redirections
+            .iter()
+            .map(|redirection| (redirection.fd, redirection.path.as_str(), redirection.append))
+            .collect();
+        assert_eq!(
+            redirections,
+            [
+                (0, "in", false),
+                (1, "out", false),
+                (2, "log", true),
+                (1, "all", true),
+                (2, "err", false),
+            ]
+        );
+    }
+
+    #[test]
+    fn a_redirection_needs_a_file_name() {
+        match split_redirections(&strings(&["a", "2>>"])) {
+            Err(message) => assert_eq!(message, "Missing file name after \"2>>\"."),
+            Ok(_) => panic!("a redirection without a file was accepted"),
+        }
+    }
+
+    #[test]
+    fn the_command_gets_the_arguments_environment_directory_and_redirections() {
+        let dir = env::temp_dir().join(format!("deet-launch-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = |name: &str| dir.join(name).to_string_lossy().to_string();
+        fs::write(path("in.txt"), "a line\n").unwrap();
+        fs::write(path("err.txt"), "before\n").unwrap();
+        let options = LaunchOptions {
+            args: vec![
+                "one".to_string(),
+                "two words".to_string(),
+                "<".to_string(),
+                path("in.txt"),
+                format!(">{}", path("out.txt")),
+                "2>>".to_string(),
+                path("err.txt"),
+            ],
+            environment: BTreeMap::from([("GREETING".to_string(), "hello".to_string())]),
+            cwd: Some(path("")),
+            tty: None,
+        };
+        let status = options.command(&sample("environment")).unwrap().status().unwrap();
+        let output = fs::read_to_string(path("out.txt")).unwrap();
+        let errors = fs::read_to_string(path("err.txt")).unwrap();
+        let cwd = fs::canonicalize(&dir).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(status.success());
+        assert_eq!(
+            output,
+            format!(
+                "argv[1] = one\nargv[2] = two words\nGREETING = hello\ncwd = {}\nread: a line\n",
+                cwd.display()
+            )
+        );
+        assert_eq!(errors, "before\ndone\n");
+    }
+
+    #[test]
+    fn a_missing_directory_is_an_error() {
+        let options = LaunchOptions { cwd: Some("/no/such/dir".to_string()), ..Default::default() };
+        match options.command("/bin/true") {
+            Err(message) => assert_eq!(message, "/no/such/dir: No such directory."),
+            Ok(_) => panic!("the command was built"),
+        }
+    }
+}
@@ -0,0 +1,310 @@
+//! Implements `deet --leak-check`, which runs the target to completion with breakpoints on libc's
+//! malloc, calloc, realloc and free, remembering where each block still allocated came from, and
+//! reports the blocks that were never freed once the target exits.
+
+use crate::debugger::{load_debug_data, Breakpoint};
+use crate::dwarf_data::{self, DwarfData};
+use crate::heap;
+use crate::inferior::{Inferior, Status};
+use crate::procfs::{self, MemoryMapping};
+use nix::sys::signal::Signal;
+use std::collections::HashMap;
+
+/// The allocation functions, by their names in libc's dynamic symbol table.
+const FUNCTIONS: [(&str, Function); 4] = [
+    ("malloc", Function::Malloc),
+    ("calloc", Function::Calloc),
+    ("realloc", Function::Realloc),
+    ("free", Function::Free),
+];
+
+#[derive(Clone, Copy)]
+enum Function {
+    Malloc,
+    Calloc,
+    Realloc,
+    Free,
+}
+
+/// A call to an allocation function that hasn't returned yet.
+struct PendingCall {
+    function: Function,
+    /// The first two arguments
+    args: (usize, usize),
+    return_addr: usize,
+    /// %rsp once the function has returned, which tells recursive calls apart
+    stack_ptr: usize,
+    backtrace: Vec<usize>,
+}
+
+/// A block that has been allocated and not yet freed.
+struct Allocation {
+    size: usize,
+    /// The return addresses of the calls that led to the allocation, innermost first
+    backtrace: Vec<usize>,
+}
+
+pub struct LeakChecker {
+    inferior: Inferior,
+    debug_data: DwarfData,
+    breakpoints: HashMap<usize, Option<Breakpoint>>,
+    /// The allocation functions, by address
+    functions: HashMap<usize, Function>,
+    /// The inferior's memory mappings as of main, to describe addresses without debugging info
+    maps: Vec<MemoryMapping>,
+    /// Innermost last
+    pending: Vec<PendingCall>,
+    /// By the address the block starts at
+    live: HashMap<usize, Allocation>,
+    allocations: usize,
+    allocated_bytes: usize,
+    frees: usize,
+}
+
+impl LeakChecker {
+    /// Spawns target under ptrace and runs it to main, where libc has been loaded, to set
+    /// breakpoints on the allocation functions. Returns None if that couldn't be done.
+    pub fn new(target: &str, args: &Vec<String>) -> Option<Self> {
+        let debug_data = load_debug_data(target);
+        let main = match debug_data.get_addr_for_function(None, "main") {
+            Some(main) => main,
+            None => {
+                eprintln!("{} has no main function.", target);
+                return None;
+            }
+        };
+        let mut breakpoints = HashMap::from([(main, None)]);
+        let mut inferior = Inferior::new(target, args, &mut breakpoints)?;
+        match inferior.cont(&breakpoints) {
+            Ok(Status::Stopped(Signal::SIGTRAP, _)) => {}
+            Ok(_) => {
+                eprintln!("{} exited before reaching main.", target);
+                return None;
+            }
+            Err(err) => {
+                eprintln!("Error running to main: {}", err);
+                return None;
+            }
+        }
+        if let Some(Some(breakpoint)) = breakpoints.remove(&main) {
+            if let Err(err) = inferior.remove_breakpoint(&breakpoint) {
+                eprintln!("Error removing breakpoint at main: {}", err);
+                return None;
+            }
+        }
+        let mut checker = LeakChecker {
+            inferior,
+            debug_data,
+            breakpoints,
+            functions: HashMap::new(),
+            maps: Vec::new(),
+            pending: Vec::new(),
+            live: HashMap::new(),
+            allocations: 0,
+            allocated_bytes: 0,
+            frees: 0,
+        };
+        if let Err(err) = checker.break_on_allocation_functions() {
+            eprintln!("{}", err);
+            let _ = checker.inferior.kill();
+            return None;
+        }
+        Some(checker)
+    }
+
+    /// Finds the allocation functions in the copy of libc loaded into the inferior, and sets
+    /// breakpoints on them.
+    fn break_on_allocation_functions(&mut self) -> Result<(), String> {
+        self.maps = procfs::read_maps(self.inferior.pid()).map_err(|err| err.to_string())?;
+        let libc = self
+            .maps
+            .iter()
+            .find(|mapping| heap::is_libc(mapping) && mapping.offset == 0)
+            .ok_or("The program isn't dynamically linked with glibc.")?;
+        let (base, path) = (libc.start, libc.path.clone());
+        let symbols = dwarf_data::read_symbol_table(&path)
+            .ok_or_else(|| format!("Could not read the symbols of {}", path))?;
+        for (name, function) in FUNCTIONS {
+            let symbol = symbols
+                .get(name, true)
+                .ok_or_else(|| format!("{} has no function named {}", path, name))?;
+            let addr = base + symbol.address;
+            self.set_breakpoint(addr).map_err(|err| err.to_string())?;
+            self.functions.insert(addr, function);
+        }
+        Ok(())
+    }
+
+    fn set_breakpoint(&mut self, addr: usize) -> Result<(), nix::Error> {
+        if !self.breakpoints.contains_key(&addr) {
+            let orig_byte = self.inferior.write_byte(addr, 0xcc)?;
+            self.breakpoints.insert(addr, Some(Breakpoint { addr, orig_byte }));
+        }
+        Ok(())
+    }
+
+    /// Runs the inferior to completion, then prints the blocks it never freed to stderr. Returns
+    /// the exit status that deet should exit with (the inferior's own exit status where
+    /// possible).
+    pub fn run(&mut self) -> i32 {
+        let mut signal: Option<Signal> = None;
+        loop {
+            let status = match self.inferior.cont_with_signal(&self.breakpoints, signal.take()) {
+                Ok(status) => status,
+                Err(err) => {
+                    eprintln!("Error running inferior: {}", err);
+                    return 1;
+                }
+            };
+            match status {
+                Status::Stopped(Signal::SIGTRAP, rip) if self.inferior.past_breakpoint() => {
+                    if let Err(err) = self.breakpoint_hit(rip - 1) {
+                        eprintln!("Error tracking allocations: {}", err);
+                        return 1;
+                    }
+                }
+                Status::Stopped(sig, _) => signal = Some(sig),
+                Status::Syscall(_) => {}
+                Status::Exited(code) => {
+                    self.report();
+                    return code;
+                }
+                Status::Signaled(sig) => {
+                    eprintln!("Program terminated with signal {}", sig);
+                    self.report();
+                    return 128 + sig as i32;
+                }
+            }
+        }
+    }
+
+    /// Handles a breakpoint on either the start of an allocation function or a return address.
+    fn breakpoint_hit(&mut self, addr: usize) -> Result<(), nix::Error> {
+        let regs = self.inferior.getregs()?;
+        let args = (regs.rdi as usize, regs.rsi as usize);
+        let function = match self.functions.get(&addr) {
+            Some(function) => *function,
+            None => {
+                let rsp = regs.rsp as usize;
+                let position = self
+                    .pending
+                    .iter()
+                    .rposition(|call| call.return_addr == addr && call.stack_ptr == rsp);
+                if let Some(position) = position {
+                    let call = self.pending.remove(position);
+                    self.returned(call, regs.rax as usize);
+                }
+                return Ok(());
+            }
+        };
+        if let Function::Free = function {
+            if args.0 != 0 {
+                self.live.remove(&args.0);
+                self.frees += 1;
+            }
+            return Ok(());
+        }
+        // The function's prologue hasn't run, so %rbp is still the caller's
+        let return_addr = self.inferior.read_word(regs.rsp as usize)?;
+        let backtrace = self
+            .inferior
+            .backtrace_from(&self.debug_data, return_addr, regs.rbp as usize)?
+            .iter()
+            .map(|frame| frame.pc)
+            .collect();
+        self.set_breakpoint(return_addr)?;
+        self.pending.push(PendingCall {
+            function,
+            args,
+            return_addr,
+            stack_ptr: regs.rsp as usize + 8,
+            backtrace,
+        });
+        Ok(())
+    }
+
+    /// Records the block an allocation function returned.
+    fn returned(&mut self, call: PendingCall, result: usize) {
+        let size = match call.function {
+            Function::Malloc => call.args.0,
+            Function::Calloc => call.args.0.saturating_mul(call.args.1),
+            Function::Realloc => {
+                // realloc(ptr, 0) frees ptr; otherwise ptr is only freed if it succeeds
+                if call.args.0 != 0 && (result != 0 || call.args.1 == 0) {
+                    self.live.remove(&call.args.0);
+                    self.frees += 1;
+                }
+                call.args.1
+            }
+            Function::Free => return,
+        };
+        if result != 0 {
+            self.allocations += 1;
+            self.allocated_bytes += size;
+            self.live.insert(result, Allocation { size, backtrace: call.backtrace });
+        }
+    }
+
+    /// Whether libc allocated the block for its own use, like the buffer behind stdout. libc keeps
+    /// pointers to those until the program exits, so they aren't leaks.
+    fn allocated_by_libc(&self, allocation: &Allocation) -> bool {
+        allocation.backtrace.first().is_some_and(|&pc| {
+            self.maps.iter().any(|mapping| mapping.contains(pc) && heap::is_libc(mapping))
+        })
+    }
+
+    /// Prints the blocks that were never freed, grouped by where they were allocated, largest
+    /// total first.
+    fn report(&self) {
+        let (internal, leaked): (Vec<&Allocation>, Vec<&Allocation>) =
+            self.live.values().partition(|allocation| self.allocated_by_libc(allocation));
+        let mut leaks: HashMap<&[usize], (usize, usize)> = HashMap::new();
+        for allocation in &leaked {
+            let (bytes, blocks) = leaks.entry(&allocation.backtrace).or_default();
+            *bytes += allocation.size;
+            *blocks += 1;
+        }
+        let mut leaks: Vec<_> = leaks.into_iter().collect();
+        leaks.sort_by(|a, b| (b.1).0.cmp(&(a.1).0).then_with(|| a.0.cmp(b.0)));
+        eprintln!();
+        for (backtrace, (bytes, blocks)) in &leaks {
+            eprintln!("{} bytes in {} blocks were never freed, allocated at", bytes, blocks);
+            for &pc in backtrace.iter() {
+                // The return address may already be on the next line
+                for frame in self.debug_data.get_frames(pc - 1) {
+                    match &frame.line {
+                        Some(line) => eprintln!("    {} ({})", frame.function, line),
+                        None => {
+                            let mapping = self.maps.iter().find(|mapping| mapping.contains(pc));
+                            match mapping.and_then(|mapping| mapping.describe(pc)) {
+                                Some(mapping) => {
+                                    eprintln!("    {} ({:#x} in {})", frame.function, pc, mapping)
+                                }
+                                None => eprintln!("    {} ({:#x})", frame.function, pc),
+                            }
+                        }
+                    }
+                }
+            }
+            eprintln!();
+        }
+        eprintln!(
+            "{} allocations and {} frees, {} bytes allocated.",
+            self.allocations, self.frees, self.allocated_bytes
+        );
+        if leaks.is_empty() {
+            eprintln!("All heap blocks were freed.");
+        } else {
+            let bytes: usize = leaked.iter().map(|allocation| allocation.size).sum();
+            eprintln!("{} bytes in {} blocks were never freed.", bytes, leaked.len());
+        }
+        if !internal.is_empty() {
+            let bytes: usize = internal.iter().map(|allocation| allocation.size).sum();
+            eprintln!(
+                "{} bytes in {} blocks allocated by libc itself are still reachable.",
+                bytes,
+                internal.len()
+            );
+        }
+    }
+}
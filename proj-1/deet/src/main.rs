@@ -1,24 +1,152 @@
+mod completion;
+mod coverage;
+mod dap;
 mod debugger;
 mod debugger_command;
-mod inferior;
 mod dwarf_data;
+mod dwarf_index;
+mod expr;
 mod gimli_wrapper;
+mod heap;
+mod inferior;
+mod launch;
+mod leak_checker;
+mod printers;
+mod procfs;
+mod record;
+mod settings;
+mod symbols;
+mod syscall_tracer;
+mod tui;
+mod unwind;
+mod value;
+
+#[cfg(test)]
+#[path = "../tests/common/mod.rs"]
+mod common;
 
+use crate::coverage::CoverageCollector;
+use crate::dap::DapServer;
 use crate::debugger::Debugger;
+use crate::leak_checker::LeakChecker;
+use crate::syscall_tracer::SyscallTracer;
 use nix::sys::signal::{signal, SigHandler, Signal};
+use std::collections::HashSet;
 use std::env;
 
+fn usage(program: &str) -> ! {
+    println!("Usage: {} [--tui] <target program>", program);
+    println!("       {} --dap", program);
+    println!(
+        "       {} --trace-syscalls [-e syscall[,syscall...]] <target program> [args...]",
+        program
+    );
+    println!("       {} --leak-check <target program> [args...]", program);
+    println!("       {} --coverage [-o report.info] <target program> [args...]", program);
+    std::process::exit(1);
+}
+
+/// Runs `deet --trace-syscalls [-e filter] target args...`
+fn trace_syscalls(program: &str, args: &[String]) -> i32 {
+    let mut args = args;
+    let mut filter = None;
+    if args.first().map(String::as_str) == Some("-e") {
+        let names = match args.get(1) {
+            Some(names) => names,
+            None => usage(program),
+        };
+        let names: HashSet<String> = names.split(',').map(str::to_string).collect();
+        for name in &names {
+            if !syscall_tracer::is_syscall_name(name) {
+                println!("Unknown syscall: {}", name);
+                std::process::exit(1);
+            }
+        }
+        filter = Some(names);
+        args = &args[2..];
+    }
+    let (target, target_args) = match args.split_first() {
+        Some(split) => split,
+        None => usage(program),
+    };
+    match SyscallTracer::new(target, &target_args.to_vec(), filter) {
+        Some(mut tracer) => tracer.run(),
+        None => {
+            println!("Error starting subprocess");
+            1
+        }
+    }
+}
+
+/// Runs `deet --leak-check target args...`
+fn run_leak_check(program: &str, args: &[String]) -> i32 {
+    let (target, target_args) = match args.split_first() {
+        Some(split) => split,
+        None => usage(program),
+    };
+    match LeakChecker::new(target, &target_args.to_vec()) {
+        Some(mut checker) => checker.run(),
+        None => {
+            println!("Error starting subprocess");
+            1
+        }
+    }
+}
+
+/// Runs `deet --coverage [-o report.info] target args...`
+fn collect_coverage(program: &str, args: &[String]) -> i32 {
+    let (output, args) = match args {
+        [flag, output, rest @ ..] if flag == "-o" => (output.as_str(), rest),
+        _ => ("coverage.info", args),
+    };
+    let (target, target_args) = match args.split_first() {
+        Some(split) => split,
+        None => usage(program),
+    };
+    match CoverageCollector::new(target, &target_args.to_vec()) {
+        Some(mut collector) => collector.run(output),
+        None => {
+            println!("Error starting subprocess");
+            1
+        }
+    }
+}
+
 fn main() {
     let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        println!("Usage: {} <target program>", args[0]);
-        std::process::exit(1);
+    if args.len() > 1 && args[1] == "--trace-syscalls" {
+        std::process::exit(trace_syscalls(&args[0], &args[2..]));
     }
-    let target = &args[1];
+    if args.len() > 1 && args[1] == "--leak-check" {
+        std::process::exit(run_leak_check(&args[0], &args[2..]));
+    }
+    if args.len() > 1 && args[1] == "--coverage" {
+        std::process::exit(collect_coverage(&args[0], &args[2..]));
+    }
+    if args.len() == 2 && args[1] == "--dap" {
+        match DapServer::new() {
+            Ok(mut server) => std::process::exit(server.run()),
+            Err(err) => {
+                println!("Error starting the debug adapter: {}", err);
+                std::process::exit(1);
+            }
+        }
+    }
+    let tui = args.len() == 3 && args[1] == "--tui";
+    if args.len() != 2 && !tui {
+        usage(&args[0]);
+    }
+    let target = &args[args.len() - 1];
 
     // Disable handling of ctrl+c in this process (so that ctrl+c only gets delivered to child
     // processes)
     unsafe { signal(Signal::SIGINT, SigHandler::SigIgn) }.expect("Error disabling SIGINT handling");
 
-    Debugger::new(target).run();
+    let mut debugger = Debugger::new(target);
+    if tui {
+        if let Err(err) = debugger.enable_tui() {
+            println!("{}", err);
+        }
+    }
+    debugger.run();
 }
@@ -0,0 +1,422 @@
+//! Pretty printers, which change how print shows values of particular types. Each printer is
+//! registered for the types whose names match a regular expression. The printers for C strings
+//! and Rust's standard library are registered by default; the rest can be registered by name with
+//! `pretty-printer <printer> <regex>`, e.g. in ~/.deetinit.
+
+use crate::debugger::DebuggerError;
+use crate::dwarf_data::{Type, TypeKind};
+use crate::expr::{self, Evaluator};
+use crate::value::TypedValue;
+use regex::Regex;
+
+pub trait ValuePrinter {
+    /// Formats a value nested depth levels deep in other values, or returns None if the printer
+    /// can't make sense of it, in which case it's formatted as usual.
+    fn format(&self, value: &TypedValue, evaluator: &Evaluator, depth: usize) -> Option<String>;
+}
+
+/// The names of the built-in printers, in the order `info pretty-printer` lists them.
+pub const PRINTER_NAMES: [&str; 8] = [
+    "c-string",
+    "linked-list",
+    "struct-array",
+    "rust-str",
+    "rust-slice",
+    "rust-string",
+    "rust-vec",
+    "rust-box",
+];
+
+/// The printers registered at startup, and the types they apply to.
+const DEFAULT_PRINTERS: [(&str, &str); 6] = [
+    ("c-string", r"^((const|volatile|signed|unsigned) )*char (\*|\[)"),
+    ("rust-str", r"^&(mut )?str$"),
+    ("rust-slice", r"^&(mut )?\["),
+    ("rust-string", r"^alloc::string::String$"),
+    ("rust-vec", r"^alloc::vec::Vec<"),
+    ("rust-box", r"^alloc::boxed::Box<"),
+];
+
+/// Returns the built-in printer with the given name.
+fn builtin(name: &str) -> Option<Box<dyn ValuePrinter>> {
+    Some(match name {
+        "c-string" => Box::new(CStringPrinter),
+        "linked-list" => Box::new(LinkedListPrinter),
+        "struct-array" => Box::new(StructArrayPrinter),
+        "rust-str" => Box::new(RustStrPrinter),
+        "rust-slice" => Box::new(RustSlicePrinter),
+        "rust-string" => Box::new(RustStringPrinter),
+        "rust-vec" => Box::new(RustVecPrinter),
+        "rust-box" => Box::new(RustBoxPrinter),
+        _ => return None,
+    })
+}
+
+pub struct Registration {
+    pub printer_name: String,
+    /// Matched against type names
+    pub pattern: Regex,
+    printer: Box<dyn ValuePrinter>,
+}
+
+/// The registered printers, most recently registered first.
+pub struct Printers {
+    registrations: Vec<Registration>,
+}
+
+impl Default for Printers {
+    fn default() -> Self {
+        let mut printers = Printers {
+            registrations: Vec::new(),
+        };
+        for (name, pattern) in DEFAULT_PRINTERS.iter().rev() {
+            printers.add(name, Regex::new(pattern).unwrap(), builtin(name).unwrap());
+        }
+        printers
+    }
+}
+
+impl Printers {
+    /// Registers a printer for the types whose names match pattern. It takes precedence over the
+    /// printers registered before it.
+    pub fn add(&mut self, name: &str, pattern: Regex, printer: Box<dyn ValuePrinter>) {
+        self.registrations.insert(
+            0,
+            Registration {
+                printer_name: name.to_string(),
+                pattern,
+                printer,
+            },
+        );
+    }
+
+    /// Registers the built-in printer with the given name.
+    pub fn register(&mut self, name: &str, pattern: Regex) -> Result<(), DebuggerError> {
+        let printer = builtin(name).ok_or_else(|| {
+            DebuggerError::Parse(format!(
+                "No pretty-printer named \"{}\"; try one of {}.",
+                name,
+                PRINTER_NAMES.join(", ")
+            ))
+        })?;
+        self.add(name, pattern, printer);
+        Ok(())
+    }
+
+    /// Removes the registration numbered number (from 1) in the order iter() returns them.
+    pub fn remove(&mut self, number: usize) -> Result<(), DebuggerError> {
+        if number == 0 || number > self.registrations.len() {
+            return Err(DebuggerError::State(format!("No pretty-printer number {}.", number)));
+        }
+        self.registrations.remove(number - 1);
+        Ok(())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Registration> {
+        self.registrations.iter()
+    }
+
+    /// Formats value with the first printer registered for its type that can handle it.
+    pub fn format(
+        &self,
+        value: &TypedValue,
+        evaluator: &Evaluator,
+        depth: usize,
+    ) -> Option<String> {
+        self.registrations
+            .iter()
+            .filter(|registration| registration.pattern.is_match(&value.value_type.name))
+            .find_map(|registration| registration.printer.format(value, evaluator, depth))
+    }
+}
+
+/// Prints char pointers and arrays as NUL-terminated strings.
+struct CStringPrinter;
+
+impl ValuePrinter for CStringPrinter {
+    fn format(&self, value: &TypedValue, evaluator: &Evaluator, _depth: usize) -> Option<String> {
+        let elements = evaluator.options().elements;
+        match &value.value_type.kind {
+            TypeKind::Pointer(pointee) if expr::is_char_type(pointee) => {
+                let addr = value.scalar().as_u64() as usize;
+                if addr == 0 {
+                    return None;
+                }
+                Some(format!("{:#x} {}", addr, evaluator.format_string(addr)))
+            }
+            TypeKind::Array(element, _) if expr::is_char_type(element) => {
+                let len = value
+                    .bytes
+                    .iter()
+                    .position(|b| *b == 0)
+                    .unwrap_or(value.bytes.len());
+                Some(expr::quote(&value.bytes[..len.min(elements)], len > elements))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Prints a singly linked list as `[1, 2, 3]`. Applies to a pointer to the first node, or to a
+/// struct whose first link member (e.g. `head`) points to it. Each node links to the next with
+/// its `next` member, and its first other member is its value. Links are pointers, or
+/// Option<Box<Node>> in Rust.
+struct LinkedListPrinter;
+
+impl ValuePrinter for LinkedListPrinter {
+    fn format(&self, value: &TypedValue, evaluator: &Evaluator, depth: usize) -> Option<String> {
+        let mut link = match &value.value_type.kind {
+            TypeKind::Struct(members) => members.iter().find_map(|member| {
+                let field = evaluator.member_value(value, member).ok()?;
+                follow_link(&field, evaluator).map(|_| field)
+            })?,
+            _ => value.clone(),
+        };
+        let mut elements = Vec::new();
+        loop {
+            let (addr, node_type) = follow_link(&link, evaluator)?;
+            if addr == 0 {
+                break;
+            }
+            // Also keeps cyclic lists from looping forever
+            if elements.len() >= evaluator.options().elements {
+                elements.push("...".to_string());
+                break;
+            }
+            let node = evaluator.read_value(addr, node_type).ok()?;
+            let members = match &node.value_type.kind {
+                TypeKind::Struct(members) => members,
+                _ => return None,
+            };
+            let item = members.iter().find(|member| member.name != "next")?;
+            let item = evaluator.member_value(&node, item).ok()?;
+            elements.push(evaluator.format_value(&item, depth + 1));
+            link = evaluator.member(node, "next").ok()?;
+        }
+        Some(format!("[{}]", elements.join(", ")))
+    }
+}
+
+/// Returns the address of the struct a linked list's link points to (0 at the end of the list)
+/// and the struct's type.
+fn follow_link(link: &TypedValue, evaluator: &Evaluator) -> Option<(usize, Type)> {
+    match &link.value_type.kind {
+        TypeKind::Pointer(node) if matches!(node.kind, TypeKind::Struct(_)) => {
+            Some((link.scalar().as_u64() as usize, (**node).clone()))
+        }
+        // None is stored as a null Box
+        TypeKind::Variants(_, variants) => {
+            let (_, some) = variants.iter().find(|(_, variant)| variant.name == "Some")?;
+            let fields = evaluator.member_value(link, some).ok()?;
+            follow_link(&evaluator.member(fields, "__0").ok()?, evaluator)
+        }
+        _ => None,
+    }
+}
+
+/// Prints an array of structs with one element per line, labelled with its index.
+struct StructArrayPrinter;
+
+impl ValuePrinter for StructArrayPrinter {
+    fn format(&self, value: &TypedValue, evaluator: &Evaluator, depth: usize) -> Option<String> {
+        let (element, count) = match &value.value_type.kind {
+            TypeKind::Array(element, count) if matches!(element.kind, TypeKind::Struct(_)) => {
+                (element, *count)
+            }
+            _ => return None,
+        };
+        let indent = "  ".repeat(depth + 1);
+        let elements = evaluator.options().elements;
+        let mut lines = Vec::new();
+        for i in 0..count.min(elements) {
+            let start = i * element.size;
+            let element_value = TypedValue {
+                value_type: (**element).clone(),
+                bytes: value.bytes.get(start..start + element.size)?.to_vec(),
+                address: value.address.map(|addr| addr + start),
+            };
+            let formatted = evaluator.format_value(&element_value, depth + 1);
+            lines.push(format!("{}[{}] = {}", indent, i, formatted));
+        }
+        if count > elements {
+            lines.push(format!("{}...", indent));
+        }
+        Some(format!("{{\n{}\n{}}}", lines.join(",\n"), "  ".repeat(depth)))
+    }
+}
+
+/// Prints &str as a quoted string.
+struct RustStrPrinter;
+
+impl ValuePrinter for RustStrPrinter {
+    fn format(&self, value: &TypedValue, evaluator: &Evaluator, _depth: usize) -> Option<String> {
+        let addr = evaluator.member(value.clone(), "data_ptr").ok()?.scalar().as_u64();
+        let len = evaluator.member(value.clone(), "length").ok()?.scalar().as_u64();
+        Some(evaluator.format_str(addr as usize, len as usize))
+    }
+}
+
+/// Prints slices as `[1, 2, 3]`.
+struct RustSlicePrinter;
+
+impl ValuePrinter for RustSlicePrinter {
+    fn format(&self, value: &TypedValue, evaluator: &Evaluator, depth: usize) -> Option<String> {
+        let data_ptr = evaluator.member(value.clone(), "data_ptr").ok()?;
+        let len = evaluator.member(value.clone(), "length").ok()?.scalar().as_u64() as usize;
+        let element = match &data_ptr.value_type.kind {
+            TypeKind::Pointer(element) => element,
+            _ => return None,
+        };
+        let addr = data_ptr.scalar().as_u64() as usize;
+        Some(format!("[{}]", evaluator.format_elements(addr, element, len, depth)))
+    }
+}
+
+/// Prints String as a quoted string.
+struct RustStringPrinter;
+
+impl ValuePrinter for RustStringPrinter {
+    fn format(&self, value: &TypedValue, evaluator: &Evaluator, _depth: usize) -> Option<String> {
+        let vec = evaluator.member(value.clone(), "vec").ok()?;
+        let (addr, len, _) = vec_contents(&vec, evaluator)?;
+        Some(evaluator.format_str(addr, len))
+    }
+}
+
+/// Prints Vec<T> as `Vec(size=3) = {1, 2, 3}`.
+struct RustVecPrinter;
+
+impl ValuePrinter for RustVecPrinter {
+    fn format(&self, value: &TypedValue, evaluator: &Evaluator, depth: usize) -> Option<String> {
+        let (addr, len, element) = vec_contents(value, evaluator)?;
+        Some(format!(
+            "Vec(size={}) = {{{}}}",
+            len,
+            evaluator.format_elements(addr, &element, len, depth)
+        ))
+    }
+}
+
+/// Returns the address of a Vec's buffer, its length and the type of its elements. The buffer
+/// pointer is the first pointer nested in the Vec's `buf` member.
+fn vec_contents(vec: &TypedValue, evaluator: &Evaluator) -> Option<(usize, usize, Type)> {
+    let len = evaluator.member(vec.clone(), "len").ok()?.scalar().as_u64() as usize;
+    let element_offset = vec.value_type.template_param("T")?;
+    let element = evaluator.debug_data().get_type(element_offset)?.clone();
+    let mut pending = vec![evaluator.member(vec.clone(), "buf").ok()?];
+    while let Some(value) = pending.pop() {
+        match &value.value_type.kind {
+            TypeKind::Pointer(_) => {
+                return Some((value.scalar().as_u64() as usize, len, element));
+            }
+            TypeKind::Struct(members) => {
+                let fields = members.iter().rev();
+                pending.extend(
+                    fields.filter_map(|member| evaluator.member_value(&value, member).ok()),
+                );
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Prints Box<T> as `Box(value)`.
+struct RustBoxPrinter;
+
+impl ValuePrinter for RustBoxPrinter {
+    fn format(&self, value: &TypedValue, evaluator: &Evaluator, depth: usize) -> Option<String> {
+        let pointee = match &value.value_type.kind {
+            TypeKind::Pointer(pointee) => (**pointee).clone(),
+            _ => return None,
+        };
+        let addr = value.scalar().as_u64() as usize;
+        let boxed = match evaluator.read_value(addr, pointee) {
+            Ok(boxed) => evaluator.format_value(&boxed, depth + 1),
+            Err(err) => format!("<error: {}>", err),
+        };
+        Some(format!("Box({})", boxed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::sample;
+    use crate::debugger::Debugger;
+
+    fn names(printers: &Printers) -> Vec<String> {
+        printers.iter().map(|registration| registration.printer_name.clone()).collect()
+    }
+
+    /// Stops sample at location, then formats each expression with printers, returning None
+    /// where no printer applied.
+    fn format_all(
+        name: &str,
+        location: &str,
+        args: &[&str],
+        printers: &Printers,
+        exprs: &[&str],
+    ) -> Vec<Option<String>> {
+        let mut debugger = Debugger::without_init_files(&sample(name));
+        debugger.launch_options().args = args.iter().map(|arg| arg.to_string()).collect();
+        debugger.set_breakpoint(location).unwrap();
+        debugger.start().unwrap();
+        let mut evaluator = debugger.evaluator().unwrap();
+        let formatted = exprs
+            .iter()
+            .map(|expr| {
+                let value = evaluator.evaluate(expr).unwrap();
+                printers.format(&value, &evaluator, 0)
+            })
+            .collect();
+        debugger.kill();
+        formatted
+    }
+
+    #[test]
+    fn later_registrations_come_first() {
+        let mut printers = Printers::default();
+        let defaults = names(&printers);
+        assert_eq!(defaults, DEFAULT_PRINTERS.map(|(name, _)| name));
+        printers.register("linked-list", Regex::new("^struct list$").unwrap()).unwrap();
+        assert_eq!(names(&printers)[0], "linked-list");
+        printers.remove(1).unwrap();
+        assert_eq!(names(&printers), defaults);
+    }
+
+    #[test]
+    fn unknown_printers_and_numbers_are_rejected() {
+        let mut printers = Printers::default();
+        let err = printers.register("nosuch", Regex::new(".").unwrap()).unwrap_err();
+        assert!(err.to_string().starts_with("No pretty-printer named \"nosuch\""), "{}", err);
+        assert!(printers.remove(0).is_err());
+        assert!(printers.remove(DEFAULT_PRINTERS.len() + 1).is_err());
+        assert_eq!(names(&printers).len(), DEFAULT_PRINTERS.len());
+    }
+
+    #[test]
+    fn linked_lists_and_struct_arrays_are_printed() {
+        let mut printers = Printers::default();
+        printers.register("linked-list", Regex::new(r"^struct (list|node \*)$").unwrap()).unwrap();
+        printers.register("struct-array", Regex::new(r"^struct point \[").unwrap()).unwrap();
+        let exprs = ["list", "list.head->next", "points", "list.size"];
+        let formatted = format_all("linked_list", "33", &[], &printers, &exprs);
+        assert_eq!(formatted[0].as_deref(), Some("[1, 2, 3]"));
+        assert_eq!(formatted[1].as_deref(), Some("[2, 3]"));
+        let points = "{\n  [0] = {x = 1, y = 2},\n  [1] = {x = 3, y = 4},\n  \
+                      [2] = {x = 5, y = 6}\n}";
+        assert_eq!(formatted[2].as_deref(), Some(points));
+        assert_eq!(formatted[3], None);
+    }
+
+    #[test]
+    fn char_pointers_are_printed_as_strings() {
+        let printers = Printers::default();
+        let exprs = ["argv[1]", "*argv[1]", "(char *) 0"];
+        let formatted = format_all("environment", "8", &["hello"], &printers, &exprs);
+        let string = formatted[0].as_deref().unwrap();
+        assert!(string.starts_with("0x") && string.ends_with(" \"hello\""), "{}", string);
+        assert_eq!(formatted[1..], [None, None]);
+    }
+}
@@ -0,0 +1,263 @@
+//! Helpers for reading information about the inferior out of /proc.
+
+use nix::unistd::Pid;
+use std::fs;
+use std::convert::TryInto;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The auxiliary vector entries deet knows, with their names, descriptions and how to show
+/// their values.
+const AUXV_TYPES: &[(u64, &str, &str, AuxvFormat)] = &[
+    (3, "AT_PHDR", "Program headers for program", AuxvFormat::Hex),
+    (4, "AT_PHENT", "Size of program header entry", AuxvFormat::Decimal),
+    (5, "AT_PHNUM", "Number of program headers", AuxvFormat::Decimal),
+    (6, "AT_PAGESZ", "System page size", AuxvFormat::Decimal),
+    (7, "AT_BASE", "Base address of interpreter", AuxvFormat::Hex),
+    (8, "AT_FLAGS", "Flags", AuxvFormat::Hex),
+    (9, "AT_ENTRY", "Entry point of program", AuxvFormat::Hex),
+    (11, "AT_UID", "Real user ID", AuxvFormat::Decimal),
+    (12, "AT_EUID", "Effective user ID", AuxvFormat::Decimal),
+    (13, "AT_GID", "Real group ID", AuxvFormat::Decimal),
+    (14, "AT_EGID", "Effective group ID", AuxvFormat::Decimal),
+    (15, "AT_PLATFORM", "String identifying platform", AuxvFormat::String),
+    (16, "AT_HWCAP", "Machine-dependent CPU capability hints", AuxvFormat::Hex),
+    (17, "AT_CLKTCK", "Frequency of times()", AuxvFormat::Decimal),
+    (23, "AT_SECURE", "Boolean, was exec setuid-like?", AuxvFormat::Decimal),
+    (25, "AT_RANDOM", "Address of 16 random bytes", AuxvFormat::Hex),
+    (26, "AT_HWCAP2", "Extension of AT_HWCAP", AuxvFormat::Hex),
+    (27, "AT_RSEQ_FEATURE_SIZE", "rseq supported feature size", AuxvFormat::Decimal),
+    (28, "AT_RSEQ_ALIGN", "rseq allocation alignment", AuxvFormat::Decimal),
+    (31, "AT_EXECFN", "File name of executable", AuxvFormat::String),
+    (33, "AT_SYSINFO_EHDR", "System-supplied DSO's ELF header", AuxvFormat::Hex),
+    (51, "AT_MINSIGSTKSZ", "Minimal stack size for signal delivery", AuxvFormat::Hex),
+];
+
+/// How an auxiliary vector entry's value is shown.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AuxvFormat {
+    Decimal,
+    Hex,
+    /// The address of a NUL-terminated string
+    String,
+}
+
+/// One entry of /proc/<pid>/auxv, which the kernel passes to a new program.
+pub struct AuxvEntry {
+    pub key: u64,
+    pub value: u64,
+}
+
+impl AuxvEntry {
+    /// Returns the entry's name, description and the way to show its value.
+    pub fn describe(&self) -> (&'static str, &'static str, AuxvFormat) {
+        match AUXV_TYPES.iter().find(|(key, ..)| *key == self.key) {
+            Some((_, name, description, format)) => (name, description, *format),
+            None => ("???", "", AuxvFormat::Hex),
+        }
+    }
+}
+
+/// One line of /proc/<pid>/maps.
+#[derive(Debug, Clone)]
+pub struct MemoryMapping {
+    pub start: usize,
+    pub end: usize,
+    pub perms: String,
+    /// Where in the file the mapping starts
+    pub offset: usize,
+    pub path: String,
+}
+
+impl MemoryMapping {
+    pub fn is_writable(&self) -> bool {
+        self.perms.as_bytes().get(1) == Some(&b'w')
+    }
+
+    pub fn contains(&self, addr: usize) -> bool {
+        self.start <= addr && addr < self.end
+    }
+
+    /// Describes where addr is, e.g. `[heap]` or `libc.so.6+0x1234` (an offset in the file).
+    /// Anonymous mappings have nothing to say.
+    pub fn describe(&self, addr: usize) -> Option<String> {
+        if self.path.is_empty() || self.path.starts_with('[') {
+            return (!self.path.is_empty()).then(|| self.path.clone());
+        }
+        let name = Path::new(&self.path).file_name()?.to_string_lossy();
+        Some(format!("{}+{:#x}", name, addr - self.start + self.offset))
+    }
+}
+
+/// Describes which mapping of a process addr falls into, if any.
+pub fn annotate(pid: Pid, addr: usize) -> Option<String> {
+    let maps = read_maps(pid).ok()?;
+    maps.iter().find(|mapping| mapping.contains(addr))?.describe(addr)
+}
+
+/// Reads the memory mappings of a process.
+pub fn read_maps(pid: Pid) -> io::Result<Vec<MemoryMapping>> {
+    let maps = fs::read_to_string(format!("/proc/{}/maps", pid))?;
+    Ok(maps.lines().filter_map(parse_maps_line).collect())
+}
+
+fn parse_maps_line(line: &str) -> Option<MemoryMapping> {
+    let mut fields = line.split_whitespace();
+    let (start, end) = fields.next()?.split_once('-')?;
+    let perms = fields.next()?.to_string();
+    let offset = usize::from_str_radix(fields.next()?, 16).ok()?;
+    // Skip the device and inode
+    fields.next()?;
+    fields.next()?;
+    Some(MemoryMapping {
+        start: usize::from_str_radix(start, 16).ok()?,
+        end: usize::from_str_radix(end, 16).ok()?,
+        perms,
+        offset,
+        path: fields.collect::<Vec<_>>().join(" "),
+    })
+}
+
+/// Opens /proc/<pid>/mem, which allows reading and writing large ranges of a traced process's
+/// memory much faster than word-at-a-time ptrace calls.
+pub fn open_mem(pid: Pid) -> io::Result<fs::File> {
+    fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(format!("/proc/{}/mem", pid))
+}
+
+/// Reads the auxiliary vector of a process, up to its AT_NULL terminator.
+pub fn read_auxv(pid: Pid) -> io::Result<Vec<AuxvEntry>> {
+    let bytes = fs::read(format!("/proc/{}/auxv", pid))?;
+    let word = |chunk: &[u8]| u64::from_ne_bytes(chunk.try_into().unwrap());
+    Ok(bytes
+        .chunks_exact(16)
+        .map(|entry| AuxvEntry { key: word(&entry[..8]), value: word(&entry[8..]) })
+        .take_while(|entry| entry.key != 0)
+        .collect())
+}
+
+/// Reads the arguments a process was started with, its program name first.
+pub fn read_cmdline(pid: Pid) -> io::Result<Vec<String>> {
+    let bytes = fs::read(format!("/proc/{}/cmdline", pid))?;
+    Ok(bytes
+        .split(|&b| b == 0)
+        .filter(|arg| !arg.is_empty())
+        .map(|arg| String::from_utf8_lossy(arg).to_string())
+        .collect())
+}
+
+pub fn read_cwd(pid: Pid) -> io::Result<PathBuf> {
+    fs::read_link(format!("/proc/{}/cwd", pid))
+}
+
+pub fn read_exe(pid: Pid) -> io::Result<PathBuf> {
+    fs::read_link(format!("/proc/{}/exe", pid))
+}
+
+/// Reads the fields of /proc/<pid>/status, like ("State", "t (tracing stop)").
+pub fn read_status(pid: Pid) -> io::Result<Vec<(String, String)>> {
+    let status = fs::read_to_string(format!("/proc/{}/status", pid))?;
+    Ok(status
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.to_string(), value.trim().to_string()))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::sample;
+    use crate::debugger::Debugger;
+
+    #[test]
+    fn maps_lines_are_parsed() {
+        let line = "7f1c2a428000-7f1c2a5bd000 r-xp 00028000 fd:01 1837 \
+                    /usr/lib/x86_64-linux-gnu/libc.so.6";
+        let mapping = parse_maps_line(line).unwrap();
+        assert_eq!((mapping.start, mapping.end), (0x7f1c2a428000, 0x7f1c2a5bd000));
+        assert_eq!((mapping.perms.as_str(), mapping.offset), ("r-xp", 0x28000));
+        assert_eq!(mapping.path, "/usr/lib/x86_64-linux-gnu/libc.so.6");
+        assert!(!mapping.is_writable());
+
+        let heap = parse_maps_line("01a5e000-01a7f000 rw-p 00000000 00:00 0    [heap]").unwrap();
+        assert_eq!(heap.path, "[heap]");
+        assert!(heap.is_writable());
+        let anonymous = parse_maps_line("7f1c2a600000-7f1c2a60d000 rw-p 00000000 00:00 0").unwrap();
+        assert_eq!(anonymous.path, "");
+        let spaced = "400000-401000 r--p 00000000 fd:01 42 /tmp/my program";
+        assert_eq!(parse_maps_line(spaced).unwrap().path, "/tmp/my program");
+
+        assert!(parse_maps_line("").is_none());
+        assert!(parse_maps_line("400000 r--p 00000000 fd:01 42").is_none());
+        assert!(parse_maps_line("zz0000-401000 r--p 00000000 fd:01 42").is_none());
+    }
+
+    #[test]
+    fn mappings_describe_addresses_in_them() {
+        let mapping = |path: &str| MemoryMapping {
+            start: 0x7f0000010000,
+            end: 0x7f0000020000,
+            perms: "r-xp".to_string(),
+            offset: 0x28000,
+            path: path.to_string(),
+        };
+        let libc = mapping("/usr/lib/x86_64-linux-gnu/libc.so.6");
+        assert!(libc.contains(0x7f0000010000) && !libc.contains(0x7f0000020000));
+        assert_eq!(libc.describe(0x7f0000010010).unwrap(), "libc.so.6+0x28010");
+        assert_eq!(mapping("[stack]").describe(0x7f0000010010).unwrap(), "[stack]");
+        assert!(mapping("").describe(0x7f0000010010).is_none());
+    }
+
+    #[test]
+    fn a_process_maps_its_own_code() {
+        let maps = read_maps(Pid::this()).unwrap();
+        let code = test_function_address as fn() -> usize as usize;
+        let exe = read_exe(Pid::this()).unwrap();
+        let mapping = maps.iter().find(|mapping| mapping.contains(code)).unwrap();
+        assert_eq!(Path::new(&mapping.path), exe);
+        let name = exe.file_name().unwrap().to_string_lossy();
+        let offset = code - mapping.start + mapping.offset;
+        assert_eq!(annotate(Pid::this(), code).unwrap(), format!("{}+{:#x}", name, offset));
+    }
+
+    fn test_function_address() -> usize {
+        0
+    }
+
+    /// Checks that formatted is a pointer followed by the mapping it points into.
+    fn assert_points_into(formatted: &str, mapping: &str) {
+        let (addr, described) = formatted.split_once(" in ").unwrap();
+        assert!(addr.starts_with("0x"), "{} isn't a pointer", formatted);
+        assert!(described.starts_with(mapping), "{} doesn't point into {}", formatted, mapping);
+    }
+
+    #[test]
+    fn pointers_inside_structs_and_arrays_are_annotated() {
+        let mut debugger = Debugger::without_init_files(&sample("linked_list"));
+        debugger.set_breakpoint("33").unwrap();
+        debugger.start().unwrap();
+        let mut evaluator = debugger.evaluator().unwrap();
+        let mut print = |expr: &str| {
+            let value = evaluator.evaluate(expr).unwrap();
+            evaluator.format(&value)
+        };
+        let list = print("list");
+        let node = print("*list.head");
+        let counters = print("counters");
+        debugger.kill();
+
+        let head = list.strip_prefix("{head = ").unwrap().strip_suffix(", size = 3}").unwrap();
+        assert_points_into(head, "[heap]");
+        let next = node.strip_prefix("{value = 1, next = ").unwrap().strip_suffix('}').unwrap();
+        assert_points_into(next, "[heap]");
+        let counters = counters.strip_prefix('{').unwrap().strip_suffix('}').unwrap();
+        let counters: Vec<&str> = counters.split(", ").collect();
+        assert_eq!(counters.len(), 2);
+        for counter in counters {
+            assert_points_into(counter, "linked_list+0x");
+        }
+    }
+}
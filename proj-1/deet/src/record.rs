@@ -0,0 +1,169 @@
+//! Implements `record` and reverse execution. While recording, the inferior is single-stepped.
+//! Before each instruction its registers are saved, and afterward its writable memory is
+//! compared against a shadow copy to find out exactly which bytes the instruction (or the syscall
+//! it made) changed. Stepping backward restores the registers and the old contents of those
+//! bytes.
+//!
+//! Comparing memory after every instruction is slow, but it needs no instruction decoding and is
+//! fast enough for small programs like the ones in samples/.
+
+use crate::inferior::{Inferior, Status};
+use crate::procfs;
+use std::collections::VecDeque;
+use std::fs;
+use std::io;
+use std::os::unix::fs::FileExt;
+
+/// Maximum number of instructions kept in the log; older ones are forgotten.
+const MAX_LOG_ENTRIES: usize = 200_000;
+
+/// Leaf functions may use this much memory below %rsp without moving it.
+const RED_ZONE_SIZE: usize = 128;
+
+/// The machine state before one recorded instruction.
+pub struct RecordEntry {
+    pub regs: libc::user_regs_struct,
+    /// The old contents of every range of memory the instruction changed
+    memory: Vec<(usize, Vec<u8>)>,
+}
+
+/// A writable mapping of the inferior, along with the contents it had after the last recorded
+/// instruction.
+struct Region {
+    start: usize,
+    is_stack: bool,
+    shadow: Vec<u8>,
+}
+
+impl Region {
+    fn end(&self) -> usize {
+        self.start + self.shadow.len()
+    }
+}
+
+pub struct Recorder {
+    mem: fs::File,
+    regions: Vec<Region>,
+    log: VecDeque<RecordEntry>,
+}
+
+impl Recorder {
+    /// Starts recording the inferior from its current state. Breakpoints must not be installed
+    /// in its memory while recording.
+    pub fn new(inferior: &Inferior) -> io::Result<Recorder> {
+        let mut recorder = Recorder {
+            mem: procfs::open_mem(inferior.pid())?,
+            regions: Vec::new(),
+            log: VecDeque::new(),
+        };
+        recorder.refresh_regions(inferior)?;
+        Ok(recorder)
+    }
+
+    /// Returns the number of instructions that can be stepped back over.
+    pub fn len(&self) -> usize {
+        self.log.len()
+    }
+
+    /// Returns the state the inferior was in before each recorded instruction, most recent first.
+    pub fn history(&self) -> impl Iterator<Item = &RecordEntry> {
+        self.log.iter().rev()
+    }
+
+    /// Executes and records one instruction.
+    pub fn step(&mut self, inferior: &mut Inferior) -> Result<Status, nix::Error> {
+        let regs = inferior.getregs()?;
+        let is_syscall = inferior
+            .read_bytes(regs.rip as usize, 2)
+            .map(|bytes| bytes == [0x0f, 0x05])
+            .unwrap_or(false);
+        let status = inferior.single_step()?;
+        if let Status::Exited(_) | Status::Signaled(_) = status {
+            return Ok(status);
+        }
+        let new_rsp = inferior.getregs()?.rsp as usize;
+        let live_stack = (regs.rsp as usize).min(new_rsp).saturating_sub(RED_ZONE_SIZE);
+        let memory = self.collect_changes(live_stack).map_err(|_| nix::Error::EIO)?;
+        self.log.push_back(RecordEntry { regs, memory });
+        if self.log.len() > MAX_LOG_ENTRIES {
+            self.log.pop_front();
+        }
+        if is_syscall {
+            // The syscall may have mapped, unmapped or resized memory
+            self.refresh_regions(inferior).map_err(|_| nix::Error::EIO)?;
+        }
+        Ok(status)
+    }
+
+    /// Undoes the most recently recorded instruction. Returns false if there is no more history.
+    pub fn reverse_step(&mut self, inferior: &mut Inferior) -> Result<bool, nix::Error> {
+        let entry = match self.log.pop_back() {
+            Some(entry) => entry,
+            None => return Ok(false),
+        };
+        for (addr, old_bytes) in &entry.memory {
+            self.mem
+                .write_all_at(old_bytes, *addr as u64)
+                .map_err(|_| nix::Error::EIO)?;
+            let region = self.regions.iter_mut().find(|r| r.start <= *addr && *addr < r.end());
+            if let Some(region) = region {
+                let offset = addr - region.start;
+                region.shadow[offset..offset + old_bytes.len()].copy_from_slice(old_bytes);
+            }
+        }
+        inferior.setregs(entry.regs)?;
+        Ok(true)
+    }
+
+    /// Compares every writable region against its shadow copy, returning the old contents of
+    /// each changed range and updating the shadow. Stack memory below live_stack is dead, so it
+    /// isn't compared.
+    fn collect_changes(&mut self, live_stack: usize) -> io::Result<Vec<(usize, Vec<u8>)>> {
+        const CHUNK: usize = 64;
+        let mut changes = Vec::new();
+        for region in &mut self.regions {
+            let start = if region.is_stack {
+                live_stack.clamp(region.start, region.end()) - region.start
+            } else {
+                0
+            };
+            let mut current = vec![0; region.shadow.len() - start];
+            if self.mem.read_exact_at(&mut current, (region.start + start) as u64).is_err() {
+                continue;
+            }
+            let shadow = &mut region.shadow[start..];
+            if current == *shadow {
+                continue;
+            }
+            for (i, (new, old)) in current.chunks(CHUNK).zip(shadow.chunks_mut(CHUNK)).enumerate() {
+                if new != old {
+                    changes.push((region.start + start + i * CHUNK, old.to_vec()));
+                    old.copy_from_slice(new);
+                }
+            }
+        }
+        Ok(changes)
+    }
+
+    /// Re-reads the inferior's writable mappings and shadows them with their current contents.
+    /// Changes to existing regions must already have been collected.
+    fn refresh_regions(&mut self, inferior: &Inferior) -> io::Result<()> {
+        let mut regions = Vec::new();
+        for mapping in procfs::read_maps(inferior.pid())? {
+            if !mapping.is_writable() || mapping.path == "[vvar]" {
+                continue;
+            }
+            let mut shadow = vec![0; mapping.end - mapping.start];
+            if self.mem.read_exact_at(&mut shadow, mapping.start as u64).is_err() {
+                continue;
+            }
+            regions.push(Region {
+                start: mapping.start,
+                is_stack: mapping.path == "[stack]",
+                shadow,
+            });
+        }
+        self.regions = regions;
+        Ok(())
+    }
+}
@@ -0,0 +1,96 @@
+//! Debugger options, changed with `set <option> <value>` and displayed with `show`.
+
+use crate::debugger::DebuggerError;
+use crate::expr::PrintOptions;
+
+/// The names of all options, in the order `show` lists them.
+pub const OPTION_NAMES: [&str; 4] = ["confirm", "output-radix", "print elements", "print pretty"];
+
+pub struct Settings {
+    /// Ask before doing things like killing a running inferior
+    pub confirm: bool,
+    pub print: PrintOptions,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            confirm: true,
+            print: PrintOptions::default(),
+        }
+    }
+}
+
+impl Settings {
+    /// Sets the named option from its textual value.
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), DebuggerError> {
+        match name {
+            "confirm" => self.confirm = parse_bool(value)?,
+            "output-radix" => {
+                self.print.hex = match value {
+                    "10" => false,
+                    "16" => true,
+                    _ => {
+                        return Err(DebuggerError::Parse(format!(
+                            "Unsupported output radix \"{}\"; use 10 or 16.",
+                            value
+                        )))
+                    }
+                }
+            }
+            "print elements" => {
+                self.print.elements = match value {
+                    "0" | "unlimited" => usize::MAX,
+                    _ => value.parse().map_err(|_| {
+                        DebuggerError::Parse(format!("Invalid number \"{}\".", value))
+                    })?,
+                }
+            }
+            "print pretty" => self.print.pretty = parse_bool(value)?,
+            _ => return Err(DebuggerError::Parse(format!("No option named \"{}\".", name))),
+        }
+        Ok(())
+    }
+
+    /// Describes the named option's current value.
+    pub fn show(&self, name: &str) -> Result<String, DebuggerError> {
+        Ok(match name {
+            "confirm" => format!(
+                "Whether to confirm potentially dangerous operations is {}.",
+                on_off(self.confirm)
+            ),
+            "output-radix" => format!(
+                "Default output radix for printing of values is {}.",
+                if self.print.hex { 16 } else { 10 }
+            ),
+            "print elements" => match self.print.elements {
+                usize::MAX => "Limit on string chars or array elements to print is unlimited."
+                    .to_string(),
+                limit => format!("Limit on string chars or array elements to print is {}.", limit),
+            },
+            "print pretty" => {
+                format!("Pretty formatting of structures is {}.", on_off(self.print.pretty))
+            }
+            _ => return Err(DebuggerError::Parse(format!("No option named \"{}\".", name))),
+        })
+    }
+}
+
+fn parse_bool(value: &str) -> Result<bool, DebuggerError> {
+    match value {
+        "" | "on" | "1" | "yes" | "enable" => Ok(true),
+        "off" | "0" | "no" | "disable" => Ok(false),
+        _ => Err(DebuggerError::Parse(format!(
+            "\"on\" or \"off\" expected, not \"{}\".",
+            value
+        ))),
+    }
+}
+
+fn on_off(value: bool) -> &'static str {
+    if value {
+        "on"
+    } else {
+        "off"
+    }
+}
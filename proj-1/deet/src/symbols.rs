@@ -0,0 +1,128 @@
+//! The ELF symbol table (.symtab and .dynsym), which names functions and global variables even in
+//! binaries compiled without -g. DwarfData falls back to it wherever DWARF has nothing to say.
+
+use object::{Object, ObjectSection, ObjectSymbol, SymbolKind};
+
+/// The x86-64 prologues that set up a frame pointer: `push %rbp; mov %rsp,%rbp`, optionally
+/// preceded by `endbr64`
+const PROLOGUES: [&[u8]; 2] = [
+    &[0xf3, 0x0f, 0x1e, 0xfa, 0x55, 0x48, 0x89, 0xe5],
+    &[0x55, 0x48, 0x89, 0xe5],
+];
+
+#[derive(Debug, Clone)]
+pub struct Symbol {
+    /// The demangled name, e.g. "std::vector<int>::push_back(int const&)" or
+    /// "core::fmt::write"
+    pub name: String,
+    /// The name as it appears in the symbol table
+    pub mangled_name: String,
+    pub address: usize,
+    pub size: usize,
+    pub is_function: bool,
+    /// For functions, the address just past the instructions that set up the frame pointer
+    pub after_prologue: usize,
+}
+
+impl Symbol {
+    /// Returns true if name refers to this symbol. C++ functions can be named without their
+    /// parameter list.
+    pub fn matches(&self, name: &str) -> bool {
+        self.name == name
+            || self.mangled_name == name
+            || self.name.strip_prefix(name).is_some_and(|rest| rest.starts_with('('))
+    }
+}
+
+#[derive(Default)]
+pub struct SymbolTable {
+    /// Function and data symbols, sorted by address
+    symbols: Vec<Symbol>,
+}
+
+impl SymbolTable {
+    pub fn from_object(object: &object::File) -> SymbolTable {
+        let mut symbols: Vec<Symbol> = object
+            .symbols()
+            .chain(object.dynamic_symbols())
+            .filter(|symbol| symbol.is_definition() && symbol.address() != 0)
+            .filter(|symbol| matches!(symbol.kind(), SymbolKind::Text | SymbolKind::Data))
+            .filter_map(|symbol| {
+                let mangled_name = symbol.name().ok()?.to_string();
+                let is_function = symbol.kind() == SymbolKind::Text;
+                let address = symbol.address() as usize;
+                let prologue_size = if is_function { prologue_size(object, &symbol) } else { 0 };
+                Some(Symbol {
+                    name: demangle(&mangled_name),
+                    mangled_name,
+                    address,
+                    size: symbol.size() as usize,
+                    is_function,
+                    after_prologue: address + prologue_size,
+                })
+            })
+            .collect();
+        // .dynsym repeats the exported symbols of .symtab
+        symbols.sort_by(|a, b| (a.address, &a.mangled_name).cmp(&(b.address, &b.mangled_name)));
+        symbols.dedup_by(|a, b| a.address == b.address && a.mangled_name == b.mangled_name);
+        SymbolTable { symbols }
+    }
+
+    /// Returns the function or data symbol with the given name.
+    pub fn get(&self, name: &str, is_function: bool) -> Option<&Symbol> {
+        self.symbols
+            .iter()
+            .find(|symbol| symbol.is_function == is_function && symbol.matches(name))
+    }
+
+    /// Returns the symbol whose code or data contains addr.
+    pub fn get_containing(&self, addr: usize) -> Option<&Symbol> {
+        let end = self.symbols.partition_point(|symbol| symbol.address <= addr);
+        let last = self.symbols.get(end.checked_sub(1)?)?;
+        // Several symbols may share an address, e.g. a function and its aliases
+        self.symbols[..end]
+            .iter()
+            .rev()
+            .take_while(|symbol| symbol.address == last.address)
+            .find(|symbol| addr < symbol.address + symbol.size.max(1))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Symbol> {
+        self.symbols.iter()
+    }
+}
+
+/// Returns the length of the frame pointer setup at the start of a function, so that breakpoints
+/// on functions without line tables stop where backtraces can find the caller's frame.
+fn prologue_size(object: &object::File, symbol: &object::Symbol) -> usize {
+    let code = symbol
+        .section_index()
+        .and_then(|index| object.section_by_index(index).ok())
+        .and_then(|section| {
+            let data = section.data().ok()?;
+            data.get((symbol.address() - section.address()) as usize..)
+        });
+    match code {
+        Some(code) => PROLOGUES
+            .iter()
+            .find(|prologue| code.starts_with(prologue))
+            .map_or(0, |prologue| prologue.len()),
+        None => 0,
+    }
+}
+
+/// Demangles a Rust or C++ symbol name, returning other names unchanged. Rust names lose their
+/// trailing hash.
+pub fn demangle(name: &str) -> String {
+    if let Ok(demangled) = rustc_demangle::try_demangle(name) {
+        return format!("{:#}", demangled);
+    }
+    if name.starts_with("_Z") {
+        if let Ok(symbol) = cpp_demangle::Symbol::new(name) {
+            if let Ok(demangled) = symbol.demangle(&Default::default()) {
+                return demangled;
+            }
+        }
+    }
+    name.to_string()
+}
@@ -0,0 +1,525 @@
+//! Implements `deet --trace-syscalls`, an strace-like mode that runs the target to completion and
+//! prints every syscall it makes, along with its decoded arguments, return value, how long it
+//! took, and the source line in the target that issued it.
+
+use crate::debugger::load_debug_data;
+use crate::dwarf_data::{DwarfData, Line};
+use crate::inferior::{Inferior, Status};
+use crate::unwind::{Registers, Unwinder};
+use nix::errno::Errno;
+use nix::sys::signal::Signal;
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+/// Maximum number of bytes of string and buffer arguments that get printed.
+const MAX_STRING_LEN: usize = 32;
+
+/// Number of frames unwound when looking for the call from the target's own code.
+const MAX_UNWIND_FRAMES: usize = 64;
+
+/// Describes how a syscall argument should be decoded.
+#[derive(Clone, Copy)]
+enum Arg {
+    /// Signed decimal integer
+    Int,
+    /// Unsigned decimal integer
+    Uint,
+    /// Flags, pointers and other values best shown in hex
+    Hex,
+    /// File mode bits
+    Oct,
+    /// A file descriptor that may be AT_FDCWD
+    Dirfd,
+    /// A NUL-terminated string
+    Str,
+    /// A buffer read by the kernel, whose length is given by the argument at the given index
+    InBuf(usize),
+    /// A buffer filled in by the kernel, whose length is given by the return value
+    OutBuf,
+}
+
+/// Returns the argument layout for the syscalls we know how to decode.
+fn syscall_args(name: &str) -> Option<&'static [Arg]> {
+    use Arg::*;
+    Some(match name {
+        "read" => &[Int, OutBuf, Uint],
+        "write" => &[Int, InBuf(2), Uint],
+        "pread64" => &[Int, OutBuf, Uint, Int],
+        "pwrite64" => &[Int, InBuf(2), Uint, Int],
+        "open" => &[Str, Hex, Oct],
+        "openat" => &[Dirfd, Str, Hex, Oct],
+        "close" | "dup" | "fsync" => &[Int],
+        "dup2" => &[Int, Int],
+        "dup3" => &[Int, Int, Hex],
+        "stat" | "lstat" => &[Str, Hex],
+        "fstat" => &[Int, Hex],
+        "newfstatat" => &[Dirfd, Str, Hex, Hex],
+        "statx" => &[Dirfd, Str, Hex, Hex, Hex],
+        "access" => &[Str, Oct],
+        "faccessat" | "faccessat2" => &[Dirfd, Str, Oct, Hex],
+        "lseek" => &[Int, Int, Int],
+        "mmap" => &[Hex, Uint, Hex, Hex, Int, Hex],
+        "mprotect" => &[Hex, Uint, Hex],
+        "munmap" => &[Hex, Uint],
+        "brk" | "set_tid_address" | "uname" | "pipe" => &[Hex],
+        "pipe2" => &[Hex, Hex],
+        "ioctl" | "fcntl" => &[Int, Hex, Hex],
+        "poll" => &[Hex, Uint, Int],
+        "readv" | "writev" => &[Int, Hex, Int],
+        "rt_sigaction" | "rt_sigprocmask" => &[Int, Hex, Hex, Uint],
+        "nanosleep" => &[Hex, Hex],
+        "clock_nanosleep" => &[Int, Int, Hex, Hex],
+        "exit" | "exit_group" => &[Int],
+        "getpid" | "getppid" | "gettid" | "getuid" | "geteuid" | "getgid" | "getegid" | "fork"
+        | "vfork" | "sched_yield" => &[],
+        "kill" => &[Int, Int],
+        "execve" => &[Str, Hex, Hex],
+        "wait4" => &[Int, Hex, Hex, Hex],
+        "getcwd" => &[Hex, Uint],
+        "chdir" | "rmdir" | "unlink" => &[Str],
+        "mkdir" => &[Str, Oct],
+        "readlink" => &[Str, OutBuf, Uint],
+        "getdents64" => &[Int, Hex, Uint],
+        "arch_prctl" => &[Hex, Hex],
+        "getrandom" => &[Hex, Uint, Hex],
+        "set_robust_list" => &[Hex, Uint],
+        "prlimit64" => &[Int, Int, Hex, Hex],
+        "rseq" => &[Hex, Uint, Hex, Hex],
+        "futex" => &[Hex, Int, Int, Hex, Hex, Int],
+        "clone" => &[Hex, Hex, Hex, Hex, Hex],
+        "socket" => &[Int, Int, Int],
+        "connect" | "bind" => &[Int, Hex, Uint],
+        _ => return None,
+    })
+}
+
+/// Returns the name of the x86_64 syscall with the given number.
+pub fn syscall_name(number: u64) -> Option<&'static str> {
+    SYSCALL_NAMES
+        .iter()
+        .find(|(nr, _)| *nr == number)
+        .map(|(_, name)| *name)
+}
+
+/// Returns true if name is a syscall that deet knows about.
+pub fn is_syscall_name(name: &str) -> bool {
+    SYSCALL_NAMES.iter().any(|(_, known)| *known == name)
+}
+
+/// A syscall whose entry has been observed but whose exit hasn't yet.
+struct PendingSyscall {
+    name: String,
+    args: Vec<u64>,
+    /// Arguments that could be decoded at entry (everything but OutBuf arguments)
+    decoded: Vec<Option<String>>,
+    line: Option<Line>,
+    start: Instant,
+}
+
+pub struct SyscallTracer {
+    inferior: Inferior,
+    debug_data: DwarfData,
+    /// If set, only syscalls with these names are printed
+    filter: Option<HashSet<String>>,
+    /// Finds the callers of libc's functions, which don't keep frame pointers
+    unwinder: Unwinder,
+}
+
+impl SyscallTracer {
+    /// Spawns target under ptrace, ready to be traced. Returns None if the process couldn't be
+    /// started.
+    pub fn new(target: &str, args: &Vec<String>, filter: Option<HashSet<String>>) -> Option<Self> {
+        let debug_data = load_debug_data(target);
+        let mut inferior = Inferior::new(target, args, &mut HashMap::new())?;
+        if let Err(err) = inferior.trace_syscalls() {
+            eprintln!("Error enabling syscall tracing: {}", err);
+            return None;
+        }
+        Some(SyscallTracer {
+            inferior,
+            debug_data,
+            filter,
+            unwinder: Unwinder::new(),
+        })
+    }
+
+    /// Runs the inferior to completion, printing each syscall to stderr. Returns the exit status
+    /// that deet should exit with (the inferior's own exit status where possible).
+    pub fn run(&mut self) -> i32 {
+        let mut pending: Option<PendingSyscall> = None;
+        let mut signal: Option<Signal> = None;
+        loop {
+            let status = match self.inferior.syscall(signal.take()) {
+                Ok(status) => status,
+                Err(err) => {
+                    eprintln!("Error tracing inferior: {}", err);
+                    return 1;
+                }
+            };
+            match status {
+                Status::Syscall(_) => {
+                    let regs = match self.inferior.getregs() {
+                        Ok(regs) => regs,
+                        Err(err) => {
+                            eprintln!("Error reading registers: {}", err);
+                            return 1;
+                        }
+                    };
+                    match pending.take() {
+                        None => pending = Some(self.syscall_entry(&regs)),
+                        Some(call) => self.syscall_exit(call, regs.rax as i64),
+                    }
+                }
+                Status::Stopped(sig, _) => {
+                    // The inferior received a signal; report it and pass it along
+                    eprintln!("--- {} ---", sig);
+                    signal = Some(sig);
+                }
+                Status::Exited(code) => {
+                    if let Some(call) = pending {
+                        self.print_unfinished(&call);
+                    }
+                    eprintln!("+++ exited with {} +++", code);
+                    return code;
+                }
+                Status::Signaled(sig) => {
+                    if let Some(call) = pending {
+                        self.print_unfinished(&call);
+                    }
+                    eprintln!("+++ killed by {} +++", sig);
+                    return 128 + sig as i32;
+                }
+            }
+        }
+    }
+
+    fn syscall_entry(&mut self, regs: &libc::user_regs_struct) -> PendingSyscall {
+        let name = match syscall_name(regs.orig_rax) {
+            Some(name) => name.to_string(),
+            None => format!("syscall_{}", regs.orig_rax),
+        };
+        let all_args = vec![regs.rdi, regs.rsi, regs.rdx, regs.r10, regs.r8, regs.r9];
+        let (args, decoded) = match syscall_args(&name) {
+            Some(layout) => {
+                let args = all_args[..layout.len()].to_vec();
+                let decoded = layout
+                    .iter()
+                    .zip(&args)
+                    .map(|(kind, value)| self.decode_arg(*kind, *value, &args))
+                    .collect();
+                (args, decoded)
+            }
+            None => {
+                let decoded = all_args.iter().map(|value| Some(format!("{:#x}", value))).collect();
+                (all_args, decoded)
+            }
+        };
+        PendingSyscall {
+            line: self.find_call_site(regs),
+            name,
+            args,
+            decoded,
+            start: Instant::now(),
+        }
+    }
+
+    fn syscall_exit(&self, call: PendingSyscall, ret: i64) {
+        let elapsed = call.start.elapsed();
+        if !self.should_print(&call.name) {
+            return;
+        }
+        let args = self.finish_args(&call, ret);
+        eprintln!(
+            "{}({}) = {} <{}>{}",
+            call.name,
+            args,
+            format_return(&call.name, ret),
+            format_duration(elapsed),
+            format_call_site(&call.line)
+        );
+    }
+
+    /// Prints a syscall that never returned (e.g. exit_group).
+    fn print_unfinished(&self, call: &PendingSyscall) {
+        if self.should_print(&call.name) {
+            eprintln!(
+                "{}({}) = ?{}",
+                call.name,
+                self.finish_args(call, 0),
+                format_call_site(&call.line)
+            );
+        }
+    }
+
+    fn should_print(&self, name: &str) -> bool {
+        match &self.filter {
+            Some(filter) => filter.contains(name),
+            None => true,
+        }
+    }
+
+    /// Decodes the arguments that could only be read once the syscall returned, and joins all of
+    /// them into a comma-separated list.
+    fn finish_args(&self, call: &PendingSyscall, ret: i64) -> String {
+        call.decoded
+            .iter()
+            .zip(&call.args)
+            .map(|(decoded, value)| match decoded {
+                Some(decoded) => decoded.clone(),
+                None if ret >= 0 => self.format_buffer(*value as usize, ret as usize),
+                None => format!("{:#x}", value),
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// Decodes a single argument at syscall entry. Returns None for arguments that can only be
+    /// decoded once the syscall returns.
+    fn decode_arg(&self, kind: Arg, value: u64, args: &[u64]) -> Option<String> {
+        Some(match kind {
+            Arg::Str => match self.inferior.read_cstring(value as usize, MAX_STRING_LEN) {
+                Ok((bytes, truncated)) => quote_bytes(&bytes, truncated),
+                Err(_) => format!("{:#x}", value),
+            },
+            Arg::InBuf(len_index) => self.format_buffer(value as usize, args[len_index] as usize),
+            _ => return decode_value(kind, value),
+        })
+    }
+
+    fn format_buffer(&self, addr: usize, len: usize) -> String {
+        match self.inferior.read_bytes(addr, len.min(MAX_STRING_LEN)) {
+            Ok(bytes) => quote_bytes(&bytes, len > MAX_STRING_LEN),
+            Err(_) => format!("{:#x}", addr),
+        }
+    }
+
+    /// Finds the source line in the target that issued the current syscall. Syscalls are usually
+    /// made from inside libc, which has no debugging symbols or frame pointers, so its frames are
+    /// unwound with their call frame information until one returns into the target's own code.
+    fn find_call_site(&mut self, regs: &libc::user_regs_struct) -> Option<Line> {
+        let mut regs = Registers {
+            rip: regs.rip as usize,
+            rsp: regs.rsp as usize,
+            rbp: regs.rbp as usize,
+        };
+        for depth in 0..MAX_UNWIND_FRAMES {
+            // Return addresses are looked up by the call instruction before them, so that calls
+            // at the end of a line are attributed to that line rather than the next one
+            let pc = if depth == 0 { regs.rip } else { regs.rip.checked_sub(1)? };
+            if self.debug_data.contains_addr(pc) {
+                return self.debug_data.get_line_from_addr(pc);
+            }
+            regs = self.unwinder.caller(&self.inferior, regs, depth == 0)?;
+        }
+        None
+    }
+}
+
+/// Decodes an argument that doesn't point into the inferior's memory. Returns None for those
+/// that do.
+fn decode_value(kind: Arg, value: u64) -> Option<String> {
+    Some(match kind {
+        Arg::Int => (value as i64 as i32).to_string(),
+        Arg::Uint => value.to_string(),
+        Arg::Hex => format!("{:#x}", value),
+        Arg::Oct if value == 0 => "0".to_string(),
+        Arg::Oct => format!("0{:o}", value),
+        Arg::Dirfd if value as i32 == libc::AT_FDCWD => "AT_FDCWD".to_string(),
+        Arg::Dirfd => (value as i32).to_string(),
+        Arg::Str | Arg::InBuf(_) | Arg::OutBuf => return None,
+    })
+}
+
+/// Formats a syscall's return value, decoding errors like strace does.
+fn format_return(name: &str, ret: i64) -> String {
+    if (-4095..0).contains(&ret) {
+        let errno = Errno::from_i32(-ret as i32);
+        format!("-1 {:?} ({})", errno, errno.desc())
+    } else if matches!(name, "mmap" | "brk" | "mremap") {
+        format!("{:#x}", ret)
+    } else {
+        ret.to_string()
+    }
+}
+
+fn format_duration(duration: Duration) -> String {
+    format!("{}.{:06}", duration.as_secs(), duration.subsec_micros())
+}
+
+fn format_call_site(line: &Option<Line>) -> String {
+    match line {
+        Some(line) => format!(" at {}", line),
+        None => String::new(),
+    }
+}
+
+/// Formats bytes as a quoted C string literal, escaping anything unprintable.
+fn quote_bytes(bytes: &[u8], truncated: bool) -> String {
+    let mut quoted = String::from("\"");
+    for &byte in bytes {
+        match byte {
+            b'\n' => quoted.push_str("\\n"),
+            b'\t' => quoted.push_str("\\t"),
+            b'\r' => quoted.push_str("\\r"),
+            b'"' => quoted.push_str("\\\""),
+            b'\\' => quoted.push_str("\\\\"),
+            0x20..=0x7e => quoted.push(byte as char),
+            _ => quoted.push_str(&format!("\\x{:02x}", byte)),
+        }
+    }
+    quoted.push('"');
+    if truncated {
+        quoted.push_str("...");
+    }
+    quoted
+}
+
+const SYSCALL_NAMES: [(u64, &str); 362] = [
+    (0, "read"), (1, "write"), (2, "open"), (3, "close"), (4, "stat"), (5, "fstat"), (6, "lstat"),
+    (7, "poll"), (8, "lseek"), (9, "mmap"), (10, "mprotect"), (11, "munmap"), (12, "brk"),
+    (13, "rt_sigaction"), (14, "rt_sigprocmask"), (15, "rt_sigreturn"), (16, "ioctl"),
+    (17, "pread64"), (18, "pwrite64"), (19, "readv"), (20, "writev"), (21, "access"), (22, "pipe"),
+    (23, "select"), (24, "sched_yield"), (25, "mremap"), (26, "msync"), (27, "mincore"),
+    (28, "madvise"), (29, "shmget"), (30, "shmat"), (31, "shmctl"), (32, "dup"), (33, "dup2"),
+    (34, "pause"), (35, "nanosleep"), (36, "getitimer"), (37, "alarm"), (38, "setitimer"),
+    (39, "getpid"), (40, "sendfile"), (41, "socket"), (42, "connect"), (43, "accept"),
+    (44, "sendto"), (45, "recvfrom"), (46, "sendmsg"), (47, "recvmsg"), (48, "shutdown"),
+    (49, "bind"), (50, "listen"), (51, "getsockname"), (52, "getpeername"), (53, "socketpair"),
+    (54, "setsockopt"), (55, "getsockopt"), (56, "clone"), (57, "fork"), (58, "vfork"),
+    (59, "execve"), (60, "exit"), (61, "wait4"), (62, "kill"), (63, "uname"), (64, "semget"),
+    (65, "semop"), (66, "semctl"), (67, "shmdt"), (68, "msgget"), (69, "msgsnd"), (70, "msgrcv"),
+    (71, "msgctl"), (72, "fcntl"), (73, "flock"), (74, "fsync"), (75, "fdatasync"),
+    (76, "truncate"), (77, "ftruncate"), (78, "getdents"), (79, "getcwd"), (80, "chdir"),
+    (81, "fchdir"), (82, "rename"), (83, "mkdir"), (84, "rmdir"), (85, "creat"), (86, "link"),
+    (87, "unlink"), (88, "symlink"), (89, "readlink"), (90, "chmod"), (91, "fchmod"), (92, "chown"),
+    (93, "fchown"), (94, "lchown"), (95, "umask"), (96, "gettimeofday"), (97, "getrlimit"),
+    (98, "getrusage"), (99, "sysinfo"), (100, "times"), (101, "ptrace"), (102, "getuid"),
+    (103, "syslog"), (104, "getgid"), (105, "setuid"), (106, "setgid"), (107, "geteuid"),
+    (108, "getegid"), (109, "setpgid"), (110, "getppid"), (111, "getpgrp"), (112, "setsid"),
+    (113, "setreuid"), (114, "setregid"), (115, "getgroups"), (116, "setgroups"),
+    (117, "setresuid"), (118, "getresuid"), (119, "setresgid"), (120, "getresgid"),
+    (121, "getpgid"), (122, "setfsuid"), (123, "setfsgid"), (124, "getsid"), (125, "capget"),
+    (126, "capset"), (127, "rt_sigpending"), (128, "rt_sigtimedwait"), (129, "rt_sigqueueinfo"),
+    (130, "rt_sigsuspend"), (131, "sigaltstack"), (132, "utime"), (133, "mknod"), (134, "uselib"),
+    (135, "personality"), (136, "ustat"), (137, "statfs"), (138, "fstatfs"), (139, "sysfs"),
+    (140, "getpriority"), (141, "setpriority"), (142, "sched_setparam"), (143, "sched_getparam"),
+    (144, "sched_setscheduler"), (145, "sched_getscheduler"), (146, "sched_get_priority_max"),
+    (147, "sched_get_priority_min"), (148, "sched_rr_get_interval"), (149, "mlock"),
+    (150, "munlock"), (151, "mlockall"), (152, "munlockall"), (153, "vhangup"), (154, "modify_ldt"),
+    (155, "pivot_root"), (156, "_sysctl"), (157, "prctl"), (158, "arch_prctl"), (159, "adjtimex"),
+    (160, "setrlimit"), (161, "chroot"), (162, "sync"), (163, "acct"), (164, "settimeofday"),
+    (165, "mount"), (166, "umount2"), (167, "swapon"), (168, "swapoff"), (169, "reboot"),
+    (170, "sethostname"), (171, "setdomainname"), (172, "iopl"), (173, "ioperm"),
+    (174, "create_module"), (175, "init_module"), (176, "delete_module"), (177, "get_kernel_syms"),
+    (178, "query_module"), (179, "quotactl"), (180, "nfsservctl"), (181, "getpmsg"),
+    (182, "putpmsg"), (183, "afs_syscall"), (184, "tuxcall"), (185, "security"), (186, "gettid"),
+    (187, "readahead"), (188, "setxattr"), (189, "lsetxattr"), (190, "fsetxattr"),
+    (191, "getxattr"), (192, "lgetxattr"), (193, "fgetxattr"), (194, "listxattr"),
+    (195, "llistxattr"), (196, "flistxattr"), (197, "removexattr"), (198, "lremovexattr"),
+    (199, "fremovexattr"), (200, "tkill"), (201, "time"), (202, "futex"),
+    (203, "sched_setaffinity"), (204, "sched_getaffinity"), (205, "set_thread_area"),
+    (206, "io_setup"), (207, "io_destroy"), (208, "io_getevents"), (209, "io_submit"),
+    (210, "io_cancel"), (211, "get_thread_area"), (212, "lookup_dcookie"), (213, "epoll_create"),
+    (214, "epoll_ctl_old"), (215, "epoll_wait_old"), (216, "remap_file_pages"), (217, "getdents64"),
+    (218, "set_tid_address"), (219, "restart_syscall"), (220, "semtimedop"), (221, "fadvise64"),
+    (222, "timer_create"), (223, "timer_settime"), (224, "timer_gettime"),
+    (225, "timer_getoverrun"), (226, "timer_delete"), (227, "clock_settime"),
+    (228, "clock_gettime"), (229, "clock_getres"), (230, "clock_nanosleep"), (231, "exit_group"),
+    (232, "epoll_wait"), (233, "epoll_ctl"), (234, "tgkill"), (235, "utimes"), (236, "vserver"),
+    (237, "mbind"), (238, "set_mempolicy"), (239, "get_mempolicy"), (240, "mq_open"),
+    (241, "mq_unlink"), (242, "mq_timedsend"), (243, "mq_timedreceive"), (244, "mq_notify"),
+    (245, "mq_getsetattr"), (246, "kexec_load"), (247, "waitid"), (248, "add_key"),
+    (249, "request_key"), (250, "keyctl"), (251, "ioprio_set"), (252, "ioprio_get"),
+    (253, "inotify_init"), (254, "inotify_add_watch"), (255, "inotify_rm_watch"),
+    (256, "migrate_pages"), (257, "openat"), (258, "mkdirat"), (259, "mknodat"), (260, "fchownat"),
+    (261, "futimesat"), (262, "newfstatat"), (263, "unlinkat"), (264, "renameat"), (265, "linkat"),
+    (266, "symlinkat"), (267, "readlinkat"), (268, "fchmodat"), (269, "faccessat"),
+    (270, "pselect6"), (271, "ppoll"), (272, "unshare"), (273, "set_robust_list"),
+    (274, "get_robust_list"), (275, "splice"), (276, "tee"), (277, "sync_file_range"),
+    (278, "vmsplice"), (279, "move_pages"), (280, "utimensat"), (281, "epoll_pwait"),
+    (282, "signalfd"), (283, "timerfd_create"), (284, "eventfd"), (285, "fallocate"),
+    (286, "timerfd_settime"), (287, "timerfd_gettime"), (288, "accept4"), (289, "signalfd4"),
+    (290, "eventfd2"), (291, "epoll_create1"), (292, "dup3"), (293, "pipe2"),
+    (294, "inotify_init1"), (295, "preadv"), (296, "pwritev"), (297, "rt_tgsigqueueinfo"),
+    (298, "perf_event_open"), (299, "recvmmsg"), (300, "fanotify_init"), (301, "fanotify_mark"),
+    (302, "prlimit64"), (303, "name_to_handle_at"), (304, "open_by_handle_at"),
+    (305, "clock_adjtime"), (306, "syncfs"), (307, "sendmmsg"), (308, "setns"), (309, "getcpu"),
+    (310, "process_vm_readv"), (311, "process_vm_writev"), (312, "kcmp"), (313, "finit_module"),
+    (314, "sched_setattr"), (315, "sched_getattr"), (316, "renameat2"), (317, "seccomp"),
+    (318, "getrandom"), (319, "memfd_create"), (320, "kexec_file_load"), (321, "bpf"),
+    (322, "execveat"), (323, "userfaultfd"), (324, "membarrier"), (325, "mlock2"),
+    (326, "copy_file_range"), (327, "preadv2"), (328, "pwritev2"), (329, "pkey_mprotect"),
+    (330, "pkey_alloc"), (331, "pkey_free"), (332, "statx"), (333, "io_pgetevents"), (334, "rseq"),
+    (424, "pidfd_send_signal"), (425, "io_uring_setup"), (426, "io_uring_enter"),
+    (427, "io_uring_register"), (428, "open_tree"), (429, "move_mount"), (430, "fsopen"),
+    (431, "fsconfig"), (432, "fsmount"), (433, "fspick"), (434, "pidfd_open"), (435, "clone3"),
+    (436, "close_range"), (437, "openat2"), (438, "pidfd_getfd"), (439, "faccessat2"),
+    (440, "process_madvise"), (441, "epoll_pwait2"), (442, "mount_setattr"), (443, "quotactl_fd"),
+    (444, "landlock_create_ruleset"), (445, "landlock_add_rule"), (446, "landlock_restrict_self"),
+    (447, "memfd_secret"), (448, "process_mrelease"), (449, "futex_waitv"),
+    (450, "set_mempolicy_home_node"),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn syscall_numbers_map_to_names() {
+        assert_eq!(syscall_name(0), Some("read"));
+        assert_eq!(syscall_name(231), Some("exit_group"));
+        assert_eq!(syscall_name(450), Some("set_mempolicy_home_node"));
+        assert_eq!(syscall_name(100_000), None);
+        assert!(is_syscall_name("openat"));
+        assert!(!is_syscall_name("printf"));
+    }
+
+    #[test]
+    fn every_decoded_syscall_exists_and_takes_at_most_six_arguments() {
+        for (_, name) in SYSCALL_NAMES.iter() {
+            if let Some(layout) = syscall_args(name) {
+                assert!(layout.len() <= 6, "{} has {} arguments", name, layout.len());
+                for arg in layout {
+                    if let Arg::InBuf(len_index) = arg {
+                        assert!(*len_index < layout.len(), "{}'s buffer length is missing", name);
+                    }
+                }
+            }
+        }
+        assert!(syscall_args("write").is_some());
+        assert!(syscall_args("syscall_1000").is_none());
+    }
+
+    #[test]
+    fn values_are_decoded_by_kind() {
+        assert_eq!(decode_value(Arg::Int, -1i64 as u64).as_deref(), Some("-1"));
+        // Only the low 32 bits of an int argument count
+        assert_eq!(decode_value(Arg::Int, 0x1_0000_0003).as_deref(), Some("3"));
+        assert_eq!(decode_value(Arg::Uint, 4096).as_deref(), Some("4096"));
+        assert_eq!(decode_value(Arg::Hex, 0x7f00_1000).as_deref(), Some("0x7f001000"));
+        assert_eq!(decode_value(Arg::Oct, 0o644).as_deref(), Some("0644"));
+        assert_eq!(decode_value(Arg::Oct, 0).as_deref(), Some("0"));
+        let at_fdcwd = libc::AT_FDCWD as i64 as u64;
+        assert_eq!(decode_value(Arg::Dirfd, at_fdcwd).as_deref(), Some("AT_FDCWD"));
+        assert_eq!(decode_value(Arg::Dirfd, 3).as_deref(), Some("3"));
+        assert_eq!(decode_value(Arg::Str, 0x1000), None);
+        assert_eq!(decode_value(Arg::InBuf(2), 0x1000), None);
+        assert_eq!(decode_value(Arg::OutBuf, 0x1000), None);
+    }
+
+    #[test]
+    fn return_values_decode_errors() {
+        assert_eq!(format_return("read", 12), "12");
+        assert_eq!(format_return("openat", -2), "-1 ENOENT (No such file or directory)");
+        assert_eq!(format_return("mmap", 0x7f00_0000_0000), "0x7f0000000000");
+        assert_eq!(format_return("brk", -12), "-1 ENOMEM (Out of memory)");
+    }
+
+    #[test]
+    fn buffers_are_quoted_as_c_strings() {
+        assert_eq!(quote_bytes(b"hi\n", false), "\"hi\\n\"");
+        assert_eq!(quote_bytes(b"a\"b\\\t\r", false), "\"a\\\"b\\\\\\t\\r\"");
+        assert_eq!(quote_bytes(&[0x7f, 0, b'x'], false), "\"\\x7f\\x00x\"");
+        assert_eq!(quote_bytes(b"abc", true), "\"abc\"...");
+    }
+}
@@ -0,0 +1,239 @@
+//! A text user interface like `gdb -tui`. Panes showing the source, disassembly, registers and
+//! local variables where the inferior is stopped are drawn at the top of the terminal, and the
+//! rows below them are set up as a scrolling region for the command prompt and its output.
+
+use iced_x86::{Decoder, DecoderOptions, Formatter, GasFormatter, Instruction};
+use std::fs;
+use std::io::{self, Write};
+
+/// The fewest rows the command area gets.
+const MIN_COMMAND_ROWS: usize = 6;
+
+/// A box of text with a title. The highlighted line is drawn in reverse video.
+#[derive(Default)]
+pub struct Pane {
+    pub title: String,
+    pub lines: Vec<String>,
+    pub highlight: Option<usize>,
+}
+
+impl Pane {
+    pub fn new(title: &str, lines: Vec<String>) -> Self {
+        Pane {
+            title: title.to_string(),
+            lines,
+            highlight: None,
+        }
+    }
+}
+
+/// What the panes show, filled in by the debugger each time the inferior stops.
+#[derive(Default)]
+pub struct Panes {
+    pub source: Pane,
+    pub disassembly: Pane,
+    pub registers: Pane,
+    pub locals: Pane,
+}
+
+/// The terminal layout, which is recomputed whenever the window's size changes.
+pub struct Tui {
+    rows: usize,
+    cols: usize,
+}
+
+/// Returns the number of rows and columns of the terminal on stdout.
+fn window_size() -> Option<(usize, usize)> {
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    if unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } != 0 {
+        return None;
+    }
+    if size.ws_row == 0 || size.ws_col == 0 {
+        return None;
+    }
+    Some((size.ws_row as usize, size.ws_col as usize))
+}
+
+impl Tui {
+    /// Takes over the terminal, or returns None if stdout isn't one.
+    pub fn enable() -> Option<Tui> {
+        let (rows, cols) = window_size()?;
+        let tui = Tui { rows, cols };
+        tui.set_up_screen();
+        Some(tui)
+    }
+
+    /// Gives the whole terminal back to the command line.
+    pub fn disable(&self) {
+        print!("\x1b[r\x1b[2J\x1b[H");
+        let _ = io::stdout().flush();
+    }
+
+    /// How many rows the panes take up.
+    pub fn pane_rows(&self) -> usize {
+        self.rows.saturating_sub(MIN_COMMAND_ROWS.max(self.rows / 3))
+    }
+
+    /// The number of lines that fit in each of the source and disassembly panes.
+    pub fn code_lines(&self) -> usize {
+        (self.pane_rows() / 2).saturating_sub(2)
+    }
+
+    /// The width of the registers and locals panes.
+    pub fn side_cols(&self) -> usize {
+        self.cols - self.cols * 2 / 3
+    }
+
+    /// Clears the screen and confines scrolling to the command area, leaving the cursor there.
+    fn set_up_screen(&self) {
+        print!(
+            "\x1b[r\x1b[2J\x1b[{};{}r\x1b[{};1H",
+            self.pane_rows() + 1,
+            self.rows,
+            self.rows
+        );
+        let _ = io::stdout().flush();
+    }
+
+    /// Redraws the panes, without moving the cursor from the command area.
+    pub fn draw(&mut self, panes: &Panes) {
+        if let Some((rows, cols)) = window_size() {
+            if (rows, cols) != (self.rows, self.cols) {
+                self.rows = rows;
+                self.cols = cols;
+                self.set_up_screen();
+            }
+        }
+        let pane_rows = self.pane_rows();
+        let left_cols = self.cols * 2 / 3;
+        let right_cols = self.cols - left_cols;
+        let upper_rows = pane_rows / 2;
+        let lower_rows = pane_rows - upper_rows;
+        let mut out = String::from("\x1b7");
+        draw_pane(&mut out, 1, 1, upper_rows, left_cols, &panes.source);
+        draw_pane(&mut out, upper_rows + 1, 1, lower_rows, left_cols, &panes.disassembly);
+        draw_pane(&mut out, 1, left_cols + 1, upper_rows, right_cols, &panes.registers);
+        draw_pane(&mut out, upper_rows + 1, left_cols + 1, lower_rows, right_cols, &panes.locals);
+        out.push_str("\x1b8");
+        print!("{}", out);
+        let _ = io::stdout().flush();
+    }
+}
+
+/// Truncates or pads text to exactly width characters.
+fn fit(text: &str, width: usize) -> String {
+    let text: String = text.chars().map(|c| if c == '\t' { ' ' } else { c }).take(width).collect();
+    format!("{:<width$}", text, width = width)
+}
+
+/// Appends the escape codes drawing pane in a box with its top left corner at (row, col).
+fn draw_pane(out: &mut String, row: usize, col: usize, height: usize, width: usize, pane: &Pane) {
+    if height < 2 || width < 2 {
+        return;
+    }
+    let inner = width - 2;
+    let title = fit(&format!(" {} ", pane.title), inner.min(pane.title.chars().count() + 2));
+    let rule = "─".repeat(inner - title.chars().count());
+    out.push_str(&format!("\x1b[{};{}H┌{}{}┐", row, col, title, rule));
+    // Scroll so that the highlighted line is in the middle
+    let visible = height - 2;
+    let last_first = pane.lines.len().saturating_sub(visible);
+    let first = match pane.highlight {
+        Some(line) => line.saturating_sub(visible / 2).min(last_first),
+        None => 0,
+    };
+    for i in 0..visible {
+        let text = pane.lines.get(first + i).map(String::as_str).unwrap_or("");
+        out.push_str(&format!("\x1b[{};{}H│", row + 1 + i, col));
+        if pane.highlight == Some(first + i) {
+            out.push_str(&format!("\x1b[7m{}\x1b[0m", fit(text, inner)));
+        } else {
+            out.push_str(&fit(text, inner));
+        }
+        out.push('│');
+    }
+    out.push_str(&format!("\x1b[{};{}H└{}┘", row + height - 1, col, "─".repeat(inner)));
+}
+
+/// Shows count lines of a source file around line center. The line the inferior is stopped at,
+/// if any, is highlighted, and lines with breakpoints are marked with "b".
+pub fn source_pane(
+    file: &str,
+    center: usize,
+    current: Option<usize>,
+    breakpoint_lines: &[usize],
+    count: usize,
+) -> Pane {
+    let contents = match fs::read_to_string(file) {
+        Ok(contents) => contents,
+        Err(err) => return Pane::new(file, vec![format!("{}: {}", file, err)]),
+    };
+    let first = center.saturating_sub(count / 2).max(1);
+    let lines = contents
+        .lines()
+        .enumerate()
+        .skip(first - 1)
+        .take(count)
+        .map(|(i, text)| {
+            let number = i + 1;
+            let marker = if breakpoint_lines.contains(&number) { 'b' } else { ' ' };
+            let arrow = if Some(number) == current { '>' } else { ' ' };
+            format!("{}{}{:>4} {}", marker, arrow, number, text)
+        })
+        .collect();
+    Pane {
+        title: file.to_string(),
+        lines,
+        highlight: current.and_then(|current| current.checked_sub(first)),
+    }
+}
+
+/// Disassembles code, which was read from address start, highlighting the instruction at pc.
+/// Instructions are labeled with their offset from the start of function, if given.
+pub fn disassembly_pane(code: &[u8], start: usize, pc: usize, function: Option<&str>) -> Pane {
+    let mut decoder = Decoder::with_ip(64, code, start as u64, DecoderOptions::NONE);
+    let mut formatter = GasFormatter::new();
+    let mut instruction = Instruction::default();
+    let mut lines = Vec::new();
+    let mut highlight = None;
+    while decoder.can_decode() {
+        decoder.decode_out(&mut instruction);
+        let addr = instruction.ip() as usize;
+        let mut text = String::new();
+        formatter.format(&instruction, &mut text);
+        let label = match function {
+            Some(name) => format!(" <{}+{}>", name, addr - start),
+            None => String::new(),
+        };
+        if addr == pc {
+            highlight = Some(lines.len());
+        }
+        let arrow = if addr == pc { '>' } else { ' ' };
+        lines.push(format!("{} {:#x}{}  {}", arrow, addr, label, text));
+    }
+    Pane {
+        title: "disassembly".to_string(),
+        lines,
+        highlight,
+    }
+}
+
+/// Shows the general purpose registers in as many columns as fit in width.
+pub fn registers_pane(regs: &libc::user_regs_struct, width: usize) -> Pane {
+    let values = [
+        ("rax", regs.rax), ("rbx", regs.rbx), ("rcx", regs.rcx), ("rdx", regs.rdx),
+        ("rsi", regs.rsi), ("rdi", regs.rdi), ("rbp", regs.rbp), ("rsp", regs.rsp),
+        ("r8", regs.r8), ("r9", regs.r9), ("r10", regs.r10), ("r11", regs.r11),
+        ("r12", regs.r12), ("r13", regs.r13), ("r14", regs.r14), ("r15", regs.r15),
+        ("rip", regs.rip), ("eflags", regs.eflags),
+    ];
+    let cells: Vec<String> =
+        values.iter().map(|(name, value)| format!("{:<3} {:#x}", name, value)).collect();
+    let cell_width = cells.iter().map(String::len).max().unwrap_or(0) + 2;
+    let columns = (width.saturating_sub(2) / cell_width).max(1);
+    let lines = cells
+        .chunks(columns)
+        .map(|row| row.iter().map(|cell| format!("{:<1$}", cell, cell_width)).collect())
+        .collect();
+    Pane::new("registers", lines)
+}
@@ -0,0 +1,159 @@
+//! Unwinds the stack through code that doesn't keep frame pointers, such as libc's, using the call
+//! frame information in the .eh_frame section of the file the code was loaded from.
+
+use crate::gimli_wrapper::DwarfReader;
+use crate::inferior::Inferior;
+use crate::procfs::{self, MemoryMapping};
+use gimli::{
+    BaseAddresses, CfaRule, CieOrFde, EhFrame, FrameDescriptionEntry, RegisterRule,
+    UnwindContext, UnwindSection, X86_64,
+};
+use object::{Object, ObjectSection, ObjectSegment};
+use std::collections::HashMap;
+use std::fs;
+use std::sync::Arc;
+
+/// The registers it takes to find a frame's caller.
+#[derive(Clone, Copy, Debug)]
+pub struct Registers {
+    pub rip: usize,
+    pub rsp: usize,
+    pub rbp: usize,
+}
+
+/// The call frame information of one file that is loaded into the inferior.
+struct FrameTable {
+    eh_frame: EhFrame<DwarfReader>,
+    bases: BaseAddresses,
+    /// Added to an address in the file to get where it is loaded
+    bias: usize,
+    /// Sorted by the address of the code they describe
+    fdes: Vec<FrameDescriptionEntry<DwarfReader>>,
+}
+
+impl FrameTable {
+    /// Reads the .eh_frame of the file mapped at mapping.
+    fn load(mapping: &MemoryMapping) -> Option<FrameTable> {
+        let data = fs::read(&mapping.path).ok()?;
+        let object = object::File::parse(&*data).ok()?;
+        let endian = if object.is_little_endian() {
+            gimli::RunTimeEndian::Little
+        } else {
+            gimli::RunTimeEndian::Big
+        };
+        let section = object.section_by_name(".eh_frame")?;
+        let bytes: Arc<[u8]> = Arc::from(section.data().ok()?);
+        let eh_frame = EhFrame::from(DwarfReader::new(bytes, endian));
+        let mut bases = BaseAddresses::default().set_eh_frame(section.address());
+        if let Some(text) = object.section_by_name(".text") {
+            bases = bases.set_text(text.address());
+        }
+        // The segment that the mapping comes from says where the file expected to be loaded
+        let offset = mapping.offset as u64;
+        let segment = object.segments().find(|segment| {
+            let (start, size) = segment.file_range();
+            start <= offset && offset < start + size
+        })?;
+        let file_address = segment.address() + offset - segment.file_range().0;
+        let bias = mapping.start.wrapping_sub(file_address as usize);
+
+        let mut fdes = Vec::new();
+        let mut entries = eh_frame.entries(&bases);
+        while let Ok(Some(entry)) = entries.next() {
+            if let CieOrFde::Fde(partial) = entry {
+                if let Ok(fde) = partial.parse(EhFrame::cie_from_offset) {
+                    fdes.push(fde);
+                }
+            }
+        }
+        fdes.sort_by_key(|fde| fde.initial_address());
+        Some(FrameTable {
+            eh_frame,
+            bases,
+            bias,
+            fdes,
+        })
+    }
+
+    fn fde_containing(&self, addr: u64) -> Option<&FrameDescriptionEntry<DwarfReader>> {
+        let end = self.fdes.partition_point(|fde| fde.initial_address() <= addr);
+        let fde = &self.fdes[end.checked_sub(1)?];
+        (addr < fde.initial_address() + fde.len()).then_some(fde)
+    }
+}
+
+/// Finds the callers of frames in the files loaded into an inferior, loading their call frame
+/// information as it's needed.
+pub struct Unwinder {
+    maps: Vec<MemoryMapping>,
+    /// By path, or None for files without usable call frame information
+    tables: HashMap<String, Option<FrameTable>>,
+    context: Box<UnwindContext<DwarfReader>>,
+}
+
+impl Unwinder {
+    pub fn new() -> Unwinder {
+        Unwinder {
+            maps: Vec::new(),
+            tables: HashMap::new(),
+            context: Box::new(UnwindContext::new()),
+        }
+    }
+
+    /// Returns the registers of the caller of the frame with regs, or None if the code it's in
+    /// has no call frame information, or the frame can't be unwound. The innermost frame is
+    /// looked up at %rip; its callers by the byte before their return address, which is still
+    /// part of the call instruction.
+    pub fn caller(
+        &mut self,
+        inferior: &Inferior,
+        regs: Registers,
+        innermost: bool,
+    ) -> Option<Registers> {
+        let pc = if innermost { regs.rip } else { regs.rip.checked_sub(1)? };
+        if !self.maps.iter().any(|mapping| mapping.contains(pc)) {
+            // Libraries are loaded as the inferior runs
+            self.maps = procfs::read_maps(inferior.pid()).ok()?;
+        }
+        let mapping = self.maps.iter().find(|mapping| mapping.contains(pc))?;
+        if !mapping.path.starts_with('/') {
+            return None;
+        }
+        let table = self
+            .tables
+            .entry(mapping.path.clone())
+            .or_insert_with(|| FrameTable::load(mapping))
+            .as_ref()?;
+        let addr = pc.wrapping_sub(table.bias) as u64;
+        let fde = table.fde_containing(addr)?;
+        let row = fde
+            .unwind_info_for_address(&table.eh_frame, &table.bases, &mut self.context, addr)
+            .ok()?;
+        let cfa = match row.cfa() {
+            CfaRule::RegisterAndOffset { register, offset } => {
+                let base = match *register {
+                    X86_64::RSP => regs.rsp,
+                    X86_64::RBP => regs.rbp,
+                    _ => return None,
+                };
+                (base as i64 + offset) as usize
+            }
+            CfaRule::Expression(_) => return None,
+        };
+        let restore = |rule: RegisterRule<DwarfReader>, value: usize| match rule {
+            RegisterRule::Undefined | RegisterRule::SameValue => Some(value),
+            RegisterRule::Offset(offset) => inferior.read_word((cfa as i64 + offset) as usize).ok(),
+            RegisterRule::ValOffset(offset) => Some((cfa as i64 + offset) as usize),
+            _ => None,
+        };
+        let rip = match row.register(X86_64::RA) {
+            RegisterRule::Undefined => return None,
+            rule => restore(rule, regs.rip)?,
+        };
+        Some(Registers {
+            rip,
+            rsp: cfa,
+            rbp: restore(row.register(X86_64::RBP), regs.rbp)?,
+        })
+    }
+}
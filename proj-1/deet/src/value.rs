@@ -0,0 +1,361 @@
+//! Values read out of the inferior's memory, and printf-style formatting of them for dprintf.
+
+use crate::dwarf_data::{Encoding, Type, TypeKind};
+use std::fmt;
+
+/// A value of any type, along with where it lives in the inferior's memory (if it's an lvalue).
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypedValue {
+    pub value_type: Type,
+    pub bytes: Vec<u8>,
+    pub address: Option<usize>,
+}
+
+impl TypedValue {
+    /// Creates a temporary (non-lvalue) of the given scalar type.
+    pub fn from_scalar(value: Value, value_type: Type) -> TypedValue {
+        let bytes = match value_type.kind {
+            TypeKind::Base(Encoding::Float) if value_type.size == 4 => {
+                (value.as_f64() as f32).to_bits().to_le_bytes().to_vec()
+            }
+            TypeKind::Base(Encoding::Float) => value.as_f64().to_bits().to_le_bytes().to_vec(),
+            _ => value.as_u64().to_le_bytes()[..value_type.size.min(8)].to_vec(),
+        };
+        TypedValue {
+            value_type,
+            bytes,
+            address: None,
+        }
+    }
+
+    /// Interprets the value as a scalar. Only meaningful if value_type.is_scalar().
+    pub fn scalar(&self) -> Value {
+        Value::from_bytes(&self.bytes, &self.value_type)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value {
+    Int(i64),
+    Uint(u64),
+    Float(f64),
+    Char(u8),
+}
+
+impl Value {
+    /// Decodes a value of the given scalar type from its little-endian in-memory representation.
+    pub fn from_bytes(bytes: &[u8], entity_type: &Type) -> Value {
+        let mut raw = [0u8; 8];
+        let len = bytes.len().min(8);
+        raw[..len].copy_from_slice(&bytes[..len]);
+        let unsigned = u64::from_le_bytes(raw);
+        match entity_type.kind {
+            TypeKind::Base(Encoding::Float) if len == 4 => {
+                Value::Float(f32::from_bits(unsigned as u32) as f64)
+            }
+            TypeKind::Base(Encoding::Float) => Value::Float(f64::from_bits(unsigned)),
+            TypeKind::Base(Encoding::SignedChar | Encoding::UnsignedChar) if len == 1 => {
+                Value::Char(raw[0])
+            }
+            TypeKind::Base(Encoding::Unsigned | Encoding::Bool | Encoding::Utf)
+            | TypeKind::Pointer(_) => {
+                Value::Uint(unsigned)
+            }
+            _ => {
+                // Sign-extend from the value's actual width
+                let shift = 64 - 8 * len.max(1) as u32;
+                Value::Int(((unsigned << shift) as i64) >> shift)
+            }
+        }
+    }
+
+    /// Returns the value's bits as a 64-bit integer, as a C vararg would pass them.
+    pub fn as_u64(&self) -> u64 {
+        match *self {
+            Value::Int(val) => val as u64,
+            Value::Uint(val) => val,
+            Value::Float(val) => val as i64 as u64,
+            Value::Char(val) => val as u64,
+        }
+    }
+
+    pub fn as_f64(&self) -> f64 {
+        match *self {
+            Value::Int(val) => val as f64,
+            Value::Uint(val) => val as f64,
+            Value::Float(val) => val,
+            Value::Char(val) => val as f64,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Value::Int(val) => write!(f, "{}", val),
+            Value::Uint(val) => write!(f, "{}", val),
+            Value::Float(val) => write!(f, "{}", val),
+            Value::Char(val) => write!(f, "{} '{}'", val, (val as char).escape_default()),
+        }
+    }
+}
+
+/// Formats args according to a C printf-style format string. Supports the d, i, u, x, X, o, c,
+/// s, p, f, F, e, E, g, G and % conversions, with flags, width, precision and length modifiers.
+/// read_string is used to fetch the strings that %s arguments point to.
+pub fn format_printf<F>(format: &str, args: &[Value], read_string: F) -> Result<String, String>
+where
+    F: Fn(usize) -> Option<String>,
+{
+    let mut out = String::new();
+    let mut args = args.iter();
+    let mut chars = format.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        if chars.peek() == Some(&'%') {
+            chars.next();
+            out.push('%');
+            continue;
+        }
+
+        let mut left_align = false;
+        let mut zero_pad = false;
+        let mut plus_sign = false;
+        while let Some(&flag) = chars.peek() {
+            match flag {
+                '-' => left_align = true,
+                '0' => zero_pad = true,
+                '+' => plus_sign = true,
+                ' ' | '#' => {}
+                _ => break,
+            }
+            chars.next();
+        }
+        let mut width = 0;
+        while let Some(digit) = chars.peek().and_then(|c| c.to_digit(10)) {
+            width = width * 10 + digit as usize;
+            chars.next();
+        }
+        let mut precision = None;
+        if chars.peek() == Some(&'.') {
+            chars.next();
+            let mut digits = 0;
+            while let Some(digit) = chars.peek().and_then(|c| c.to_digit(10)) {
+                digits = digits * 10 + digit as usize;
+                chars.next();
+            }
+            precision = Some(digits);
+        }
+        let mut modifier = String::new();
+        while let Some(&c @ ('h' | 'l' | 'z' | 'j' | 't' | 'L')) = chars.peek() {
+            modifier.push(c);
+            chars.next();
+        }
+
+        let conversion = chars
+            .next()
+            .ok_or_else(|| "format string ends in the middle of a conversion".to_string())?;
+        let arg = args
+            .next()
+            .ok_or_else(|| format!("missing argument for %{}", conversion))?;
+        // Integers are converted to the type the length modifier gives them, as C would
+        let bits = integer_bits(&modifier);
+        let unsigned = arg.as_u64() & (u64::MAX >> (64 - bits));
+        let signed = ((unsigned << (64 - bits)) as i64) >> (64 - bits);
+        let float = arg.as_f64();
+        let precision_or_default = precision.unwrap_or(6);
+        let mut formatted = match conversion {
+            'd' | 'i' => signed.to_string(),
+            'u' => unsigned.to_string(),
+            'x' => format!("{:x}", unsigned),
+            'X' => format!("{:X}", unsigned),
+            'o' => format!("{:o}", unsigned),
+            'p' => format!("{:#x}", arg.as_u64()),
+            'c' => ((arg.as_u64() as u8) as char).to_string(),
+            'f' | 'e' | 'g' if !float.is_finite() => format_non_finite(float),
+            'F' | 'E' | 'G' if !float.is_finite() => format_non_finite(float).to_uppercase(),
+            'f' | 'F' => format!("{:.*}", precision_or_default, float),
+            'e' => format_exponent(float, precision_or_default),
+            'E' => format_exponent(float, precision_or_default).to_uppercase(),
+            'g' => format_general(float, precision_or_default),
+            'G' => format_general(float, precision_or_default).to_uppercase(),
+            's' => {
+                let s = read_string(arg.as_u64() as usize)
+                    .ok_or_else(|| format!("cannot read string at {:#x}", arg.as_u64()))?;
+                match precision {
+                    Some(max) => s.chars().take(max).collect(),
+                    None => s,
+                }
+            }
+            other => return Err(format!("unsupported conversion %{}", other)),
+        };
+        let is_signed = matches!(conversion, 'd' | 'i' | 'f' | 'F' | 'e' | 'E' | 'g' | 'G');
+        if plus_sign && is_signed && !formatted.starts_with('-') {
+            formatted.insert(0, '+');
+        }
+        let padding = width.saturating_sub(formatted.chars().count());
+        if left_align {
+            out.push_str(&formatted);
+            out.push_str(&" ".repeat(padding));
+        } else if zero_pad && conversion != 's' && conversion != 'c' {
+            let sign_len = if formatted.starts_with(['-', '+']) {
+                1
+            } else {
+                0
+            };
+            formatted.insert_str(sign_len, &"0".repeat(padding));
+            out.push_str(&formatted);
+        } else {
+            out.push_str(&" ".repeat(padding));
+            out.push_str(&formatted);
+        }
+    }
+    if args.next().is_some() {
+        return Err("too many arguments for format string".to_string());
+    }
+    Ok(out)
+}
+
+/// Returns the width in bits of an integer argument with the given length modifier. Without one
+/// it's an int; h and hh make it a short or a char.
+fn integer_bits(modifier: &str) -> u32 {
+    match modifier {
+        "hh" => 8,
+        "h" => 16,
+        "" => 32,
+        _ => 64,
+    }
+}
+
+fn format_non_finite(val: f64) -> String {
+    match val {
+        _ if val.is_nan() => "nan".to_string(),
+        _ if val < 0.0 => "-inf".to_string(),
+        _ => "inf".to_string(),
+    }
+}
+
+/// Splits a number formatted with Rust's {:e} into its mantissa and exponent.
+fn split_exponent(formatted: &str) -> (&str, i32) {
+    let (mantissa, exponent) = formatted.split_once('e').unwrap_or((formatted, "0"));
+    (mantissa, exponent.parse().unwrap_or(0))
+}
+
+/// Formats val like C's %e, whose exponent has a sign and at least two digits.
+fn format_exponent(val: f64, precision: usize) -> String {
+    let formatted = format!("{:.*e}", precision, val);
+    let (mantissa, exponent) = split_exponent(&formatted);
+    let sign = if exponent < 0 { '-' } else { '+' };
+    format!("{}e{}{:02}", mantissa, sign, exponent.abs())
+}
+
+/// Formats val like C's %g: with precision significant digits, in exponent form if the exponent
+/// is less than -4 or at least the precision, and without trailing zeros.
+fn format_general(val: f64, precision: usize) -> String {
+    let precision = precision.max(1);
+    // The exponent after rounding, which can carry into the next power of ten
+    let (_, exponent) = split_exponent(&format!("{:.*e}", precision - 1, val));
+    let formatted = if exponent < -4 || exponent >= precision as i32 {
+        format_exponent(val, precision - 1)
+    } else {
+        format!("{:.*}", (precision as i32 - 1 - exponent) as usize, val)
+    };
+    let (mantissa, exponent) = formatted.split_at(formatted.find('e').unwrap_or(formatted.len()));
+    let mantissa = if mantissa.contains('.') {
+        mantissa.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        mantissa
+    };
+    format!("{}{}", mantissa, exponent)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn printf(format: &str, args: &[Value]) -> String {
+        format_printf(format, args, |addr| Some(format!("<{:#x}>", addr))).unwrap()
+    }
+
+    #[test]
+    fn integers_take_the_width_of_their_length_modifier() {
+        assert_eq!(printf("%x", &[Value::Int(-1)]), "ffffffff");
+        assert_eq!(printf("%X", &[Value::Int(-2)]), "FFFFFFFE");
+        assert_eq!(printf("%o", &[Value::Int(-1)]), "37777777777");
+        assert_eq!(printf("%u", &[Value::Int(-1)]), "4294967295");
+        assert_eq!(printf("%lx", &[Value::Int(-1)]), "ffffffffffffffff");
+        assert_eq!(printf("%llu", &[Value::Int(-1)]), "18446744073709551615");
+        assert_eq!(printf("%hx", &[Value::Int(-1)]), "ffff");
+        assert_eq!(printf("%hhx", &[Value::Int(-1)]), "ff");
+        assert_eq!(printf("%zx", &[Value::Uint(1 << 40)]), "10000000000");
+    }
+
+    #[test]
+    fn signed_integers_are_truncated_then_sign_extended() {
+        assert_eq!(printf("%d", &[Value::Int(-5)]), "-5");
+        assert_eq!(printf("%d", &[Value::Uint(0xffff_ffff)]), "-1");
+        assert_eq!(printf("%ld", &[Value::Uint(0xffff_ffff)]), "4294967295");
+        assert_eq!(printf("%hd", &[Value::Int(40000)]), "-25536");
+        assert_eq!(printf("%hhd", &[Value::Int(200)]), "-56");
+        assert_eq!(printf("%d", &[Value::Char(b'A')]), "65");
+    }
+
+    #[test]
+    fn flags_and_width() {
+        assert_eq!(printf("[%5d]", &[Value::Int(42)]), "[   42]");
+        assert_eq!(printf("[%-5d]", &[Value::Int(42)]), "[42   ]");
+        assert_eq!(printf("[%05d]", &[Value::Int(-42)]), "[-0042]");
+        assert_eq!(printf("[%+d]", &[Value::Int(42)]), "[+42]");
+        assert_eq!(printf("[%08.3f]", &[Value::Float(-1.23456)]), "[-001.235]");
+        assert_eq!(printf("%p", &[Value::Uint(0x401000)]), "0x401000");
+        assert_eq!(printf("%c%c", &[Value::Char(b'h'), Value::Int(105)]), "hi");
+        assert_eq!(printf("100%%", &[]), "100%");
+    }
+
+    #[test]
+    fn exponent_form_has_a_signed_two_digit_exponent() {
+        assert_eq!(printf("%e", &[Value::Float(12345.678)]), "1.234568e+04");
+        assert_eq!(printf("%.2e", &[Value::Float(0.000123)]), "1.23e-04");
+        assert_eq!(printf("%E", &[Value::Float(1e100)]), "1.000000E+100");
+        assert_eq!(printf("%e", &[Value::Float(0.0)]), "0.000000e+00");
+    }
+
+    #[test]
+    fn general_form_follows_c() {
+        assert_eq!(printf("%g", &[Value::Float(100000.0)]), "100000");
+        assert_eq!(printf("%g", &[Value::Float(1000000.0)]), "1e+06");
+        assert_eq!(printf("%g", &[Value::Float(1.234567891)]), "1.23457");
+        assert_eq!(printf("%g", &[Value::Float(0.0001)]), "0.0001");
+        assert_eq!(printf("%g", &[Value::Float(0.00001)]), "1e-05");
+        assert_eq!(printf("%g", &[Value::Float(0.0)]), "0");
+        assert_eq!(printf("%g", &[Value::Int(7)]), "7");
+        assert_eq!(printf("%.3g", &[Value::Float(1.23456)]), "1.23");
+        assert_eq!(printf("%.3g", &[Value::Float(1234.5)]), "1.23e+03");
+        assert_eq!(printf("%.0g", &[Value::Float(25.0)]), "2e+01");
+        assert_eq!(printf("%.2g", &[Value::Float(99.9)]), "1e+02");
+        assert_eq!(printf("%G", &[Value::Float(1.5e-10)]), "1.5E-10");
+        assert_eq!(printf("%g", &[Value::Float(-2.5)]), "-2.5");
+        let non_finite = [Value::Float(f64::INFINITY), Value::Float(f64::NAN)];
+        assert_eq!(printf("%g %G", &non_finite), "inf NAN");
+    }
+
+    #[test]
+    fn strings_are_read_from_the_inferior() {
+        assert_eq!(printf("%s!", &[Value::Uint(0x10)]), "<0x10>!");
+        assert_eq!(printf("%.3s", &[Value::Uint(0x10)]), "<0x");
+        let result = format_printf("%s", &[Value::Uint(0)], |_| None);
+        assert_eq!(result, Err("cannot read string at 0x0".to_string()));
+    }
+
+    #[test]
+    fn arguments_must_match_the_conversions() {
+        let no_strings = |_| None;
+        assert!(format_printf("%d %d", &[Value::Int(1)], no_strings).is_err());
+        assert!(format_printf("%d", &[Value::Int(1), Value::Int(2)], no_strings).is_err());
+        assert!(format_printf("%q", &[Value::Int(1)], no_strings).is_err());
+        assert!(format_printf("%", &[], no_strings).is_err());
+    }
+}
@@ -0,0 +1,16 @@
+//! Helpers shared by the unit tests and the integration tests.
+
+use std::process::Command;
+use std::sync::Mutex;
+
+/// Builds one of the sample programs, returning its path.
+pub fn sample(name: &str) -> String {
+    // Tests run in parallel, and two makes writing the same file would trip over each other
+    static MAKE: Mutex<()> = Mutex::new(());
+    let _guard = MAKE.lock().unwrap_or_else(|err| err.into_inner());
+    let dir = env!("CARGO_MANIFEST_DIR");
+    let path = format!("samples/{}", name);
+    let status = Command::new("make").args(["-s", &path]).current_dir(dir).status();
+    assert!(status.is_ok_and(|status| status.success()), "make {} failed", path);
+    format!("{}/{}", dir, path)
+}
@@ -0,0 +1,206 @@
+//! Drives `deet --dap` the way an editor would, through a session on samples/function_calls.c.
+
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::Duration;
+
+mod common;
+
+/// How long to wait for each message before deciding deet is stuck.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+struct Client {
+    process: Child,
+    input: ChildStdin,
+    messages: Receiver<Value>,
+    seq: i64,
+}
+
+impl Client {
+    fn start() -> Client {
+        common::sample("function_calls");
+        let dir = env!("CARGO_MANIFEST_DIR");
+        let mut process = Command::new(env!("CARGO_BIN_EXE_deet"))
+            .arg("--dap")
+            .current_dir(dir)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("couldn't run deet");
+        let input = process.stdin.take().unwrap();
+        let output = process.stdout.take().unwrap();
+        let (sender, messages) = mpsc::channel();
+        thread::spawn(move || {
+            let mut output = BufReader::new(output);
+            while let Some(message) = read_message(&mut output) {
+                if sender.send(message).is_err() {
+                    break;
+                }
+            }
+        });
+        Client { process, input, messages, seq: 0 }
+    }
+
+    fn send(&mut self, command: &str, arguments: Value) {
+        self.seq += 1;
+        let request = json!({
+            "seq": self.seq,
+            "type": "request",
+            "command": command,
+            "arguments": arguments,
+        });
+        let body = request.to_string();
+        write!(self.input, "Content-Length: {}\r\n\r\n{}", body.len(), body).unwrap();
+        self.input.flush().unwrap();
+    }
+
+    /// Skips messages until one matches, returning it.
+    fn wait_for(&self, description: &str, matches: impl Fn(&Value) -> bool) -> Value {
+        loop {
+            match self.messages.recv_timeout(TIMEOUT) {
+                Ok(message) if matches(&message) => return message,
+                Ok(_) => {}
+                Err(_) => panic!("no {} arrived", description),
+            }
+        }
+    }
+
+    fn event(&self, event: &str) -> Value {
+        self.wait_for(event, |message| message["type"] == "event" && message["event"] == event)
+    }
+
+    /// Sends a request and returns the body of its response, which must be successful.
+    fn request(&mut self, command: &str, arguments: Value) -> Value {
+        self.send(command, arguments);
+        let seq = self.seq;
+        let response = self.wait_for(command, |message| {
+            message["type"] == "response" && message["request_seq"] == seq
+        });
+        assert_eq!(response["success"], true, "{} failed: {}", command, response);
+        assert_eq!(response["command"], command);
+        response["body"].clone()
+    }
+
+    /// Returns the (function, line) of each stack frame.
+    fn stack(&mut self) -> Vec<(String, i64)> {
+        let body = self.request("stackTrace", json!({ "threadId": 1 }));
+        let frames = body["stackFrames"].as_array().unwrap();
+        let frame = |frame: &Value| {
+            (frame["name"].as_str().unwrap().to_string(), frame["line"].as_i64().unwrap())
+        };
+        frames.iter().map(frame).collect()
+    }
+}
+
+/// Reads one Content-Length framed message, or returns None at the end of the stream.
+fn read_message(output: &mut impl BufRead) -> Option<Value> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if output.read_line(&mut header).ok()? == 0 {
+            return None;
+        }
+        let header = header.trim();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            length = value.trim().parse().ok();
+        }
+    }
+    let mut body = vec![0; length?];
+    output.read_exact(&mut body).ok()?;
+    serde_json::from_slice(&body).ok()
+}
+
+fn frames(frames: &[(&str, i64)]) -> Vec<(String, i64)> {
+    frames.iter().map(|&(name, line)| (name.to_string(), line)).collect()
+}
+
+#[test]
+fn debugs_a_program() {
+    let mut client = Client::start();
+    let source = format!("{}/samples/function_calls.c", env!("CARGO_MANIFEST_DIR"));
+
+    let capabilities = client.request("initialize", json!({ "adapterID": "deet" }));
+    assert_eq!(capabilities["supportsConfigurationDoneRequest"], true);
+    client.event("initialized");
+    client.request("launch", json!({ "program": "samples/function_calls" }));
+
+    let lines = json!([{ "line": 11 }, { "line": 200 }]);
+    let arguments = json!({ "source": { "path": source }, "breakpoints": lines });
+    let body = client.request("setBreakpoints", arguments);
+    let breakpoints = body["breakpoints"].as_array().unwrap();
+    assert_eq!(breakpoints.len(), 2);
+    assert_eq!(breakpoints[0]["verified"], true);
+    assert_eq!(breakpoints[0]["line"], 11);
+    assert_eq!(breakpoints[1]["verified"], false);
+
+    client.request("configurationDone", json!({}));
+    assert_eq!(client.event("stopped")["body"]["reason"], "breakpoint");
+    let threads = client.request("threads", json!({}));
+    assert_eq!(threads["threads"][0]["id"], 1);
+    assert_eq!(client.stack(), frames(&[("func2", 11), ("func1", 18), ("main", 24)]));
+
+    let scopes = client.request("scopes", json!({ "frameId": 0 }));
+    let reference = scopes["scopes"][0]["variablesReference"].clone();
+    let body = client.request("variables", json!({ "variablesReference": reference }));
+    let variables: Vec<(&str, &str)> = body["variables"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|var| (var["name"].as_str().unwrap(), var["value"].as_str().unwrap()))
+        .collect();
+    assert!(variables.contains(&("a", "42")), "{:?}", variables);
+    assert!(variables.contains(&("b", "5")), "{:?}", variables);
+    assert!(variables.iter().any(|(name, _)| *name == "sum"), "{:?}", variables);
+
+    let body = client.request("evaluate", json!({ "expression": "a + b" }));
+    assert_eq!(body["result"], "47");
+    assert_eq!(body["type"], "int");
+
+    client.request("next", json!({ "threadId": 1 }));
+    assert_eq!(client.event("stopped")["body"]["reason"], "step");
+    assert_eq!(client.stack()[0], ("func2".to_string(), 12));
+
+    // printf has no line information, so the first step goes over it
+    client.request("stepIn", json!({ "threadId": 1 }));
+    client.event("stopped");
+    client.request("stepIn", json!({ "threadId": 1 }));
+    client.event("stopped");
+    assert_eq!(client.stack(), frames(&[("func3", 6), ("func2", 13), ("func1", 18), ("main", 24)]));
+
+    client.request("stepOut", json!({ "threadId": 1 }));
+    client.event("stopped");
+    assert_eq!(client.stack()[0].0, "func2");
+
+    let arguments = json!({ "source": { "path": source }, "breakpoints": [] });
+    let body = client.request("setBreakpoints", arguments);
+    assert_eq!(body["breakpoints"], json!([]));
+    client.request("continue", json!({ "threadId": 1 }));
+    let output = client.event("output");
+    assert!(output["body"]["output"].as_str().unwrap().contains("sum = 47"));
+    assert_eq!(client.event("exited")["body"]["exitCode"], 0);
+    client.event("terminated");
+
+    client.request("disconnect", json!({}));
+    drop(client.input);
+    assert!(client.process.wait().unwrap().success());
+}
+
+#[test]
+fn rejects_unknown_requests() {
+    let mut client = Client::start();
+    client.request("initialize", json!({ "adapterID": "deet" }));
+    client.send("frobnicate", json!({}));
+    let seq = client.seq;
+    let response = client.wait_for("response", |message| message["request_seq"] == seq);
+    assert_eq!(response["success"], false);
+    assert!(response["message"].as_str().unwrap().contains("frobnicate"));
+    client.request("disconnect", json!({}));
+    drop(client.input);
+    assert!(client.process.wait().unwrap().success());
+}